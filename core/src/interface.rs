@@ -0,0 +1,88 @@
+/// The well-known bus name of the daemon.
+pub const DBUS_NAME: &str = "com.system76.PopUpgrade";
+
+/// The object path which the daemon's interface is served from.
+pub const DBUS_PATH: &str = "/com/system76/PopUpgrade";
+
+/// The interface which the daemon's methods, properties, and signals belong to.
+pub const DBUS_IFACE: &str = "com.system76.PopUpgrade";
+
+/// Version of the DBus interface, which is incremented when the arguments of an existing method,
+/// property, or signal change. Features which are added are listed by `capabilities` instead.
+pub const API_VERSION: u32 = 1;
+
+/// Features of the daemon which clients may check for, as listed by its `Capabilities` property.
+pub mod capabilities {
+    /// Operations in progress may be cancelled.
+    pub const CANCEL: &str = "cancel";
+    /// The `StatusDetails` method reports the progress, initiator, and last error of the daemon.
+    pub const STATUS_DETAILS: &str = "status-details";
+    /// Release upgrades may be scheduled to begin at a later time.
+    pub const SCHEDULE: &str = "schedule";
+    /// The recovery partition may be upgraded from a file or from the release API.
+    pub const RECOVERY_UPGRADE: &str = "recovery-upgrade";
+    /// Release upgrades which were interrupted may be resumed.
+    pub const RELEASE_RESUME: &str = "release-resume";
+    /// The packages which a release upgrade removes or downgrades may be previewed.
+    pub const RELEASE_PREVIEW: &str = "release-preview";
+    /// Snapshots taken before release upgrades may be listed and restored.
+    pub const SNAPSHOTS: &str = "snapshots";
+    /// Upgrades to development releases may be allowed by the `DevelopmentReleases` property.
+    pub const DEVELOPMENT_RELEASES: &str = "development-releases";
+    /// The releases which upgrades would pass through may be listed by `UpgradePath`.
+    pub const UPGRADE_PATH: &str = "upgrade-path";
+    /// Users may dismiss notifications of new releases for themselves through
+    /// `DismissNotification`, and snooze them through the `Notification*` methods.
+    pub const NOTIFICATION_PREFERENCES: &str = "notification-preferences";
+    /// Whether a newer version of pop-upgrade is available may be checked by `SelfUpdateCheck`,
+    /// without installing it.
+    pub const SELF_UPDATE: &str = "self-update";
+    /// Whether a signed policy restricts upgrades is reported by the `Managed` property, and
+    /// upgrades which it does not permit are refused with `POLICY_ERROR`.
+    pub const MANAGED: &str = "managed";
+    /// Release upgrades may be simulated by `ReleaseSimulate`, which also reports the packages
+    /// to install and upgrade, and the size of the download.
+    pub const RELEASE_SIMULATE: &str = "release-simulate";
+    /// The operations which the daemon has performed may be listed by `History`.
+    pub const HISTORY: &str = "history";
+    /// Release upgrades may be given options by name through `ReleaseUpgradeWithOptions`.
+    pub const RELEASE_UPGRADE_OPTIONS: &str = "release-upgrade-options";
+
+    /// Every capability of this version of the daemon.
+    pub const ALL: &[&str] = &[
+        CANCEL,
+        STATUS_DETAILS,
+        SCHEDULE,
+        RECOVERY_UPGRADE,
+        RELEASE_RESUME,
+        RELEASE_PREVIEW,
+        SNAPSHOTS,
+        DEVELOPMENT_RELEASES,
+        UPGRADE_PATH,
+        NOTIFICATION_PREFERENCES,
+        SELF_UPDATE,
+        MANAGED,
+        RELEASE_SIMULATE,
+        HISTORY,
+        RELEASE_UPGRADE_OPTIONS,
+    ];
+}
+
+/// Names of the DBus errors which the daemon replies with, that clients may act upon.
+pub mod errors {
+    /// Another upgrade operation, possibly of another process, is already in progress.
+    pub const BUSY_ERROR: &str = "com.system76.PopUpgrade.Error.Busy";
+    /// A captive portal intercepts requests, and the user must log into the network.
+    pub const CAPTIVE_PORTAL_ERROR: &str = "com.system76.PopUpgrade.Error.CaptivePortal";
+    /// The system is on a battery which may run out before the upgrade completes.
+    pub const LOW_BATTERY_ERROR: &str = "com.system76.PopUpgrade.Error.LowBattery";
+    /// The connection is metered, and the download was deferred.
+    pub const METERED_ERROR: &str = "com.system76.PopUpgrade.Error.Metered";
+    /// The upgrade servers are unreachable.
+    pub const NO_CONNECTION_ERROR: &str = "com.system76.PopUpgrade.Error.NoConnection";
+    /// Polkit denied the action, such as when the user dismissed the authentication.
+    pub const NOT_AUTHORIZED_ERROR: &str = "com.system76.PopUpgrade.Error.NotAuthorized";
+    /// The upgrade policy of a managed machine does not permit the upgrade, or could not be
+    /// verified.
+    pub const POLICY_ERROR: &str = "com.system76.PopUpgrade.Error.Policy";
+}
@@ -0,0 +1,33 @@
+//! Types which are shared by the Pop!_OS upgrade daemon and the frontends which drive it, such as
+//! the GNOME Settings plugin, along with any third-party tooling.
+//!
+//! These types describe the state of the daemon, and of the operations which it performs, as
+//! they are exchanged over its DBus interface. Their numeric values are part of that interface,
+//! and never change. The API of this crate follows semantic versioning: adding a field to a
+//! struct is a breaking change which increments the major version. The enums of daemon statuses
+//! and operation events are `#[non_exhaustive]`, and may gain variants in a minor version.
+
+#![deny(missing_docs)]
+
+#[macro_use]
+extern crate bitflags;
+
+#[macro_use]
+extern crate num_derive;
+
+/// Names, capabilities, and errors of the daemon's DBus interface
+pub mod interface;
+
+/// Outcomes of the checks which are performed before an upgrade
+pub mod preflight;
+
+/// Events and options of recovery partition upgrades
+pub mod recovery;
+
+/// Events and options of release upgrades
+pub mod release;
+
+/// Statuses of the daemon, and the phases of its operations
+pub mod status;
+
+pub use self::status::DaemonStatus;
@@ -0,0 +1,66 @@
+use serde_derive::Serialize;
+
+/// The name of the check which fails when the package database is broken, and which may be
+/// resolved by a release repair.
+pub const APT_CHECK: &str = "apt";
+
+/// The name of the check which fails when the battery is low, and which an upgrade may be
+/// forced to ignore.
+pub const POWER_CHECK: &str = "AC power";
+
+/// The outcome of a check, ordered from best to worst.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, FromPrimitive, Ord, PartialEq, PartialOrd, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// The condition holds.
+    Pass = 0,
+    /// The upgrade may proceed, but the condition deserves attention.
+    Warn = 1,
+    /// The upgrade would fail, or leave the system unbootable.
+    Fail = 2,
+}
+
+/// A condition which must hold for an upgrade to succeed.
+pub trait Check {
+    /// The name which the check is reported under.
+    fn name(&self) -> String;
+
+    /// Checks the condition, describing the outcome.
+    fn run(&self) -> (Status, String);
+}
+
+/// The outcome of a check.
+#[derive(Clone, Debug)]
+pub struct CheckResult {
+    /// The name of the check.
+    pub name:    String,
+    /// Whether the condition held.
+    pub status:  Status,
+    /// A description of the outcome.
+    pub message: String,
+}
+
+/// The outcomes of each of the checks which were run.
+#[derive(Clone, Debug, Default)]
+pub struct Report(pub Vec<CheckResult>);
+
+impl Report {
+    /// The worst status of every check in the report.
+    pub fn status(&self) -> Status {
+        self.0.iter().map(|result| result.status).max().unwrap_or(Status::Pass)
+    }
+}
+
+/// Runs each of the checks, and collects their outcomes into a report.
+pub fn run(checks: &[Box<dyn Check>]) -> Report {
+    Report(
+        checks
+            .iter()
+            .map(|check| {
+                let (status, message) = check.run();
+                CheckResult { name: check.name(), status, message }
+            })
+            .collect(),
+    )
+}
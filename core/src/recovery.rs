@@ -0,0 +1,37 @@
+bitflags! {
+    /// Options of an upgrade of the recovery partition from the release API.
+    pub struct ReleaseFlags: u8 {
+        /// Upgrade to the ISO of the next release, rather than of the current release.
+        const NEXT = 1;
+        /// Upgrade even if the system is on a battery which is low.
+        const IGNORE_BATTERY = 2;
+        /// Download the ISO even if the connection is metered.
+        const ALLOW_METERED = 4;
+    }
+}
+
+/// The phases of a recovery partition upgrade, as signaled by `RecoveryUpgradeEvent`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+#[non_exhaustive]
+pub enum RecoveryEvent {
+    /// The ISO is being downloaded.
+    Fetching = 1,
+    /// The checksums of the ISO and its files are being verified.
+    Verifying = 2,
+    /// The files of the ISO are being written to the recovery partition.
+    Syncing = 3,
+    /// The recovery partition was upgraded.
+    Complete = 4,
+}
+
+impl From<RecoveryEvent> for &'static str {
+    fn from(event: RecoveryEvent) -> Self {
+        match event {
+            RecoveryEvent::Fetching => "fetching recovery files",
+            RecoveryEvent::Syncing => "syncing recovery files with recovery partition",
+            RecoveryEvent::Verifying => "verifying checksums of fetched files",
+            RecoveryEvent::Complete => "recovery partition upgrade completed",
+        }
+    }
+}
@@ -0,0 +1,260 @@
+use serde::Serializer;
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// How the new release is installed once its packages have been fetched.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum UpgradeMethod {
+    /// The new release is installed on the next boot, before the desktop starts.
+    Offline = 1,
+}
+
+impl From<UpgradeMethod> for &'static str {
+    fn from(action: UpgradeMethod) -> Self {
+        match action {
+            UpgradeMethod::Offline => "offline upgrade",
+        }
+    }
+}
+
+bitflags! {
+    /// Conditions which a release upgrade may be forced to proceed despite.
+    pub struct UpgradeFlags: u8 {
+        /// Upgrade even if the system is on a battery which is low.
+        const IGNORE_BATTERY = 1;
+        /// Download the upgrade even if the connection is metered.
+        const ALLOW_METERED = 2;
+    }
+}
+
+/// Whether the system is set to refresh the OS from the recovery partition on the next boot.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug)]
+pub enum RefreshOp {
+    /// Reports whether the refresh is enabled.
+    Status = 0,
+    /// Boots into the recovery partition to refresh the OS.
+    Enable = 1,
+    /// Boots into the OS as usual.
+    Disable = 2,
+}
+
+/// How close the current release is to its end of life.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum EolStatus {
+    /// The release is supported.
+    Ok = 0,
+    /// The release reaches its end of life soon.
+    Imminent = 1,
+    /// The release is no longer supported.
+    Exceeded = 2,
+}
+
+/// Bytes fetched of a package, and of all packages being fetched.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FetchProgress {
+    /// The file name of the package.
+    pub package:       String,
+    /// Bytes of the package which have been fetched.
+    pub fetched:       u64,
+    /// Size of the package, in bytes.
+    pub size:          u64,
+    /// Bytes of every package which have been fetched.
+    pub total_fetched: u64,
+    /// Size of every package, in bytes.
+    pub total:         u64,
+}
+
+/// The phases of a release upgrade, as signaled by `ReleaseUpgradeEvent`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+#[non_exhaustive]
+pub enum UpgradeEvent {
+    /// Updating the package lists of the current release.
+    UpdatingPackageLists = 1,
+    /// Fetching updated packages for the current release.
+    FetchingPackages = 2,
+    /// Upgrading the packages of the current release.
+    UpgradingPackages = 3,
+    /// Ensuring that system-critical packages are installed.
+    InstallingPackages = 4,
+    /// Updating the source lists to the new release.
+    UpdatingSourceLists = 5,
+    /// Fetching the packages of the new release.
+    FetchingPackagesForNewRelease = 6,
+    /// Attempting to upgrade to the new release while the system is running.
+    AttemptingLiveUpgrade = 7,
+    /// Setting up the system to install the new release on the next boot.
+    AttemptingSystemdUnit = 8,
+    /// Setting up the recovery partition to install the new release.
+    AttemptingRecovery = 9,
+    /// The new release is ready to install.
+    Success = 10,
+    /// The new release was installed.
+    SuccessLive = 11,
+    /// An error occurred while setting up the upgrade.
+    Failure = 12,
+    /// Waiting on another process to release the package manager's locks.
+    AptFilesLocked = 13,
+    /// Removing deprecated and conflicting packages.
+    RemovingConflicts = 14,
+    /// Simulating the upgrade, to find what it would do.
+    Simulating = 15,
+    /// Updating Flatpak runtimes and applications.
+    UpdatingFlatpaks = 16,
+    /// Repairing the Flathub remote.
+    RepairingFlatpakRemote = 17,
+    /// Pausing automatic refreshes of snaps.
+    PausingSnapRefresh = 18,
+    /// Refreshing snaps.
+    RefreshingSnaps = 19,
+    /// Removing packages which are no longer required, and clearing the package cache.
+    ReclaimingSpace = 20,
+}
+
+impl From<UpgradeEvent> for &'static str {
+    fn from(action: UpgradeEvent) -> Self {
+        match action {
+            UpgradeEvent::AptFilesLocked => "waiting on a process holding the apt lock files",
+            UpgradeEvent::AttemptingLiveUpgrade => "attempting live upgrade to the new release",
+            UpgradeEvent::AttemptingSystemdUnit => {
+                "setting up the system to perform an offline upgrade on the next boot"
+            }
+            UpgradeEvent::AttemptingRecovery => {
+                "setting up the recovery partition to install the new release"
+            }
+            UpgradeEvent::Failure => "an error occurred while setting up the release upgrade",
+            UpgradeEvent::FetchingPackages => "fetching updated packages for the current release",
+            UpgradeEvent::FetchingPackagesForNewRelease => "fetching packages for the new release",
+            UpgradeEvent::InstallingPackages => {
+                "ensuring that system-critical packages are installed"
+            }
+            UpgradeEvent::PausingSnapRefresh => "pausing automatic refreshes of snaps",
+            UpgradeEvent::ReclaimingSpace => {
+                "removing packages which are no longer required, and clearing the package cache"
+            }
+            UpgradeEvent::RefreshingSnaps => "refreshing snaps",
+            UpgradeEvent::RemovingConflicts => "removing deprecated and/or conflicting packages",
+            UpgradeEvent::RepairingFlatpakRemote => "repairing the Flathub remote",
+            UpgradeEvent::Success => "new release is ready to install",
+            UpgradeEvent::SuccessLive => "new release was successfully installed",
+            UpgradeEvent::UpdatingPackageLists => "updating package lists",
+            UpgradeEvent::UpdatingSourceLists => "updating the source lists",
+            UpgradeEvent::UpgradingPackages => "upgrading packages for the current release",
+            UpgradeEvent::Simulating => "simulating upgrade",
+            UpgradeEvent::UpdatingFlatpaks => "updating Flatpak runtimes and applications",
+        }
+    }
+}
+
+/// A build of a release which is available to upgrade to.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Build {
+    /// The build number of the ISO.
+    pub build:         u16,
+    /// Size of the ISO, in bytes.
+    pub size:          u64,
+    /// A link to the notes of the release, if it has any.
+    pub release_notes: Option<String>,
+}
+
+/// Whether a build of the next release is available, and why not if it is not.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildStatus {
+    /// Upgrades to the next release are not offered.
+    Blacklisted,
+    /// A build of the next release is available.
+    Build(Build),
+    /// The release API could not be reached.
+    ConnectionIssue(String),
+    /// The response of the release API could not be used.
+    InternalIssue(String),
+    /// The release is being phased in, and has been offered to this percentage of machines,
+    /// which does not include this one yet.
+    NotYetRolledOut(u8),
+    /// The release API responded with this HTTP status code.
+    ServerStatus(u16),
+    /// The server is rate limiting requests, and asked to wait this long before retrying.
+    Throttled(Duration),
+}
+
+impl BuildStatus {
+    /// Whether a build is available.
+    pub fn is_ok(&self) -> bool { self.build().is_some() }
+
+    /// The available build, if there is one.
+    pub fn build(&self) -> Option<&Build> {
+        if let BuildStatus::Build(ref build) = *self {
+            Some(build)
+        } else {
+            None
+        }
+    }
+
+    /// The build number if a build is available, or a negative code for why it is not.
+    pub fn status_code(&self) -> i16 {
+        match *self {
+            BuildStatus::ConnectionIssue(_) => -3,
+            BuildStatus::ServerStatus(_) => -2,
+            BuildStatus::InternalIssue(_) => -1,
+            BuildStatus::Build(ref build) => build.build as i16,
+            BuildStatus::Blacklisted => -4,
+            BuildStatus::Throttled(_) => -5,
+            BuildStatus::NotYetRolledOut(_) => -6,
+        }
+    }
+}
+
+/// Serialized as an object with a `status` tag, so that frontends need not interpret the
+/// integers of `status_code`.
+impl serde::Serialize for BuildStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(tag = "status", rename_all = "snake_case")]
+        enum Repr<'a> {
+            Available(&'a Build),
+            Blacklisted,
+            ConnectionIssue { error: &'a str },
+            InternalIssue { error: &'a str },
+            NotYetRolledOut { rollout: u8 },
+            ServerStatus { code: u16 },
+            Throttled { retry_after: u64 },
+        }
+
+        let repr = match *self {
+            BuildStatus::Blacklisted => Repr::Blacklisted,
+            BuildStatus::Build(ref build) => Repr::Available(build),
+            BuildStatus::ConnectionIssue(ref error) => Repr::ConnectionIssue { error },
+            BuildStatus::InternalIssue(ref error) => Repr::InternalIssue { error },
+            BuildStatus::NotYetRolledOut(rollout) => Repr::NotYetRolledOut { rollout },
+            BuildStatus::ServerStatus(code) => Repr::ServerStatus { code },
+            BuildStatus::Throttled(wait) => Repr::Throttled { retry_after: wait.as_secs() },
+        };
+
+        serde::Serialize::serialize(&repr, serializer)
+    }
+}
+
+/// The outcome of a check for the next release.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct ReleaseStatus {
+    /// The version of the installed release.
+    pub current: Box<str>,
+    /// The version of the release to upgrade to.
+    pub next:    Box<str>,
+    /// The ISO variant that the build was checked for, such as `intel` or `nvidia`.
+    pub arch:    Box<str>,
+    /// Whether a build of the next release is available.
+    pub build:   BuildStatus,
+    /// Whether the installed release is a long-term support release.
+    pub is_lts:  bool,
+}
+
+impl ReleaseStatus {
+    /// Whether the installed release is a long-term support release.
+    pub fn is_lts(&self) -> bool { self.is_lts }
+}
@@ -0,0 +1,63 @@
+use crate::{recovery::RecoveryEvent, release::UpgradeEvent};
+use num_traits::FromPrimitive;
+use std::fmt::{self, Display};
+
+/// The operation which the daemon is performing, as reported by its `Status` method.
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[non_exhaustive]
+pub enum DaemonStatus {
+    /// No operation is in progress.
+    Inactive = 0,
+    /// Packages are being fetched for a later upgrade.
+    FetchingPackages = 1,
+    /// The recovery partition is being upgraded.
+    RecoveryUpgrade = 2,
+    /// The system is being set up to upgrade to the next release.
+    ReleaseUpgrade = 3,
+    /// The packages of the current release are being upgraded.
+    PackageUpgrade = 4,
+}
+
+impl DaemonStatus {
+    /// The phase of the operation which a sub-status of this status stands for.
+    pub fn phase(self, sub_status: u8) -> &'static str {
+        let phase = match self {
+            DaemonStatus::RecoveryUpgrade => RecoveryEvent::from_u8(sub_status).map(Into::into),
+            DaemonStatus::ReleaseUpgrade => UpgradeEvent::from_u8(sub_status).map(Into::into),
+            _ => None,
+        };
+
+        phase.unwrap_or_else(|| self.into())
+    }
+}
+
+impl From<DaemonStatus> for &'static str {
+    fn from(status: DaemonStatus) -> Self {
+        match status {
+            DaemonStatus::Inactive => "inactive",
+            DaemonStatus::FetchingPackages => "fetching package updates",
+            DaemonStatus::RecoveryUpgrade => "upgrading recovery partition",
+            DaemonStatus::ReleaseUpgrade => "upgrading distribution release",
+            DaemonStatus::PackageUpgrade => "upgrading packages",
+        }
+    }
+}
+
+impl Display for DaemonStatus {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(<&'static str>::from(*self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases() {
+        assert_eq!(DaemonStatus::ReleaseUpgrade.phase(6), "fetching packages for the new release");
+        assert_eq!(DaemonStatus::ReleaseUpgrade.phase(0), "upgrading distribution release");
+        assert_eq!(DaemonStatus::PackageUpgrade.phase(6), "upgrading packages");
+    }
+}
@@ -7,6 +7,12 @@ rm -rf /pop-upgrade /pop_preparing_release_upgrade
 
 PREINST=(zlib1g libc6:i386 libmount1:i386)
 
+# Resolve conflicts with modified conffiles by the policy recorded for the upgrade
+case "$(cat /var/lib/pop-upgrade/conffiles 2>/dev/null)" in
+    new) CONFFILES=(-o Dpkg::Options::="--force-confnew") ;;
+    *) CONFFILES=(-o Dpkg::Options::="--force-confdef" -o Dpkg::Options::="--force-confold") ;;
+esac
+
 message () {
     plymouth message --text="system-updates"
 
@@ -60,8 +66,7 @@ dpkg_configure () {
 apt_install_fix () {
     message -i "Checking for package fixes"
     env LANG=C apt-get -o Dpkg::Options::="--force-overwrite" \
-        -o Dpkg::Options::="--force-confdef" \
-        -o Dpkg::Options::="--force-confold" \
+        "${CONFFILES[@]}" \
         install -f -y --allow-downgrades --show-progress \
         --no-download --ignore-missing
 }
@@ -71,8 +76,7 @@ apt_full_upgrade () {
 
     # Watch progress of an update, and report it to the splash screen
     env LANG=C apt-get -o Dpkg::Options::="--force-overwrite" \
-        -o Dpkg::Options::="--force-confdef" \
-        -o Dpkg::Options::="--force-confold" \
+        "${CONFFILES[@]}" \
         full-upgrade -y --allow-downgrades --show-progress \
         --no-download --ignore-missing | while read -r line; do
             if test "Progress: [" = "$(echo ${line} | cut -c-11)"; then
@@ -109,8 +113,7 @@ apt_install_prereq () {
     for package in ${PREINST[@]}; do
       if [[ $(dpkg -s $package | wc -l) > 0 ]]; then
         env LANG=C apt-get -o Dpkg::Options::="--force-overwrite" \
-          -o Dpkg::Options::="--force-confdef" \
-          -o Dpkg::Options::="--force-confold" \
+          "${CONFFILES[@]}" \
           install -y --allow-downgrades --show-progress \
           --no-download --ignore-missing $package
       fi
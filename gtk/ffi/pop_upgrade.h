@@ -0,0 +1,98 @@
+#include <stdbool.h>
+#include <stdint.h>
+
+typedef struct PopUpgradeClient PopUpgradeClient;
+
+/// The release which the system may be upgraded to, as found by a release check.
+typedef struct {
+    char *current;
+    char *next;
+    /// The build of the next release, or a negative error code if none is available.
+    int16_t build;
+    /// The build which the upgrade is urgent for, or -1.
+    int32_t urgent;
+    bool is_lts;
+    /// Size of the ISO to download, in bytes.
+    uint64_t size;
+    /// A link to the notes of the next release, or NULL.
+    char *release_notes_url;
+} PopUpgradeReleaseInfo;
+
+typedef enum {
+    /// `value` is the status of the daemon, and `total` its sub-status.
+    POP_UPGRADE_EVENT_STATUS_CHANGED = 1,
+    /// `value` is the upgrade event, which `message` describes.
+    POP_UPGRADE_EVENT_RELEASE_EVENT = 2,
+    /// `value` is the bytes of packages fetched, of `total` bytes.
+    POP_UPGRADE_EVENT_FETCH_PROGRESS = 3,
+    /// `value` is the number of packages fetched, of `total` packages, the last of which was
+    /// `message`.
+    POP_UPGRADE_EVENT_FETCHED = 4,
+    /// The apt repositories are unreachable.
+    POP_UPGRADE_EVENT_NO_CONNECTION = 5,
+    /// `value` is 0 if the upgrade succeeded, and `message` describes why it failed otherwise.
+    /// No more events follow it.
+    POP_UPGRADE_EVENT_RELEASE_RESULT = 6,
+} PopUpgradeEventKindValues;
+
+typedef uint8_t PopUpgradeEventKind;
+
+/// An event of an upgrade in progress. The message is only valid for the duration of the
+/// callback, and may be NULL.
+typedef struct {
+    PopUpgradeEventKind kind;
+    uint64_t value;
+    uint64_t total;
+    const char *message;
+} PopUpgradeEvent;
+
+typedef void (*PopUpgradeEventCallback)(const PopUpgradeEvent*, void*);
+
+/// Describes the last failure of a function on this thread, or returns NULL.
+///
+/// # Notes
+///
+/// Functions which may fail return 0 on success, and -1 on failure. The description is valid
+/// until the next failure.
+const char *pop_upgrade_last_error (void);
+
+/// Connects to the daemon, or returns NULL on failure.
+PopUpgradeClient *pop_upgrade_client_new (void);
+
+void pop_upgrade_client_free (PopUpgradeClient *self);
+
+/// Checks for a release to upgrade to, writing it to `info`.
+///
+/// # Notes
+///
+/// The strings of `info` must be freed with `pop_upgrade_release_info_free`.
+int pop_upgrade_client_release_check (
+    const PopUpgradeClient *self,
+    bool development,
+    bool force,
+    PopUpgradeReleaseInfo *info
+);
+
+void pop_upgrade_release_info_free (PopUpgradeReleaseInfo *info);
+
+/// Writes the status of the daemon, and the sub-status of its operation.
+int pop_upgrade_client_status (
+    const PopUpgradeClient *self,
+    uint8_t *status,
+    uint8_t *sub_status
+);
+
+/// Upgrades from the `from` release to the `to` release, with the upgrade flags of `flags`.
+///
+/// # Notes
+///
+/// Blocks until the upgrade is ready to install on the next boot, passing each of its events
+/// to `callback` meanwhile, from the calling thread. `callback` may be NULL.
+int pop_upgrade_client_release_upgrade (
+    const PopUpgradeClient *self,
+    const char *from,
+    const char *to,
+    uint8_t flags,
+    PopUpgradeEventCallback callback,
+    void *user_data
+);
@@ -2,6 +2,8 @@ use glib::object::ObjectType;
 use pop_upgrade_gtk::*;
 use std::{ffi, ptr};
 
+pub use pop_upgrade::ffi::*;
+
 #[no_mangle]
 pub struct PopUpgradeWidget;
 
@@ -5,7 +5,7 @@ use apt_cmd::AptUpgradeEvent;
 use pop_upgrade::{
     client::{self, Client, ReleaseInfo, Signal},
     daemon::DaemonStatus,
-    release::UpgradeMethod,
+    release::{UpgradeFlags, UpgradeMethod},
 };
 
 pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
@@ -31,7 +31,8 @@ pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
 
     send(UiEvent::Initiated(InitiatedEvent::Download(next.clone())));
 
-    if let Err(why) = client.release_upgrade(how, current, next) {
+    let flags = UpgradeFlags::empty();
+    if let Err(why) = client.release_upgrade(how, current, next, false, None, flags) {
         send(UiEvent::Error(UiError::Upgrade(why.into())));
         return;
     }
@@ -55,10 +56,10 @@ pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
                         return Ok(client::Continue(false));
                     }
                 }
-                Signal::PackageFetched(status) => {
+                Signal::PackageFetchProgress(progress) if progress.total != 0 => {
                     send(UiEvent::Progress(ProgressEvent::Fetching(
-                        status.completed as u64,
-                        status.total as u64,
+                        progress.total_fetched,
+                        progress.total,
                     )));
                 }
                 Signal::PackageUpgrade(event) => {
@@ -72,6 +73,7 @@ pub fn download(client: &Client, send: &dyn Fn(UiEvent), info: ReleaseInfo) {
                         _ => (),
                     }
                 }
+                Signal::PackageLockWait(_) => send(UiEvent::WaitingOnLock),
                 Signal::ReleaseEvent(event) => {
                     send(UiEvent::Upgrade(OsUpgradeEvent::Event(event)));
                 }
@@ -140,10 +142,10 @@ pub fn update(client: &Client, send: &dyn Fn(UiEvent)) -> bool {
 
                         return Ok(client::Continue(false));
                     }
-                    Signal::PackageFetched(status) => {
+                    Signal::PackageFetchProgress(progress) if progress.total != 0 => {
                         send(UiEvent::Progress(ProgressEvent::Fetching(
-                            status.completed as u64,
-                            status.total as u64,
+                            progress.total_fetched,
+                            progress.total,
                         )));
                     }
                     Signal::PackageUpgrade(event) => {
@@ -157,6 +159,7 @@ pub fn update(client: &Client, send: &dyn Fn(UiEvent)) -> bool {
                             _ => (),
                         }
                     }
+                    Signal::PackageLockWait(_) => send(UiEvent::WaitingOnLock),
                     Signal::ReleaseEvent(event) => {
                         send(UiEvent::Upgrade(OsUpgradeEvent::Event(event)));
                     }
@@ -58,7 +58,7 @@ pub fn scan(client: &Client, send: &dyn Fn(UiEvent)) {
         fl!("upgrade-downloading")
     } else {
         let devel = pop_upgrade::development_releases_enabled();
-        let result = client.release_check(devel);
+        let result = client.release_check(devel, false);
         match result {
             Ok(info) => {
                 current = dbg!(Some(info.current.clone()));
@@ -100,6 +100,12 @@ pub fn scan(client: &Client, send: &dyn Fn(UiEvent)) {
                             fl!("release-current")
                         }
                         -3 => fl!("error-connection"),
+                        -5 => fl!("error-throttled"),
+                        -6 => {
+                            is_current = true;
+                            status_failed = false;
+                            fl!("release-rolling-out", version = (&*info.next))
+                        }
                         _ => fl!("error-unknown-status"),
                     }
                 }
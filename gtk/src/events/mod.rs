@@ -326,7 +326,7 @@ fn connect_upgrade(state: &mut State, widgets: &EventWidgets, is_lts: bool, rebo
                     current = fomat!((eol.version)),
                     date = fomat!((Utc.ymd(y as i32, m, d).format("%B %-d, %Y")))
                 )),
-                EolStatus::Ok => None,
+                _ => None,
             }
         }
         Err(why) => {
@@ -0,0 +1,62 @@
+-os = Pop!_OS
+
+## Notifications from the daemon
+
+notification-app-name = {-os} Upgrade
+notification-dismiss = Dismiss
+notification-snooze = Remind Me Later
+
+release-available = {-os} {$version} is available
+release-available-body = Open Settings to upgrade to the new release
+
+upgrade-downloaded = Upgrade downloaded, restart to install
+upgrade-downloaded-body = {-os} {$version} is ready to be installed
+
+## Notifications from `pop-upgrade release check`
+
+eol-exceeded = Support for {-os} {$current} has ended
+eol-exceeded-body = Security and application updates are no longer provided for {-os} {$current}. Upgrade to {-os} {$next} to keep your computer secure.
+eol-imminent = Support for {-os} {$current} ends {$date}
+eol-imminent-body = This computer will soon stop receiving updates. Upgrade to {-os} {$next} to keep your computer secure.
+upgrade-available = Upgrade Available
+upgrade-available-body = {-os} {$next} is available to download
+
+## Results of operations
+
+fetch-result = Package fetch status
+fetch-result-success = cargo has been loaded successfully
+fetch-result-error = package-fetching aborted
+
+recovery-result = Recovery upgrade status
+recovery-result-success = recovery partition refueled and ready to go
+recovery-result-error = recovery upgrade aborted
+
+upgrade-result = Release upgrade status
+upgrade-result-success = systems are go for launch: reboot now
+upgrade-result-error = release upgrade aborted
+
+## Cancellation
+
+cancel-idle = no operation is in progress
+cancel-waiting = cancelling {$operation}: waiting for the daemon to clean up
+cancel-done = {$operation} was cancelled: {$why}
+cancel-too-late = {$operation} completed before it could be cancelled
+cancel-timed-out = {$operation} did not finish cleaning up within {$seconds} seconds
+
+## Updates of pop-upgrade itself
+
+self-update-checking = checking if pop-upgrade requires an update
+self-update-prompt = {$name} {$installed} may not know how to upgrade to the newest release. Update to {$candidate} first? [Y/n]
+self-update-declined = continuing with pop-upgrade {$installed}
+self-update-waiting = waiting for daemon to update and restart
+self-update-reconnecting = reconnecting to pop-upgrade daemon
+
+## Errors
+
+error-caused-by = caused by: {$why}
+error-not-root = root is required for this operation
+error-not-ready = the system is not ready to be upgraded
+error-preflight-failed = preflight checks failed: resolve the issues above before upgrading
+no-updates = no updates available to fetch
+
+prompt-answer = The answer must be either `y` or `n`.
@@ -49,6 +49,7 @@ error-package-manager = If you are seeing package manager issues, please run the
 error-recovery-check = Failed to check for recovery updates
 error-recovery-download = Failed to download recovery update
 error-recovery-update = Recovery update failed
+error-throttled = The release server is busy. Try again later
 error-try-again = Try again later
 error-unknown-status = Unknown status received.
 error-update-check = Failed to check for updates
@@ -82,6 +83,7 @@ refresh-description = Reinstall while keeping user accounts and files
 refresh-header = Refresh OS
 
 release-current = You are running the most current {-os} version
+release-rolling-out = {-os} {$version} is being released gradually, and will be available soon
 
 upgrade-available = {-os} {$version} is available
 upgrade-canceling = Canceling upgrade
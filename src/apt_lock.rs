@@ -0,0 +1,125 @@
+use crate::config::Config;
+use async_io::Timer;
+use std::{
+    fs::{self, File},
+    io,
+    os::unix::io::AsRawFd,
+    time::{Duration, Instant},
+};
+
+/// Locks which apt and dpkg hold while they are modifying the system.
+const LOCK_FILES: &[&str] = &[
+    "/var/lib/dpkg/lock-frontend",
+    "/var/lib/dpkg/lock",
+    "/var/lib/apt/lists/lock",
+    "/var/cache/apt/archives/lock",
+];
+
+/// How long to wait for the locks to be released, if not configured.
+const DEFAULT_TIMEOUT: u64 = 600;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A process which holds one of the package manager's locks.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LockHolder {
+    pub pid:  u32,
+    /// The name of the process, or an empty string if it could not be determined.
+    pub name: String,
+}
+
+impl LockHolder {
+    fn read(pid: u32) -> Self {
+        let name = fs::read_to_string(["/proc/", &pid.to_string(), "/comm"].concat())
+            .map(|name| name.trim().to_owned())
+            .unwrap_or_default();
+
+        LockHolder { pid, name }
+    }
+}
+
+/// The process which holds one of the package manager's locks, if any of them are held.
+pub fn holder() -> Option<LockHolder> { LOCK_FILES.iter().find_map(|path| lock_holder(path)) }
+
+/// The configured duration to wait for the locks to be released.
+pub fn timeout() -> Duration {
+    Duration::from_secs(Config::load_or_default().release.lock_timeout.unwrap_or(DEFAULT_TIMEOUT))
+}
+
+/// Waits for the package manager's locks to be released, failing if they are held for longer
+/// than the configured timeout. `on_wait` is called whenever a different process is found to be
+/// holding them.
+pub async fn wait(on_wait: &(dyn Fn(&LockHolder) + Send + Sync)) -> io::Result<()> {
+    let timeout = timeout();
+    let start = Instant::now();
+    let mut last: Option<LockHolder> = None;
+
+    while let Some(holder) = holder() {
+        if start.elapsed() >= timeout {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                fomat!(
+                    "timed out after " (timeout.as_secs()) " seconds waiting for "
+                    (describe(&holder)) " to release the package manager lock"
+                ),
+            ));
+        }
+
+        if last.as_ref() != Some(&holder) {
+            info!("waiting for {} to release the package manager lock", describe(&holder));
+            on_wait(&holder);
+            last = Some(holder);
+        }
+
+        Timer::after(POLL_INTERVAL).await;
+    }
+
+    Ok(())
+}
+
+/// Describes a process holding the lock, such as `unattended-upgr (pid 1234)`.
+pub fn describe(holder: &LockHolder) -> String {
+    match (holder.name.as_str(), holder.pid) {
+        ("", 0) => "another process".into(),
+        ("", pid) => fomat!("pid " (pid)),
+        (name, pid) => fomat!((name) " (pid " (pid) ")"),
+    }
+}
+
+/// Queries which process holds a write lock on the file, if any.
+fn lock_holder(path: &str) -> Option<LockHolder> {
+    let file = File::open(path).ok()?;
+
+    let mut lock: libc::flock = unsafe { std::mem::zeroed() };
+    lock.l_type = libc::F_WRLCK as libc::c_short;
+    lock.l_whence = libc::SEEK_SET as libc::c_short;
+
+    if unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut lock) } != 0 {
+        return None;
+    }
+
+    if lock.l_type == libc::F_UNLCK as libc::c_short {
+        return None;
+    }
+
+    // Open file description locks are not owned by a process, and report a pid of -1.
+    Some(if lock.l_pid > 0 {
+        LockHolder::read(lock.l_pid as u32)
+    } else {
+        LockHolder { pid: 0, name: String::new() }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions() {
+        let holder = |pid, name: &str| LockHolder { pid, name: name.into() };
+
+        assert_eq!(describe(&holder(1234, "unattended-upgr")), "unattended-upgr (pid 1234)");
+        assert_eq!(describe(&holder(1234, "")), "pid 1234");
+        assert_eq!(describe(&holder(0, "")), "another process");
+    }
+}
@@ -1,10 +1,14 @@
 use async_fs::File;
+use digest::Digest;
 use futures::prelude::*;
-use hex::FromHex;
-use sha2::{digest::generic_array::GenericArray, Digest, Sha256};
-use std::io;
+use md5::Md5;
+use sha2::{Sha256, Sha512};
+use std::{io, str::FromStr};
 use thiserror::Error;
 
+/// Size of each chunk read from a file being checksummed.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
 #[derive(Debug, Error)]
 pub enum ValidateError {
     #[error("checksum failed; expected {}, found {}", expected, found)]
@@ -15,31 +19,226 @@ pub enum ValidateError {
 
     #[error("I/O error while checksumming")]
     Io(#[from] io::Error),
+
+    #[error("unsupported checksum algorithm: {}", _0)]
+    UnsupportedAlgorithm(String),
+}
+
+/// Digest algorithms which checksums may be validated with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Algorithm {
+    Md5,
+    Sha256,
+    Sha512,
+    Blake3,
+}
+
+impl Algorithm {
+    /// Determines the algorithm of a checksum, and returns it along with the hex digest.
+    ///
+    /// Checksums may be tagged with their algorithm, as in `sha512:<digest>`. Untagged checksums
+    /// are identified by the length of their digest. As BLAKE3 and SHA256 digests are of the same
+    /// length, BLAKE3 checksums must always be tagged.
+    pub fn detect(checksum: &str) -> Result<(Self, &str), ValidateError> {
+        if let Some(pos) = checksum.find(':') {
+            let algorithm = checksum[..pos].parse::<Algorithm>()?;
+            return Ok((algorithm, &checksum[pos + 1..]));
+        }
+
+        let algorithm = match checksum.len() {
+            32 => Algorithm::Md5,
+            64 => Algorithm::Sha256,
+            128 => Algorithm::Sha512,
+            _ => return Err(ValidateError::InvalidInput),
+        };
+
+        Ok((algorithm, checksum))
+    }
 }
 
+impl FromStr for Algorithm {
+    type Err = ValidateError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let algorithm = match input.to_ascii_lowercase().as_str() {
+            "md5" => Algorithm::Md5,
+            "sha256" => Algorithm::Sha256,
+            "sha512" => Algorithm::Sha512,
+            "blake3" => Algorithm::Blake3,
+            _ => return Err(ValidateError::UnsupportedAlgorithm(input.to_owned())),
+        };
+
+        Ok(algorithm)
+    }
+}
+
+/// Incrementally hashes data with the algorithm of the checksum it will be validated against.
+pub enum Hasher {
+    Md5(Md5),
+    Sha256(Sha256),
+    Sha512(Sha512),
+    Blake3(Box<blake3::Hasher>),
+}
+
+impl Hasher {
+    pub fn new(algorithm: Algorithm) -> Self {
+        match algorithm {
+            Algorithm::Md5 => Hasher::Md5(Md5::new()),
+            Algorithm::Sha256 => Hasher::Sha256(Sha256::new()),
+            Algorithm::Sha512 => Hasher::Sha512(Sha512::new()),
+            Algorithm::Blake3 => Hasher::Blake3(Box::new(blake3::Hasher::new())),
+        }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        match self {
+            Hasher::Md5(hasher) => hasher.update(data),
+            Hasher::Sha256(hasher) => hasher.update(data),
+            Hasher::Sha512(hasher) => hasher.update(data),
+            Hasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+        }
+    }
+
+    /// Compares the digest of the hashed data against the expected hex digest.
+    pub fn validate(self, expected: &str) -> Result<(), ValidateError> {
+        let expected_bytes = hex::decode(expected).map_err(|_| ValidateError::InvalidInput)?;
+
+        let found = match self {
+            Hasher::Md5(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha256(hasher) => hasher.finalize().to_vec(),
+            Hasher::Sha512(hasher) => hasher.finalize().to_vec(),
+            Hasher::Blake3(hasher) => hasher.finalize().as_bytes().to_vec(),
+        };
+
+        if found.len() != expected_bytes.len() {
+            return Err(ValidateError::InvalidInput);
+        }
+
+        if found != expected_bytes {
+            return Err(ValidateError::Checksum {
+                expected: expected.into(),
+                found:    hex::encode(&found),
+            });
+        }
+
+        Ok(())
+    }
+}
+
+/// Validates a file against a checksum, whose algorithm is detected with `Algorithm::detect`.
 pub async fn validate_checksum(file: &mut File, checksum: &str) -> Result<(), ValidateError> {
     info!("validating checksum of downloaded ISO");
-    let expected = <[u8; 32]>::from_hex(checksum)
-        .map(GenericArray::from)
-        .map_err(|_| ValidateError::InvalidInput)?;
+    let (algorithm, checksum) = Algorithm::detect(checksum)?;
+    validate(file, algorithm, checksum).await
+}
 
-    let mut hasher = Sha256::new();
-    let mut buffer = vec![0u8; 8 * 1024];
+/// Validates a file against a MD5 checksum, as found in an ISO's `md5sum.txt`.
+pub async fn validate_md5(file: &mut File, checksum: &str) -> Result<(), ValidateError> {
+    validate(file, Algorithm::Md5, checksum).await
+}
 
-    loop {
-        match file.read(&mut buffer).await? {
-            0 => break,
-            read => hasher.update(&buffer[..read]),
+/// Validates a file against a SHA256 checksum, as found in an ISO's `SHA256SUMS`.
+pub async fn validate_sha256(file: &mut File, checksum: &str) -> Result<(), ValidateError> {
+    validate(file, Algorithm::Sha256, checksum).await
+}
+
+/// Parses a checksum list in the format generated by `md5sum` and `sha256sum`.
+///
+/// Returns pairs of checksums and paths, with the `./` prefix of each path removed.
+pub fn parse_checksum_list(list: &str) -> impl Iterator<Item = (&str, &str)> {
+    list.lines().filter_map(|line| {
+        let line = line.trim();
+        let pos = line.find(char::is_whitespace)?;
+        let (checksum, path) = line.split_at(pos);
+
+        // A leading `*` marks a file that was checksummed in binary mode.
+        let path = path.trim_start();
+        let path = path.trim_start_matches('*');
+        let path = path.trim_start_matches("./");
+
+        if checksum.is_empty() || path.is_empty() {
+            None
+        } else {
+            Some((checksum, path))
         }
+    })
+}
+
+async fn validate(
+    file: &mut File,
+    algorithm: Algorithm,
+    checksum: &str,
+) -> Result<(), ValidateError> {
+    let mut hasher = Hasher::new(algorithm);
+
+    // Reads are performed on a background thread, so the next chunk is fetched from the disk
+    // while the current chunk is being hashed.
+    let mut current = vec![0u8; BUFFER_SIZE];
+    let mut next = vec![0u8; BUFFER_SIZE];
+    let mut read = file.read(&mut current).await?;
+
+    while read != 0 {
+        let (next_read, ()) =
+            futures::join!(file.read(&mut next), async { hasher.update(&current[..read]) });
+
+        read = next_read?;
+        std::mem::swap(&mut current, &mut next);
     }
 
-    let found = hasher.finalize();
-    if &*found != &*expected {
-        return Err(ValidateError::Checksum {
-            expected: checksum.into(),
-            found:    format!("{:x}", found),
-        });
+    hasher.validate(checksum)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_list() {
+        const LIST: &str = "d41d8cd98f00b204e9800998ecf8427e  ./.disk/info
+0cc175b9c0f1b6a831c399e269772661  ./casper/filesystem.squashfs
+92eb5ffee6ae2fec3ad71c777531578f *./dists/focal/Release
+
+";
+
+        assert_eq!(
+            parse_checksum_list(LIST).collect::<Vec<_>>(),
+            vec![
+                ("d41d8cd98f00b204e9800998ecf8427e", ".disk/info"),
+                ("0cc175b9c0f1b6a831c399e269772661", "casper/filesystem.squashfs"),
+                ("92eb5ffee6ae2fec3ad71c777531578f", "dists/focal/Release"),
+            ]
+        );
+    }
+
+    #[test]
+    fn detect_algorithm() {
+        let md5 = "d41d8cd98f00b204e9800998ecf8427e";
+        let sha256 = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+        let blake3 = "blake3:af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262";
+
+        assert_eq!(Algorithm::detect(md5).unwrap(), (Algorithm::Md5, md5));
+        assert_eq!(Algorithm::detect(sha256).unwrap(), (Algorithm::Sha256, sha256));
+        assert_eq!(Algorithm::detect(blake3).unwrap(), (Algorithm::Blake3, &blake3[7..]));
+        assert_eq!(Algorithm::detect(&[sha256, sha256].concat()).unwrap().0, Algorithm::Sha512);
+        assert!(Algorithm::detect("sha1:da39a3ee5e6b4b0d3255bfef95601890afd80709").is_err());
     }
 
-    Ok(())
+    #[test]
+    fn empty_digests() {
+        let validate = |algorithm, checksum| Hasher::new(algorithm).validate(checksum);
+
+        assert!(validate(Algorithm::Md5, "d41d8cd98f00b204e9800998ecf8427e").is_ok());
+        assert!(validate(
+            Algorithm::Blake3,
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        )
+        .is_ok());
+        assert!(validate(
+            Algorithm::Sha256,
+            "af1349b9f5f9a1a6a0404dea36dcc9499bcb25c9adc112b7cc9a93cae41f3262"
+        )
+        .is_err());
+    }
 }
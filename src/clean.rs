@@ -0,0 +1,300 @@
+use crate::{
+    external::findmnt,
+    logging::LOG_DIR,
+    recovery::ISO_DIR_PREFIX,
+    release::{self, prefetch, resume::UpgradeState},
+};
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+};
+use ubuntu_version::Version;
+
+const RECOVERY: &str = "/recovery";
+
+/// The daemon's log file, whose rotations are suffixed with `.1`, `.2`, and so on.
+const LOG_NAME: &str = "pop-upgrade.log";
+
+/// Files which the offline upgrade reads, and which are stale once it has completed.
+const STATE_FILES: &[&str] = &[crate::CONFFILE_POLICY];
+
+/// A category of files which are removed.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Category {
+    /// Recovery ISOs left in temporary directories by downloads which were interrupted.
+    IsoCaches,
+    /// Casper directories of recovery partitions which no longer exist.
+    CasperDirectories,
+    /// State files of upgrades which have completed, and packages which were fetched in advance
+    /// of a release that the system has since reached.
+    StateFiles,
+    /// Rotated logs of the daemon.
+    Logs,
+}
+
+impl Category {
+    pub const ALL: &'static [Category] =
+        &[Category::IsoCaches, Category::CasperDirectories, Category::StateFiles, Category::Logs];
+
+    /// The identifier of the category, which is sent over DBus.
+    pub fn id(self) -> &'static str {
+        match self {
+            Category::IsoCaches => "iso-caches",
+            Category::CasperDirectories => "casper-directories",
+            Category::StateFiles => "state-files",
+            Category::Logs => "logs",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|category| category.id() == id)
+    }
+}
+
+impl fmt::Display for Category {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Category::IsoCaches => "ISO caches",
+            Category::CasperDirectories => "casper directories",
+            Category::StateFiles => "upgrade state files",
+            Category::Logs => "logs",
+        })
+    }
+}
+
+/// The space reclaimed from a category of files.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cleaned {
+    pub category: Category,
+    /// Files and directories which were removed.
+    pub removed:  Vec<PathBuf>,
+    pub bytes:    u64,
+}
+
+/// Removes the files which pop-upgrade leaves behind. Categories which cannot safely be cleaned
+/// at the moment are skipped, and failures are logged rather than aborting the others. Packages
+/// are not fetched in advance while they are `prefetching`.
+pub async fn clean(prefetching: bool) -> Vec<Cleaned> {
+    let mut cleaned = Vec::new();
+
+    let mut record = |category, result: io::Result<Vec<PathBuf>>| match result {
+        Ok(paths) => cleaned.push(remove(category, paths)),
+        Err(why) => error!("failed to find {}: {}", category, why),
+    };
+
+    record(Category::IsoCaches, iso_caches(&std::env::temp_dir()));
+
+    let recovery = Path::new(RECOVERY);
+    if recovery.exists() {
+        match findmnt(RECOVERY, "UUID").await {
+            Ok(uuid) => record(Category::CasperDirectories, orphaned_casper(recovery, &uuid)),
+            Err(why) => {
+                warn!("skipping casper directories, as the recovery UUID is unknown: {}", why)
+            }
+        }
+    }
+
+    // Files of an upgrade which is being prepared, staged, or may be resumed are retained.
+    if release::upgrade_in_progress() || UpgradeState::load().is_some() {
+        info!("skipping upgrade state files, as an upgrade is in progress");
+    } else {
+        let mut files = state_files();
+
+        if prefetching {
+            info!("skipping prefetched packages, as they are being fetched");
+        } else if let Ok(current) = Version::detect() {
+            let prefetched = Path::new(prefetch::PREFETCH);
+            if prefetched.exists() && prefetch_stale(&current, prefetch::release().as_deref()) {
+                files.push(prefetched.to_path_buf());
+            }
+        }
+
+        record(Category::StateFiles, Ok(files));
+    }
+
+    match rotated_logs(Path::new(LOG_DIR)) {
+        Err(why) if why.kind() == io::ErrorKind::NotFound => (),
+        logs => record(Category::Logs, logs),
+    }
+
+    cleaned
+}
+
+/// Removes the paths, and sums the space that they occupied.
+fn remove(category: Category, paths: Vec<PathBuf>) -> Cleaned {
+    let mut cleaned = Cleaned { category, removed: Vec::new(), bytes: 0 };
+
+    for path in paths {
+        let size = disk_usage(&path);
+
+        let result =
+            if path.is_dir() { fs::remove_dir_all(&path) } else { fs::remove_file(&path) };
+
+        match result {
+            Ok(()) => {
+                info!("removed {}", path.display());
+                cleaned.bytes += size;
+                cleaned.removed.push(path);
+            }
+            Err(why) => error!("failed to remove {}: {}", path.display(), why),
+        }
+    }
+
+    cleaned
+}
+
+/// Temporary directories which pop-upgrade downloaded a recovery ISO to.
+fn iso_caches(temp: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut caches = Vec::new();
+
+    for entry in fs::read_dir(temp)?.filter_map(Result::ok) {
+        let path = entry.path();
+        let ours = entry.file_name().to_string_lossy().starts_with(ISO_DIR_PREFIX);
+
+        if !ours || !path.is_dir() {
+            continue;
+        }
+
+        let holds_iso = fs::read_dir(&path)?
+            .filter_map(Result::ok)
+            .any(|file| file.path().extension().map_or(false, |ext| ext == "iso"));
+
+        if holds_iso {
+            caches.push(path);
+        }
+    }
+
+    Ok(caches)
+}
+
+/// Casper directories on the recovery partition which are not named for its current UUID.
+fn orphaned_casper(recovery: &Path, uuid: &str) -> io::Result<Vec<PathBuf>> {
+    let current = ["casper-", uuid].concat();
+
+    let mut orphaned = fs::read_dir(recovery)?
+        .filter_map(Result::ok)
+        .filter(|entry| {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            name.starts_with("casper-") && name != current
+        })
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+
+    orphaned.sort();
+    Ok(orphaned)
+}
+
+fn state_files() -> Vec<PathBuf> {
+    STATE_FILES.iter().map(PathBuf::from).filter(|path| path.exists()).collect()
+}
+
+/// Whether packages which were fetched in advance of the `fetched` release are of no further
+/// use, as the system has reached that release, or the release is unknown.
+fn prefetch_stale(current: &Version, fetched: Option<&str>) -> bool {
+    match fetched.and_then(|release| release.trim().parse::<Version>().ok()) {
+        Some(fetched) => (fetched.major, fetched.minor) <= (current.major, current.minor),
+        None => true,
+    }
+}
+
+/// Rotations of the daemon's log, such as `pop-upgrade.log.1`.
+fn rotated_logs(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let prefix = [LOG_NAME, "."].concat();
+
+    let mut logs = fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .filter(|entry| entry.path().is_file())
+        .filter(|entry| entry.file_name().to_string_lossy().starts_with(&prefix))
+        .map(|entry| entry.path())
+        .collect::<Vec<_>>();
+
+    logs.sort();
+    Ok(logs)
+}
+
+fn disk_usage(path: &Path) -> u64 {
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    fs::read_dir(path)
+        .map(|entries| entries.filter_map(Result::ok).map(|entry| disk_usage(&entry.path())).sum())
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn isos() {
+        let temp = tempfile::tempdir().unwrap();
+        let temp = temp.path();
+
+        fs::create_dir_all(temp.join("pop-upgrade-iso-A1b2C3")).unwrap();
+        fs::write(temp.join("pop-upgrade-iso-A1b2C3/new.iso"), b"iso").unwrap();
+        fs::create_dir_all(temp.join("pop-upgrade-iso-D4e5F6")).unwrap();
+        fs::write(temp.join("pop-upgrade-iso-D4e5F6/notes.txt"), b"").unwrap();
+        fs::create_dir_all(temp.join(".tmpG7h8I9")).unwrap();
+        fs::write(temp.join(".tmpG7h8I9/other.iso"), b"").unwrap();
+
+        assert_eq!(iso_caches(temp).unwrap(), vec![temp.join("pop-upgrade-iso-A1b2C3")]);
+
+        let cleaned = remove(Category::IsoCaches, iso_caches(temp).unwrap());
+        assert_eq!(cleaned.bytes, 3);
+        assert!(!temp.join("pop-upgrade-iso-A1b2C3").exists());
+        assert!(temp.join(".tmpG7h8I9").exists());
+    }
+
+    #[test]
+    fn casper() {
+        let recovery = tempfile::tempdir().unwrap();
+        let recovery = recovery.path();
+
+        for dir in &["casper-0E2B-5A1C", "casper-7F3D-9C8E", "dists", "pool"] {
+            fs::create_dir_all(recovery.join(dir)).unwrap();
+        }
+
+        assert_eq!(orphaned_casper(recovery, "7F3D-9C8E").unwrap(), vec![
+            recovery.join("casper-0E2B-5A1C")
+        ]);
+    }
+
+    #[test]
+    fn categories() {
+        for &category in Category::ALL {
+            assert_eq!(Category::from_id(category.id()), Some(category));
+        }
+    }
+
+    #[test]
+    fn logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        for log in &["pop-upgrade.log", "pop-upgrade.log.1", "pop-upgrade.log.2", "auth.log.1"] {
+            fs::write(dir.join(log), b"").unwrap();
+        }
+
+        assert_eq!(rotated_logs(dir).unwrap(), vec![
+            dir.join("pop-upgrade.log.1"),
+            dir.join("pop-upgrade.log.2")
+        ]);
+    }
+
+    #[test]
+    fn prefetch() {
+        let current = "20.10".parse::<Version>().unwrap();
+
+        assert!(prefetch_stale(&current, Some("20.04")));
+        assert!(prefetch_stale(&current, Some("20.10")));
+        assert!(!prefetch_stale(&current, Some("21.04")));
+        assert!(prefetch_stale(&current, None));
+    }
+}
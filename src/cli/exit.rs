@@ -0,0 +1,79 @@
+//! Exit codes which scripts may branch on, rather than parsing the output of a command. Their
+//! values are stable across versions.
+
+use pop_upgrade::client;
+
+#[repr(i32)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ExitCode {
+    /// The command succeeded. Checks exit with this if an update is available.
+    Success = 0,
+    /// The command failed for a reason which has no code of its own.
+    Failure = 1,
+    /// No release is available to upgrade to, or no updates are available to fetch.
+    NoUpdateAvailable = 2,
+    /// A check which must pass before upgrading failed.
+    PreflightFailed = 3,
+    /// Fetching a release upgrade, updates, or the recovery image failed.
+    DownloadFailed = 4,
+    /// The operation was cancelled.
+    Cancelled = 5,
+    /// Polkit did not authorize the operation.
+    NotAuthorized = 6,
+    /// Another upgrade operation is in progress.
+    Busy = 7,
+    /// The upgrade servers or apt repositories are unreachable.
+    NoConnection = 8,
+    /// A captive portal intercepts requests, and the user must log into the network.
+    CaptivePortal = 9,
+    /// The connection is metered, and the download was deferred.
+    Metered = 10,
+    /// The battery may run out before the upgrade completes.
+    LowBattery = 11,
+    /// The upgrade policy of this managed machine does not permit the upgrade.
+    PolicyRefused = 12,
+}
+
+impl ExitCode {
+    /// The exit code of a command which ended with an error.
+    pub fn of(why: &anyhow::Error) -> Self {
+        if let Some(exit) = why.downcast_ref::<Exit>() {
+            return exit.code;
+        }
+
+        let client = why.chain().find_map(|why| why.downcast_ref::<client::Error>());
+        match client {
+            Some(why) if why.is_not_authorized() => ExitCode::NotAuthorized,
+            Some(why) if why.is_busy() => ExitCode::Busy,
+            Some(why) if why.is_no_connection() => ExitCode::NoConnection,
+            Some(why) if why.is_captive_portal() => ExitCode::CaptivePortal,
+            Some(why) if why.is_metered() => ExitCode::Metered,
+            Some(why) if why.is_low_battery() => ExitCode::LowBattery,
+            Some(why) if why.is_policy() => ExitCode::PolicyRefused,
+            _ => ExitCode::Failure,
+        }
+    }
+}
+
+/// Ends a command with an exit code, describing why unless the output already has.
+#[derive(Debug, Error)]
+#[error("{why}")]
+pub struct Exit {
+    pub code: ExitCode,
+    pub why:  String,
+}
+
+impl Exit {
+    pub fn new(code: ExitCode, why: impl Into<String>) -> Self { Self { code, why: why.into() } }
+
+    /// Ends the command with the exit code, without describing why.
+    pub fn silent(code: ExitCode) -> Self { Self::new(code, String::new()) }
+
+    /// Ends the command with the result of an operation of the daemon which did not succeed.
+    pub fn failed(result: &client::Status) -> Self {
+        let code =
+            if result.is_cancelled() { ExitCode::Cancelled } else { ExitCode::DownloadFailed };
+
+        Self::new(code, &*result.why)
+    }
+}
@@ -0,0 +1,50 @@
+use super::colors::*;
+
+/// Prints release notes to the terminal, with headings and list items highlighted.
+///
+/// Only the subset of markdown used by release notes is recognized: headings, nested list
+/// items written with `-` or `*`, and inline code. Everything else is printed as is.
+pub fn print(notes: &str) {
+    for line in notes.lines() {
+        let trimmed = line.trim_start();
+
+        if trimmed.starts_with('#') {
+            println!("{}", color_primary(inline(trimmed.trim_start_matches('#').trim())));
+        } else if let Some((depth, item)) = list_item(trimmed) {
+            let indent = "  ".repeat(depth + (line.len() - trimmed.len()) / 2);
+            println!("{}{} {}", indent, color_info("•"), inline(item));
+        } else {
+            println!("{}", inline(line));
+        }
+    }
+}
+
+/// The nesting depth and text of a list item, for both the `- item` and `** item` styles.
+fn list_item(line: &str) -> Option<(usize, &str)> {
+    let marker = line.chars().next().filter(|&c| c == '-' || c == '*')?;
+    let text = line.trim_start_matches(marker);
+    let depth = line.len() - text.len() - 1;
+
+    if text.starts_with(' ') {
+        Some((depth, text.trim()))
+    } else {
+        None
+    }
+}
+
+/// Strips the markup of inline code, which has no special rendering in the terminal.
+fn inline(text: &str) -> String { text.replace('`', "") }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_items() {
+        assert_eq!(list_item("* Pop Shell"), Some((0, "Pop Shell")));
+        assert_eq!(list_item("** Added stacking"), Some((1, "Added stacking")));
+        assert_eq!(list_item("- GNOME 3.38"), Some((0, "GNOME 3.38")));
+        assert_eq!(list_item("**bold** text"), None);
+        assert_eq!(list_item("plain text"), None);
+    }
+}
@@ -1,22 +1,40 @@
 mod colors;
+mod exit;
+mod markdown;
+mod progress;
 mod prompt;
+mod schedule;
+mod tui;
 
-use self::colors::*;
+pub use self::{
+    exit::{Exit, ExitCode},
+    prompt::set_non_interactive,
+};
+
+use self::{
+    colors::*,
+    progress::{Bar, Unit},
+};
 use crate::notify::notify;
 
 use apt_cmd::AptUpgradeEvent;
-use chrono::{TimeZone, Utc};
+use chrono::NaiveDate;
 use clap::ArgMatches;
 use num_traits::FromPrimitive;
 use pop_upgrade::{
-    client,
+    apt_lock::{self, LockHolder},
+    clean, client,
+    config::{ConffilePolicy, Config},
     daemon::*,
-    misc,
+    dual_boot, fl, misc, notification,
+    preflight::{self, Status as PreflightStatus},
     recovery::{RecoveryEvent, ReleaseFlags as RecoveryReleaseFlags},
     release::{
-        eol::{EolDate, EolStatus},
+        eol::EolStatus,
+        paths::Availability,
+        report::Report,
         systemd::{self, LoaderEntry},
-        RefreshOp, UpgradeEvent, UpgradeMethod,
+        RefreshOp, UpgradeEvent, UpgradeFlags, UpgradeMethod,
     },
 };
 use std::{
@@ -24,21 +42,30 @@ use std::{
     fs,
     io::{self, Write},
     path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
 };
 use ubuntu_version::{Codename, Version as UbuntuVersion};
 use yansi::Paint;
 
-const FETCH_RESULT_STR: &str = "Package fetch status";
-const FETCH_RESULT_SUCCESS: &str = "cargo has been loaded successfully";
-const FETCH_RESULT_ERROR: &str = "package-fetching aborted";
+/// How often the daemon is checked for having finished an operation which was cancelled.
+const CANCEL_POLL: Duration = Duration::from_millis(500);
 
-const RECOVERY_RESULT_STR: &str = "Recovery upgrade status";
-const RECOVERY_RESULT_SUCCESS: &str = "recovery partition refueled and ready to go";
-const RECOVERY_RESULT_ERROR: &str = "recovery upgrade aborted";
+/// How long the daemon is given to finish an operation which was cancelled.
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(120);
 
-const UPGRADE_RESULT_STR: &str = "Release upgrade status";
-const UPGRADE_RESULT_SUCCESS: &str = "systems are go for launch: reboot now";
-const UPGRADE_RESULT_ERROR: &str = "release upgrade aborted";
+/// Set in the environment of the client which replaces this one once pop-upgrade is updated.
+const UPDATED_ENV: &str = "POP_UPGRADE_UPDATED";
+
+/// Whether progress is hidden, so that only errors are printed.
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Hides the progress of operations, and the results of those which succeed.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::SeqCst);
+}
+
+fn quiet() -> bool { QUIET.load(Ordering::SeqCst) }
 
 #[derive(Shrinkwrap)]
 pub struct Client(client::Client);
@@ -46,23 +73,183 @@ pub struct Client(client::Client);
 impl Client {
     pub fn new() -> Result<Self, client::Error> { client::Client::new().map(Client) }
 
+    /// Cancels the operation in progress, and waits for the daemon to finish cleaning up after
+    /// it, such as unmounting the recovery partition and removing partially-fetched files.
+    pub fn cancel(&self, _matches: &ArgMatches) -> anyhow::Result<()> {
+        let before = self.0.status_details()?;
+        if before.status == DaemonStatus::Inactive {
+            println!("{}", fl!("cancel-idle"));
+            return Ok(());
+        }
+
+        let operation = <&'static str>::from(before.status);
+        self.0.cancel()?;
+        println!("{}", fl!("cancel-waiting", operation = operation));
+
+        let deadline = Instant::now() + CANCEL_TIMEOUT;
+        let after = loop {
+            std::thread::sleep(CANCEL_POLL);
+
+            let details = self.0.status_details()?;
+            if details.status == DaemonStatus::Inactive {
+                break details;
+            }
+
+            if Instant::now() >= deadline {
+                let seconds = CANCEL_TIMEOUT.as_secs();
+                let why = fl!("cancel-timed-out", operation = operation, seconds = seconds);
+                return Err(anyhow!(why));
+            }
+        };
+
+        // The last error is cleared when an operation begins, so any error is of this one.
+        match after.last_error {
+            Some(why) => println!("{}", fl!("cancel-done", operation = operation, why = &*why)),
+            None => println!("{}", fl!("cancel-too-late", operation = operation)),
+        }
+
+        Ok(())
+    }
+
+    /// Executes the clean subcommand of the client.
+    pub fn clean(&self, matches: &ArgMatches) -> anyhow::Result<()> {
+        match matches.subcommand() {
+            ("kernels", Some(matches)) => {
+                let keep = matches
+                    .value_of("keep")
+                    .unwrap_or("1")
+                    .parse::<u32>()
+                    .map_err(|_| anyhow!("--keep must be a number of kernels"))?;
+
+                let dry_run = matches.is_present("dry-run");
+                let cleaned = self.clean_kernels(keep, dry_run)?;
+
+                for kernel in &cleaned.kept {
+                    println!("{}: {}", color_primary("Keeping"), color_secondary(kernel));
+                }
+
+                let action = if dry_run { "Would remove" } else { "Removed" };
+                for kernel in &cleaned.removed {
+                    println!("{}: {}", color_primary(action), color_secondary(kernel));
+                }
+
+                for entry in &cleaned.entries {
+                    println!("{}: {}", color_primary("Removed boot entry"), color_secondary(entry));
+                }
+
+                if cleaned.removed.is_empty() {
+                    println!("no kernels needed to be removed");
+                }
+            }
+            ("", _) => {
+                let cleaned = self.0.clean()?;
+
+                if matches.is_present("json") {
+                    let total = cleaned.iter().map(|category| category.bytes).sum::<u64>();
+                    let report = serde_json::json!({ "categories": cleaned, "total": total });
+                    return print_json(&report);
+                }
+
+                for category in &cleaned {
+                    let name = clean::Category::from_id(&category.category)
+                        .map_or_else(|| category.category.to_string(), |c| c.to_string());
+
+                    println!(
+                        "{}: {} removed, {} reclaimed",
+                        color_primary(name),
+                        color_secondary(category.files),
+                        color_secondary(misc::format_size(category.bytes))
+                    );
+                }
+
+                let total = cleaned.iter().map(|category| category.bytes).sum::<u64>();
+                let total = misc::format_size(total);
+                println!("{}: {}", color_primary("Total reclaimed"), color_secondary(total));
+            }
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    /// Executes the history subcommand of the client.
+    pub fn history(&self, matches: &ArgMatches) -> anyhow::Result<()> {
+        let entries = self.0.history()?;
+
+        if matches.is_present("json") {
+            return print_json(&entries);
+        }
+
+        if entries.is_empty() {
+            println!("no operations have been recorded");
+        }
+
+        for entry in entries {
+            let releases = match (entry.from.as_deref(), entry.to.as_deref()) {
+                (Some(from), Some(to)) => fomat!(" from " (from) " to " (to)),
+                (None, Some(to)) => fomat!(" to " (to)),
+                _ => String::new(),
+            };
+
+            let outcome = if entry.error.is_some() { "failed" } else { "succeeded" };
+
+            pintln!(
+                (schedule::format(entry.started)) "  " (color_secondary(&entry.operation))
+                (releases) ": " (outcome) " at " (schedule::format(entry.ended))
+            );
+
+            if let Some(ref client) = entry.client {
+                println!("  requested by: {}", client);
+            }
+
+            if let Some(ref why) = entry.error {
+                println!("  error: {}", why);
+            }
+        }
+
+        Ok(())
+    }
+
     /// Executes the recovery subcommand of the client.
     pub fn recovery(&self, matches: &ArgMatches) -> anyhow::Result<()> {
         match matches.subcommand() {
             ("default-boot", _) => {
                 root_required()?;
-                systemd::BootConf::load()?.set_default_boot_variant(LoaderEntry::Recovery)?;
+
+                let mut conf = systemd::BootConf::load()?;
+
+                if dual_boot::boots_windows(conf.default_boot()) {
+                    println!(
+                        "{}",
+                        color_error(
+                            "Windows is the default boot target, and will be replaced by the \
+                             recovery partition."
+                        )
+                    );
+
+                    let prompt = format!("    {} y/N", color_primary("Continue?"));
+                    if !prompt::get_bool(&prompt, false) {
+                        return Ok(());
+                    }
+                }
+
+                conf.set_default_boot_variant(LoaderEntry::Recovery)?;
             }
             ("upgrade", Some(matches)) => {
                 match matches.subcommand() {
                     ("from-release", Some(matches)) => {
                         let version = matches.value_of("VERSION").unwrap_or("");
                         let arch = matches.value_of("ARCH").unwrap_or("");
-                        let flags = if matches.is_present("next") {
-                            RecoveryReleaseFlags::NEXT
-                        } else {
-                            RecoveryReleaseFlags::empty()
-                        };
+                        let mut flags = RecoveryReleaseFlags::empty();
+                        flags.set(RecoveryReleaseFlags::NEXT, matches.is_present("next"));
+                        flags.set(
+                            RecoveryReleaseFlags::IGNORE_BATTERY,
+                            matches.is_present("ignore-battery"),
+                        );
+                        flags.set(
+                            RecoveryReleaseFlags::ALLOW_METERED,
+                            matches.is_present("allow-metered"),
+                        );
 
                         self.recovery_upgrade_release(version, arch, flags)?;
                     }
@@ -75,9 +262,19 @@ impl Client {
                 }
 
                 self.event_listen_recovery_upgrade()?;
+
+                let status = self.recovery_upgrade_release_status()?;
+                if status.status != 0 {
+                    return Err(Exit::failed(&status).into());
+                }
             }
-            ("check", _) => {
+            ("check", Some(matches)) => {
                 let version = self.recovery_version()?;
+
+                if matches.is_present("json") {
+                    return print_json(&version);
+                }
+
                 pintln!(
                     "version: " (version.version) "\n"
                     "build: " (version.build)
@@ -92,17 +289,59 @@ impl Client {
     pub fn release(&self, matches: &ArgMatches) -> anyhow::Result<()> {
         match matches.subcommand() {
             ("dismiss", _) => {
-                let devel = pop_upgrade::development_releases_enabled();
-                let (_, _, _, is_lts) = self.release_check(devel)?;
-                if is_lts {
-                    self.dismiss_notification(DismissEvent::ByUser)?;
-                } else {
-                    println!("Only LTS releases may dismiss notifications");
-                }
+                self.dismiss_notification(DismissEvent::ByUser)?;
             }
-            ("check", _) => {
+            ("check", Some(matches)) => {
                 let mut buffer = String::new();
-                let (current, next, available, is_lts) = self.release_check(false)?;
+                let client::ReleaseInfo {
+                    current,
+                    next,
+                    build: available,
+                    is_lts,
+                    size,
+                    release_notes,
+                    ..
+                } = self.0.release_check(pop_upgrade::development_releases_enabled(), false)?;
+
+                if matches.is_present("json") {
+                    let build = available;
+                    let available = build >= 0;
+                    let preview = if available && matches.is_present("preview") {
+                        Some(self.release_preview(&next)?)
+                    } else {
+                        None
+                    };
+
+                    let simulation = if available && matches.is_present("simulate") {
+                        Some(self.release_simulate(&next)?)
+                    } else {
+                        None
+                    };
+
+                    let incompatible_repos = if available {
+                        self.repo_compatibility(&next)
+                            .map_err(|why| error!("failed to check third party repos: {}", why))
+                            .ok()
+                    } else {
+                        None
+                    };
+
+                    print_json(&serde_json::json!({
+                        "current": current,
+                        "next": next,
+                        "build": build,
+                        "available": available,
+                        "is_lts": is_lts,
+                        "size": size,
+                        "release_notes": release_notes,
+                        "end_of_life": self.end_of_life().ok(),
+                        "incompatible_repos": incompatible_repos,
+                        "preview": preview,
+                        "simulation": simulation,
+                    }))?;
+
+                    return no_update_unless(available);
+                }
 
                 if atty::is(atty::Stream::Stdout) {
                     println!(
@@ -112,12 +351,113 @@ impl Client {
                         next,
                         misc::format_build_number(available, &mut buffer)
                     );
-                } else if available >= 0 {
-                    if is_lts && (self.dismissed(&next) || self.dismiss_by_timestamp(&next)?) {
+
+                    if available >= 0 {
+                        println!("        Download Size: {}", misc::format_size(size));
+
+                        if let Some(release_notes) = release_notes {
+                            println!("        Release Notes: {}", release_notes);
+                        }
+
+                        match self.repo_compatibility(&next) {
+                            Ok(repos) if !repos.is_empty() => {
+                                println!("   Incompatible Repos:");
+                                for repo in repos {
+                                    println!("      {}", color_secondary(&repo.path));
+                                    for uri in repo.uris.iter() {
+                                        println!("        {}", uri);
+                                    }
+                                }
+                            }
+                            Ok(_) => (),
+                            Err(why) => error!("failed to check third party repos: {}", why),
+                        }
+                    }
+
+                    match self.end_of_life() {
+                        Ok(eol) => println!(
+                            "          End of Life: {}{}",
+                            eol.date,
+                            match eol.status {
+                                EolStatus::Exceeded => " (no longer supported)",
+                                EolStatus::Imminent => " (support ends soon)",
+                                _ => "",
+                            }
+                        ),
+                        Err(why) => error!("failed to fetch EOL date: {}", why),
+                    }
+
+                    if available >= 0 && matches.is_present("preview") {
+                        println!("previewing the upgrade to {}", next);
+                        let client::Preview { removed, downgraded } =
+                            self.release_preview(&next)?;
+
+                        if removed.is_empty() && downgraded.is_empty() {
+                            println!("No packages will be removed or downgraded");
+                        }
+
+                        print_packages(&[
+                            ("Packages to remove", removed),
+                            ("Packages to downgrade", downgraded),
+                        ]);
+                    }
+
+                    if available >= 0 && matches.is_present("simulate") {
+                        println!("simulating the upgrade to {}", next);
+                        let client::Simulation {
+                            installed,
+                            upgraded,
+                            removed,
+                            downgraded,
+                            download_size,
+                        } = self.release_simulate(&next)?;
+
+                        print_packages(&[
+                            ("Packages to install", installed),
+                            ("Packages to upgrade", upgraded),
+                            ("Packages to remove", removed),
+                            ("Packages to downgrade", downgraded),
+                        ]);
+
+                        println!(
+                            "{}: {}",
+                            color_primary("Download Size"),
+                            misc::format_size(download_size)
+                        );
+                    }
+
+                    return no_update_unless(available >= 0);
+                } else {
+                    // The notification service runs this daily, and is not failed by there
+                    // being no release to notify of.
+                    if available < 0 {
                         return Ok(());
                     }
 
-                    let (summary, body) = notification_message(&current, &next);
+                    let eol = self
+                        .end_of_life()
+                        .map_err(|why| error!("failed to fetch EOL date: {}", why))
+                        .ok();
+
+                    // Releases which are no longer supported may not dismiss notifications.
+                    let exceeded =
+                        eol.as_ref().map_or(false, |eol| eol.status == EolStatus::Exceeded);
+
+                    if is_lts
+                        && !exceeded
+                        && (self.dismissed(&next) || self.dismiss_by_timestamp(&next)?)
+                    {
+                        return Ok(());
+                    }
+
+                    if !exceeded
+                        && self.notification_preferences()?.suppresses(&next, notification::now())
+                    {
+                        info!("the notification of {} was dismissed or snoozed", next);
+                        return Ok(());
+                    }
+
+                    let (summary, body) = notification_message(&current, &next, eol.as_ref());
 
                     let upgrade_panel =
                         if &*current == "18.04" { "info-overview" } else { "upgrade" };
@@ -128,18 +468,68 @@ impl Client {
                     });
                 }
             }
+            // Resume a release upgrade from the last phase that it completed.
+            ("resume", _) => {
+                self.preflight(false)?;
+
+                self.await_release_upgrade(|| {
+                    let (from, to) = self.release_resume()?;
+                    pintln!(
+                        (color_primary("Resuming upgrade")) ": " (color_secondary(&from)) " to "
+                        (color_secondary(&to))
+                    );
+
+                    Ok(())
+                })?;
+            }
+            // Cancel a release upgrade which was scheduled to begin later.
+            ("unschedule", _) => {
+                if self.0.schedule_cancel()? {
+                    println!("the scheduled release upgrade was cancelled");
+                } else {
+                    println!("no release upgrade is scheduled");
+                }
+            }
+            // Check whether the system is ready to be upgraded.
+            ("preflight", Some(matches)) => {
+                let checks = self.preflight_check()?;
+
+                if matches.is_present("json") {
+                    print_json(&checks)?;
+                } else {
+                    print_preflight(checks.iter());
+                }
+
+                if checks.iter().any(|check| check.status == PreflightStatus::Fail) {
+                    let why = fl!("error-not-ready");
+                    return Err(Exit::new(ExitCode::PreflightFailed, why).into());
+                }
+            }
             // Update the current system, without performing a release upgrade
             ("update", Some(matches)) => {
-                let updates =
-                    self.fetch_updates(Vec::new(), matches.is_present("download-only"))?;
+                let offline = matches.is_present("offline");
+                let download_only = offline || matches.is_present("download-only");
+                let updates = self.fetch_updates(Vec::new(), download_only)?;
 
                 let client::Fetched { updates_available, completed, total } = updates;
 
                 if !updates_available || total == 0 {
-                    println!("no updates available to fetch");
-                } else {
-                    println!("fetching updates: {} of {} updates fetched", completed, total);
-                    self.event_listen_fetch_updates()?;
+                    println!("{}", fl!("no-updates"));
+                    return Err(Exit::silent(ExitCode::NoUpdateAvailable).into());
+                }
+
+                println!("fetching updates: {} of {} updates fetched", completed, total);
+                self.event_listen_fetch_updates()?;
+
+                let status = self.fetch_updates_status()?;
+                if status.status != 0 {
+                    return Err(Exit::failed(&status).into());
+                }
+
+                // Updates are only staged if every package was fetched.
+                if offline {
+                    self.stage_offline_update()?;
+                    println!("updates will be installed on the next boot");
                 }
             }
             // Perform an upgrade to the next release. Supports either systemd or recovery upgrades.
@@ -147,7 +537,14 @@ impl Client {
                 let (method, matches) = (UpgradeMethod::Offline, matches);
                 let forcing =
                     matches.is_present("force-next") || pop_upgrade::development_releases_enabled();
-                let (current, next, available, _is_lts) = self.release_check(forcing)?;
+                let client::ReleaseInfo {
+                    current,
+                    next,
+                    build: available,
+                    size,
+                    release_notes,
+                    ..
+                } = self.0.release_check(forcing, matches.is_present("force"))?;
 
                 if atty::is(atty::Stream::Stdout) {
                     let mut buffer = String::new();
@@ -156,29 +553,113 @@ impl Client {
                         (color_primary("Upgrading to")) ": " (color_secondary(&next)) "\n"
                         (color_primary("New version available")) ": " (color_secondary(misc::format_build_number(available, &mut buffer)))
                     );
-                }
-
-                // Only upgrade if an upgrade is possible, or if being forced to upgrade.
-                if forcing || available >= 0 {
-                    // Ask to perform the release upgrade, and then listen for its signals.
-                    self.release_upgrade(method, current.as_ref(), next.as_ref())?;
-                    let mut recall = self.event_listen_release_upgrade()?;
 
-                    // Repeat as necessary.
-                    while recall {
-                        println!(
-                            "{}: {}",
-                            color_primary("Event"),
-                            color_secondary("attempting to perform upgrade again")
+                    if available >= 0 {
+                        pintln!(
+                            (color_primary("Download size")) ": " (color_secondary(misc::format_size(size)))
+                            if let Some(ref release_notes) = release_notes {
+                                "\n" (color_primary("What's new")) ": " (color_secondary(release_notes))
+                            }
                         );
-                        self.release_upgrade(method, current.as_ref(), next.as_ref())?;
-                        recall = self.event_listen_release_upgrade()?;
                     }
+                }
+
+                // Schedule the upgrade to begin later, or only upgrade if an upgrade is possible,
+                // or if being forced to upgrade.
+                if matches.is_present("schedule") {
+                    let time = match matches.value_of("schedule") {
+                        Some(time) => time.to_owned(),
+                        None => Config::load()?.schedule.time.ok_or_else(|| {
+                            anyhow!("no time was given, and no schedule.time is configured")
+                        })?,
+                    };
+
+                    let timestamp = schedule::parse(&time).ok_or_else(|| {
+                        anyhow!("{} is not a time of the form HH:MM or YYYY-MM-DD HH:MM", time)
+                    })?;
 
-                    // Finalize the release upgrade.
-                    self.release_upgrade_finalize()?;
+                    self.0.schedule(timestamp)?;
+                    pintln!(
+                        (color_primary("Upgrade scheduled")) ": " (color_secondary(&next))
+                        " at " (color_secondary(schedule::format(timestamp)))
+                    );
+                } else if forcing || available >= 0 {
+                    self.upgrade_release(
+                        method,
+                        &current,
+                        &next,
+                        matches.is_present("lift-holds"),
+                        conffile_policy(matches),
+                        upgrade_flags(matches),
+                    )?;
+                } else if available == -6 {
+                    println!(
+                        "{} is being released gradually, and has not been offered to this system \
+                         yet: use --force to upgrade now",
+                        next
+                    );
+                    return Err(Exit::silent(ExitCode::NoUpdateAvailable).into());
                 } else {
                     println!("no release available to upgrade to");
+                    return Err(Exit::silent(ExitCode::NoUpdateAvailable).into());
+                }
+            }
+            // Show the release notes of a release, rendering them if printing to a terminal.
+            ("notes", Some(matches)) => {
+                let notes = self.release_notes(matches.value_of("VERSION").unwrap_or(""))?;
+
+                if atty::is(atty::Stream::Stdout) {
+                    markdown::print(&notes);
+                } else {
+                    print!("{}", notes);
+                }
+            }
+            // Plan the sequence of upgrades required to reach a release.
+            ("path", Some(matches)) => {
+                let to = match matches.value_of("VERSION") {
+                    Some(to) => to,
+                    None => {
+                        print_upgrade_path(&self.upgrade_path()?);
+                        return Ok(());
+                    }
+                };
+
+                let from = matches.value_of("from").unwrap_or("");
+                let path = self.release_path(from, to)?;
+
+                if path.is_empty() {
+                    println!("already on {}", to);
+                    return Ok(());
+                }
+
+                for (step, version) in path.iter().enumerate() {
+                    pintln!(
+                        (color_primary(fomat!("Step " (step + 1)))) ": upgrade to "
+                        (color_secondary(version))
+                    );
+                }
+
+                if matches.is_present("upgrade") {
+                    if !from.is_empty() {
+                        return Err(anyhow!("upgrades may only begin from the current release"));
+                    }
+
+                    let current = self.0.release_check(true, false)?.current;
+                    self.upgrade_release(
+                        UpgradeMethod::Offline,
+                        &current,
+                        &path[0],
+                        matches.is_present("lift-holds"),
+                        conffile_policy(matches),
+                        upgrade_flags(matches),
+                    )?;
+
+                    if path.len() > 1 {
+                        println!(
+                            "after rebooting, run `pop-upgrade release path {}` to continue",
+                            to
+                        );
+                    }
                 }
             }
             // Set the recovery partition as the next boot target, and configure it to
@@ -192,8 +673,51 @@ impl Client {
                 self.refresh_os(action)?;
                 println!("reboot to boot into the recovery partition to begin the refresh install");
             }
+            ("reclaim", Some(_)) => {
+                let (removed, bytes) = self.release_reclaim()?;
+
+                for package in &removed {
+                    println!("{}: {}", color_primary("Removed"), color_secondary(package));
+                }
+
+                println!("reclaimed {} MiB", bytes / 1024 / 1024);
+            }
             ("repair", Some(_)) => {
-                self.release_repair()?;
+                print_repaired(&self.release_repair()?);
+            }
+            ("report", Some(matches)) => match Report::latest()? {
+                Some(report) if matches.is_present("json") => print_json(&report)?,
+                Some(report) => println!("{}", report.summary()),
+                None => println!("no release upgrade has been installed"),
+            },
+            _ => unreachable!(),
+        }
+
+        Ok(())
+    }
+
+    pub fn snapshot(&self, matches: &ArgMatches) -> anyhow::Result<()> {
+        match matches.subcommand() {
+            ("list", _) => {
+                let snapshots = self.snapshot_list()?;
+
+                if snapshots.is_empty() {
+                    println!("no snapshots have been taken by pop-upgrade");
+                }
+
+                for snapshot in snapshots {
+                    let note =
+                        if snapshot.pre_upgrade { " taken before the last upgrade" } else { "" };
+                    pintln!(
+                        (color_secondary(&snapshot.name)) " (" (snapshot.backend) ")"
+                        (color_info(note))
+                    );
+                }
+            }
+            ("restore", Some(matches)) => {
+                let name = matches.value_of("NAME").expect("missing required NAME argument");
+                let backend = self.snapshot_restore(name)?;
+                println!("restored {} with {}: reboot into the restored system", name, backend);
             }
             _ => unreachable!(),
         }
@@ -201,8 +725,11 @@ impl Client {
         Ok(())
     }
 
-    pub fn status(&self, _matches: &ArgMatches) -> anyhow::Result<()> {
-        let info = self.0.status()?;
+    /// Executes the status subcommand of the client, which reports the daemon's `StatusDetails`,
+    /// rather than its `Status`.
+    pub fn status(&self, matches: &ArgMatches) -> anyhow::Result<()> {
+        let details = self.0.status_details()?;
+        let info = &details.status;
 
         let (status, sub_status) = match DaemonStatus::from_u8(info.status) {
             Some(status) => {
@@ -226,15 +753,136 @@ impl Client {
             None => ("unknown status", ""),
         };
 
+        let scheduled = self.0.scheduled()?;
+        let interrupted = self.0.interrupted_upgrade()?;
+        let managed = self.0.managed()?;
+
+        if matches.is_present("json") {
+            let interrupted = interrupted.map(|upgrade| {
+                serde_json::json!({
+                    "from": upgrade.from,
+                    "to": upgrade.to,
+                    "phase": upgrade.phase.map(<&'static str>::from),
+                })
+            });
+
+            let report = serde_json::json!({
+                "status": status,
+                "sub_status": if sub_status.is_empty() { None } else { Some(sub_status) },
+                "progress": details.progress,
+                "client": details.client,
+                "last_error": details.last_error,
+                "scheduled": scheduled,
+                "interrupted_upgrade": interrupted,
+                "managed": managed,
+            });
+
+            return print_json(&report);
+        }
+
         if sub_status.is_empty() {
             println!("{}", status);
         } else {
             println!("{}: {}", status, sub_status);
         }
 
+        if details.progress != 0 {
+            println!("  progress: {}%", details.progress);
+        }
+
+        if let Some(ref client) = details.client {
+            println!("  requested by: {}", client);
+        }
+
+        if let Some(ref why) = details.last_error {
+            println!("last error: {}", why);
+        }
+
+        if let Some(timestamp) = scheduled {
+            println!("release upgrade scheduled for {}", schedule::format(timestamp));
+        }
+
+        if let Some(upgrade) = interrupted {
+            let phase = upgrade.phase.map_or("not started", <&'static str>::from);
+            println!(
+                "release upgrade from {} to {} was interrupted ({}): resume it with `pop-upgrade \
+                 release resume`",
+                upgrade.from, upgrade.to, phase
+            );
+        }
+
+        if managed {
+            println!("upgrades are managed by the policy in {}", pop_upgrade::policy::POLICY);
+        }
+
         Ok(())
     }
 
+    pub fn tui(&self, _matches: &ArgMatches) -> anyhow::Result<()> { tui::run(&self.0) }
+
+    /// Updates pop-upgrade before it is used, and then replaces this process with the updated
+    /// client, given the same arguments. Before a release upgrade, the user is asked first, as an
+    /// outdated version may not know how to upgrade to the newest release.
+    pub fn self_update(&self, ask: bool, chatty: bool) -> anyhow::Result<()> {
+        // The client which replaced this one once pop-upgrade was updated does not try again, as
+        // the update may have failed, or left a version which apt still deems outdated.
+        if std::env::var_os(UPDATED_ENV).is_some() {
+            return Ok(());
+        }
+
+        if chatty {
+            println!("{}", fl!("self-update-checking"));
+        }
+
+        if ask && self.supports(capabilities::SELF_UPDATE)? {
+            let update = match self.self_update_check()? {
+                Some(update) => update,
+                None => return Ok(()),
+            };
+
+            let prompt = fl!(
+                "self-update-prompt",
+                name = color_primary("pop-upgrade").to_string(),
+                installed = &*update.installed,
+                candidate = &*update.candidate
+            );
+
+            if !prompt::get_bool(&fomat!((prompt) " "), true) {
+                println!("{}", fl!("self-update-declined", installed = &*update.installed));
+                return Ok(());
+            }
+        }
+
+        if !self.update_and_restart()? {
+            return Ok(());
+        }
+
+        if chatty {
+            println!("{}", fl!("self-update-waiting"));
+        }
+
+        let file = Path::new(pop_upgrade::RESTART_SCHEDULED);
+        while file.exists() {
+            std::thread::sleep(Duration::from_secs(1));
+        }
+
+        // The file is removed as the daemon starts, before it has registered on the bus.
+        std::thread::sleep(Duration::from_secs(1));
+
+        if chatty {
+            println!("{}", fl!("self-update-reconnecting"));
+        }
+
+        // The client was updated along with the daemon, so it is executed again.
+        std::env::set_var(UPDATED_ENV, "1");
+        let mut args = std::env::args_os();
+        let program = args.next().unwrap_or_else(|| "pop-upgrade".into());
+        let args = args.collect::<Vec<_>>();
+        let why = exec::Command::new(program).args(&args).exec();
+
+        Err(anyhow!("failed to restart pop-upgrade once it was updated: {}", why))
+    }
+
     /// Check if this release has already been dismissed
     fn dismissed(&self, next: &str) -> bool {
         Path::new(DISMISSED).exists() && {
@@ -255,15 +903,6 @@ impl Client {
         }
     }
 
-    fn release_check(
-        &self,
-        force_next: bool,
-    ) -> Result<(Box<str>, Box<str>, i16, bool), client::Error> {
-        let info = self.0.release_check(force_next)?;
-
-        Ok((info.current, info.next, info.build, info.is_lts))
-    }
-
     fn event_listen_fetch_updates(&self) -> Result<(), client::Error> {
         self.event_listen(
             DaemonStatus::FetchingPackages,
@@ -271,13 +910,17 @@ impl Client {
             |new_status| {
                 log_result(
                     new_status.status,
-                    FETCH_RESULT_STR,
-                    FETCH_RESULT_SUCCESS,
-                    FETCH_RESULT_ERROR,
+                    &fl!("fetch-result"),
+                    &fl!("fetch-result-success"),
+                    &fl!("fetch-result-error"),
                     &new_status.why,
                 )
             },
             |_client, signal| {
+                if quiet() && reports_progress(&signal) {
+                    return Ok(client::Continue(true));
+                }
+
                 match signal {
                     client::Signal::PackageFetchResult(status) => {
                         log_result(
@@ -302,6 +945,7 @@ impl Client {
                     client::Signal::PackageFetching(package) => {
                         println!("{} {}", color_primary("Fetching"), color_secondary(package));
                     }
+                    client::Signal::PackageLockWait(holder) => print_lock_wait(&holder),
                     client::Signal::PackageUpgrade(event) => {
                         if let Ok(event) = AptUpgradeEvent::from_dbus_map(event.into_iter()) {
                             write_apt_event(event);
@@ -318,7 +962,8 @@ impl Client {
     }
 
     fn event_listen_recovery_upgrade(&self) -> Result<(), client::Error> {
-        let mut reset = false;
+        // The bar of the phase in progress, which is finished once another phase begins.
+        let mut bar: Option<Bar> = None;
 
         self.event_listen(
             DaemonStatus::RecoveryUpgrade,
@@ -326,33 +971,32 @@ impl Client {
             |new_status| {
                 log_result(
                     new_status.status,
-                    RECOVERY_RESULT_STR,
-                    RECOVERY_RESULT_SUCCESS,
-                    RECOVERY_RESULT_ERROR,
+                    &fl!("recovery-result"),
+                    &fl!("recovery-result-success"),
+                    &fl!("recovery-result-error"),
                     &new_status.why,
                 )
             },
             move |_client, signal| {
-                match signal {
-                    client::Signal::RecoveryDownloadProgress(progress) => {
-                        print!(
-                            "\r{} {}/{} {}",
-                            color_primary("Fetched"),
-                            color_info(progress.progress / 1024),
-                            color_info(progress.total / 1024),
-                            color_primary("MiB")
-                        );
-
-                        let _ = io::stdout().flush();
+                if quiet() && reports_progress(&signal) {
+                    return Ok(client::Continue(true));
+                }
 
-                        reset = true;
+                match signal {
+                    client::Signal::RecoveryDownloadProgress(download) => {
+                        progress::phase(&mut bar, "Fetching", Unit::KiB)
+                            .update(download.progress, download.total);
+                    }
+                    client::Signal::RecoveryVerifyProgress(percent) => {
+                        progress::phase(&mut bar, "Verifying", Unit::Percent)
+                            .update(percent.into(), 100);
+                    }
+                    client::Signal::RecoverySyncProgress(percent) => {
+                        progress::phase(&mut bar, "Syncing", Unit::Percent)
+                            .update(percent.into(), 100);
                     }
                     client::Signal::RecoveryEvent(event) => {
-                        if reset {
-                            reset = false;
-                            println!();
-                        }
-
+                        bar = None;
                         println!(
                             "{}: {}",
                             color_primary("Recovery event"),
@@ -360,16 +1004,12 @@ impl Client {
                         );
                     }
                     client::Signal::RecoveryResult(status) => {
-                        if reset {
-                            reset = false;
-                            println!();
-                        }
-
+                        bar = None;
                         log_result(
                             status.status,
-                            RECOVERY_RESULT_STR,
-                            RECOVERY_RESULT_SUCCESS,
-                            RECOVERY_RESULT_ERROR,
+                            &fl!("recovery-result"),
+                            &fl!("recovery-result-success"),
+                            &fl!("recovery-result-error"),
                             &status.why,
                         );
 
@@ -383,6 +1023,103 @@ impl Client {
         )
     }
 
+    /// Performs a release upgrade, listening for its signals, and retrying as necessary.
+    fn upgrade_release(
+        &self,
+        method: UpgradeMethod,
+        current: &str,
+        next: &str,
+        lift_holds: bool,
+        conffiles: Option<ConffilePolicy>,
+        flags: UpgradeFlags,
+    ) -> anyhow::Result<()> {
+        self.preflight(flags.contains(UpgradeFlags::IGNORE_BATTERY))?;
+
+        if !lift_holds {
+            let held = self.held_packages()?;
+            if !held.is_empty() {
+                println!("{}:", color_primary("Held packages"));
+                for package in held.iter() {
+                    println!("  {}", color_secondary(package));
+                }
+
+                return Err(anyhow!(
+                    "held packages may prevent the upgrade: rerun with --lift-holds to lift their \
+                     holds until the upgrade completes"
+                ));
+            }
+        }
+
+        self.await_release_upgrade(|| {
+            self.release_upgrade(method, current, next, lift_holds, conffiles, flags)
+        })
+    }
+
+    /// Prints the preflight checks which did not pass, and fails if any of them failed.
+    ///
+    /// A broken package database is repaired, and the checks are then performed again. A low
+    /// battery is only warned about if `ignore_battery` is set.
+    fn preflight(&self, ignore_battery: bool) -> anyhow::Result<()> {
+        let mut checks = self.preflight_check()?;
+
+        let broken_apt = checks.iter().any(|check| {
+            &*check.name == preflight::APT_CHECK && check.status == PreflightStatus::Fail
+        });
+
+        if broken_apt {
+            println!("{}", color_primary("Repairing the package database"));
+            print_repaired(&self.release_repair()?);
+            checks = self.preflight_check()?;
+        }
+
+        if ignore_battery {
+            for check in checks.iter_mut().filter(|check| &*check.name == preflight::POWER_CHECK) {
+                check.status = check.status.min(PreflightStatus::Warn);
+            }
+        }
+
+        print_preflight(checks.iter().filter(|check| check.status != PreflightStatus::Pass));
+
+        if checks.iter().any(|check| check.status == PreflightStatus::Fail) {
+            let why = fl!("error-preflight-failed");
+            return Err(Exit::new(ExitCode::PreflightFailed, why).into());
+        }
+
+        Ok(())
+    }
+
+    /// Starts a release upgrade, listens for its signals until it completes, and then finalizes
+    /// it. The upgrade is started again for as long as the user asks to retry.
+    fn await_release_upgrade(
+        &self,
+        mut start: impl FnMut() -> Result<(), client::Error>,
+    ) -> anyhow::Result<()> {
+        // Ask to perform the release upgrade, and then listen for its signals.
+        start()?;
+        let mut recall = self.event_listen_release_upgrade()?;
+
+        // Repeat as necessary.
+        while recall {
+            println!(
+                "{}: {}",
+                color_primary("Event"),
+                color_secondary("attempting to perform upgrade again")
+            );
+            start()?;
+            recall = self.event_listen_release_upgrade()?;
+        }
+
+        let status = self.release_upgrade_status()?;
+        if status.status != 0 {
+            return Err(Exit::failed(&status).into());
+        }
+
+        // Finalize the release upgrade.
+        self.release_upgrade_finalize()?;
+
+        Ok(())
+    }
+
     fn event_listen_release_upgrade(&self) -> Result<bool, client::Error> {
         let recall = &mut false;
 
@@ -392,20 +1129,24 @@ impl Client {
             |new_status| {
                 log_result(
                     new_status.status,
-                    UPGRADE_RESULT_STR,
-                    UPGRADE_RESULT_SUCCESS,
-                    UPGRADE_RESULT_ERROR,
+                    &fl!("upgrade-result"),
+                    &fl!("upgrade-result-success"),
+                    &fl!("upgrade-result-error"),
                     &new_status.why,
                 )
             },
             |_client, signal| {
+                if quiet() && reports_progress(&signal) {
+                    return Ok(client::Continue(true));
+                }
+
                 match signal {
                     client::Signal::PackageFetchResult(status) => {
                         log_result(
                             status.status,
-                            FETCH_RESULT_STR,
-                            FETCH_RESULT_SUCCESS,
-                            FETCH_RESULT_ERROR,
+                            &fl!("fetch-result"),
+                            &fl!("fetch-result-success"),
+                            &fl!("fetch-result-error"),
                             &status.why,
                         );
                     }
@@ -421,6 +1162,7 @@ impl Client {
                     client::Signal::PackageFetching(package) => {
                         println!("{} {}", color_primary("Fetching"), color_secondary(&package));
                     }
+                    client::Signal::PackageLockWait(holder) => print_lock_wait(&holder),
                     client::Signal::PackageUpgrade(event) => {
                         match AptUpgradeEvent::from_dbus_map(event.clone().into_iter()) {
                             Ok(event) => write_apt_event(event),
@@ -431,9 +1173,9 @@ impl Client {
                         if !*recall {
                             log_result(
                                 status.status,
-                                UPGRADE_RESULT_STR,
-                                UPGRADE_RESULT_SUCCESS,
-                                UPGRADE_RESULT_ERROR,
+                                &fl!("upgrade-result"),
+                                &fl!("upgrade-result-success"),
+                                &fl!("upgrade-result-error"),
                                 &status.why,
                             );
                         }
@@ -458,7 +1200,8 @@ impl Client {
 
                         let prompt = format!("    {} y/N", color_primary("Try again?"));
 
-                        if prompt::get_bool(&prompt, false) {
+                        // Retrying without end would never let an unattended upgrade fail.
+                        if !prompt::non_interactive() && prompt::get_bool(&prompt, false) {
                             *recall = true;
                         } else {
                             return Ok(client::Continue(false));
@@ -510,37 +1253,28 @@ fn installed_after_release(next: &str) -> bool {
     false
 }
 
-fn notification_message(current: &str, next: &str) -> (String, String) {
-    match EolDate::fetch() {
-        Ok(eol) => match eol.status() {
-            EolStatus::Exceeded => {
-                return (
-                    fomat!("Support for Pop!_OS " (current) " has ended"),
-                    fomat!(
-                        "Security and application updates are no longer provided for Pop!_OS "
-                        (current) ". Upgrade to Pop!_OS " (next) " to keep your computer secure."
-                    ),
-                );
-            }
-            EolStatus::Imminent => {
-                let (y, m, d) = eol.ymd;
-                return (
-                    fomat!(
-                        "Support for Pop!_OS " (current) " ends "
-                        (Utc.ymd(y as i32, m, d).format("%B %-d, %Y"))
-                    ),
-                    fomat!(
-                        "This computer will soon stop receiving updates"
-                        ". Upgrade to Pop!_OS " (next) " to keep your computer secure."
-                    ),
-                );
-            }
-            EolStatus::Ok => (),
-        },
-        Err(why) => error!("failed to fetch EOL date: {}", why),
-    }
+fn notification_message(
+    current: &str,
+    next: &str,
+    eol: Option<&client::EndOfLife>,
+) -> (String, String) {
+    match eol.map(|eol| (eol.status, &eol.date)) {
+        Some((EolStatus::Exceeded, _)) => (
+            fl!("eol-exceeded", current = current),
+            fl!("eol-exceeded-body", current = current, next = next),
+        ),
+        Some((EolStatus::Imminent, date)) => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map(|date| date.format("%B %-d, %Y").to_string())
+                .unwrap_or_else(|_| date.to_string());
 
-    ("Upgrade Available".into(), fomat!("Pop!_OS " (next) " is available to download"))
+            (
+                fl!("eol-imminent", current = current, date = date),
+                fl!("eol-imminent-body", next = next),
+            )
+        }
+        _ => (fl!("upgrade-available"), fl!("upgrade-available-body", next = next)),
+    }
 }
 
 fn write_apt_event(event: AptUpgradeEvent) {
@@ -573,13 +1307,123 @@ fn write_apt_event(event: AptUpgradeEvent) {
     }
 }
 
-fn log_result(
-    status: u8,
-    event: &'static str,
-    success: &'static str,
-    error: &'static str,
-    why: &str,
-) {
+/// The conffile policy given on the command line, if it overrides the configured policy.
+fn conffile_policy(matches: &ArgMatches) -> Option<ConffilePolicy> {
+    matches.value_of("conffiles").map(|policy| policy.parse().expect("invalid conffile policy"))
+}
+
+/// The conditions which the upgrade was asked on the command line to proceed despite.
+fn upgrade_flags(matches: &ArgMatches) -> UpgradeFlags {
+    let mut flags = UpgradeFlags::empty();
+    flags.set(UpgradeFlags::IGNORE_BATTERY, matches.is_present("ignore-battery"));
+    flags.set(UpgradeFlags::ALLOW_METERED, matches.is_present("allow-metered"));
+    flags
+}
+
+fn print_lock_wait(holder: &LockHolder) {
+    println!(
+        "{} for {} to release the package manager",
+        color_primary("Waiting"),
+        color_secondary(apt_lock::describe(holder))
+    );
+}
+
+fn print_repaired(repaired: &[Box<str>]) {
+    if repaired.is_empty() {
+        println!("no packages required repair");
+        return;
+    }
+
+    println!("{}:", color_primary("Repaired"));
+    for repair in repaired {
+        println!("  {}", color_secondary(repair));
+    }
+}
+
+/// Lists the packages under each heading, skipping headings without packages.
+fn print_packages(sections: &[(&str, Vec<Box<str>>)]) {
+    for (heading, packages) in sections {
+        if !packages.is_empty() {
+            println!("{}:", color_primary(heading));
+            for package in packages {
+                println!("  {}", color_secondary(package));
+            }
+        }
+    }
+}
+
+/// Prints the releases which upgrades would pass through, such as `20.04 LTS → 20.10`.
+fn print_upgrade_path(path: &[client::UpgradeStop]) {
+    if path.len() < 2 {
+        println!("no upgrades are available from the current release");
+        return;
+    }
+
+    let stops = path.iter().map(|stop| {
+        let lts = if stop.lts { " LTS" } else { "" };
+        let upgrade = match stop.upgrade {
+            Availability::Enabled => "",
+            Availability::Development => " (development)",
+            Availability::Disabled => " (not yet available)",
+        };
+
+        fomat!((color_secondary(&stop.version)) (lts) (upgrade))
+    });
+
+    println!("{}", stops.collect::<Vec<_>>().join(" → "));
+}
+
+/// Ends a check with the exit code of there being no update, unless one is available.
+fn no_update_unless(available: bool) -> anyhow::Result<()> {
+    if available {
+        Ok(())
+    } else {
+        Err(Exit::silent(ExitCode::NoUpdateAvailable).into())
+    }
+}
+
+/// Prints a JSON document for scripts to parse, in place of the human-readable output.
+fn print_json<T: serde::Serialize>(value: &T) -> anyhow::Result<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+fn print_preflight<'a>(checks: impl Iterator<Item = &'a client::PreflightCheck>) {
+    for check in checks {
+        let status = match check.status {
+            PreflightStatus::Pass => color_info("pass"),
+            PreflightStatus::Warn => Paint::yellow("warn").bold(),
+            PreflightStatus::Fail => color_error("fail"),
+        };
+
+        println!("[{}] {}: {}", status, color_primary(&check.name), check.message);
+    }
+}
+
+/// Whether the signal only reports the progress of an operation, which quiet mode hides.
+fn reports_progress(signal: &client::Signal) -> bool {
+    matches!(
+        signal,
+        client::Signal::PackageFetchProgress(_)
+            | client::Signal::PackageFetched(_)
+            | client::Signal::PackageFetching(_)
+            | client::Signal::PackageUpgrade(_)
+            | client::Signal::PackageUpgradeProgress(_)
+            | client::Signal::PackageUpgradeStage(..)
+            | client::Signal::RecoveryDownloadProgress(_)
+            | client::Signal::RecoveryEvent(_)
+            | client::Signal::RecoverySyncProgress(_)
+            | client::Signal::RecoveryVerifyProgress(_)
+            | client::Signal::ReleaseEvent(_)
+            | client::Signal::ReleaseProgress(_)
+    )
+}
+
+fn log_result(status: u8, event: &str, success: &str, error: &str, why: &str) {
+    if status == 0 && quiet() {
+        return;
+    }
+
     let inner: String;
 
     println!(
@@ -599,6 +1443,6 @@ pub fn root_required() -> anyhow::Result<()> {
     if unsafe { libc::geteuid() == 0 } {
         Ok(())
     } else {
-        Err(anyhow!("root is required for this operation"))
+        Err(anyhow!(fl!("error-not-root")))
     }
 }
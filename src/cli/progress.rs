@@ -0,0 +1,183 @@
+//! Progress bars for the phases of an operation, which degrade to a line per tenth of progress
+//! when stdout is not a terminal.
+
+use super::colors::*;
+use pop_upgrade::misc;
+use std::{
+    io::{self, Write},
+    time::{Duration, Instant},
+};
+
+const WIDTH: usize = 30;
+
+/// What the progress of a phase is measured in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Unit {
+    /// Kibibytes, of which the speed and remaining time are shown.
+    KiB,
+    Percent,
+}
+
+pub struct Bar {
+    label:   &'static str,
+    unit:    Unit,
+    started: Instant,
+    tty:     bool,
+    /// The tenth of progress last printed as a line, when stdout is not a terminal.
+    printed: Option<u64>,
+    drawn:   bool,
+}
+
+impl Bar {
+    pub fn new(label: &'static str, unit: Unit) -> Self {
+        Self {
+            label,
+            unit,
+            started: Instant::now(),
+            tty:     atty::is(atty::Stream::Stdout),
+            printed: None,
+            drawn:   false,
+        }
+    }
+
+    pub fn update(&mut self, done: u64, total: u64) {
+        // Sizes which are not known beforehand are counted up, rather than measured against.
+        if total == 0 && self.unit == Unit::KiB {
+            return self.count(done);
+        }
+
+        let done = done.min(total);
+        let percent = if total == 0 { 0 } else { done * 100 / total };
+
+        if !self.tty {
+            if self.printed.map_or(true, |printed| percent / 10 > printed) {
+                self.printed = Some(percent / 10);
+                println!("{}: {}%", self.label, percent);
+            }
+
+            return;
+        }
+
+        let filled = WIDTH * percent as usize / 100;
+        let mut line = fomat!(
+            "\r" (color_primary(self.label)) " ["
+            for _ in 0..filled { "=" }
+            if filled < WIDTH { ">" for _ in filled + 1..WIDTH { " " } }
+            "] " (color_info(fomat!((percent) "%")))
+        );
+
+        if self.unit == Unit::KiB {
+            let elapsed = self.started.elapsed();
+            line.push_str(&fomat!(
+                " " (misc::format_size(done * 1024)) " / " (misc::format_size(total * 1024))
+                if let Some(speed) = speed(done, elapsed) {
+                    " " (misc::format_size(speed)) "/s"
+                    if let Some(eta) = eta(done, total, elapsed) {
+                        " ETA " (format_duration(eta))
+                    }
+                }
+            ));
+        }
+
+        // Clears what remains of a longer line which was drawn before.
+        print!("{}\x1B[K", line);
+        let _ = io::stdout().flush();
+        self.drawn = true;
+    }
+
+    /// Shows how many kibibytes were fetched, and how fast, when their total is unknown.
+    fn count(&mut self, done: u64) {
+        let fetched = fomat!("Fetched " (misc::format_size(done * 1024)));
+
+        if !self.tty {
+            // Without a total to take tenths of, a line is printed per 100 MiB.
+            let step = done / (100 * 1024);
+            if self.printed.map_or(true, |printed| step > printed) {
+                self.printed = Some(step);
+                println!("{}: {}", self.label, fetched);
+            }
+
+            return;
+        }
+
+        let line = fomat!(
+            "\r" (color_primary(self.label)) " " (color_info(&fetched))
+            if let Some(speed) = speed(done, self.started.elapsed()) {
+                " " (misc::format_size(speed)) "/s"
+            }
+        );
+
+        print!("{}\x1B[K", line);
+        let _ = io::stdout().flush();
+        self.drawn = true;
+    }
+
+    /// Ends the line of the bar, so that what is printed next is not drawn over it.
+    pub fn finish(&mut self) {
+        if self.drawn {
+            println!();
+            self.drawn = false;
+        }
+    }
+}
+
+impl Drop for Bar {
+    fn drop(&mut self) { self.finish(); }
+}
+
+/// The bar of a phase, which replaces the bar of the phase before it.
+pub fn phase<'a>(bar: &'a mut Option<Bar>, label: &'static str, unit: Unit) -> &'a mut Bar {
+    if bar.as_ref().map_or(true, |bar| bar.label != label) {
+        *bar = Some(Bar::new(label, unit));
+    }
+
+    bar.as_mut().unwrap()
+}
+
+/// Bytes per second, from the kibibytes done so far.
+fn speed(done: u64, elapsed: Duration) -> Option<u64> {
+    let millis = elapsed.as_millis() as u64;
+    if millis < 1000 || done == 0 {
+        None
+    } else {
+        Some(done * 1024 * 1000 / millis)
+    }
+}
+
+/// The time remaining, if progress continues at the rate it has been made so far.
+fn eta(done: u64, total: u64, elapsed: Duration) -> Option<Duration> {
+    if done == 0 {
+        return None;
+    }
+
+    let millis = elapsed.as_millis() as u64;
+    Some(Duration::from_millis((total - done).saturating_mul(millis) / done))
+}
+
+fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rates() {
+        assert_eq!(speed(2048, Duration::from_secs(2)), Some(1024 * 1024));
+        assert_eq!(speed(2048, Duration::from_millis(500)), None);
+        assert_eq!(eta(25, 100, Duration::from_secs(10)), Some(Duration::from_secs(30)));
+        assert_eq!(eta(0, 100, Duration::from_secs(10)), None);
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(Duration::from_secs(75)), "1:15");
+        assert_eq!(format_duration(Duration::from_secs(3725)), "1:02:05");
+    }
+}
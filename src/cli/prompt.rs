@@ -1,10 +1,27 @@
+use pop_upgrade::fl;
 use std::{
     io,
     io::{BufRead, Write},
+    sync::atomic::{AtomicBool, Ordering},
 };
 
+/// Whether prompts are answered with yes, rather than waiting on stdin.
+static NON_INTERACTIVE: AtomicBool = AtomicBool::new(false);
+
+/// Answers every prompt from now on with yes, so that the process never blocks on stdin.
+pub fn set_non_interactive(non_interactive: bool) {
+    NON_INTERACTIVE.store(non_interactive, Ordering::SeqCst);
+}
+
+pub fn non_interactive() -> bool { NON_INTERACTIVE.load(Ordering::SeqCst) }
+
 // Write a prompt to the terminal, and wait for an answer.
 pub fn get_bool(message: &str, default: bool) -> bool {
+    if non_interactive() {
+        println!("{} y", message);
+        return true;
+    }
+
     let std_in = io::stdin();
     let mut std_in_lock = std_in.lock();
 
@@ -16,7 +33,7 @@ pub fn get_bool(message: &str, default: bool) -> bool {
             .map(|input| parse_bool(&input, Some(default)))?;
 
         if response.is_none() {
-            writeln!(std_out_lock, "The answer must be either `y` or `n`.")?;
+            writeln!(std_out_lock, "{}", fl!("prompt-answer"))?;
         }
         Ok(response)
     };
@@ -0,0 +1,43 @@
+use chrono::{Duration, Local, NaiveDateTime, NaiveTime, TimeZone};
+
+/// Parses the local time to schedule an upgrade for, as `HH:MM` for its next occurrence, or as
+/// `YYYY-MM-DD HH:MM`, into seconds since the Unix epoch.
+pub fn parse(time: &str) -> Option<u64> {
+    let at = next_occurrence(time, Local::now().naive_local())?;
+    Local.from_local_datetime(&at).earliest().map(|at| at.timestamp() as u64)
+}
+
+/// Formats seconds since the Unix epoch as a local time.
+pub fn format(timestamp: u64) -> String {
+    Local.timestamp(timestamp as i64, 0).format("%Y-%m-%d %H:%M").to_string()
+}
+
+fn next_occurrence(time: &str, now: NaiveDateTime) -> Option<NaiveDateTime> {
+    if let Ok(at) = NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M") {
+        return Some(at);
+    }
+
+    let today = now.date().and_time(NaiveTime::parse_from_str(time, "%H:%M").ok()?);
+    Some(if today > now { today } else { today + Duration::days(1) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn occurrences() {
+        let now = NaiveDate::from_ymd(2022, 4, 21).and_hms(22, 30, 0);
+
+        let at = |day, hour, min| NaiveDate::from_ymd(2022, 4, day).and_hms(hour, min, 0);
+
+        assert_eq!(next_occurrence("02:00", now), Some(at(22, 2, 0)));
+        assert_eq!(next_occurrence("23:00", now), Some(at(21, 23, 0)));
+        assert_eq!(
+            next_occurrence("2022-05-01 03:15", now),
+            Some(NaiveDate::from_ymd(2022, 5, 1).and_hms(3, 15, 0))
+        );
+        assert_eq!(next_occurrence("2 AM", now), None);
+    }
+}
@@ -0,0 +1,444 @@
+//! A full-screen terminal interface for upgrading to the next release, for those upgrading over
+//! SSH. It shows the release which is available, the preflight checks, the packages which the
+//! upgrade would remove or downgrade, and the progress of the upgrade as it is fetched. Held
+//! packages, incompatible repositories, and the conffile policy are confirmed before upgrading.
+
+use super::{colors::*, Exit};
+use pop_upgrade::{
+    apt_lock,
+    client::{self, Client, Continue, Signal},
+    config::{ConffilePolicy, Config},
+    daemon::DaemonStatus,
+    misc::{self, format_error},
+    preflight::Status as PreflightStatus,
+    release::{UpgradeFlags, UpgradeMethod},
+};
+use std::{
+    collections::VecDeque,
+    io::{self, Read, Write},
+    mem,
+};
+
+/// Lines of progress which are kept on screen.
+const LOG_LINES: usize = 12;
+
+/// Packages which are listed in each section of the preview, before the rest are counted.
+const PREVIEW_PACKAGES: usize = 8;
+
+/// The key which Ctrl-C sends, as signals are not raised while the interface is shown.
+const CTRL_C: u8 = 3;
+
+/// Runs the interface until the user quits it.
+pub fn run(client: &Client) -> anyhow::Result<()> {
+    if !atty::is(atty::Stream::Stdin) || !atty::is(atty::Stream::Stdout) {
+        return Err(anyhow!("the interface must be run in a terminal"));
+    }
+
+    let terminal = Terminal::enter()?;
+    let mut screen = Screen::default();
+    screen.refresh(client);
+
+    loop {
+        screen.draw(&terminal);
+
+        match terminal.key()? {
+            b'q' | CTRL_C => return Ok(()),
+            b'r' => screen.refresh(client),
+            b'u' => {
+                let next = match screen.upgradable() {
+                    Ok(next) => next,
+                    Err(why) => {
+                        screen.message = why.into();
+                        continue;
+                    }
+                };
+
+                let options = match screen.confirm(&terminal, &next)? {
+                    Some(options) => options,
+                    None => {
+                        screen.message = "The upgrade was not started".into();
+                        continue;
+                    }
+                };
+
+                screen.message = match screen.upgrade(client, &terminal, options) {
+                    Ok(()) => "The upgrade is ready: reboot to install it".into(),
+                    Err(why) => fomat!("The upgrade failed: " (format_error(&*why))),
+                };
+            }
+            _ => (),
+        }
+    }
+}
+
+/// What the user agreed to when confirming the upgrade.
+struct Options {
+    lift_holds: bool,
+    conffiles:  Option<ConffilePolicy>,
+}
+
+#[derive(Default)]
+struct Screen {
+    release:      Option<client::ReleaseInfo>,
+    eol:          Option<client::EndOfLife>,
+    checks:       Vec<client::PreflightCheck>,
+    preview:      Option<client::Preview>,
+    held:         Vec<Box<str>>,
+    /// Third party sources which are disabled during the upgrade, as they lack the release.
+    incompatible: Vec<client::IncompatibleRepo>,
+    /// Bytes fetched of the packages of the upgrade, of the total bytes.
+    download:     Option<(u64, u64)>,
+    log:          VecDeque<String>,
+    /// What happened last, or what the user is being asked.
+    message:      String,
+}
+
+impl Screen {
+    /// Fetches the release which is available, and what upgrading to it would involve.
+    fn refresh(&mut self, client: &Client) {
+        self.message.clear();
+
+        let development = pop_upgrade::development_releases_enabled();
+        self.release = match client.release_check(development, false) {
+            Ok(release) => Some(release),
+            Err(why) => {
+                self.message = fomat!("Failed to check for a new release: " (format_error(&why)));
+                None
+            }
+        };
+
+        self.eol = client.end_of_life().ok();
+
+        self.checks = match client.preflight_check() {
+            Ok(checks) => checks,
+            Err(why) => {
+                self.message = fomat!("Failed to check the system: " (format_error(&why)));
+                Vec::new()
+            }
+        };
+
+        self.preview = match self.release {
+            Some(ref release) if release.build >= 0 => client.release_preview(&release.next).ok(),
+            _ => None,
+        };
+
+        self.held = client.held_packages().unwrap_or_default();
+
+        self.incompatible = match self.release {
+            Some(ref release) if release.build >= 0 => {
+                client.repo_compatibility(&release.next).unwrap_or_default()
+            }
+            _ => Vec::new(),
+        };
+    }
+
+    /// Asks to upgrade to the `next` release, and to what the upgrade requires, returning `None`
+    /// if the user declined any of it.
+    fn confirm(&mut self, terminal: &Terminal, next: &str) -> io::Result<Option<Options>> {
+        if !self.ask(terminal, fomat!("Upgrade to Pop!_OS " (next) "? [y/N]"))? {
+            return Ok(None);
+        }
+
+        let lift_holds = !self.held.is_empty();
+        if lift_holds {
+            let question = fomat!(
+                "Lift the holds of " (self.held.len()) " packages until the upgrade completes? "
+                "[y/N]"
+            );
+
+            if !self.ask(terminal, question)? {
+                return Ok(None);
+            }
+        }
+
+        if !self.incompatible.is_empty() {
+            let question = fomat!(
+                "Disable " (self.incompatible.len()) " incompatible repositories during the "
+                "upgrade? [y/N]"
+            );
+
+            if !self.ask(terminal, question)? {
+                return Ok(None);
+            }
+        }
+
+        let (question, instead) = match Config::load_or_default().release.conffiles {
+            ConffilePolicy::Old => (
+                "Modified configuration files will be kept. Replace them with those of the new \
+                 release instead? [y/N]",
+                ConffilePolicy::New,
+            ),
+            ConffilePolicy::New => (
+                "Modified configuration files will be replaced by those of the new release. Keep \
+                 them instead? [y/N]",
+                ConffilePolicy::Old,
+            ),
+        };
+
+        let conffiles = if self.ask(terminal, question.into())? { Some(instead) } else { None };
+
+        Ok(Some(Options { lift_holds, conffiles }))
+    }
+
+    /// Shows the question, and waits for the answer, which is only yes if `y` is pressed.
+    fn ask(&mut self, terminal: &Terminal, question: String) -> io::Result<bool> {
+        self.message = question;
+        self.draw(terminal);
+        Ok(terminal.key()? == b'y')
+    }
+
+    /// The release which may be upgraded to, or why it may not be.
+    fn upgradable(&self) -> Result<Box<str>, &'static str> {
+        let release = match self.release {
+            Some(ref release) if release.build >= 0 => release,
+            _ => return Err("No release is available to upgrade to"),
+        };
+
+        if self.checks.iter().any(|check| check.status == PreflightStatus::Fail) {
+            return Err("Resolve the failed preflight checks before upgrading");
+        }
+
+        Ok(release.next.clone())
+    }
+
+    /// Fetches the upgrade, showing its progress, and then readies it to install on reboot.
+    fn upgrade(
+        &mut self,
+        client: &Client,
+        terminal: &Terminal,
+        options: Options,
+    ) -> anyhow::Result<()> {
+        let release = self.release.clone().expect("upgrading without a release");
+
+        client.release_upgrade(
+            UpgradeMethod::Offline,
+            &release.current,
+            &release.next,
+            options.lift_holds,
+            options.conffiles,
+            UpgradeFlags::empty(),
+        )?;
+
+        self.log.clear();
+        self.download = None;
+        self.message = "Upgrading: run `pop-upgrade cancel` in another terminal to cancel".into();
+        self.draw(terminal);
+
+        client.event_listen(
+            DaemonStatus::ReleaseUpgrade,
+            Client::release_upgrade_status,
+            |_status| (),
+            |_client, signal| {
+                let last = matches!(signal, Signal::ReleaseResult(_));
+
+                if let Signal::PackageFetchProgress(ref progress) = signal {
+                    self.download = Some((progress.total_fetched, progress.total));
+                } else if let Some(line) = describe(&signal) {
+                    if self.log.len() == LOG_LINES {
+                        self.log.pop_front();
+                    }
+
+                    self.log.push_back(line);
+                }
+
+                self.draw(terminal);
+                Ok(Continue(!last))
+            },
+        )?;
+
+        let status = client.release_upgrade_status()?;
+        if status.status != 0 {
+            return Err(Exit::failed(&status).into());
+        }
+
+        client.release_upgrade_finalize()?;
+        Ok(())
+    }
+
+    fn draw(&self, terminal: &Terminal) {
+        let mut lines = vec![fomat!((color_primary("Pop!_OS Upgrade"))), String::new()];
+
+        lines.push(fomat!((color_primary("Release"))));
+        match self.release {
+            Some(ref release) => {
+                let mut buffer = String::new();
+                lines.push(fomat!("  Current: " (color_secondary(&release.current))));
+                lines.push(fomat!(
+                    "  Next: " (color_secondary(&release.next))
+                    " (" (misc::format_build_number(release.build, &mut buffer)) ")"
+                ));
+
+                if release.build >= 0 {
+                    lines.push(fomat!("  Download size: " (misc::format_size(release.size))));
+                }
+            }
+            None => lines.push("  Unknown".into()),
+        }
+
+        if let Some(ref eol) = self.eol {
+            lines.push(fomat!("  End of life: " (eol.date)));
+        }
+
+        lines.push(String::new());
+        lines.push(fomat!((color_primary("Preflight checks"))));
+        for check in &self.checks {
+            let status = match check.status {
+                PreflightStatus::Pass => color_info("pass"),
+                PreflightStatus::Warn => color_secondary("warn"),
+                PreflightStatus::Fail => color_error("fail"),
+            };
+
+            lines.push(fomat!("  [" (status) "] " (check.name) ": " (check.message)));
+        }
+
+        if !self.held.is_empty() {
+            lines.push(String::new());
+            lines.push(fomat!((color_primary("Held packages")) " (" (self.held.len()) ")"));
+            let rest = self.held.len().saturating_sub(PREVIEW_PACKAGES);
+            lines.push(fomat!(
+                "  " for package in self.held.iter().take(PREVIEW_PACKAGES) { (package) }
+                sep { ", " }
+                if rest > 0 { ", and " (rest) " more" }
+            ));
+        }
+
+        if !self.incompatible.is_empty() {
+            lines.push(String::new());
+            lines.push(fomat!((color_primary("Incompatible repositories"))));
+            for repo in &self.incompatible {
+                lines.push(fomat!("  " (color_secondary(&repo.path))));
+            }
+        }
+
+        if let Some(ref preview) = self.preview {
+            let sections = [
+                ("Packages to remove", &preview.removed),
+                ("Packages to downgrade", &preview.downgraded),
+            ];
+
+            for (heading, packages) in &sections {
+                if packages.is_empty() {
+                    continue;
+                }
+
+                lines.push(String::new());
+                lines.push(fomat!((color_primary(heading)) " (" (packages.len()) ")"));
+
+                let shown = packages.iter().take(PREVIEW_PACKAGES);
+                let rest = packages.len().saturating_sub(PREVIEW_PACKAGES);
+                lines.push(fomat!(
+                    "  " for package in shown { (package) } sep { ", " }
+                    if rest > 0 { ", and " (rest) " more" }
+                ));
+            }
+        }
+
+        if self.download.is_some() || !self.log.is_empty() {
+            lines.push(String::new());
+            lines.push(fomat!((color_primary("Progress"))));
+
+            if let Some((fetched, total)) = self.download {
+                lines.push(fomat!(
+                    "  Fetched " (misc::format_size(fetched)) " of " (misc::format_size(total))
+                ));
+            }
+
+            lines.extend(self.log.iter().map(|line| fomat!("  " (line))));
+        }
+
+        let rows = terminal.rows();
+        let footer = fomat!(
+            (color_secondary("[u]")) " upgrade  " (color_secondary("[r]")) " refresh  "
+            (color_secondary("[q]")) " quit"
+        );
+
+        // The footer and the message are kept at the bottom, beneath whatever fits above them.
+        lines.truncate(rows.saturating_sub(3));
+        while lines.len() < rows.saturating_sub(3) {
+            lines.push(String::new());
+        }
+
+        lines.push(String::new());
+        lines.push(self.message.clone());
+        lines.push(footer);
+
+        print!("\x1B[H\x1B[2J{}", lines.join("\r\n"));
+        let _ = io::stdout().flush();
+    }
+}
+
+/// A line of the progress of an upgrade which the signal reports, if any.
+fn describe(signal: &Signal) -> Option<String> {
+    let line = match signal {
+        Signal::PackageFetching(package) => fomat!("Fetching " (package)),
+        Signal::PackageFetched(fetched) => fomat!(
+            "Fetched (" (fetched.completed) "/" (fetched.total) ") " (fetched.package)
+        ),
+        Signal::PackageLockWait(holder) => {
+            fomat!("Waiting for " (apt_lock::describe(holder)) " to release the package manager")
+        }
+        Signal::ReleaseEvent(event) => <&'static str>::from(*event).to_owned(),
+        Signal::NoConnection => "Failed to connect to an apt repository".into(),
+        Signal::ReleaseResult(status) if status.status == 0 => "The upgrade was fetched".into(),
+        Signal::ReleaseResult(status) => fomat!("The upgrade failed: " (status.why)),
+        _ => return None,
+    };
+
+    Some(line)
+}
+
+/// Owns the terminal while the interface is shown, restoring it once dropped.
+struct Terminal {
+    saved: libc::termios,
+}
+
+impl Terminal {
+    /// Switches to the alternate screen, and reads each key as it is pressed, without echoing it.
+    /// Ctrl-C is read as a key too, rather than interrupting the process, which would otherwise
+    /// exit without restoring the terminal.
+    fn enter() -> io::Result<Self> {
+        let mut saved = unsafe { mem::zeroed::<libc::termios>() };
+        if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut raw = saved;
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        print!("\x1B[?1049h\x1B[?25l");
+        io::stdout().flush()?;
+
+        Ok(Self { saved })
+    }
+
+    fn key(&self) -> io::Result<u8> {
+        let mut key = [0u8; 1];
+        io::stdin().read_exact(&mut key)?;
+        Ok(key[0])
+    }
+
+    fn rows(&self) -> usize {
+        let mut size = unsafe { mem::zeroed::<libc::winsize>() };
+        let result = unsafe { libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) };
+
+        if result == 0 && size.ws_row > 0 {
+            usize::from(size.ws_row)
+        } else {
+            24
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        print!("\x1B[?25h\x1B[?1049l");
+        let _ = io::stdout().flush();
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
@@ -1,17 +1,32 @@
+/// A client for async frontends, which owns its connection on a worker thread
+pub mod nonblocking;
+
+pub use self::nonblocking::AsyncClient;
+
 use crate::{
+    apt_lock::LockHolder,
+    config::ConffilePolicy,
     daemon::{DaemonStatus as PrimaryStatus, *},
     recovery::{RecoveryEvent, ReleaseFlags as RecoveryReleaseFlags},
-    release::{RefreshOp, UpgradeEvent, UpgradeMethod},
+    preflight::Status as PreflightStatus,
+    release::{
+        eol::EolStatus, paths::Availability, resume::Phase, RefreshOp, UpgradeEvent, UpgradeFlags,
+        UpgradeMethod,
+    },
     sighandler, DBUS_IFACE, DBUS_NAME, DBUS_PATH,
 };
 
 use dbus::{
-    arg::messageitem::{MessageItem, MessageItemArray},
-    ffidisp::{Connection, ConnectionItem},
+    arg::{
+        messageitem::{MessageItem, MessageItemArray},
+        PropMap, Variant,
+    },
+    ffidisp::{stdintf::org_freedesktop_dbus::Properties, Connection, ConnectionItem},
     Message, Signature,
 };
 
 use num_traits::FromPrimitive;
+use serde_derive::Serialize;
 use std::collections::HashMap;
 use thiserror::Error;
 
@@ -25,6 +40,16 @@ pub struct FetchStatus {
     pub total:     u32,
 }
 
+/// Bytes fetched of a package, and of all packages being fetched.
+#[derive(Clone, Debug)]
+pub struct FetchProgress {
+    pub package:       Box<str>,
+    pub fetched:       u64,
+    pub size:          u64,
+    pub total_fetched: u64,
+    pub total:         u64,
+}
+
 /// Data for tracking progress of an action.
 #[derive(Clone, Debug)]
 pub struct Progress {
@@ -40,23 +65,50 @@ pub struct RepoCompatError {
 }
 
 /// A signal received by the daemon.
+#[derive(Clone, Debug)]
 pub enum Signal {
     NoConnection,
+    PackageFetchProgress(FetchProgress),
     PackageFetchResult(Status),
     PackageFetched(FetchStatus),
     PackageFetching(Box<str>),
+    PackageLockWait(LockHolder),
     PackageUpgrade(HashMap<Box<str>, Box<str>>),
+    PackageUpgradeProgress(u8),
+    PackageUpgradeStage(signals::AptStage, Box<str>),
     RecoveryDownloadProgress(Progress),
     RecoveryEvent(RecoveryEvent),
     RecoveryResult(Status),
+    RecoverySyncProgress(u8),
+    RecoveryVerifyProgress(u8),
     ReleaseResult(Status),
     ReleaseEvent(UpgradeEvent),
+    ReleaseProgress(u8),
+    StatusChanged(DaemonStatus),
 }
 
 /// Designates if the signal event loop should continue listening for signals.
 #[derive(Clone, Debug)]
 pub struct Continue(pub bool);
 
+/// The space reclaimed from a category of files which pop-upgrade left behind.
+#[derive(Clone, Debug, Serialize)]
+pub struct CleanedCategory {
+    /// The ID of a `clean::Category`.
+    pub category: Box<str>,
+    /// The number of files and directories which were removed.
+    pub files:    u32,
+    pub bytes:    u64,
+}
+
+/// The kernels which were kept and removed, and the loader entries which booted removed kernels.
+#[derive(Clone, Debug)]
+pub struct CleanedKernels {
+    pub kept:    Vec<Box<str>>,
+    pub removed: Vec<Box<str>>,
+    pub entries: Vec<Box<str>>,
+}
+
 /// The status of the daemon that was retrieved.
 #[derive(Clone, Debug)]
 pub struct DaemonStatus {
@@ -64,6 +116,34 @@ pub struct DaemonStatus {
     pub sub_status: u8,
 }
 
+/// A release upgrade which was interrupted by a crash or reboot, and may be resumed.
+#[derive(Clone, Debug)]
+pub struct InterruptedUpgrade {
+    pub from:  Box<str>,
+    pub to:    Box<str>,
+    /// The last phase which the upgrade completed.
+    pub phase: Option<Phase>,
+}
+
+/// What the daemon is doing, who asked it to, and how its last failed operation failed.
+#[derive(Clone, Debug)]
+pub struct StatusDetails {
+    pub status:     DaemonStatus,
+    /// The percentage of the current phase of the operation which has completed.
+    pub progress:   u8,
+    /// The client which initiated the operation that is in progress, if it is known.
+    pub client:     Option<Box<str>>,
+    pub last_error: Option<Box<str>>,
+}
+
+/// When the current release stops receiving updates.
+#[derive(Clone, Debug, Serialize)]
+pub struct EndOfLife {
+    pub status: EolStatus,
+    /// Formatted as `YYYY-MM-DD`.
+    pub date:   Box<str>,
+}
+
 /// Information about available system updates.
 #[derive(Clone, Debug)]
 pub struct Fetched {
@@ -73,7 +153,7 @@ pub struct Fetched {
 }
 
 /// The version of the recovery partition's image.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct RecoveryVersion {
     pub version: Box<str>,
     pub build:   i16,
@@ -83,13 +163,108 @@ pub struct RecoveryVersion {
 ///
 /// The build is set to `-1` if the next release is
 /// not available.
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct ReleaseInfo {
-    pub current: Box<str>,
-    pub next:    Box<str>,
-    pub build:   i16,
-    pub urgent:  Option<u16>,
-    pub is_lts:  bool,
+    pub current:       Box<str>,
+    pub next:          Box<str>,
+    pub build:         i16,
+    pub urgent:        Option<u16>,
+    pub is_lts:        bool,
+    /// Size of the ISO to download, in bytes.
+    pub size:          u64,
+    pub release_notes: Option<Box<str>>,
+}
+
+/// Packages which would be affected by a release upgrade.
+#[derive(Clone, Debug, Serialize)]
+pub struct Preview {
+    pub removed:    Vec<Box<str>>,
+    pub downgraded: Vec<Box<str>>,
+}
+
+/// The packages which a release upgrade would affect, and how much it would download.
+#[derive(Clone, Debug, Serialize)]
+pub struct Simulation {
+    pub installed:     Vec<Box<str>>,
+    pub upgraded:      Vec<Box<str>>,
+    pub removed:       Vec<Box<str>>,
+    pub downgraded:    Vec<Box<str>>,
+    pub download_size: u64,
+}
+
+/// A third party source which does not publish the release being upgraded to.
+#[derive(Clone, Debug, Serialize)]
+pub struct IncompatibleRepo {
+    pub path: Box<str>,
+    /// The URIs of the repositories which lack the release.
+    pub uris: Vec<Box<str>>,
+}
+
+/// A snapshot of the root filesystem, taken by pop-upgrade.
+#[derive(Clone, Debug, Serialize)]
+pub struct Snapshot {
+    pub backend:     Box<str>,
+    pub name:        Box<str>,
+    /// Whether this snapshot was taken before the last release upgrade.
+    pub pre_upgrade: bool,
+}
+
+/// An operation which the daemon performed.
+#[derive(Clone, Debug, Serialize)]
+pub struct HistoryEntry {
+    /// Such as `release-upgrade` or `recovery-upgrade`.
+    pub operation: Box<str>,
+    /// When the operation began, in seconds since the Unix epoch.
+    pub started:   u64,
+    /// When the operation ended, in seconds since the Unix epoch.
+    pub ended:     u64,
+    pub from:      Option<Box<str>>,
+    pub to:        Option<Box<str>>,
+    /// The client which requested the operation.
+    pub client:    Option<Box<str>>,
+    /// Why the operation failed, if it did.
+    pub error:     Option<Box<str>>,
+}
+
+/// A release which the machine would pass through when upgrading.
+#[derive(Clone, Debug)]
+pub struct UpgradeStop {
+    pub version: Box<str>,
+    pub lts:     bool,
+    /// Whether the upgrade to this release is offered.
+    pub upgrade: Availability,
+}
+
+/// A version of pop-upgrade which is newer than the installed version.
+#[derive(Clone, Debug)]
+pub struct SelfUpdate {
+    pub installed: Box<str>,
+    pub candidate: Box<str>,
+}
+
+/// Whether the user has dismissed or snoozed the notification of the next release.
+#[derive(Clone, Debug, Default)]
+pub struct NotificationPreferences {
+    /// The release whose notification was dismissed.
+    pub dismissed:     Option<Box<str>>,
+    /// When the notification may be shown again, in seconds since the Unix epoch.
+    pub snoozed_until: Option<u64>,
+}
+
+impl NotificationPreferences {
+    /// Whether the notification of the given release should not be shown at this time.
+    pub fn suppresses(&self, release: &str, now: u64) -> bool {
+        self.dismissed.as_deref() == Some(release)
+            || self.snoozed_until.map_or(false, |until| now < until)
+    }
+}
+
+/// The outcome of a check performed before upgrading.
+#[derive(Clone, Debug, Serialize)]
+pub struct PreflightCheck {
+    pub name:    Box<str>,
+    pub status:  PreflightStatus,
+    pub message: Box<str>,
 }
 
 /// The status of an action, and a description of why.
@@ -99,11 +274,19 @@ pub struct Status {
     pub why:    Box<str>,
 }
 
+impl Status {
+    /// Whether the operation was cancelled, rather than having failed.
+    pub fn is_cancelled(&self) -> bool { self.status == signals::ResultStatus::Cancelled as u8 }
+}
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("failed to add match on client connection")]
     AddMatch(#[source] dbus::Error),
 
+    #[error("upgrade availability integer was outside the acceptable range of values")]
+    AvailabilityOutOfRange,
+
     #[error("argument mismatch in {} method", _0)]
     ArgumentMismatch(&'static str, #[source] dbus::arg::TypeMismatchError),
 
@@ -116,8 +299,55 @@ pub enum Error {
     #[error("daemon status integer was outside the acceptable range of values")]
     DaemonStatusOutOfRange,
 
+    #[error("the worker which owns the dbus connection is not connected")]
+    Disconnected,
+
+    #[error("end of life status integer was outside the acceptable range of values")]
+    EolStatusOutOfRange,
+
     #[error("failed to create {} method call", _0)]
     NewMethodCall(&'static str, String),
+
+    #[error("preflight status integer was outside the acceptable range of values")]
+    PreflightStatusOutOfRange,
+
+    #[error("failed to get {} property", _0)]
+    Property(&'static str, #[source] dbus::Error),
+
+    #[error("failed to set {} property", _0)]
+    SetProperty(&'static str, #[source] dbus::Error),
+}
+
+impl Error {
+    /// Whether the daemon refused to upgrade because the system is on a low battery.
+    pub fn is_low_battery(&self) -> bool { self.is_named(crate::power::LOW_BATTERY_ERROR) }
+
+    /// Whether the daemon refused to upgrade because a captive portal intercepts requests, and
+    /// the user must log into the network before upgrading.
+    pub fn is_captive_portal(&self) -> bool { self.is_named(crate::network::CAPTIVE_PORTAL_ERROR) }
+
+    /// Whether the daemon refused to upgrade because the upgrade servers are unreachable.
+    pub fn is_no_connection(&self) -> bool { self.is_named(crate::network::NO_CONNECTION_ERROR) }
+
+    /// Whether another upgrade operation, possibly of another process, is already in progress.
+    pub fn is_busy(&self) -> bool { self.is_named(crate::lock::BUSY_ERROR) }
+
+    /// Whether the daemon deferred the download because the connection is metered.
+    pub fn is_metered(&self) -> bool { self.is_named(crate::network::METERED_ERROR) }
+
+    /// Whether polkit denied the action, such as when the user dismissed the authentication.
+    pub fn is_not_authorized(&self) -> bool { self.is_named(crate::polkit::NOT_AUTHORIZED_ERROR) }
+
+    /// Whether the daemon refused the upgrade because the upgrade policy of this managed machine
+    /// does not permit it, or could not be verified.
+    pub fn is_policy(&self) -> bool { self.is_named(crate::policy::POLICY_ERROR) }
+
+    fn is_named(&self, name: &str) -> bool {
+        match self {
+            Error::Call(_, why) | Error::SetProperty(_, why) => why.name() == Some(name),
+            _ => false,
+        }
+    }
 }
 
 pub struct Client {
@@ -138,16 +368,24 @@ impl Client {
             {
                 let bus = &bus;
                 add_match(bus, signals::NO_CONNECTION)?;
+                add_match(bus, signals::PACKAGE_FETCH_PROGRESS)?;
                 add_match(bus, signals::PACKAGE_FETCH_RESULT)?;
                 add_match(bus, signals::PACKAGE_FETCHED)?;
                 add_match(bus, signals::PACKAGE_FETCHING)?;
+                add_match(bus, signals::PACKAGE_LOCK_WAIT)?;
                 add_match(bus, signals::PACKAGE_UPGRADE)?;
+                add_match(bus, signals::PACKAGE_UPGRADE_PROGRESS)?;
+                add_match(bus, signals::PACKAGE_UPGRADE_STAGE)?;
                 add_match(bus, signals::RECOVERY_DOWNLOAD_PROGRESS)?;
                 add_match(bus, signals::RECOVERY_RESULT)?;
+                add_match(bus, signals::RECOVERY_SYNC_PROGRESS)?;
+                add_match(bus, signals::RECOVERY_VERIFY_PROGRESS)?;
                 add_match(bus, signals::RECOVERY_EVENT)?;
                 add_match(bus, signals::RELEASE_RESULT)?;
                 add_match(bus, signals::RELEASE_EVENT)?;
+                add_match(bus, signals::RELEASE_PROGRESS)?;
                 add_match(bus, signals::REPO_COMPAT_ERROR)?;
+                add_match(bus, signals::STATUS_CHANGED)?;
             }
 
             Ok(Client { bus })
@@ -160,13 +398,137 @@ impl Client {
         Ok(())
     }
 
-    /// Dismiss future desktop notifications for the currently-available upgrade.
+    /// Dismiss future desktop notifications for the currently-available upgrade. The calling user
+    /// is no longer notified of it, and neither is anyone else if it is an LTS release.
     pub fn dismiss_notification(&self, event: DismissEvent) -> Result<bool, Error> {
         self.call_method(methods::DISMISS_NOTIFICATION, |m| m.append1(event as u8))?
             .read1::<bool>()
             .map_err(|why| Error::ArgumentMismatch(methods::DISMISS_NOTIFICATION, why))
     }
 
+    /// Whether the calling user has dismissed or snoozed the notification of the next release.
+    pub fn notification_preferences(&self) -> Result<NotificationPreferences, Error> {
+        // Daemons which predate the preferences have no record of them.
+        if !self.supports(capabilities::NOTIFICATION_PREFERENCES)? {
+            return Ok(NotificationPreferences::default());
+        }
+
+        let (dismissed, snoozed_until) = self
+            .call_method(methods::NOTIFICATION_PREFERENCES, |m| m)?
+            .read2::<&str, u64>()
+            .map_err(|why| Error::ArgumentMismatch(methods::NOTIFICATION_PREFERENCES, why))?;
+
+        Ok(NotificationPreferences {
+            dismissed:     if dismissed.is_empty() { None } else { Some(dismissed.into()) },
+            snoozed_until: if snoozed_until == 0 { None } else { Some(snoozed_until) },
+        })
+    }
+
+    /// Stops notifying the calling user of new releases until the given time, in seconds since
+    /// the Unix epoch, or resumes if `None`.
+    pub fn notification_snooze(&self, until: Option<u64>) -> Result<(), Error> {
+        self.call_method(methods::NOTIFICATION_SNOOZE, |m| m.append1(until.unwrap_or(0)))?;
+        Ok(())
+    }
+
+    /// Whether upgrades to development releases are allowed, which release checks honor.
+    pub fn development_releases(&self) -> Result<bool, Error> {
+        self.bus
+            .with_path(DBUS_NAME, DBUS_PATH, TIMEOUT)
+            .get::<bool>(DBUS_IFACE, properties::DEVELOPMENT_RELEASES)
+            .map_err(|why| Error::Property(properties::DEVELOPMENT_RELEASES, why))
+    }
+
+    /// Allows or disallows upgrades to development releases, which requires authorization.
+    pub fn set_development_releases(&self, enabled: bool) -> Result<(), Error> {
+        self.bus
+            .with_path(DBUS_NAME, DBUS_PATH, TIMEOUT)
+            .set(DBUS_IFACE, properties::DEVELOPMENT_RELEASES, enabled)
+            .map_err(|why| Error::SetProperty(properties::DEVELOPMENT_RELEASES, why))
+    }
+
+    /// Fetches when the current release stops receiving updates.
+    pub fn end_of_life(&self) -> Result<EndOfLife, Error> {
+        let (status, date) = self
+            .bus
+            .with_path(DBUS_NAME, DBUS_PATH, TIMEOUT)
+            .get::<(u8, String)>(DBUS_IFACE, properties::END_OF_LIFE)
+            .map_err(|why| Error::Property(properties::END_OF_LIFE, why))?;
+
+        let status = EolStatus::from_u8(status).ok_or(Error::EolStatusOutOfRange)?;
+
+        Ok(EndOfLife { status, date: date.into() })
+    }
+
+    /// Why the daemon would defer downloading an upgrade, such as the connection being metered,
+    /// or `None` if it would not.
+    pub fn download_deferred(&self) -> Result<Option<Box<str>>, Error> {
+        let why = self
+            .bus
+            .with_path(DBUS_NAME, DBUS_PATH, TIMEOUT)
+            .get::<String>(DBUS_IFACE, properties::DOWNLOAD_DEFERRED)
+            .map_err(|why| Error::Property(properties::DOWNLOAD_DEFERRED, why))?;
+
+        Ok(if why.is_empty() { None } else { Some(why.into()) })
+    }
+
+    /// The version of the daemon's DBus interface, which is zero for daemons that predate it.
+    pub fn api_version(&self) -> Result<u32, Error> {
+        self.optional_property::<u32>(properties::API_VERSION).map(|version| version.unwrap_or(0))
+    }
+
+    /// The features which the daemon supports, which are listed in `daemon::capabilities`. Daemons
+    /// which predate the property support none of them.
+    pub fn capabilities(&self) -> Result<Vec<Box<str>>, Error> {
+        self.optional_property::<Vec<String>>(properties::CAPABILITIES).map(|capabilities| {
+            capabilities.unwrap_or_default().into_iter().map(String::into_boxed_str).collect()
+        })
+    }
+
+    /// Whether the daemon supports a feature, which is one of `daemon::capabilities`.
+    pub fn supports(&self, capability: &str) -> Result<bool, Error> {
+        Ok(self.capabilities()?.iter().any(|supported| &**supported == capability))
+    }
+
+    /// Reads a property which older daemons may lack, in which case `None` is returned.
+    fn optional_property<T>(&self, property: &'static str) -> Result<Option<T>, Error>
+    where
+        T: for<'b> dbus::arg::Get<'b> + 'static,
+    {
+        const UNKNOWN: &[&str] = &[
+            "org.freedesktop.DBus.Error.UnknownProperty",
+            "org.freedesktop.DBus.Error.InvalidArgs",
+        ];
+
+        match self.bus.with_path(DBUS_NAME, DBUS_PATH, TIMEOUT).get::<T>(DBUS_IFACE, property) {
+            Ok(value) => Ok(Some(value)),
+            Err(why) if why.name().map_or(false, |name| UNKNOWN.contains(&name)) => Ok(None),
+            Err(why) => Err(Error::Property(property, why)),
+        }
+    }
+
+    /// The release upgrade which was interrupted, and may be resumed, if there is one.
+    pub fn interrupted_upgrade(&self) -> Result<Option<InterruptedUpgrade>, Error> {
+        let (from, to, phase) = self
+            .bus
+            .with_path(DBUS_NAME, DBUS_PATH, TIMEOUT)
+            .get::<(String, String, u8)>(DBUS_IFACE, properties::INTERRUPTED_UPGRADE)
+            .map_err(|why| Error::Property(properties::INTERRUPTED_UPGRADE, why))?;
+
+        if from.is_empty() {
+            return Ok(None);
+        }
+
+        let phase = Phase::from_u8(phase);
+        Ok(Some(InterruptedUpgrade { from: from.into(), to: to.into(), phase }))
+    }
+
+    /// Whether upgrades are restricted by the signed policy of a managed machine. Daemons which
+    /// predate managed mode are never managed.
+    pub fn managed(&self) -> Result<bool, Error> {
+        self.optional_property::<bool>(properties::MANAGED).map(|managed| managed.unwrap_or(false))
+    }
+
     /// Initiates fetching system updates (not release updates).
     ///
     /// By default, the system is updated once updates have been fetched. This
@@ -210,6 +572,20 @@ impl Client {
         Ok(())
     }
 
+    /// Checks whether the system is ready to be upgraded.
+    pub fn preflight_check(&self) -> Result<Vec<PreflightCheck>, Error> {
+        self.call_method(methods::PREFLIGHT_CHECK, |m| m)?
+            .read1::<Vec<(&str, u8, &str)>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::PREFLIGHT_CHECK, why))?
+            .into_iter()
+            .map(|(name, status, message)| {
+                let status =
+                    PreflightStatus::from_u8(status).ok_or(Error::PreflightStatusOutOfRange)?;
+                Ok(PreflightCheck { name: name.into(), status, message: message.into() })
+            })
+            .collect()
+    }
+
     /// Initiates upgrading the recovery partition via a recovery image file.
     pub fn recovery_upgrade_file<P: AsRef<str>>(&self, path: P) -> Result<u8, Error> {
         self.call_method(methods::RECOVERY_UPGRADE_FILE, move |m| m.append1(path.as_ref()))?
@@ -255,23 +631,153 @@ impl Client {
 
     /// Check the current release information
     ///
-    /// Used to determine if a release upgrade is available.
-    pub fn release_check(&self, development: bool) -> Result<ReleaseInfo, Error> {
-        self.call_method(methods::RELEASE_CHECK, |m| m.append1(development))?
-            .read5::<&str, &str, i16, i16, bool>()
+    /// Used to determine if a release upgrade is available. If `force` is set, a release which
+    /// is being phased in will be offered even if it has not been rolled out to this system.
+    pub fn release_check(&self, development: bool, force: bool) -> Result<ReleaseInfo, Error> {
+        self.call_method(methods::RELEASE_CHECK, |m| m.append2(development, force))?
+            .read_all::<(&str, &str, i16, i16, bool, u64, &str)>()
             .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_CHECK, why))
-            .map(|(current, next, build, urgent, is_lts)| ReleaseInfo {
+            .map(|(current, next, build, urgent, is_lts, size, release_notes)| ReleaseInfo {
                 current: current.into(),
                 next: next.into(),
                 build,
                 urgent: if urgent > -1 { Some(urgent as u16) } else { None },
                 is_lts,
+                size,
+                release_notes: if release_notes.is_empty() {
+                    None
+                } else {
+                    Some(release_notes.into())
+                },
             })
     }
 
+    /// Fetches the release notes of a release, as markdown.
+    ///
+    /// If `version` is empty, the notes of the next release are fetched.
+    pub fn release_notes(&self, version: &str) -> Result<Box<str>, Error> {
+        self.call_method(methods::RELEASE_NOTES, |m| m.append1(version))?
+            .read1::<&str>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_NOTES, why))
+            .map(Box::from)
+    }
+
+    /// Plans the releases to upgrade through to get from one release to another.
+    ///
+    /// If `from` is empty, the plan starts from the current release.
+    pub fn release_path(&self, from: &str, to: &str) -> Result<Vec<Box<str>>, Error> {
+        self.call_method(methods::RELEASE_PATH, |m| m.append2(from, to))?
+            .read1::<Vec<&str>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_PATH, why))
+            .map(|path| path.into_iter().map(Box::from).collect())
+    }
+
+    /// Simulates a release upgrade, to find the packages it would remove or downgrade.
+    ///
+    /// If `version` is empty, the upgrade to the next release is previewed.
+    pub fn release_preview(&self, version: &str) -> Result<Preview, Error> {
+        self.call_method(methods::RELEASE_PREVIEW, |m| m.append1(version))?
+            .read2::<Vec<&str>, Vec<&str>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_PREVIEW, why))
+            .map(|(removed, downgraded)| Preview {
+                removed:    removed.into_iter().map(Box::from).collect(),
+                downgraded: downgraded.into_iter().map(Box::from).collect(),
+            })
+    }
+
+    /// The operations which the daemon has performed, oldest first.
+    pub fn history(&self) -> Result<Vec<HistoryEntry>, Error> {
+        let optional = |value: &str| if value.is_empty() { None } else { Some(Box::from(value)) };
+
+        self.call_method(methods::HISTORY, |m| m)?
+            .read1::<Vec<(&str, u64, u64, &str, &str, &str, &str)>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::HISTORY, why))
+            .map(|entries| {
+                entries
+                    .into_iter()
+                    .map(|(operation, started, ended, from, to, client, error)| HistoryEntry {
+                        operation: operation.into(),
+                        started,
+                        ended,
+                        from:      optional(from),
+                        to:        optional(to),
+                        client:    optional(client),
+                        error:     optional(error),
+                    })
+                    .collect()
+            })
+    }
+
+    /// Simulates a release upgrade against a temporary copy of the sources of the new release, to
+    /// find the packages it would install, upgrade, remove, or downgrade, and the bytes it would
+    /// download. The state of apt on the system is untouched.
+    ///
+    /// If `version` is empty, the upgrade to the next release is simulated.
+    pub fn release_simulate(&self, version: &str) -> Result<Simulation, Error> {
+        let boxed = |packages: Vec<&str>| -> Vec<Box<str>> {
+            packages.into_iter().map(Box::from).collect()
+        };
+
+        self.call_method(methods::RELEASE_SIMULATE, |m| m.append1(version))?
+            .read5::<Vec<&str>, Vec<&str>, Vec<&str>, Vec<&str>, u64>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_SIMULATE, why))
+            .map(|(installed, upgraded, removed, downgraded, download_size)| Simulation {
+                installed:  boxed(installed),
+                upgraded:   boxed(upgraded),
+                removed:    boxed(removed),
+                downgraded: boxed(downgraded),
+                download_size,
+            })
+    }
+
+    /// Packages which are held, and would prevent a release upgrade.
+    pub fn held_packages(&self) -> Result<Vec<Box<str>>, Error> {
+        self.call_method(methods::HELD_PACKAGES, |m| m)?
+            .read1::<Vec<&str>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::HELD_PACKAGES, why))
+            .map(|packages| packages.into_iter().map(Box::from).collect())
+    }
+
     /// Initiates a release upgrade using the given method.
-    pub fn release_upgrade(&self, how: UpgradeMethod, from: &str, to: &str) -> Result<(), Error> {
-        self.call_method(methods::RELEASE_UPGRADE, move |m| m.append3(how as u8, from, to))?;
+    ///
+    /// If `lift_holds` is set, holds on packages are lifted for the duration of the upgrade.
+    /// Otherwise, the upgrade fails if any packages are held.
+    ///
+    /// Conflicts with modified conffiles are resolved by `conffiles`, or by the daemon's
+    /// configured policy if it is `None`.
+    ///
+    /// Unless `UpgradeFlags::IGNORE_BATTERY` is set, the daemon refuses to upgrade on a low
+    /// battery, which `Error::is_low_battery` identifies. Downloads are refused if the upgrade
+    /// servers are unreachable, or if a captive portal intercepts them, which
+    /// `Error::is_no_connection` and `Error::is_captive_portal` identify.
+    ///
+    /// Unless `UpgradeFlags::ALLOW_METERED` is set, or the configuration allows it, the download
+    /// is deferred while the connection is metered, which `Error::is_metered` identifies.
+    pub fn release_upgrade(
+        &self,
+        how: UpgradeMethod,
+        from: &str,
+        to: &str,
+        lift_holds: bool,
+        conffiles: Option<ConffilePolicy>,
+        flags: UpgradeFlags,
+    ) -> Result<(), Error> {
+        let conffiles = conffiles.map_or(0, |policy| policy as u8);
+
+        // Daemons which predate the options are only told which release to upgrade to.
+        if !self.supports(capabilities::RELEASE_UPGRADE_OPTIONS)? {
+            self.call_method(methods::RELEASE_UPGRADE, move |m| m.append3(how as u8, from, to))?;
+            return Ok(());
+        }
+
+        let mut options = PropMap::new();
+        options.insert("lift-holds".into(), Variant(Box::new(lift_holds)));
+        options.insert("conffiles".into(), Variant(Box::new(conffiles)));
+        options.insert("flags".into(), Variant(Box::new(flags.bits())));
+
+        self.call_method(methods::RELEASE_UPGRADE_WITH_OPTIONS, move |m| {
+            m.append3(how as u8, from, to).append1(options)
+        })?;
 
         Ok(())
     }
@@ -289,10 +795,82 @@ impl Client {
             .map(|(status, why)| Status { status, why: why.into() })
     }
 
-    /// Attempts to repair any system issues detected.
-    pub fn release_repair(&self) -> Result<(), Error> {
-        self.call_method(methods::RELEASE_REPAIR, |m| m)?;
-        Ok(())
+    /// Attempts to repair any system issues detected, returning a description of each source and
+    /// package that was repaired.
+    pub fn release_repair(&self) -> Result<Vec<Box<str>>, Error> {
+        self.call_method(methods::RELEASE_REPAIR, |m| m)?
+            .read1::<Vec<&str>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_REPAIR, why))
+            .map(|repaired| repaired.into_iter().map(Box::from).collect())
+    }
+
+    /// Removes the files which pop-upgrade leaves behind, returning the space reclaimed from
+    /// each category of files.
+    pub fn clean(&self) -> Result<Vec<CleanedCategory>, Error> {
+        self.call_method(methods::CLEAN, |m| m)?
+            .read1::<Vec<(&str, u32, u64)>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::CLEAN, why))
+            .map(|cleaned| {
+                cleaned
+                    .into_iter()
+                    .map(|(category, files, bytes)| CleanedCategory {
+                        category: category.into(),
+                        files,
+                        bytes,
+                    })
+                    .collect()
+            })
+    }
+
+    /// Removes every kernel except for the running kernel and the `keep` most recent kernels.
+    /// Returns the kept and removed kernels, and the loader entries which were removed.
+    pub fn clean_kernels(&self, keep: u32, dry_run: bool) -> Result<CleanedKernels, Error> {
+        self.call_method(methods::CLEAN_KERNELS, |m| m.append2(keep, dry_run))?
+            .read3::<Vec<&str>, Vec<&str>, Vec<&str>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::CLEAN_KERNELS, why))
+            .map(|(kept, removed, entries)| {
+                let boxed = |list: Vec<&str>| list.into_iter().map(Box::from).collect();
+                CleanedKernels {
+                    kept:    boxed(kept),
+                    removed: boxed(removed),
+                    entries: boxed(entries),
+                }
+            })
+    }
+
+    /// Removes packages which are no longer required, and clears the package cache, returning
+    /// the packages which were removed and the number of bytes which were freed.
+    pub fn release_reclaim(&self) -> Result<(Vec<Box<str>>, u64), Error> {
+        self.call_method(methods::RELEASE_RECLAIM, |m| m)?
+            .read2::<Vec<&str>, u64>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_RECLAIM, why))
+            .map(|(removed, bytes)| (removed.into_iter().map(Box::from).collect(), bytes))
+    }
+
+    /// Resumes a release upgrade which was interrupted, returning the releases it is between.
+    pub fn release_resume(&self) -> Result<(Box<str>, Box<str>), Error> {
+        self.call_method(methods::RELEASE_RESUME, |m| m)?
+            .read2::<&str, &str>()
+            .map_err(|why| Error::ArgumentMismatch(methods::RELEASE_RESUME, why))
+            .map(|(from, to)| (from.into(), to.into()))
+    }
+
+    /// Checks which third party sources do not publish a release.
+    ///
+    /// If `version` is empty, the sources are checked against the next release.
+    pub fn repo_compatibility(&self, version: &str) -> Result<Vec<IncompatibleRepo>, Error> {
+        self.call_method(methods::REPO_COMPATIBILITY, |m| m.append1(version))?
+            .read1::<Vec<(&str, Vec<&str>)>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::REPO_COMPATIBILITY, why))
+            .map(|repos| {
+                repos
+                    .into_iter()
+                    .map(|(path, uris)| IncompatibleRepo {
+                        path: path.into(),
+                        uris: uris.into_iter().map(Box::from).collect(),
+                    })
+                    .collect()
+            })
     }
 
     /// Reset the daemon to its initial state, and clean up any changes.
@@ -301,6 +879,63 @@ impl Client {
         Ok(())
     }
 
+    /// Schedules an upgrade to the next release, to begin at the given time in seconds since the
+    /// Unix epoch.
+    pub fn schedule(&self, timestamp: u64) -> Result<(), Error> {
+        self.call_method(methods::SCHEDULE, |m| m.append1(timestamp))?;
+        Ok(())
+    }
+
+    /// Cancels the scheduled release upgrade, returning whether one was scheduled.
+    pub fn schedule_cancel(&self) -> Result<bool, Error> {
+        self.call_method(methods::SCHEDULE_CANCEL, |m| m)?
+            .read1::<bool>()
+            .map_err(|why| Error::ArgumentMismatch(methods::SCHEDULE_CANCEL, why))
+    }
+
+    /// When the scheduled release upgrade begins, in seconds since the Unix epoch, if one is.
+    pub fn scheduled(&self) -> Result<Option<u64>, Error> {
+        let timestamp = self
+            .bus
+            .with_path(DBUS_NAME, DBUS_PATH, TIMEOUT)
+            .get::<u64>(DBUS_IFACE, properties::SCHEDULED)
+            .map_err(|why| Error::Property(properties::SCHEDULED, why))?;
+
+        Ok(if timestamp == 0 { None } else { Some(timestamp) })
+    }
+
+    /// Snapshots of the root filesystem which were taken by pop-upgrade, oldest first.
+    pub fn snapshot_list(&self) -> Result<Vec<Snapshot>, Error> {
+        self.call_method(methods::SNAPSHOT_LIST, |m| m)?
+            .read1::<Vec<(&str, &str, bool)>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::SNAPSHOT_LIST, why))
+            .map(|snapshots| {
+                snapshots
+                    .into_iter()
+                    .map(|(backend, name, pre_upgrade)| Snapshot {
+                        backend: backend.into(),
+                        name: name.into(),
+                        pre_upgrade,
+                    })
+                    .collect()
+            })
+    }
+
+    /// Restores the root filesystem to a snapshot, returning the backend which restored it.
+    pub fn snapshot_restore(&self, name: &str) -> Result<Box<str>, Error> {
+        self.call_method(methods::SNAPSHOT_RESTORE, |m| m.append1(name))?
+            .read1::<&str>()
+            .map_err(|why| Error::ArgumentMismatch(methods::SNAPSHOT_RESTORE, why))
+            .map(Box::from)
+    }
+
+    /// Stages the updates fetched by a download-only `fetch_updates` to be installed by
+    /// systemd on the next boot, instead of on the running system.
+    pub fn stage_offline_update(&self) -> Result<(), Error> {
+        self.call_method(methods::STAGE_OFFLINE_UPDATE, |m| m)?;
+        Ok(())
+    }
+
     /// Retrieves the status of the daemon.
     pub fn status(&self) -> Result<DaemonStatus, Error> {
         self.call_method(methods::STATUS, |m| m)?
@@ -309,6 +944,52 @@ impl Client {
             .map(|(status, sub_status)| DaemonStatus { status, sub_status })
     }
 
+    /// The releases which upgrading from the current release would pass through, beginning with
+    /// the current release.
+    pub fn upgrade_path(&self) -> Result<Vec<UpgradeStop>, Error> {
+        self.call_method(methods::UPGRADE_PATH, |m| m)?
+            .read1::<Vec<(&str, bool, u8)>>()
+            .map_err(|why| Error::ArgumentMismatch(methods::UPGRADE_PATH, why))?
+            .into_iter()
+            .map(|(version, lts, upgrade)| {
+                let upgrade = Availability::from_u8(upgrade).ok_or(Error::AvailabilityOutOfRange)?;
+                Ok(UpgradeStop { version: version.into(), lts, upgrade })
+            })
+            .collect()
+    }
+
+    /// Retrieves the status of the daemon, with the progress and initiator of the operation in
+    /// progress, and the error of the last operation which failed.
+    pub fn status_details(&self) -> Result<StatusDetails, Error> {
+        let non_empty = |value: &str| -> Option<Box<str>> {
+            if value.is_empty() {
+                None
+            } else {
+                Some(value.into())
+            }
+        };
+
+        // Daemons which predate the details only report the status.
+        if !self.supports(capabilities::STATUS_DETAILS)? {
+            return self.status().map(|status| StatusDetails {
+                status,
+                progress: 0,
+                client: None,
+                last_error: None,
+            });
+        }
+
+        self.call_method(methods::STATUS_DETAILS, |m| m)?
+            .read5::<u8, u8, u8, &str, &str>()
+            .map_err(|why| Error::ArgumentMismatch(methods::STATUS_DETAILS, why))
+            .map(|(status, sub_status, progress, client, last_error)| StatusDetails {
+                status: DaemonStatus { status, sub_status },
+                progress,
+                client: non_empty(client),
+                last_error: non_empty(last_error),
+            })
+    }
+
     pub fn update_and_restart(&self) -> Result<bool, Error> {
         self.call_method(methods::UPDATE_CHECK, |m| m)?
             .read1::<u8>()
@@ -316,6 +997,21 @@ impl Client {
             .map(|v| v == 1)
     }
 
+    /// Checks if a newer version of pop-upgrade is available, without installing it.
+    pub fn self_update_check(&self) -> Result<Option<SelfUpdate>, Error> {
+        let (installed, candidate) = self
+            .call_method(methods::SELF_UPDATE_CHECK, |m| m)?
+            .read2::<&str, &str>()
+            .map_err(|why| Error::ArgumentMismatch(methods::SELF_UPDATE_CHECK, why))?;
+
+        // The daemon only reports a candidate which is newer than the installed version.
+        if candidate.is_empty() || candidate == "(none)" {
+            return Ok(None);
+        }
+
+        Ok(Some(SelfUpdate { installed: installed.into(), candidate: candidate.into() }))
+    }
+
     /// Verifies if a recovery partition exists.
     pub fn recovery_exists(&self) -> bool { crate::recovery::recovery_exists().unwrap_or(false) }
 
@@ -327,6 +1023,14 @@ impl Client {
         mut log_cb: impl FnMut(Status),
         mut event: impl FnMut(&Self, Signal) -> Result<Continue, Error>,
     ) -> Result<(), Error> {
+        // The daemon signals each change of its status, after the task may have already completed.
+        if !self.status_is(expected_status)? {
+            log_cb(status_func(self)?);
+            return Ok(());
+        }
+
+        // The status is also polled whenever the bus is idle, in case the daemon exits or
+        // restarts without signalling that its status changed.
         let mut break_on_next = false;
         for item in self.bus.iter(3000) {
             if sighandler::status().is_some() {
@@ -337,79 +1041,24 @@ impl Client {
                 if !self.status_is(expected_status)? {
                     if break_on_next {
                         log_cb(status_func(self)?);
-
                         break;
                     }
 
                     break_on_next = true;
                 }
             } else if let Some(signal) = filter_signal(item) {
-                let signal = match &*signal.member().unwrap() {
-                    signals::NO_CONNECTION => Signal::NoConnection,
-                    signals::PACKAGE_FETCH_RESULT => signal
-                        .read2::<u8, String>()
-                        .map(|(status, why)| Status { status, why: why.into() })
-                        .map(Signal::PackageFetchResult)
-                        .map_err(|why| {
-                            Error::ArgumentMismatch(signals::PACKAGE_FETCH_RESULT, why)
-                        })?,
-                    signals::PACKAGE_FETCHED => signal
-                        .read3::<String, u32, u32>()
-                        .map(|(package, completed, total)| FetchStatus {
-                            package: package.into(),
-                            completed,
-                            total,
-                        })
-                        .map(Signal::PackageFetched)
-                        .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_FETCHED, why))?,
-                    signals::PACKAGE_FETCHING => signal
-                        .read1::<String>()
-                        .map(|package| Signal::PackageFetching(Box::from(package)))
-                        .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_FETCHING, why))?,
-                    signals::PACKAGE_UPGRADE => signal
-                        .read1::<HashMap<String, String>>()
-                        .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_UPGRADE, why))
-                        .map(|upgrade| {
-                            upgrade
-                                .into_iter()
-                                .map(|(key, value)| (Box::from(key), Box::from(value)))
-                                .collect::<HashMap<Box<str>, Box<str>>>()
-                        })
-                        .map(Signal::PackageUpgrade)?,
-                    signals::RECOVERY_DOWNLOAD_PROGRESS => signal
-                        .read2::<u64, u64>()
-                        .map_err(|why| {
-                            Error::ArgumentMismatch(signals::RECOVERY_DOWNLOAD_PROGRESS, why)
-                        })
-                        .map(|(progress, total)| Progress { progress, total })
-                        .map(Signal::RecoveryDownloadProgress)?,
-                    signals::RECOVERY_EVENT => signal
-                        .read1::<u8>()
-                        .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_EVENT, why))
-                        .map(|event| {
-                            RecoveryEvent::from_u8(event).expect("unexpected recovery event value")
-                        })
-                        .map(Signal::RecoveryEvent)?,
-                    signals::RECOVERY_RESULT => signal
-                        .read2::<u8, String>()
-                        .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_RESULT, why))
-                        .map(|(status, why)| Status { status, why: why.into() })
-                        .map(Signal::RecoveryResult)?,
-                    signals::RELEASE_EVENT => signal
-                        .read1::<u8>()
-                        .map_err(|why| Error::ArgumentMismatch(signals::RELEASE_EVENT, why))
-                        .map(|event| {
-                            UpgradeEvent::from_u8(event).expect("unexpected upgrade event value")
-                        })
-                        .map(Signal::ReleaseEvent)?,
-                    signals::RELEASE_RESULT => signal
-                        .read2::<u8, String>()
-                        .map_err(|why| Error::ArgumentMismatch(signals::RELEASE_RESULT, why))
-                        .map(|(status, why)| Status { status, why: why.into() })
-                        .map(Signal::ReleaseResult)?,
-                    _ => continue,
+                let signal = match parse_signal(&signal)? {
+                    Some(signal) => signal,
+                    None => continue,
                 };
 
+                if let Signal::StatusChanged(ref status) = signal {
+                    if status.status != expected_status as u8 {
+                        log_cb(status_func(self)?);
+                        break;
+                    }
+                }
+
                 if !event(self, signal)?.0 {
                     break;
                 }
@@ -446,3 +1095,133 @@ fn filter_signal(ci: ConnectionItem) -> Option<Message> {
         None
     }
 }
+
+/// Reads a signal of the daemon, or returns `None` if the signal is not one which clients handle.
+fn parse_signal(signal: &Message) -> Result<Option<Signal>, Error> {
+    let member = match signal.member() {
+        Some(member) => member,
+        None => return Ok(None),
+    };
+
+    let signal = match &*member {
+        signals::NO_CONNECTION => Signal::NoConnection,
+        signals::PACKAGE_FETCH_PROGRESS => signal
+            .read5::<String, u64, u64, u64, u64>()
+            .map(|(package, fetched, size, total_fetched, total)| FetchProgress {
+                package: package.into(),
+                fetched,
+                size,
+                total_fetched,
+                total,
+            })
+            .map(Signal::PackageFetchProgress)
+            .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_FETCH_PROGRESS, why))?,
+        signals::PACKAGE_FETCH_RESULT => signal
+            .read2::<u8, String>()
+            .map(|(status, why)| Status { status, why: why.into() })
+            .map(Signal::PackageFetchResult)
+            .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_FETCH_RESULT, why))?,
+        signals::PACKAGE_FETCHED => signal
+            .read3::<String, u32, u32>()
+            .map(|(package, completed, total)| FetchStatus {
+                package: package.into(),
+                completed,
+                total,
+            })
+            .map(Signal::PackageFetched)
+            .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_FETCHED, why))?,
+        signals::PACKAGE_FETCHING => signal
+            .read1::<String>()
+            .map(|package| Signal::PackageFetching(Box::from(package)))
+            .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_FETCHING, why))?,
+        signals::PACKAGE_LOCK_WAIT => signal
+            .read2::<u32, String>()
+            .map(|(pid, name)| Signal::PackageLockWait(LockHolder { pid, name }))
+            .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_LOCK_WAIT, why))?,
+        signals::PACKAGE_UPGRADE => signal
+            .read1::<HashMap<String, String>>()
+            .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_UPGRADE, why))
+            .map(|upgrade| {
+                upgrade
+                    .into_iter()
+                    .map(|(key, value)| (Box::from(key), Box::from(value)))
+                    .collect::<HashMap<Box<str>, Box<str>>>()
+            })
+            .map(Signal::PackageUpgrade)?,
+        signals::PACKAGE_UPGRADE_PROGRESS => signal
+            .read1::<u8>()
+            .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_UPGRADE_PROGRESS, why))
+            .map(Signal::PackageUpgradeProgress)?,
+        signals::PACKAGE_UPGRADE_STAGE => {
+            let (stage, package) = signal
+                .read2::<u8, String>()
+                .map_err(|why| Error::ArgumentMismatch(signals::PACKAGE_UPGRADE_STAGE, why))?;
+
+            match signals::AptStage::from_u8(stage) {
+                Some(stage) => Signal::PackageUpgradeStage(stage, package.into()),
+                None => return Ok(unknown(signals::PACKAGE_UPGRADE_STAGE, stage)),
+            }
+        }
+        signals::RECOVERY_DOWNLOAD_PROGRESS => signal
+            .read2::<u64, u64>()
+            .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_DOWNLOAD_PROGRESS, why))
+            .map(|(progress, total)| Progress { progress, total })
+            .map(Signal::RecoveryDownloadProgress)?,
+        signals::RECOVERY_EVENT => {
+            let event = signal
+                .read1::<u8>()
+                .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_EVENT, why))?;
+
+            match RecoveryEvent::from_u8(event) {
+                Some(event) => Signal::RecoveryEvent(event),
+                None => return Ok(unknown(signals::RECOVERY_EVENT, event)),
+            }
+        }
+        signals::RECOVERY_RESULT => signal
+            .read2::<u8, String>()
+            .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_RESULT, why))
+            .map(|(status, why)| Status { status, why: why.into() })
+            .map(Signal::RecoveryResult)?,
+        signals::RECOVERY_SYNC_PROGRESS => signal
+            .read1::<u8>()
+            .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_SYNC_PROGRESS, why))
+            .map(Signal::RecoverySyncProgress)?,
+        signals::RECOVERY_VERIFY_PROGRESS => signal
+            .read1::<u8>()
+            .map_err(|why| Error::ArgumentMismatch(signals::RECOVERY_VERIFY_PROGRESS, why))
+            .map(Signal::RecoveryVerifyProgress)?,
+        signals::RELEASE_EVENT => {
+            let event = signal
+                .read1::<u8>()
+                .map_err(|why| Error::ArgumentMismatch(signals::RELEASE_EVENT, why))?;
+
+            match UpgradeEvent::from_u8(event) {
+                Some(event) => Signal::ReleaseEvent(event),
+                None => return Ok(unknown(signals::RELEASE_EVENT, event)),
+            }
+        }
+        signals::RELEASE_PROGRESS => signal
+            .read1::<u8>()
+            .map_err(|why| Error::ArgumentMismatch(signals::RELEASE_PROGRESS, why))
+            .map(Signal::ReleaseProgress)?,
+        signals::RELEASE_RESULT => signal
+            .read2::<u8, String>()
+            .map_err(|why| Error::ArgumentMismatch(signals::RELEASE_RESULT, why))
+            .map(|(status, why)| Status { status, why: why.into() })
+            .map(Signal::ReleaseResult)?,
+        signals::STATUS_CHANGED => signal
+            .read2::<u8, u8>()
+            .map_err(|why| Error::ArgumentMismatch(signals::STATUS_CHANGED, why))
+            .map(|(status, sub_status)| DaemonStatus { status, sub_status })
+            .map(Signal::StatusChanged)?,
+        _ => return Ok(None),
+    };
+
+    Ok(Some(signal))
+}
+
+/// Skips a signal carrying a value this client does not know, such as from a newer daemon.
+fn unknown(signal: &str, value: u8) -> Option<Signal> {
+    warn!("skipping {} signal with unknown value {}", signal, value);
+    None
+}
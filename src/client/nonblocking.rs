@@ -0,0 +1,209 @@
+//! A client of the daemon for async frontends, whichever executor they run on.
+//!
+//! The DBus connection is owned by a worker thread, which performs each method call that is
+//! requested of it, and forwards the signals of the daemon to every stream which was subscribed.
+//! If the connection to the system bus is lost, the worker reconnects with an increasing delay,
+//! and notifies each stream with `Event::Reconnected`, so that frontends may refresh their state.
+
+use super::{
+    filter_signal, parse_signal, Client, DaemonStatus, Error, ReleaseInfo, Signal, Status,
+    StatusDetails,
+};
+use crate::{
+    config::ConffilePolicy,
+    release::{UpgradeFlags, UpgradeMethod},
+};
+use dbus::ffidisp::ConnectionItem;
+use futures::{channel::oneshot, prelude::*};
+use std::{
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long the worker waits on signals before performing the calls which were requested.
+const POLL: i32 = 50;
+
+const RECONNECT_MIN: Duration = Duration::from_secs(1);
+const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+type Request = Box<dyn FnOnce(Option<&Client>) + Send>;
+
+/// What a stream of the daemon's signals receives.
+#[derive(Debug)]
+pub enum Event {
+    /// The connection to the system bus was lost, and has since been re-established. Signals
+    /// which were emitted in the meantime were missed.
+    Reconnected,
+    Signal(Signal),
+}
+
+/// A handle to the worker which owns the connection to the daemon. The worker exits when the
+/// client is dropped, ending every stream of its signals.
+pub struct AsyncClient {
+    requests:    flume::Sender<Request>,
+    subscribers: Arc<Mutex<Vec<flume::Sender<Event>>>>,
+}
+
+impl AsyncClient {
+    /// Connects to the daemon, from a worker thread which is spawned for the client.
+    pub async fn connect() -> Result<Self, Error> {
+        let (requests, receiver) = flume::unbounded();
+        let subscribers = Arc::new(Mutex::new(Vec::new()));
+        let (connected_tx, connected) = oneshot::channel();
+
+        let worker_subscribers = subscribers.clone();
+        thread::spawn(move || {
+            let client = match Client::new() {
+                Ok(client) => {
+                    let _ = connected_tx.send(Ok(()));
+                    client
+                }
+                Err(why) => {
+                    let _ = connected_tx.send(Err(why));
+                    return;
+                }
+            };
+
+            worker(client, receiver, worker_subscribers);
+        });
+
+        connected.await.map_err(|_| Error::Disconnected)??;
+
+        Ok(Self { requests, subscribers })
+    }
+
+    /// Performs a call of the blocking client on the worker, such as one which this client has
+    /// no method for.
+    pub async fn call<T, F>(&self, func: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Client) -> Result<T, Error> + Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+
+        let request: Request = Box::new(move |client| {
+            let _ = tx.send(client.map_or(Err(Error::Disconnected), func));
+        });
+
+        self.requests.send(request).map_err(|_| Error::Disconnected)?;
+        rx.await.map_err(|_| Error::Disconnected)?
+    }
+
+    /// A stream of the signals of the daemon which are received from now on.
+    pub fn signals(&self) -> impl Stream<Item = Event> {
+        let (tx, rx) = flume::unbounded();
+        self.subscribers.lock().expect("subscribers poisoned").push(tx);
+        rx.into_stream()
+    }
+
+    pub async fn cancel(&self) -> Result<(), Error> { self.call(Client::cancel).await }
+
+    pub async fn release_check(
+        &self,
+        development: bool,
+        force: bool,
+    ) -> Result<ReleaseInfo, Error> {
+        self.call(move |client| client.release_check(development, force)).await
+    }
+
+    pub async fn release_upgrade(
+        &self,
+        how: UpgradeMethod,
+        from: &str,
+        to: &str,
+        lift_holds: bool,
+        conffiles: Option<ConffilePolicy>,
+        flags: UpgradeFlags,
+    ) -> Result<(), Error> {
+        let (from, to) = (String::from(from), String::from(to));
+        self.call(move |client| {
+            client.release_upgrade(how, &from, &to, lift_holds, conffiles, flags)
+        })
+        .await
+    }
+
+    pub async fn release_upgrade_status(&self) -> Result<Status, Error> {
+        self.call(Client::release_upgrade_status).await
+    }
+
+    pub async fn status(&self) -> Result<DaemonStatus, Error> { self.call(Client::status).await }
+
+    pub async fn status_details(&self) -> Result<StatusDetails, Error> {
+        self.call(Client::status_details).await
+    }
+}
+
+fn worker(
+    client: Client,
+    requests: flume::Receiver<Request>,
+    subscribers: Arc<Mutex<Vec<flume::Sender<Event>>>>,
+) {
+    let broadcast = |event: fn() -> Event| {
+        let mut subscribers = subscribers.lock().expect("subscribers poisoned");
+        subscribers.retain(|subscriber| subscriber.send(event()).is_ok());
+    };
+
+    let mut client = Some(client);
+    let mut delay = RECONNECT_MIN;
+    let mut reconnect_at = Instant::now();
+
+    loop {
+        if client.as_ref().map_or(false, |client| !client.bus.is_connected()) {
+            warn!("lost the connection to the system bus");
+            client = None;
+        }
+
+        if client.is_none() && Instant::now() >= reconnect_at {
+            match Client::new() {
+                Ok(new) => {
+                    info!("reconnected to the system bus");
+                    client = Some(new);
+                    delay = RECONNECT_MIN;
+                    broadcast(|| Event::Reconnected);
+                }
+                Err(why) => {
+                    warn!("failed to reconnect to the system bus: {}", why);
+                    reconnect_at = Instant::now() + delay;
+                    delay = (delay * 2).min(RECONNECT_MAX);
+                }
+            }
+        }
+
+        loop {
+            match requests.try_recv() {
+                Ok(request) => request(client.as_ref()),
+                Err(flume::TryRecvError::Empty) => break,
+                Err(flume::TryRecvError::Disconnected) => return,
+            }
+        }
+
+        let connected = match client.as_ref() {
+            Some(connected) => connected,
+            None => {
+                thread::sleep(Duration::from_millis(POLL as u64));
+                continue;
+            }
+        };
+
+        for item in connected.bus.iter(POLL) {
+            if let ConnectionItem::Nothing = item {
+                break;
+            }
+
+            let signal = match filter_signal(item).map(|signal| parse_signal(&signal)) {
+                Some(Ok(Some(signal))) => signal,
+                Some(Err(why)) => {
+                    warn!("failed to read a signal of the daemon: {}", why);
+                    continue;
+                }
+                _ => continue,
+            };
+
+            let mut subscribers = subscribers.lock().expect("subscribers poisoned");
+            subscribers.retain(|subscriber| {
+                subscriber.send(Event::Signal(signal.clone())).is_ok()
+            });
+        }
+    }
+}
@@ -0,0 +1,537 @@
+//! The system-wide configuration of pop-upgrade, read from `/etc/pop-upgrade/config.toml`.
+//!
+//! Where a setting can be given in more than one way, the first of these which is set applies:
+//! command-line flags, the arguments of DBus method calls, environment variables, this file, and
+//! then the built-in defaults.
+
+use chrono::NaiveTime;
+use isahc::http::Uri;
+use once_cell::sync::Lazy;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Mutex, PoisonError},
+};
+use thiserror::Error;
+
+pub const CONFIG_PATH: &str = "/etc/pop-upgrade/config.toml";
+
+/// The configuration file as it was last loaded.
+static LOADED: Lazy<Mutex<Loaded>> = Lazy::new(Mutex::default);
+
+#[derive(Default)]
+struct Loaded {
+    /// The contents of the file when it was last valid.
+    valid: Option<String>,
+    /// The error which was last logged, which is not logged again until the file changes.
+    error: Option<String>,
+}
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("failed to read {}", CONFIG_PATH)]
+    Read(#[source] io::Error),
+
+    #[error("failed to parse {}", CONFIG_PATH)]
+    Parse(#[source] toml::de::Error),
+
+    #[error("invalid {} in {}: {}", .0, CONFIG_PATH, .1)]
+    Invalid(&'static str, String),
+}
+
+/// System-wide configuration of pop-upgrade.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub api:           ApiConfig,
+    pub commands:      CommandConfig,
+    pub hooks:         HooksConfig,
+    pub mirrors:       MirrorConfig,
+    pub network:       NetworkConfig,
+    pub notifications: NotificationConfig,
+    pub power:         PowerConfig,
+    pub release:       ReleaseConfig,
+    pub schedule:      ScheduleConfig,
+    pub telemetry:     TelemetryConfig,
+}
+
+/// Configuration of the Pop release API client.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ApiConfig {
+    /// Base URL of the release API, in place of `https://api.pop-os.org/`.
+    pub endpoint: Option<String>,
+}
+
+/// Configuration of the external commands which the daemon runs, such as rsync.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct CommandConfig {
+    /// Path of the `findmnt` binary, in place of `/usr/bin/findmnt`.
+    pub findmnt: Option<PathBuf>,
+    /// Path of the `gpgv` binary, in place of `/usr/bin/gpgv`.
+    pub gpgv:    Option<PathBuf>,
+    /// Path of the `rsync` binary, in place of `/usr/bin/rsync`.
+    pub rsync:   Option<PathBuf>,
+    /// Deny commands the use of system calls which none of them need, such as loading modules.
+    pub seccomp: bool,
+}
+
+/// Configuration of the scripts in `/etc/pop-upgrade/hooks` which are run around upgrades.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HooksConfig {
+    /// Seconds that each hook may run for before it is killed, in place of five minutes.
+    pub timeout:    Option<u64>,
+    /// What happens when a hook fails, or runs out of time.
+    pub on_failure: FailurePolicy,
+    /// The timeout and failure policy of individual hooks, by their file names.
+    pub scripts:    HashMap<String, HookConfig>,
+}
+
+/// Configuration of an individual hook, in place of that of every hook.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct HookConfig {
+    pub timeout:    Option<u64>,
+    pub on_failure: Option<FailurePolicy>,
+}
+
+/// What happens when a hook fails.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FailurePolicy {
+    /// Run none of the hooks after it, and fail the upgrade if it has not begun.
+    Abort,
+    /// Log the failure, and run the hooks after it.
+    Continue,
+}
+
+impl Default for FailurePolicy {
+    fn default() -> Self { FailurePolicy::Abort }
+}
+
+/// Configuration of the archive mirror which the packages of a release upgrade are fetched from.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct MirrorConfig {
+    /// Benchmark mirrors, and fetch the upgrade from the fastest.
+    pub benchmark:  bool,
+    /// Mirrors to benchmark, in place of the mirrors listed for the system's country.
+    pub candidates: Vec<String>,
+}
+
+/// Configuration of network requests made to the release API and ISO mirrors. Apart from
+/// `allow_metered`, these do not apply to the packages which apt fetches, which are fetched with
+/// apt's own configuration.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// A PEM bundle of the only certificate authorities that will be trusted for HTTPS requests.
+    pub ca_certificate:  Option<PathBuf>,
+    /// Download upgrades on metered connections, instead of deferring them.
+    pub allow_metered:   bool,
+    /// The proxy which requests are made through, in place of the proxy apt is configured with.
+    pub proxy:           Option<String>,
+    /// Kibibytes per second which each of these downloads is limited to, rather than unlimited.
+    pub bandwidth_limit: Option<u64>,
+}
+
+/// Configuration of the notifications which the daemon shows to users of graphical sessions.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct NotificationConfig {
+    /// Notify users of new releases, and of upgrades which are ready to install.
+    pub enabled:      bool,
+    /// Hours for which a snoozed notification is hidden, in place of a day.
+    pub snooze_hours: Option<u64>,
+}
+
+impl Default for NotificationConfig {
+    fn default() -> Self { Self { enabled: true, snooze_hours: None } }
+}
+
+/// Configuration of the power which upgrades require.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct PowerConfig {
+    /// Percentage of charge below which upgrades are refused without AC power, in place of 30%.
+    pub min_battery: Option<u8>,
+}
+
+/// Configuration of release checks and upgrades.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ReleaseConfig {
+    /// The ISO variant to use, such as `intel` or `nvidia`, in place of the detected variant.
+    pub variant:      Option<String>,
+    /// Seconds for which the result of a release check is reused, in place of one hour.
+    pub check_ttl:    Option<u64>,
+    /// The releases which will be offered as upgrades.
+    pub channel:      Channel,
+    /// How conflicts with locally modified conffiles are resolved when upgrading packages.
+    pub conffiles:    ConffilePolicy,
+    /// Seconds to wait for another package manager to release its lock, in place of ten minutes.
+    pub lock_timeout: Option<u64>,
+    /// Remove packages which are no longer required, and clear the package cache, once a release
+    /// upgrade has completed.
+    pub autoclean:    bool,
+    /// Update Flatpak runtimes and applications once a release upgrade has completed.
+    pub flatpak:      bool,
+    /// Fetch the packages of a new release in the background once it is available, so that most
+    /// of the upgrade has been fetched by the time that it is requested.
+    pub prefetch:     bool,
+}
+
+/// Configuration of scheduled upgrades.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct ScheduleConfig {
+    /// The local time, as `HH:MM`, at which upgrades are scheduled when no time is given.
+    pub time: Option<String>,
+}
+
+/// Configuration of the reports of the outcomes of release upgrades, which are opt-in.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct TelemetryConfig {
+    /// Report whether each release upgrade succeeded, and the phase and kind of error if not.
+    pub enabled:  bool,
+    /// The URL which reports are posted to, as JSON.
+    pub endpoint: Option<String>,
+}
+
+/// Which releases are offered as upgrades.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    /// Every release, including interim releases.
+    Latest,
+    /// Only LTS releases, skipping the interim releases between them.
+    Lts,
+}
+
+impl Default for Channel {
+    fn default() -> Self { Channel::Latest }
+}
+
+/// How dpkg resolves a conflict between a locally modified conffile and the conffile of the
+/// package that is being upgraded. Conflicts are never prompted for, as the upgrade is performed
+/// by the daemon, and on boot, where there is nobody to answer.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, FromPrimitive, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConffilePolicy {
+    /// Keep the modified conffile, unless dpkg has a default action.
+    Old = 1,
+    /// Replace the modified conffile with that of the package.
+    New = 2,
+}
+
+impl Default for ConffilePolicy {
+    fn default() -> Self { ConffilePolicy::Old }
+}
+
+impl ConffilePolicy {
+    /// Options which apply the policy to dpkg when it is invoked by apt-get.
+    pub fn apt_options(self) -> &'static [&'static str] {
+        match self {
+            ConffilePolicy::Old => {
+                &["-o", "Dpkg::Options::=--force-confdef", "-o", "Dpkg::Options::=--force-confold"]
+            }
+            ConffilePolicy::New => &["-o", "Dpkg::Options::=--force-confnew"],
+        }
+    }
+
+    /// The policy which was recorded for the last release upgrade.
+    pub fn recorded() -> Option<Self> {
+        fs::read_to_string(crate::CONFFILE_POLICY).ok()?.trim().parse().ok()
+    }
+
+    /// Records the policy for the offline upgrade script, which applies it on the next boot.
+    pub fn record(self) -> io::Result<()> {
+        fs::write(crate::CONFFILE_POLICY, <&'static str>::from(self))
+    }
+}
+
+impl From<ConffilePolicy> for &'static str {
+    fn from(policy: ConffilePolicy) -> Self {
+        match policy {
+            ConffilePolicy::Old => "old",
+            ConffilePolicy::New => "new",
+        }
+    }
+}
+
+impl std::str::FromStr for ConffilePolicy {
+    type Err = ();
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "old" => Ok(ConffilePolicy::Old),
+            "new" => Ok(ConffilePolicy::New),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file, or the default configuration if it does not exist.
+    pub fn load() -> Result<Self, ConfigError> {
+        let contents = match fs::read_to_string(CONFIG_PATH) {
+            Ok(contents) => contents,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(why) => return Err(ConfigError::Read(why)),
+        };
+
+        let config = contents.parse::<Config>()?;
+
+        let mut loaded = LOADED.lock().unwrap_or_else(PoisonError::into_inner);
+        loaded.valid = Some(contents);
+        loaded.error = None;
+
+        Ok(config)
+    }
+
+    /// Checks the values which could be parsed, but which are not meaningful.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |setting, why: &str| Err(ConfigError::Invalid(setting, why.to_owned()));
+
+        if let Some(ref endpoint) = self.api.endpoint {
+            if !is_http_url(endpoint) {
+                return invalid("api.endpoint", "expected an http or https URL");
+            }
+        }
+
+        if let Some(ref proxy) = self.network.proxy {
+            if proxy.parse::<Uri>().map_or(true, |uri| uri.host().is_none()) {
+                return invalid("network.proxy", "expected a URL such as http://10.0.0.2:3142/");
+            }
+        }
+
+        let timeouts = self.hooks.scripts.values().map(|script| script.timeout);
+        if std::iter::once(self.hooks.timeout).chain(timeouts).any(|timeout| timeout == Some(0)) {
+            return invalid("hooks.timeout", "expected a positive number of seconds");
+        }
+
+        if self.network.bandwidth_limit == Some(0) {
+            return invalid("network.bandwidth_limit", "expected a positive number of KiB/s");
+        }
+
+        if self.notifications.snooze_hours == Some(0) {
+            return invalid("notifications.snooze_hours", "expected a positive number of hours");
+        }
+
+        if self.power.min_battery.map_or(false, |min| min > 100) {
+            return invalid("power.min_battery", "expected a percentage from 0 to 100");
+        }
+
+        if let Some(ref time) = self.schedule.time {
+            if NaiveTime::parse_from_str(time, "%H:%M").is_err() {
+                return invalid("schedule.time", "expected a local time of the form HH:MM");
+            }
+        }
+
+        if let Some(ref endpoint) = self.telemetry.endpoint {
+            if !is_http_url(endpoint) {
+                return invalid("telemetry.endpoint", "expected an http or https URL");
+            }
+        }
+
+        if self.telemetry.enabled && self.telemetry.endpoint.is_none() {
+            return invalid("telemetry.enabled", "reports require an endpoint to be sent to");
+        }
+
+        Ok(())
+    }
+
+    /// Loads the configuration file. If it has been made invalid since it was last loaded, the
+    /// error is logged once, and its last valid contents go on applying. The defaults apply only
+    /// if it was never valid, which the daemon refuses to start with.
+    pub fn load_or_default() -> Self {
+        Self::load().unwrap_or_else(|why| {
+            let why = crate::misc::format_error(&why);
+            let mut loaded = LOADED.lock().unwrap_or_else(PoisonError::into_inner);
+
+            if loaded.error.as_deref() != Some(why.as_str()) {
+                error!("{}", why);
+                loaded.error = Some(why);
+            }
+
+            loaded.valid.as_deref().and_then(|valid| valid.parse().ok()).unwrap_or_default()
+        })
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = ConfigError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let config = toml::from_str::<Self>(input).map_err(ConfigError::Parse)?;
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+fn is_http_url(url: &str) -> bool {
+    url.parse::<Uri>().map_or(false, |uri| {
+        matches!(uri.scheme_str(), Some("http") | Some("https")) && uri.host().is_some()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    #[test]
+    fn network() {
+        let config = "[network]\nca_certificate = \"/etc/ssl/pop.pem\"";
+        let config = config.parse::<Config>().unwrap();
+        assert_eq!(config.network.ca_certificate.as_deref(), Some(Path::new("/etc/ssl/pop.pem")));
+
+        assert!(!config.network.allow_metered);
+
+        let config = "".parse::<Config>().unwrap();
+        assert_eq!(config.network.ca_certificate, None);
+
+        let config = "[network]\nallow_metered = true".parse::<Config>().unwrap();
+        assert!(config.network.allow_metered);
+    }
+
+    #[test]
+    fn api() {
+        let config = "[api]\nendpoint = \"https://mirror.example.com/pop\"";
+        let config = config.parse::<Config>().unwrap();
+        assert_eq!(config.api.endpoint.as_deref(), Some("https://mirror.example.com/pop"));
+    }
+
+    #[test]
+    fn commands() {
+        let config = "[commands]\nrsync = \"/opt/rsync/bin/rsync\"\nseccomp = true";
+        let config = config.parse::<Config>().unwrap();
+        assert_eq!(config.commands.rsync.as_deref(), Some(Path::new("/opt/rsync/bin/rsync")));
+        assert_eq!(config.commands.gpgv, None);
+        assert!(config.commands.seccomp);
+
+        let config = "".parse::<Config>().unwrap();
+        assert!(!config.commands.seccomp);
+    }
+
+    #[test]
+    fn hooks() {
+        let config = "".parse::<Config>().unwrap();
+        assert_eq!(config.hooks.on_failure, FailurePolicy::Abort);
+        assert_eq!(config.hooks.timeout, None);
+
+        let config = "[hooks]\ntimeout = 60\non_failure = \"continue\"\n\
+                      [hooks.scripts.10-agent]\non_failure = \"abort\"";
+        let config = config.parse::<Config>().unwrap();
+        assert_eq!(config.hooks.timeout, Some(60));
+        assert_eq!(config.hooks.on_failure, FailurePolicy::Continue);
+        assert_eq!(config.hooks.scripts["10-agent"].on_failure, Some(FailurePolicy::Abort));
+        assert_eq!(config.hooks.scripts["10-agent"].timeout, None);
+
+        assert!("[hooks.scripts.10-agent]\ntimeout = 0".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn mirrors() {
+        let config = "[mirrors]\nbenchmark = true\n\
+                      candidates = [\"http://mirror.example.com/ubuntu/\"]";
+        let config = config.parse::<Config>().unwrap();
+        assert!(config.mirrors.benchmark);
+        assert_eq!(config.mirrors.candidates, vec!["http://mirror.example.com/ubuntu/"]);
+
+        let config = "".parse::<Config>().unwrap();
+        assert!(!config.mirrors.benchmark);
+    }
+
+    #[test]
+    fn network_limits() {
+        let config = "[network]\nproxy = \"http://10.0.0.2:3142/\"\nbandwidth_limit = 512";
+        let config = config.parse::<Config>().unwrap();
+        assert_eq!(config.network.proxy.as_deref(), Some("http://10.0.0.2:3142/"));
+        assert_eq!(config.network.bandwidth_limit, Some(512));
+
+        assert!("[network]\nproxy = \"not a proxy\"".parse::<Config>().is_err());
+        assert!("[network]\nbandwidth_limit = 0".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn notifications() {
+        let config = "".parse::<Config>().unwrap();
+        assert!(config.notifications.enabled);
+        assert_eq!(config.notifications.snooze_hours, None);
+
+        let config = "[notifications]\nenabled = false\nsnooze_hours = 72";
+        let config = config.parse::<Config>().unwrap();
+        assert!(!config.notifications.enabled);
+        assert_eq!(config.notifications.snooze_hours, Some(72));
+    }
+
+    #[test]
+    fn schedule() {
+        let config = "[schedule]\ntime = \"02:30\"".parse::<Config>().unwrap();
+        assert_eq!(config.schedule.time.as_deref(), Some("02:30"));
+
+        assert!("[schedule]\ntime = \"2 AM\"".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn telemetry() {
+        let config = "".parse::<Config>().unwrap();
+        assert!(!config.telemetry.enabled);
+
+        let config = "[telemetry]\nenabled = true\nendpoint = \"https://example.com/reports\"";
+        let config = config.parse::<Config>().unwrap();
+        assert!(config.telemetry.enabled);
+        assert_eq!(config.telemetry.endpoint.as_deref(), Some("https://example.com/reports"));
+
+        assert!("[telemetry]\nenabled = true".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn validation() {
+        assert!("[api]\nendpoint = \"ftp://example.com/\"".parse::<Config>().is_err());
+        assert!("[power]\nmin_battery = 101".parse::<Config>().is_err());
+    }
+
+    #[test]
+    fn power() {
+        let config = "[power]\nmin_battery = 30".parse::<Config>().unwrap();
+        assert_eq!(config.power.min_battery, Some(30));
+
+        let config = "".parse::<Config>().unwrap();
+        assert_eq!(config.power.min_battery, None);
+    }
+
+    #[test]
+    fn release() {
+        let config = "[release]\nvariant = \"nvidia\"".parse::<Config>().unwrap();
+        assert_eq!(config.release.variant.as_deref(), Some("nvidia"));
+        assert_eq!(config.release.channel, Channel::Latest);
+
+        let config = "[release]\nchannel = \"lts\"".parse::<Config>().unwrap();
+        assert_eq!(config.release.channel, Channel::Lts);
+        assert_eq!(config.release.conffiles, ConffilePolicy::Old);
+
+        let config = "[release]\nconffiles = \"new\"".parse::<Config>().unwrap();
+        assert_eq!(config.release.conffiles, ConffilePolicy::New);
+        assert!("[release]\nconffiles = \"prompt\"".parse::<Config>().is_err());
+        assert!(!config.release.autoclean);
+
+        let config = "[release]\nautoclean = true".parse::<Config>().unwrap();
+        assert!(config.release.autoclean);
+        assert!(!config.release.flatpak);
+
+        let config = "[release]\nflatpak = true".parse::<Config>().unwrap();
+        assert!(config.release.flatpak);
+        assert!(!config.release.prefetch);
+
+        let config = "[release]\nprefetch = true".parse::<Config>().unwrap();
+        assert!(config.release.prefetch);
+    }
+}
@@ -1,9 +1,13 @@
+use crate::config::ConfigError;
 use dbus;
 use std::io;
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum DaemonError {
+    #[error("refusing to start with an invalid configuration")]
+    Config(#[source] ConfigError),
+
     #[error("failed to make a private dbus connection to the system bus")]
     PrivateConnection(#[source] dbus::Error),
 
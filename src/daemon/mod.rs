@@ -1,5 +1,7 @@
 pub mod signals;
 
+pub use pop_upgrade_core::interface::{capabilities, API_VERSION};
+
 pub mod methods {
     #[repr(u8)]
     #[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
@@ -10,86 +12,203 @@ pub mod methods {
     }
 
     pub const CANCEL: &str = "Cancel";
+    pub const CLEAN: &str = "Clean";
+    pub const CLEAN_KERNELS: &str = "CleanKernels";
     pub const DISMISS_NOTIFICATION: &str = "DismissNotification";
     pub const FETCH_UPDATES: &str = "FetchUpdates";
     pub const FETCH_UPDATES_STATUS: &str = "FetchUpdatesStatus";
+    pub const HELD_PACKAGES: &str = "HeldPackages";
+    pub const HISTORY: &str = "History";
+    pub const NOTIFICATION_PREFERENCES: &str = "NotificationPreferences";
+    pub const NOTIFICATION_SNOOZE: &str = "NotificationSnooze";
     pub const PACKAGE_UPGRADE: &str = "UpgradePackages";
+    pub const PREFLIGHT_CHECK: &str = "PreflightCheck";
     pub const RECOVERY_UPGRADE_FILE: &str = "RecoveryUpgradeFile";
     pub const RECOVERY_UPGRADE_RELEASE: &str = "RecoveryUpgradeRelease";
     pub const RECOVERY_UPGRADE_RELEASE_STATUS: &str = "RecoveryUpgradeReleaseStatus";
     pub const RECOVERY_VERSION: &str = "RecoveryVersion";
     pub const REFRESH_OS: &str = "RefreshOS";
     pub const RELEASE_CHECK: &str = "ReleaseCheck";
+    pub const RELEASE_NOTES: &str = "ReleaseNotes";
+    pub const RELEASE_PATH: &str = "ReleasePath";
+    pub const RELEASE_PREVIEW: &str = "ReleasePreview";
+    pub const RELEASE_RECLAIM: &str = "ReleaseReclaim";
     pub const RELEASE_UPGRADE: &str = "ReleaseUpgrade";
     pub const RELEASE_UPGRADE_FINALIZE: &str = "ReleaseUpgradeFinalize";
     pub const RELEASE_UPGRADE_STATUS: &str = "ReleaseUpgradeStatus";
+    pub const RELEASE_UPGRADE_WITH_OPTIONS: &str = "ReleaseUpgradeWithOptions";
     pub const RELEASE_REPAIR: &str = "ReleaseRepair";
+    pub const RELEASE_RESUME: &str = "ReleaseResume";
+    pub const RELEASE_SIMULATE: &str = "ReleaseSimulate";
+    pub const REPO_COMPATIBILITY: &str = "RepoCompatibility";
     pub const RESET: &str = "Reset";
+    pub const SCHEDULE: &str = "Schedule";
+    pub const SCHEDULE_CANCEL: &str = "ScheduleCancel";
+    pub const SELF_UPDATE_CHECK: &str = "SelfUpdateCheck";
+    pub const SNAPSHOT_LIST: &str = "SnapshotList";
+    pub const SNAPSHOT_RESTORE: &str = "SnapshotRestore";
+    pub const STAGE_OFFLINE_UPDATE: &str = "StageOfflineUpdate";
     pub const STATUS: &str = "Status";
+    pub const STATUS_DETAILS: &str = "StatusDetails";
     pub const UPDATE_CHECK: &str = "UpdateCheck";
+    pub const UPGRADE_PATH: &str = "UpgradePath";
+}
+
+pub mod properties {
+    pub const API_VERSION: &str = "Version";
+    pub const CAPABILITIES: &str = "Capabilities";
+    pub const DEVELOPMENT_RELEASES: &str = "DevelopmentReleases";
+    pub const DOWNLOAD_DEFERRED: &str = "DownloadDeferred";
+    pub const END_OF_LIFE: &str = "EndOfLife";
+    pub const INTERRUPTED_UPGRADE: &str = "InterruptedUpgrade";
+    pub const MANAGED: &str = "Managed";
+    pub const SCHEDULED: &str = "Scheduled";
 }
 
 mod error;
+mod operation;
 mod runtime;
-mod status;
+mod schedule;
+mod sd_notify;
 
 pub use self::{
     error::DaemonError, methods::DismissEvent, runtime::DaemonRuntime, signals::SignalEvent,
-    status::DaemonStatus,
 };
+pub use pop_upgrade_core::DaemonStatus;
+
+use self::signals::{OperationError, ResultStatus};
 
 use crate::{
+    clean,
+    config::{Channel, ConffilePolicy, Config},
+    fetch::apt::PackageUri,
+    history,
+    hooks::{self, Stage},
+    kernels,
+    lock::LockError,
     misc::{self, format_error},
+    notification::{self, Notice},
     recovery::{
         self, RecoveryError, RecoveryVersion, RecoveryVersionError,
         ReleaseFlags as RecoveryReleaseFlags, UpgradeMethod as RecoveryUpgradeMethod,
     },
     release::{
-        self, FetchEvent, RefreshOp, ReleaseError, ReleaseStatus,
+        self,
+        eol::{EolDate, EolStatus},
+        paths::UpgradePaths,
+        prefetch::PrefetchError,
+        FetchEvent, RefreshOp, ReleaseError, ReleaseStatus, UpgradeEvent, UpgradeFlags,
         UpgradeMethod as ReleaseUpgradeMethod,
     },
-    sighandler, DBUS_IFACE, DBUS_NAME, DBUS_PATH, RESTART_SCHEDULED,
+    release_architecture::detect_arch,
+    policy::{self, Policy, PolicyError, POLICY_ERROR},
+    polkit, preflight, sighandler, snapshot,
+    telemetry::{self, Report},
+    DBUS_IFACE, DBUS_NAME, DBUS_PATH, RESTART_SCHEDULED,
 };
 
 use anyhow::Context as AnyhowContext;
-use apt_cmd::{request::Request as AptRequest, AptCache, AptGet, AptMark};
+use apt_cmd::{AptCache, AptGet, AptMark, AptUpgradeEvent};
 use as_result::*;
 use atomic::Atomic;
+use chrono::TimeZone;
 use dbus::{
-    blocking::Connection,
+    arg::{prop_cast, AppendAll, PropMap},
+    blocking::SyncConnection,
     channel::{MatchingReceiver, Sender as DBusSender},
     message::{MatchRule, Message},
 };
-use dbus_crossroads::{Context, Crossroads, MethodErr};
+use dbus_crossroads::{Context, Crossroads, MethodErr, PropContext};
 use flume::{bounded, Receiver, Sender};
 use futures::prelude::*;
 use logind_dbus::LoginManager;
 use num_traits::FromPrimitive;
 use std::{
     collections::{HashMap, HashSet},
-    fs,
-    path::PathBuf,
+    convert::TryFrom,
+    fs, io,
+    path::{Path, PathBuf},
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
+    time::{Duration, Instant},
 };
+use ubuntu_version::{Codename, Version};
 
 pub const DISMISSED: &str = "/usr/lib/pop-upgrade/dismissed";
 pub const INSTALL_DATE: &str = "/usr/lib/pop-upgrade/install_date";
 
-#[derive(Debug)]
+/// What is blocked while an operation is performed: shutdowns, reboots, and sleep, as well as
+/// the lid switch and suspend key, which would otherwise suspend the system without asking.
+const INHIBIT: &str = "shutdown:sleep:handle-lid-switch:handle-suspend-key";
+
+/// Time after the daemon starts when it first checks for a new release to prefetch, which leaves
+/// the network time to come up.
+const RELEASE_PREFETCH_DELAY: Duration = Duration::from_secs(15 * 60);
+
+/// Time between each check for a new release to prefetch.
+const RELEASE_PREFETCH_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
 pub enum Event {
     Cancel,
-    FetchUpdates { apt_uris: HashSet<AptRequest>, download_only: bool },
+    FetchUpdates { apt_uris: HashSet<PackageUri>, download_only: bool },
     PackageUpgrade,
     RecoveryUpgrade(RecoveryUpgradeMethod),
-    ReleaseUpgrade { how: ReleaseUpgradeMethod, from: String, to: String },
+    ReleaseUpgrade {
+        how:        ReleaseUpgradeMethod,
+        from:       String,
+        to:         String,
+        lift_holds: bool,
+        conffiles:  ConffilePolicy,
+        resume:     Option<release::resume::Phase>,
+    },
+    /// Refreshes snaps, reclaims space, and updates Flatpaks once an upgrade has been installed.
+    AfterUpgrade(release::AfterUpgrade),
+    /// Fetches the packages of the next release in the background.
+    Prefetch,
+    /// Answers a method call, which is replied to once the query is answered.
+    Query(Query, Context),
 }
 
+/// Method calls which wait on apt, and so are answered by the background thread, rather than by
+/// the loop which handles DBus messages.
 #[derive(Debug)]
+pub enum Query {
+    /// Purges every kernel except for the running kernel, the given number of the most recent
+    /// others, and a kernel older than the running kernel.
+    CleanKernels(usize),
+    /// Runs the checks which precede an upgrade, which may fetch the package lists of the next
+    /// release.
+    PreflightCheck,
+    /// Removes packages which are no longer required, and clears the package cache, signaling
+    /// the progress of the removal.
+    ReleaseReclaim,
+    /// Previews the packages which the upgrade to the release, or to the next release if it is
+    /// empty, would remove or downgrade, which updates the package lists of the release.
+    ReleasePreview(String),
+    /// Simulates the upgrade to the release, or to the next release if it is empty, which
+    /// updates the package lists of the release.
+    ReleaseSimulate(String),
+    SelfUpdateCheck,
+    /// Checks for a newer pop-upgrade, which the client with this bus name must be authorized to
+    /// install.
+    UpdateCheck(Option<String>),
+}
+
+/// Resumes handling a method call with the daemon, once its caller has been authorized.
+type Resume = Box<dyn FnOnce(Context, &mut Daemon) -> Option<Context> + Send>;
+
 pub enum FgEvent {
     SetUpgradeState(Result<(), ReleaseError>, ReleaseUpgradeMethod, Box<str>, Box<str>),
+    /// Sends the reply to a method call which was answered off the loop.
+    Reply(Context),
+    /// Resumes a method call which polkit has authorized.
+    Resume(Context, Resume),
+    /// Begins the scheduled release upgrade, if its checks passed.
+    ScheduledUpgrade(Result<ReleaseStatus, String>),
+    /// Updates pop-upgrade, which restarts the daemon.
+    SelfUpgrade,
 }
 
 pub struct LastKnown {
@@ -111,25 +230,42 @@ pub struct ReleaseUpgradeState {
 }
 
 pub struct Daemon {
-    event_tx:        Sender<Event>,
+    /// Operations for the background thread, with the client which initiated them.
+    event_tx:        Sender<(Event, Option<String>)>,
+    fg_tx:           Sender<FgEvent>,
     fg_rx:           Receiver<FgEvent>,
     dbus_rx:         Receiver<SignalEvent>,
+    dbus_tx:         Sender<SignalEvent>,
     status:          Arc<Atomic<DaemonStatus>>,
     sub_status:      Arc<Atomic<u8>>,
     fetching_state:  Arc<Atomic<(u64, u64)>>,
     cancel:          Arc<AtomicBool>,
     last_known:      LastKnown,
+    /// The error which the most recent operation to fail reported.
+    last_error:      Option<String>,
+    /// The client which initiated the operation that is in progress.
+    initiator:       Option<String>,
+    /// The percentage of the current phase of the operation which has completed.
+    progress:        u8,
+    /// When the daemon next checks for a new release to prefetch.
+    prefetch_check:  Instant,
+    /// Whether the packages of the next release are being fetched in the background.
+    prefetching:     Arc<AtomicBool>,
+    /// When a release upgrade is scheduled to begin, in seconds since the Unix epoch.
+    scheduled:       Option<u64>,
     release_upgrade: Option<ReleaseUpgradeState>,
     perform_upgrade: bool,
+    /// The daemon's connection to the system bus, which also identifies its clients.
+    bus:             Arc<SyncConnection>,
 }
 
 impl Daemon {
-    pub fn new() -> Result<Self, DaemonError> {
+    pub fn new(bus: Arc<SyncConnection>) -> Result<Self, DaemonError> {
         // Only accept one event at a time.
         let (event_tx, event_rx) = bounded(4);
 
-        // Events to be handled in the foreground.
-        let (fg_tx, fg_rx) = bounded(4);
+        // Events to be handled in the foreground, including the replies to method calls.
+        let (fg_tx, fg_rx) = bounded(16);
 
         // Dbus events are checked at least once per second, so we will allow buffering some events.
         let (dbus_tx, dbus_rx) = bounded(64);
@@ -146,12 +282,15 @@ impl Daemon {
         // Cancels a process which is in progress
         let cancel = Arc::new(AtomicBool::new(false));
         let cancel_process: Arc<dyn Fn() -> bool + Send + Sync> =
-            Arc::new(enclose!((cancel => c) move || c.swap(false, Ordering::SeqCst)));
+            Arc::new(enclose!((cancel => c) move || c.load(Ordering::SeqCst)));
+
+        // Whether the packages of the next release are being fetched in the background.
+        let prefetching = Arc::new(AtomicBool::new(false));
 
         let mut processing = false;
 
         std::thread::spawn(
-            enclose!((cancel, status, sub_status, prog_state) move || async_io::block_on(async move {
+            enclose!((cancel, dbus_tx, fg_tx, status, sub_status, prog_state, prefetching) move || async_io::block_on(async move {
                 let mut logind = match LoginManager::new() {
                     Ok(logind) => Some(logind),
                     Err(why) => {
@@ -160,38 +299,65 @@ impl Daemon {
                     }
                 };
 
-                let mut runtime = DaemonRuntime::new();
+                let mut runtime = DaemonRuntime::new(
+                    Arc::new(enclose!((dbus_tx) move |holder| {
+                        let _ = dbus_tx.send(SignalEvent::PackageLockWait(holder.clone()));
+                    })),
+                    cancel_process.clone(),
+                );
 
                 let fetch_closure = Arc::new(enclose!((prog_state, dbus_tx) move |event| {
                     match event {
-                        FetchEvent::Fetched(uri) => {
+                        FetchEvent::Fetched(name) => {
                             let (current, npackages) = prog_state.load(Ordering::SeqCst);
                             prog_state.store((current + 1, npackages), Ordering::SeqCst);
 
                             let _ = dbus_tx.send(SignalEvent::Fetched(
-                                uri.name,
+                                name,
                                 current as u32 + 1,
                                 npackages as u32,
                             ));
                         }
-                        FetchEvent::Fetching(uri) => {
-                            let _ = dbus_tx.send(SignalEvent::Fetching(uri.name));
+                        FetchEvent::Fetching(name) => {
+                            let _ = dbus_tx.send(SignalEvent::Fetching(name));
                         }
                         FetchEvent::Init(total) => {
                             prog_state.store((0, total as u64), Ordering::SeqCst);
                         }
+                        FetchEvent::Progress(progress) => {
+                            // Progress is skipped, rather than blocking the fetch, if the
+                            // signals have yet to be sent.
+                            let _ = dbus_tx.try_send(SignalEvent::FetchProgress(progress));
+                        }
                     }
                 }));
 
-                while let Ok(event) = event_rx.recv() {
-                    let _suspend_lock = logind.as_mut().and_then(|logind| {
-                        match logind
-                            .connect()
-                            .inhibit_suspend("pop-upgrade", "performing upgrade event")
-                        {
+                while let Ok((event, initiator)) = event_rx.recv() {
+                    let started = schedule::now();
+
+                    // The phase of the previous operation must not be mistaken for this one's.
+                    if !matches!(event, Event::Cancel | Event::Prefetch | Event::Query(..)) {
+                        sub_status.store(0, Ordering::SeqCst);
+                    }
+
+                    // Fetching in the background should not keep the system from suspending,
+                    // nor should queries, unless they remove packages.
+                    let inhibit = !matches!(event, Event::Prefetch | Event::Query(..))
+                        || matches!(
+                            event,
+                            Event::Query(Query::CleanKernels(_), _)
+                                | Event::Query(Query::ReleaseReclaim, _)
+                        );
+                    let _suspend_lock = logind.as_mut().filter(|_| inhibit).and_then(|logind| {
+                        match logind.connect().inhibit(
+                            INHIBIT,
+                            "pop-upgrade",
+                            "performing upgrade event",
+                            "block",
+                        ) {
                             Ok(lock) => Some(lock),
                             Err(why) => {
-                                error!("failed to inhibit suspension: {}", why);
+                                error!("failed to inhibit shutdown and sleep: {}", why);
                                 None
                             }
                         }
@@ -223,10 +389,17 @@ impl Daemon {
                                     } else {
                                         (async {
                                             info!("performing upgrade");
-                                            let (mut child, events) = AptGet::new()
+                                            let conffiles =
+                                                Config::load_or_default().release.conffiles;
+
+                                            let mut apt_get = AptGet::new()
                                                 .noninteractive()
                                                 .allow_downgrades()
-                                                .force()
+                                                .force();
+
+                                            apt_get.args(conffiles.apt_options());
+
+                                            let (mut child, events) = apt_get
                                                 .stream_upgrade()
                                                 .await
                                                 .map_err(ReleaseError::Upgrade)?;
@@ -246,38 +419,108 @@ impl Daemon {
                                 Err(why) => Err(why)
                             };
 
+                            let entry = history::Entry::new(
+                                history::Operation::FetchUpdates,
+                                started,
+                                initiator,
+                                result.as_ref(),
+                            );
+                            history::record(&entry);
+
                             let _ = dbus_tx.send(SignalEvent::FetchResult(result));
                         }
 
                         Event::PackageUpgrade => {
                             info!("upgrading packages");
-                            let _ = runtime.package_upgrade(|event| {
-                                let _ = dbus_tx.send(SignalEvent::Upgrade(event));
-                            });
+                            let conffiles = Config::load_or_default().release.conffiles;
+                            let result = runtime
+                                .package_upgrade(conffiles, |event| {
+                                    let _ = dbus_tx.send(SignalEvent::Upgrade(event));
+                                })
+                                .await;
+
+                            let entry = history::Entry::new(
+                                history::Operation::PackageUpgrade,
+                                started,
+                                initiator,
+                                result.as_ref(),
+                            );
+                            history::record(&entry);
                         }
 
                         Event::RecoveryUpgrade(action) => {
                             info!("attempting recovery upgrade with {:?}", action);
-                            let result = recovery::recovery(
-                                &|| (*cancel_process)(),
-                                &action,
-                                enclose!((dbus_tx, prog_state) move |p, t| {
-                                    prog_state.store((p, t), Ordering::SeqCst);
-                                    let _ = dbus_tx
-                                        .send(SignalEvent::RecoveryDownloadProgress(p, t));
-                                }),
-                                enclose!((dbus_tx, sub_status) move |status| {
-                                    sub_status.store(status as u8, Ordering::SeqCst);
-                                    let _ =
-                                        dbus_tx.send(SignalEvent::RecoveryUpgradeEvent(status));
-                                }),
-                            ).await;
+
+                            let to = match action {
+                                RecoveryUpgradeMethod::FromRelease { ref version, .. } => {
+                                    version.as_deref()
+                                }
+                                RecoveryUpgradeMethod::FromFile(_) => None,
+                            };
+
+                            let context = hooks::Context {
+                                operation: hooks::Operation::RecoveryUpgrade,
+                                from:      None,
+                                to,
+                                succeeded: None,
+                            };
+
+                            let result = match hooks::run(Stage::Pre, &context).await {
+                                Ok(()) => recovery::recovery(
+                                    &|| (*cancel_process)(),
+                                    &action,
+                                    enclose!((dbus_tx, prog_state) move |p, t| {
+                                        prog_state.store((p, t), Ordering::SeqCst);
+                                        let _ = dbus_tx
+                                            .send(SignalEvent::RecoveryDownloadProgress(p, t));
+                                    }),
+                                    enclose!((dbus_tx, prog_state) move |percent| {
+                                        let progress = (u64::from(percent), 100);
+                                        prog_state.store(progress, Ordering::SeqCst);
+                                        let _ = dbus_tx
+                                            .send(SignalEvent::RecoverySyncProgress(percent));
+                                    }),
+                                    enclose!((dbus_tx, prog_state) move |percent| {
+                                        let progress = (u64::from(percent), 100);
+                                        prog_state.store(progress, Ordering::SeqCst);
+                                        let _ = dbus_tx
+                                            .send(SignalEvent::RecoveryVerifyProgress(percent));
+                                    }),
+                                    enclose!((dbus_tx, sub_status) move |status| {
+                                        sub_status.store(status as u8, Ordering::SeqCst);
+                                        operation::set_phase(
+                                            DaemonStatus::RecoveryUpgrade,
+                                            status as u8,
+                                        );
+                                        let _ =
+                                            dbus_tx.send(SignalEvent::RecoveryUpgradeEvent(status));
+                                    }),
+                                ).await,
+                                Err(why) => Err(RecoveryError::Hook(why)),
+                            };
+
+                            let context =
+                                hooks::Context { succeeded: Some(result.is_ok()), ..context };
+                            if let Err(why) = hooks::run(Stage::Post, &context).await {
+                                warn!("{}", format_error(&why));
+                            }
+
+                            let entry = history::Entry {
+                                to: to.map(String::from),
+                                ..history::Entry::new(
+                                    history::Operation::RecoveryUpgrade,
+                                    started,
+                                    initiator,
+                                    result.as_ref(),
+                                )
+                            };
+                            history::record(&entry);
 
                             let _ = dbus_tx.send(SignalEvent::RecoveryUpgradeResult(result));
                             processing = false;
                         }
 
-                        Event::ReleaseUpgrade { how, from, to } => {
+                        Event::ReleaseUpgrade { how, from, to, lift_holds, conffiles, resume } => {
                             info!(
                                 "attempting release upgrade, using a {}",
                                 <&'static str>::from(how)
@@ -286,21 +529,61 @@ impl Daemon {
                             let progress = enclose!((dbus_tx, sub_status) move |event| {
                                 let _ = dbus_tx.send(SignalEvent::ReleaseUpgradeEvent(event));
                                 sub_status.store(event as u8, Ordering::SeqCst);
+                                operation::set_phase(DaemonStatus::ReleaseUpgrade, event as u8);
                             });
 
-                            let result = runtime.upgrade(
-                                how,
-                                &from,
-                                &to,
-                                &progress,
-                                fetch_closure.clone(),
-                                &|event| {
-                                    let _ = dbus_tx.send(SignalEvent::Upgrade(event));
-                                },
-                            ).await;
+                            let context = hooks::Context {
+                                operation: hooks::Operation::ReleaseUpgrade,
+                                from:      Some(&from),
+                                to:        Some(&to),
+                                succeeded: None,
+                            };
+
+                            let result = match hooks::run(Stage::Pre, &context).await {
+                                Ok(()) => runtime.upgrade(
+                                    how,
+                                    &from,
+                                    &to,
+                                    lift_holds,
+                                    conffiles,
+                                    resume,
+                                    &progress,
+                                    fetch_closure.clone(),
+                                    &|event| {
+                                        let _ = dbus_tx.send(SignalEvent::Upgrade(event));
+                                    },
+                                ).await,
+                                Err(why) => Err(ReleaseError::Hook(why)),
+                            };
 
                             let _ = AptMark::new().unhold(&["pop-upgrade"]).await;
 
+                            let context =
+                                hooks::Context { succeeded: Some(result.is_ok()), ..context };
+                            if let Err(why) = hooks::run(Stage::Post, &context).await {
+                                warn!("{}", format_error(&why));
+                            }
+
+                            // Staged upgrades are reported by `release::cleanup` once they
+                            // have been installed, or have failed to be.
+                            if result.is_err() {
+                                let phase =
+                                    UpgradeEvent::from_u8(sub_status.load(Ordering::SeqCst));
+                                telemetry::report(Report::new(&from, &to, phase, result.as_ref()));
+                            }
+
+                            let entry = history::Entry {
+                                from: Some(from.clone()),
+                                to:   Some(to.clone()),
+                                ..history::Entry::new(
+                                    history::Operation::ReleaseUpgrade,
+                                    started,
+                                    initiator,
+                                    result.as_ref(),
+                                )
+                            };
+                            history::record(&entry);
+
                             let _ = fg_tx.send(FgEvent::SetUpgradeState(
                                 result,
                                 how,
@@ -308,22 +591,158 @@ impl Daemon {
                                 to.into(),
                             ));
                         }
+
+                        Event::AfterUpgrade(after) => {
+                            let logger = |event| {
+                                let _ = dbus_tx.send(SignalEvent::ReleaseUpgradeEvent(event));
+                            };
+
+                            // Progress is skipped, rather than blocking, if the signals have yet
+                            // to be sent.
+                            let progress = |percent| {
+                                let progress = SignalEvent::ReleaseUpgradeProgress(percent);
+                                let _ = dbus_tx.try_send(progress);
+                            };
+
+                            release::after_upgrade(after, &logger, &progress).await;
+
+                            // Nothing which follows the upgrade is an operation of a client.
+                            continue;
+                        }
+
+                        Event::Prefetch => {
+                            // Fetching stops once an operation begins, or once an operation or
+                            // query is waiting behind it.
+                            let active = || {
+                                status.load(Ordering::SeqCst) != DaemonStatus::Inactive
+                                    || !event_rx.is_empty()
+                            };
+
+                            match prefetch_target() {
+                                Ok(to) => match release::prefetch::prefetch(&to, &active).await {
+                                    Ok(()) => (),
+                                    Err(why @ PrefetchError::Cancelled(_)) => info!("{}", why),
+                                    Err(why) => warn!("{}", format_error(&why)),
+                                },
+                                Err(why) => info!(
+                                    "not fetching the next release in the background: {}",
+                                    why
+                                ),
+                            }
+
+                            prefetching.store(false, Ordering::SeqCst);
+
+                            // The status belongs to the operation which may be waiting.
+                            continue;
+                        }
+
+                        Event::Query(query, mut ctx) => {
+                            info!("answering {:?}", query);
+
+                            let mut restart = false;
+                            match query {
+                                Query::CleanKernels(keep) => {
+                                    let result = clean_kernels(keep, false).await;
+                                    ctx.reply(result.map_err(method_error));
+                                }
+                                Query::PreflightCheck => {
+                                    ctx.reply(Ok::<_, MethodErr>((preflight_check(),)));
+                                }
+                                Query::ReleaseReclaim => {
+                                    let progress = |percent| {
+                                        let progress = SignalEvent::ReleaseUpgradeProgress(percent);
+                                        let _ = dbus_tx.try_send(progress);
+                                    };
+
+                                    let result = release::reclaim::reclaim(&progress)
+                                        .await
+                                        .map(|reclaimed| (reclaimed.removed, reclaimed.bytes))
+                                        .map_err(method_error);
+
+                                    ctx.reply(result);
+                                }
+                                Query::ReleasePreview(version) => {
+                                    let result = release_preview(version)
+                                        .await
+                                        .map(|preview| (preview.removed, preview.downgraded));
+
+                                    ctx.reply(result.map_err(method_error));
+                                }
+                                Query::ReleaseSimulate(version) => {
+                                    let result = release_simulate(version).await.map(|simulation| {
+                                        let preview = simulation.preview;
+                                        (
+                                            preview.installed,
+                                            preview.upgraded,
+                                            preview.removed,
+                                            preview.downgraded,
+                                            simulation.download_size,
+                                        )
+                                    });
+
+                                    ctx.reply(result.map_err(method_error));
+                                }
+                                Query::SelfUpdateCheck => {
+                                    ctx.reply(self_update_check().await.map_err(method_error));
+                                }
+                                Query::UpdateCheck(sender) => {
+                                    let result = update_check(sender).await;
+                                    restart = result.as_ref().map_or(false, |&status| status == 1);
+                                    ctx.reply(result.map(|status| (status,)).map_err(method_error));
+                                }
+                            }
+
+                            let _ = fg_tx.send(FgEvent::Reply(ctx));
+                            if restart {
+                                let _ = fg_tx.send(FgEvent::SelfUpgrade);
+                            }
+
+                            // Queries are not operations, and so leave the status alone.
+                            continue;
+                        }
                     }
 
                     cancel.store(false, Ordering::SeqCst);
                     status.store(DaemonStatus::Inactive, Ordering::SeqCst);
+                    operation::end();
+                    let inactive = SignalEvent::StatusChanged(
+                        DaemonStatus::Inactive,
+                        sub_status.load(Ordering::SeqCst),
+                    );
+                    let _ = dbus_tx.send(inactive);
                     info!("event processed");
                 }
             })),
         );
 
+        // An operation which never ended was interrupted by a crash or reboot.
+        let last_error = operation::interrupted().map(|status| {
+            let resume = match release::resume::UpgradeState::load() {
+                Some(_) => ": resume it with `pop-upgrade release resume`",
+                None => "",
+            };
+
+            let why = fomat!("the daemon was interrupted while " (status) (resume));
+            warn!("{}", why);
+            why
+        });
+
         Ok(Daemon {
+            bus,
             cancel,
             dbus_rx,
+            dbus_tx,
             event_tx,
             fetching_state: prog_state,
+            fg_tx,
             fg_rx,
             last_known: Default::default(),
+            last_error,
+            initiator: None,
+            progress: 0,
+            prefetch_check: Instant::now() + RELEASE_PREFETCH_DELAY,
+            prefetching,
+            scheduled: schedule::load(),
             release_upgrade: None,
             status,
             sub_status,
@@ -333,6 +752,8 @@ impl Daemon {
 
     pub fn init() -> Result<(), DaemonError> {
         info!("initializing daemon");
+        Config::load().map_err(DaemonError::Config)?;
+
         fs::create_dir_all(crate::VAR_LIB_DIR)
             .map_err(|why| DaemonError::VarLibDirectory(crate::VAR_LIB_DIR, why))?;
 
@@ -340,9 +761,10 @@ impl Daemon {
             warn!("failure restoring previous boot entry: {}", why);
         }
 
-        let daemon = Self::new()?;
+        let bus = Arc::new(SyncConnection::new_system().map_err(DaemonError::PrivateConnection)?);
+        let connection: &SyncConnection = &bus;
 
-        let connection = Connection::new_system().map_err(DaemonError::PrivateConnection)?;
+        let daemon = Self::new(bus.clone())?;
 
         connection
             .request_name(DBUS_NAME, false, true, false)
@@ -369,11 +791,20 @@ impl Daemon {
 
             let _recovery_event = b.signal::<(u8,), _>(signals::RECOVERY_EVENT, ("event",));
 
+            let _recovery_sync_progress =
+                b.signal::<(u8,), _>(signals::RECOVERY_SYNC_PROGRESS, ("percent",));
+
+            let _recovery_verify_progress =
+                b.signal::<(u8,), _>(signals::RECOVERY_VERIFY_PROGRESS, ("percent",));
+
             let _recovery_result =
                 b.signal::<(u8, String), _>(signals::RECOVERY_RESULT, ("result", "why"));
 
             let _release_event = b.signal::<(u8,), _>(signals::RELEASE_EVENT, ("event",));
 
+            let _release_progress =
+                b.signal::<(u8,), _>(signals::RELEASE_PROGRESS, ("percent",));
+
             let _release_result =
                 b.signal::<(u8, String), _>(signals::RELEASE_RESULT, ("result", "why"));
 
@@ -382,9 +813,82 @@ impl Daemon {
                 ("success", "failed"),
             );
 
+            let _status_changed =
+                b.signal::<(u8, u8), _>(signals::STATUS_CHANGED, ("status", "sub_status"));
+
             let _upgrade_event =
                 b.signal::<(HashMap<String, String>,), _>(signals::PACKAGE_UPGRADE, ("event",));
 
+            let _upgrade_progress =
+                b.signal::<(u8,), _>(signals::PACKAGE_UPGRADE_PROGRESS, ("percent",));
+
+            let _upgrade_stage = b.signal::<(u8, String), _>(
+                signals::PACKAGE_UPGRADE_STAGE,
+                ("stage", "package"),
+            );
+
+            b.property::<u32, _>(properties::API_VERSION)
+                .get(|_ctx: &mut PropContext, _daemon: &mut Daemon| Ok(API_VERSION));
+
+            b.property::<Vec<String>, _>(properties::CAPABILITIES).get(
+                |_ctx: &mut PropContext, _daemon: &mut Daemon| {
+                    Ok(capabilities::ALL.iter().map(|&capability| capability.to_owned()).collect())
+                },
+            );
+
+            b.property::<bool, _>(properties::DEVELOPMENT_RELEASES)
+                .get(|_ctx: &mut PropContext, _daemon: &mut Daemon| {
+                    Ok(crate::development_releases_enabled())
+                })
+                .set(|ctx: &mut PropContext, _daemon: &mut Daemon, enabled: bool| {
+                    // Properties are set on the loop, which cannot wait on the user.
+                    authorize_now(ctx.message().sender(), polkit::DEVELOPMENT_RELEASES)?;
+
+                    let verb = if enabled { "allowing" } else { "disallowing" };
+                    info!("{} upgrades to development releases", verb);
+
+                    crate::set_development_releases(enabled)
+                        .map(|_| Some(enabled))
+                        .map_err(|why| MethodErr::failed(&why))
+                });
+
+            b.property::<String, _>(properties::DOWNLOAD_DEFERRED).get(
+                |_ctx: &mut PropContext, _daemon: &mut Daemon| {
+                    let deferral = crate::network::deferral().err();
+                    Ok(deferral.map_or_else(String::new, |why| why.to_string()))
+                },
+            );
+
+            b.property::<(u8, String), _>(properties::END_OF_LIFE).get(
+                |_ctx: &mut PropContext, daemon: &mut Daemon| {
+                    daemon
+                        .end_of_life()
+                        .map(|eol| (eol.status() as u8, eol.date()))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.property::<(String, String, u8), _>(properties::INTERRUPTED_UPGRADE).get(
+                |_ctx: &mut PropContext, daemon: &mut Daemon| {
+                    // The state of an upgrade in progress is being written as it advances.
+                    if daemon.status.load(Ordering::SeqCst) != DaemonStatus::Inactive {
+                        return Ok((String::new(), String::new(), 0));
+                    }
+
+                    Ok(release::resume::UpgradeState::load().map_or_else(
+                        || (String::new(), String::new(), 0),
+                        |state| (state.from, state.to, state.phase.map_or(0, |phase| phase as u8)),
+                    ))
+                },
+            );
+
+            b.property::<bool, _>(properties::MANAGED)
+                .get(|_ctx: &mut PropContext, _daemon: &mut Daemon| Ok(policy::managed()));
+
+            b.property::<u64, _>(properties::SCHEDULED).get(
+                |_ctx: &mut PropContext, daemon: &mut Daemon| Ok(daemon.scheduled.unwrap_or(0)),
+            );
+
             b.method(
                 methods::CANCEL,
                 (),
@@ -399,27 +903,55 @@ impl Daemon {
                 methods::DISMISS_NOTIFICATION,
                 ("dismiss",),
                 ("dismissed",),
-                |_ctx: &mut Context, daemon: &mut Daemon, (dismiss,): (u8,)| {
+                |ctx: &mut Context, daemon: &mut Daemon, (dismiss,): (u8,)| {
                     let event = DismissEvent::from_u8(dismiss)
                         .ok_or("dismiss value is out of range")
                         .map_err(|why| MethodErr::failed(&why))?;
 
+                    let uid = caller_uid(ctx, &daemon.bus)?;
+
                     daemon
-                        .dismiss_notification(event)
+                        .dismiss_notification(uid, event)
                         .map(|v| (v,))
                         .map_err(|why| MethodErr::failed(&why))
                 },
             );
 
             b.method(
+                methods::NOTIFICATION_PREFERENCES,
+                (),
+                ("dismissed", "snoozed_until"),
+                |ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    let prefs = notification::Preferences::load(caller_uid(ctx, &daemon.bus)?);
+                    let kind = notification::Kind::ReleaseAvailable;
+                    let dismissed = prefs.dismissed(kind).unwrap_or_default().to_owned();
+                    Ok((dismissed, prefs.snoozed_until(kind).unwrap_or(0)))
+                },
+            );
+
+            b.method(
+                methods::NOTIFICATION_SNOOZE,
+                ("until",),
+                (),
+                |ctx: &mut Context, daemon: &mut Daemon, (until,): (u64,)| {
+                    let until = if until == 0 { None } else { Some(until) };
+                    update_notification_preferences(caller_uid(ctx, &daemon.bus)?, |prefs| {
+                        prefs.set_snoozed_until(notification::Kind::ReleaseAvailable, until)
+                    })
+                    .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method_with_cr_custom::<(Vec<String>, bool), (bool, u32, u32), _, _>(
                 methods::FETCH_UPDATES,
                 ("additional_packages", "download_only"),
                 ("updates_available", "completed", "total"),
-                |_ctx: &mut Context,
-                 daemon: &mut Daemon,
+                |ctx: Context,
+                 cr: &mut Crossroads,
                  (additional_packages, download_only): (Vec<String>, bool)| {
-                    daemon
-                        .set_status(
+                    let fetch = move |ctx: Context, daemon: &mut Daemon| {
+                        let result = daemon.set_status(
+                            &ctx,
                             DaemonStatus::FetchingPackages,
                             move |daemon, already_active| {
                                 if already_active {
@@ -436,8 +968,17 @@ impl Daemon {
                                     .map_err(|ref why| format_error(why.as_ref()))
                                 }
                             },
-                        )
-                        .map_err(|why| MethodErr::failed(&why))
+                        );
+
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    };
+
+                    // Packages which are only fetched are not installed.
+                    if download_only {
+                        with_daemon(ctx, cr, fetch)
+                    } else {
+                        authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, fetch)
+                    }
                 },
             );
 
@@ -451,57 +992,172 @@ impl Daemon {
             );
 
             b.method(
+                methods::HELD_PACKAGES,
+                (),
+                ("packages",),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    async_io::block_on(release::holds::held())
+                        .map(|packages| (packages,))
+                        .map_err(|ref why| format_error(why))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method(
+                methods::HISTORY,
+                (),
+                ("entries",),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    let entries = history::load()
+                        .into_iter()
+                        .map(|entry| {
+                            (
+                                <&'static str>::from(entry.operation).to_owned(),
+                                entry.started,
+                                entry.ended,
+                                entry.from.unwrap_or_default(),
+                                entry.to.unwrap_or_default(),
+                                entry.client.unwrap_or_default(),
+                                entry.error.unwrap_or_default(),
+                            )
+                        })
+                        .collect::<Vec<_>>();
+
+                    Ok((entries,))
+                },
+            );
+
+            b.method_with_cr_custom::<(), (), _, _>(
                 methods::PACKAGE_UPGRADE,
                 (),
                 (),
-                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
-                    daemon.set_status(DaemonStatus::PackageUpgrade, move |daemon, active| {
-                        if !active {
-                            daemon
-                                .package_upgrade()
-                                .map_err(|ref why| format_error(why.as_ref()))
-                                .map_err(|why| MethodErr::failed(&why))?;
-                        }
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, |ctx, daemon| {
+                        let result = daemon.set_status(
+                            &ctx,
+                            DaemonStatus::PackageUpgrade,
+                            move |daemon, active| {
+                                if !active {
+                                    daemon
+                                        .package_upgrade()
+                                        .map_err(|ref why| format_error(why.as_ref()))
+                                        .map_err(|why| MethodErr::failed(&why))?;
+                                }
+
+                                Ok(())
+                            },
+                        );
 
-                        Ok(())
+                        reply(ctx, result)
                     })
                 },
             );
 
-            b.method(
+            b.method_with_cr_custom::<(), (Vec<(&'static str, u32, u64)>,), _, _>(
+                methods::CLEAN,
+                (),
+                ("cleaned",),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, |ctx, daemon| {
+                        let result = async_io::block_on(daemon.clean());
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(u32, bool), (Vec<String>, Vec<String>, Vec<String>), _, _>(
+                methods::CLEAN_KERNELS,
+                ("keep", "dry_run"),
+                ("kept", "removed", "entries"),
+                |ctx: Context, cr: &mut Crossroads, (keep, dry_run): (u32, bool)| {
+                    let keep = keep as usize;
+
+                    // A dry run only reports what would be removed, which apt need not wait on.
+                    if dry_run {
+                        let result = async_io::block_on(clean_kernels(keep, true));
+                        return reply(ctx, result.map_err(method_error));
+                    }
+
+                    authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, move |ctx, daemon| {
+                        submit_query(ctx, daemon, Query::CleanKernels(keep))
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(), (Vec<(String, u8, String)>,), _, _>(
+                methods::PREFLIGHT_CHECK,
+                (),
+                ("checks",),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    with_daemon(ctx, cr, |ctx, daemon| {
+                        submit_query(ctx, daemon, Query::PreflightCheck)
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(String,), (), _, _>(
                 methods::RECOVERY_UPGRADE_FILE,
                 ("path",),
                 (),
-                |_ctx: &mut Context, daemon: &mut Daemon, (path,): (String,)| {
-                    daemon.set_status(DaemonStatus::RecoveryUpgrade, move |daemon, active| {
-                        if !active {
-                            daemon
-                                .recovery_upgrade_file(&path)
-                                .map_err(|ref why| format_error(why.as_ref()))
-                                .map_err(|why| MethodErr::failed(&why))?;
-                        }
+                |ctx: Context, cr: &mut Crossroads, (path,): (String,)| {
+                    authorize_then(ctx, cr, polkit::RECOVERY_UPGRADE, move |ctx, daemon| {
+                        let result = daemon.set_status(
+                            &ctx,
+                            DaemonStatus::RecoveryUpgrade,
+                            move |daemon, active| {
+                                if !active {
+                                    daemon
+                                        .recovery_upgrade_file(&path)
+                                        .map_err(|ref why| format_error(why.as_ref()))
+                                        .map_err(|why| MethodErr::failed(&why))?;
+                                }
 
-                        Ok(())
+                                Ok(())
+                            },
+                        );
+
+                        reply(ctx, result)
                     })
                 },
             );
 
-            b.method(
+            b.method_with_cr_custom::<(String, String, u8), (), _, _>(
                 methods::RECOVERY_UPGRADE_RELEASE,
                 ("version", "arch", "flags"),
                 (),
-                |_ctx: &mut Context,
-                 daemon: &mut Daemon,
-                 (version, arch, flags): (String, String, u8)| {
-                    daemon.set_status(DaemonStatus::RecoveryUpgrade, move |daemon, active| {
-                        if !active {
-                            daemon
-                                .recovery_upgrade_release(&version, &arch, flags)
-                                .map_err(|ref why| format_error(why.as_ref()))
-                                .map_err(|why| MethodErr::failed(&why))?;
+                |ctx: Context, cr: &mut Crossroads, (version, arch, flags): (String, String, u8)| {
+                    let overrides = RecoveryReleaseFlags::from_bits_truncate(flags);
+
+                    let checks = move || {
+                        if !overrides.contains(RecoveryReleaseFlags::IGNORE_BATTERY) {
+                            require_power()?;
                         }
 
-                        Ok(())
+                        if !overrides.contains(RecoveryReleaseFlags::ALLOW_METERED) {
+                            require_unmetered()?;
+                        }
+
+                        require_network()
+                    };
+
+                    let action = polkit::RECOVERY_UPGRADE;
+                    authorize_check_then(ctx, cr, action, checks, move |ctx, daemon| {
+                        let result = daemon.set_status(
+                            &ctx,
+                            DaemonStatus::RecoveryUpgrade,
+                            |daemon, active| {
+                                if !active {
+                                    daemon
+                                        .recovery_upgrade_release(&version, &arch, flags)
+                                        .map_err(|ref why| format_error(why.as_ref()))
+                                        .map_err(|why| MethodErr::failed(&why))?;
+                                }
+
+                                Ok(())
+                            },
+                        );
+
+                        reply(ctx, result)
                     })
                 },
             );
@@ -527,52 +1183,69 @@ impl Daemon {
                 },
             );
 
-            b.method(
+            b.method_with_cr_custom::<(u8,), (bool,), _, _>(
                 methods::REFRESH_OS,
                 ("input",),
                 ("enabled",),
-                |_ctx: &mut Context, daemon: &mut Daemon, (input,): (u8,)| {
-                    let value = daemon
-                        .refresh_os(match input {
-                            1u8 => RefreshOp::Enable,
-                            2u8 => RefreshOp::Disable,
-                            _ => RefreshOp::Status,
-                        })
-                        .map_err(|why| MethodErr::failed(&why))?;
-
-                    info!("responding with value of {}", value);
-
-                    Ok((value,))
+                |ctx: Context, cr: &mut Crossroads, (input,): (u8,)| {
+                    let op = match input {
+                        1u8 => RefreshOp::Enable,
+                        2u8 => RefreshOp::Disable,
+                        _ => RefreshOp::Status,
+                    };
+
+                    let refresh = move |ctx: Context, daemon: &mut Daemon| {
+                        let result = daemon.refresh_os(op).map(|value| {
+                            info!("responding with value of {}", value);
+                            (value,)
+                        });
+
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    };
+
+                    if matches!(op, RefreshOp::Status) {
+                        with_daemon(ctx, cr, refresh)
+                    } else {
+                        authorize_then(ctx, cr, polkit::DEFAULT_BOOT, refresh)
+                    }
                 },
             );
 
             b.method(
                 methods::RELEASE_CHECK,
-                ("development",),
-                ("current", "next", "build", "urgent", "is_lts"),
-                |_ctx: &mut Context, daemon: &mut Daemon, (development,): (bool,)| {
+                ("development", "force"),
+                ("current", "next", "build", "urgent", "is_lts", "size", "release_notes"),
+                |_ctx: &mut Context, daemon: &mut Daemon, (development, force): (bool, bool)| {
+                    let development = development || crate::development_releases_enabled();
                     daemon
-                        .release_check(development)
+                        .release_check(development, force)
                         .map(|status| {
                             let is_lts = status.is_lts();
                             let mut urgent = -1;
 
                             if let Ok(release) =
-                                crate::release_api::Release::get_release(status.current, "nvidia")
+                                crate::release_api::Release::get_release(&status.current, "nvidia")
                             {
                                 urgent = release.build as i16;
                             }
 
-                            if status.current == "20.10" {
+                            if &*status.current == "20.10" {
                                 urgent = urgent.max(14);
                             }
 
+                            let (size, release_notes) =
+                                status.build.build().map_or((0, String::new()), |build| {
+                                    (build.size, build.release_notes.clone().unwrap_or_default())
+                                });
+
                             (
                                 String::from(status.current),
                                 String::from(status.next),
                                 status.build.status_code(),
                                 urgent,
                                 is_lts,
+                                size,
+                                release_notes,
                             )
                         })
                         .map_err(|why| MethodErr::failed(&why))
@@ -580,29 +1253,81 @@ impl Daemon {
             );
 
             b.method(
+                methods::RELEASE_NOTES,
+                ("version",),
+                ("notes",),
+                |_ctx: &mut Context, daemon: &mut Daemon, (version,): (String,)| {
+                    daemon
+                        .release_notes(&version)
+                        .map(|notes| (notes,))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method(
+                methods::RELEASE_PATH,
+                ("from", "to"),
+                ("path",),
+                |_ctx: &mut Context, daemon: &mut Daemon, (from, to): (String, String)| {
+                    daemon
+                        .release_path(&from, &to)
+                        .map(|path| (path,))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method_with_cr_custom::<(String,), (Vec<String>, Vec<String>), _, _>(
+                methods::RELEASE_PREVIEW,
+                ("version",),
+                ("removed", "downgraded"),
+                |ctx: Context, cr: &mut Crossroads, (version,): (String,)| {
+                    with_daemon(ctx, cr, |ctx, daemon| {
+                        submit_query(ctx, daemon, Query::ReleasePreview(version))
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(u8, String, String), (), _, _>(
                 methods::RELEASE_UPGRADE,
                 ("how", "from", "to"),
                 (),
-                |_ctx: &mut Context, daemon: &mut Daemon, (how, from, to): (u8, String, String)| {
-                    daemon.set_status(DaemonStatus::ReleaseUpgrade, move |daemon, active| {
-                        if !active {
-                            daemon
-                                .release_upgrade(how, &from, &to)
-                                .map_err(|ref why| format_error(why.as_ref()))
-                                .map_err(|why| MethodErr::failed(&why))?;
-                        }
+                |ctx: Context, cr: &mut Crossroads, (how, from, to): (u8, String, String)| {
+                    release_upgrade(ctx, cr, how, from, to, UpgradeOptions::default())
+                },
+            );
 
-                        Ok(())
-                    })
+            b.method_with_cr_custom::<(u8, String, String, PropMap), (), _, _>(
+                methods::RELEASE_UPGRADE_WITH_OPTIONS,
+                ("how", "from", "to", "options"),
+                (),
+                |ctx: Context,
+                 cr: &mut Crossroads,
+                 (how, from, to, options): (u8, String, String, PropMap)| {
+                    match UpgradeOptions::parse(&options) {
+                        Ok(options) => release_upgrade(ctx, cr, how, from, to, options),
+                        Err(why) => reply::<()>(ctx, Err(why)),
+                    }
                 },
             );
 
-            b.method(
+            b.method_with_cr_custom::<(), (), _, _>(
                 methods::RELEASE_UPGRADE_FINALIZE,
                 (),
                 (),
-                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
-                    daemon.release_upgrade_finalize().map_err(|why| MethodErr::failed(&why))
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::RELEASE_UPGRADE, |ctx, daemon| {
+                        let to = match daemon.release_upgrade.as_ref() {
+                            Some(upgrade) => Some(upgrade.to.to_string()),
+                            None => release::resume::UpgradeState::load().map(|state| state.to),
+                        };
+
+                        if let Err(why) = require_policy(to.as_deref(), UpgradeFlags::empty()) {
+                            return reply::<()>(ctx, Err(why));
+                        }
+
+                        let result = daemon.release_upgrade_finalize();
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    })
                 },
             );
 
@@ -615,44 +1340,239 @@ impl Daemon {
                 },
             );
 
-            b.method(
+            b.method_with_cr_custom::<(), (Vec<String>,), _, _>(
                 methods::RELEASE_REPAIR,
                 (),
+                ("repaired",),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, |ctx, daemon| {
+                        let result = async_io::block_on(daemon.release_repair())
+                            .map(|repaired| (repaired,))
+                            .map_err(|ref why| format_error(why.as_ref()))
+                            .map_err(|why| MethodErr::failed(&why));
+
+                        reply(ctx, result)
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(), (Vec<String>, u64), _, _>(
+                methods::RELEASE_RECLAIM,
+                (),
+                ("removed", "reclaimed"),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, |ctx, daemon| {
+                        submit_query(ctx, daemon, Query::ReleaseReclaim)
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(), (String, String), _, _>(
+                methods::RELEASE_RESUME,
                 (),
-                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
-                    async_io::block_on(daemon.release_repair())
-                        .map_err(|ref why| format_error(why.as_ref()))
-                        .map_err(|why| MethodErr::failed(&why))
+                ("from", "to"),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::RELEASE_UPGRADE, |ctx, daemon| {
+                        if daemon.status.load(Ordering::SeqCst) != DaemonStatus::Inactive {
+                            return reply::<()>(ctx, Err(MethodErr::failed(&"the daemon is busy")));
+                        }
+
+                        let to = release::resume::UpgradeState::load().map(|state| state.to);
+                        if let Err(why) = require_policy(to.as_deref(), UpgradeFlags::empty()) {
+                            return reply::<()>(ctx, Err(why));
+                        }
+
+                        let result = daemon.set_status(
+                            &ctx,
+                            DaemonStatus::ReleaseUpgrade,
+                            move |daemon, _active| {
+                                daemon
+                                    .release_resume()
+                                    .map_err(|ref why| format_error(why.as_ref()))
+                                    .map_err(|why| MethodErr::failed(&why))
+                            },
+                        );
+
+                        reply(ctx, result)
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<
+                (String,),
+                (Vec<String>, Vec<String>, Vec<String>, Vec<String>, u64),
+                _,
+                _,
+            >(
+                methods::RELEASE_SIMULATE,
+                ("version",),
+                ("installed", "upgraded", "removed", "downgraded", "download_size"),
+                |ctx: Context, cr: &mut Crossroads, (version,): (String,)| {
+                    with_daemon(ctx, cr, |ctx, daemon| {
+                        submit_query(ctx, daemon, Query::ReleaseSimulate(version))
+                    })
                 },
             );
 
             b.method(
+                methods::REPO_COMPATIBILITY,
+                ("version",),
+                ("incompatible",),
+                |_ctx: &mut Context, daemon: &mut Daemon, (version,): (String,)| {
+                    daemon
+                        .repo_compatibility(&version)
+                        .map(|incompatible| (incompatible,))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method_with_cr_custom::<(), (), _, _>(
                 methods::RESET,
                 (),
                 (),
-                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
-                    async_io::block_on(daemon.reset()).map_err(|why| MethodErr::failed(&why))
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::RELEASE_UPGRADE, |ctx, daemon| {
+                        let result = async_io::block_on(daemon.reset());
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    })
                 },
             );
 
-            b.method(
-                methods::STATUS,
+            b.method_with_cr_custom::<(u64,), (), _, _>(
+                methods::SCHEDULE,
+                ("timestamp",),
                 (),
-                ("status", "sub_status"),
-                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
-                    let status = daemon.status.load(Ordering::SeqCst) as u8;
-                    let sub_status = daemon.sub_status.load(Ordering::SeqCst) as u8;
+                |ctx: Context, cr: &mut Crossroads, (timestamp,): (u64,)| {
+                    authorize_then(ctx, cr, polkit::RELEASE_UPGRADE, move |ctx, daemon| {
+                        let result = daemon.schedule_upgrade(timestamp);
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    })
+                },
+            );
 
-                    Ok((status, sub_status))
+            b.method_with_cr_custom::<(), (bool,), _, _>(
+                methods::SCHEDULE_CANCEL,
+                (),
+                ("cancelled",),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::RELEASE_UPGRADE, |ctx, daemon| {
+                        let cancelled = daemon.schedule_cancel();
+                        reply(ctx, Ok((cancelled,)))
+                    })
                 },
             );
 
             b.method(
-                methods::UPDATE_CHECK,
+                methods::SNAPSHOT_LIST,
+                (),
+                ("snapshots",),
+                |_ctx: &mut Context, _daemon: &mut Daemon, _inputs: ()| {
+                    snapshot::list()
+                        .map(|snapshots| {
+                            let snapshots = snapshots
+                                .into_iter()
+                                .map(|snapshot| {
+                                    let backend = <&'static str>::from(snapshot.backend);
+                                    (backend.to_owned(), snapshot.name, snapshot.pre_upgrade)
+                                })
+                                .collect::<Vec<_>>();
+
+                            (snapshots,)
+                        })
+                        .map_err(|ref why| format_error(why))
+                        .map_err(|why| MethodErr::failed(&why))
+                },
+            );
+
+            b.method_with_cr_custom::<(String,), (String,), _, _>(
+                methods::SNAPSHOT_RESTORE,
+                ("name",),
+                ("backend",),
+                |ctx: Context, cr: &mut Crossroads, (name,): (String,)| {
+                    authorize_then(ctx, cr, polkit::SNAPSHOT_RESTORE, move |ctx, daemon| {
+                        let result = daemon.snapshot_restore(&name).map(|backend| (backend,));
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(), (), _, _>(
+                methods::STAGE_OFFLINE_UPDATE,
+                (),
+                (),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, |ctx, daemon| {
+                        let result = daemon.stage_offline_update();
+                        reply(ctx, result.map_err(|why| MethodErr::failed(&why)))
+                    })
+                },
+            );
+
+            b.method(
+                methods::STATUS,
+                (),
+                ("status", "sub_status"),
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    let status = daemon.status.load(Ordering::SeqCst) as u8;
+                    let sub_status = daemon.sub_status.load(Ordering::SeqCst) as u8;
+
+                    Ok((status, sub_status))
+                },
+            );
+
+            b.method(
+                methods::STATUS_DETAILS,
+                (),
+                ("status", "sub_status", "progress", "client", "last_error"),
+                |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
+                    let status = daemon.status.load(Ordering::SeqCst);
+                    let sub_status = daemon.sub_status.load(Ordering::SeqCst) as u8;
+
+                    // The initiator and progress of a finished operation are no longer relevant.
+                    let (progress, client) = if status == DaemonStatus::Inactive {
+                        (0, String::new())
+                    } else {
+                        (daemon.progress, daemon.initiator.clone().unwrap_or_default())
+                    };
+
+                    let last_error = daemon.last_error.clone().unwrap_or_default();
+
+                    Ok((status as u8, sub_status, progress, client, last_error))
+                },
+            );
+
+            b.method_with_cr_custom::<(), (String, String), _, _>(
+                methods::SELF_UPDATE_CHECK,
+                (),
+                ("installed", "candidate"),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    authorize_then(ctx, cr, polkit::PACKAGE_UPGRADE, |ctx, daemon| {
+                        submit_query(ctx, daemon, Query::SelfUpdateCheck)
+                    })
+                },
+            );
+
+            b.method_with_cr_custom::<(), (u8,), _, _>(
+                methods::UPDATE_CHECK,
                 (),
                 ("status",),
+                |ctx: Context, cr: &mut Crossroads, _inputs: ()| {
+                    with_daemon(ctx, cr, |ctx, daemon| {
+                        let sender = ctx.message().sender().map(|sender| sender.to_string());
+                        submit_query(ctx, daemon, Query::UpdateCheck(sender))
+                    })
+                },
+            );
+
+            b.method(
+                methods::UPGRADE_PATH,
+                (),
+                ("path",),
                 |_ctx: &mut Context, daemon: &mut Daemon, _inputs: ()| {
-                    Ok((async_io::block_on(daemon.update_and_restart()),))
+                    daemon
+                        .upgrade_path()
+                        .map(|path| (path,))
+                        .map_err(|why| MethodErr::failed(&why))
                 },
             );
         });
@@ -667,21 +1587,33 @@ impl Daemon {
         connection.start_receive(
             MatchRule::new_method_call(),
             Box::new(move |msg, c| {
-                eprintln!("handling message {:#?}", msg);
+                debug!("handling message {:#?}", msg);
                 cr_.lock().unwrap().handle_message(msg, c).unwrap();
                 true
             }),
         );
 
         info!("daemon registered -- listening for new events");
+        sd_notify::ready();
+        sd_notify::status(DaemonStatus::Inactive.into());
+
+        sd_notify::watchdog();
 
         async_io::block_on(async move {
-            release::cleanup().await;
+            let after = release::cleanup().await;
 
             let path = dbus::strings::Path::from_slice("/com/system76/PopUpgrade\0").unwrap();
 
+            // What follows an upgrade is left to the worker, so that its progress is signaled.
+            if let Some(after) = after {
+                let mut lock = cr.lock().unwrap();
+                let daemon: &mut Daemon = lock.data_mut(&path).unwrap();
+                let _ = daemon.submit_event(Event::AfterUpgrade(after));
+            }
+
             loop {
                 let _ = connection.process(std::time::Duration::from_millis(1000));
+
                 let mut lock = cr.lock().unwrap();
                 let daemon: &mut Daemon = lock.data_mut(&path).unwrap();
 
@@ -719,28 +1651,69 @@ impl Daemon {
                     }
                 }
 
+                if daemon.status.load(Ordering::SeqCst) == DaemonStatus::Inactive
+                    && daemon.scheduled.map_or(false, |at| at <= schedule::now())
+                {
+                    daemon.scheduled = None;
+                    schedule::remove();
+
+                    info!("beginning the scheduled release upgrade");
+                    let fg_tx = daemon.fg_tx.clone();
+                    std::thread::spawn(move || {
+                        let _ = fg_tx.send(FgEvent::ScheduledUpgrade(scheduled_checks()));
+                    });
+                }
+
+                if daemon.status.load(Ordering::SeqCst) == DaemonStatus::Inactive
+                    && Instant::now() >= daemon.prefetch_check
+                {
+                    daemon.prefetch_check = Instant::now() + RELEASE_PREFETCH_INTERVAL;
+                    daemon.prefetch_release();
+                }
+
                 while let Ok(fg_event) = fg_receiver.try_recv() {
                     match fg_event {
                         FgEvent::SetUpgradeState(result, action, from, to) => {
                             if result.is_ok() {
+                                notification::dispatch(&Notice::upgrade_downloaded(&to));
                                 info!("setting release upgrade state");
                                 let state = ReleaseUpgradeState { action, from, to };
                                 daemon.release_upgrade = Some(state);
                             }
 
+                            daemon.record_error(result.as_ref().err());
                             daemon.last_known.release_upgrade = result;
                         }
+                        FgEvent::Reply(mut ctx) => {
+                            let _ = ctx.flush_messages(connection);
+                        }
+                        FgEvent::Resume(ctx, resume) => {
+                            if let Some(mut ctx) = resume(ctx, daemon) {
+                                let _ = ctx.flush_messages(connection);
+                            }
+                        }
+                        FgEvent::ScheduledUpgrade(checked) => {
+                            let result = checked.and_then(|next| daemon.scheduled_upgrade(next));
+                            if let Err(why) = result {
+                                let why = fomat!("scheduled release upgrade failed: " (why));
+                                error!("{}", why);
+                                daemon.last_error = Some(why);
+                            }
+                        }
+                        FgEvent::SelfUpgrade => daemon.perform_upgrade = true,
                     }
                 }
 
                 while let Ok(dbus_event) = receiver.try_recv() {
-                    Self::send_signal_message(&connection, {
+                    Self::send_signal_message(connection, {
                         match &dbus_event {
                             SignalEvent::Fetched(..)
                             | SignalEvent::Fetching(_)
+                            | SignalEvent::PackageLockWait(_)
                             | SignalEvent::RecoveryUpgradeEvent(_)
                             | SignalEvent::RecoveryUpgradeResult(_)
                             | SignalEvent::ReleaseUpgradeEvent(_)
+                            | SignalEvent::StatusChanged(..)
                             | SignalEvent::Upgrade(_) => info!("{}", dbus_event),
                             _ => (),
                         }
@@ -751,6 +1724,7 @@ impl Daemon {
                                 let message = Self::signal_message(signals::PACKAGE_FETCH_RESULT)
                                     .append2(status, why);
 
+                                daemon.record_error(result.as_ref().err());
                                 daemon.last_known.fetch = result;
                                 message
                             }
@@ -762,14 +1736,38 @@ impl Daemon {
                                 Self::signal_message(signals::PACKAGE_FETCHING)
                                     .append1(name.as_str())
                             }
+                            SignalEvent::FetchProgress(progress) => {
+                                daemon.progress = percent(progress.total_fetched, progress.total);
+                                Self::signal_message(signals::PACKAGE_FETCH_PROGRESS)
+                                    .append3(progress.package, progress.fetched, progress.size)
+                                    .append2(progress.total_fetched, progress.total)
+                            }
                             SignalEvent::NoConnection => {
                                 Self::signal_message(signals::NO_CONNECTION)
                             }
+                            SignalEvent::PackageLockWait(holder) => {
+                                Self::signal_message(signals::PACKAGE_LOCK_WAIT)
+                                    .append2(holder.pid, holder.name)
+                            }
                             SignalEvent::RecoveryDownloadProgress(progress, total) => {
+                                daemon.progress = percent(progress, total);
                                 Self::signal_message(signals::RECOVERY_DOWNLOAD_PROGRESS)
                                     .append2(progress, total)
                             }
+                            SignalEvent::RecoverySyncProgress(percent) => {
+                                daemon.progress = percent;
+                                Self::signal_message(signals::RECOVERY_SYNC_PROGRESS)
+                                    .append1(percent)
+                            }
+                            SignalEvent::RecoveryVerifyProgress(percent) => {
+                                daemon.progress = percent;
+                                Self::signal_message(signals::RECOVERY_VERIFY_PROGRESS)
+                                    .append1(percent)
+                            }
                             SignalEvent::RecoveryUpgradeEvent(event) => {
+                                // Each event begins a phase whose progress is tracked anew.
+                                daemon.progress = 0;
+                                sd_notify::status(event.into());
                                 Self::signal_message(signals::RECOVERY_EVENT).append1(event as u8)
                             }
                             SignalEvent::RecoveryUpgradeResult(result) => {
@@ -777,13 +1775,45 @@ impl Daemon {
                                 let message = Self::signal_message(signals::RECOVERY_RESULT)
                                     .append2(status, why);
 
+                                daemon.record_error(result.as_ref().err());
                                 daemon.last_known.recovery_upgrade = result;
                                 message
                             }
                             SignalEvent::ReleaseUpgradeEvent(event) => {
+                                // Each event begins a phase whose progress is tracked anew.
+                                daemon.progress = 0;
+                                sd_notify::status(event.into());
                                 Self::signal_message(signals::RELEASE_EVENT).append1(event as u8)
                             }
+                            SignalEvent::ReleaseUpgradeProgress(percent) => {
+                                daemon.progress = percent;
+                                Self::signal_message(signals::RELEASE_PROGRESS).append1(percent)
+                            }
+                            SignalEvent::StatusChanged(status, sub_status) => {
+                                daemon.progress = 0;
+                                sd_notify::status(status.into());
+                                Self::signal_message(signals::STATUS_CHANGED)
+                                    .append2(status as u8, sub_status)
+                            }
                             SignalEvent::Upgrade(ref event) => {
+                                // Typed signals accompany the map, for the events which have them.
+                                if let AptUpgradeEvent::Progress { percent } = event {
+                                    daemon.progress = *percent;
+                                    Self::send_signal_message(
+                                        connection,
+                                        Self::signal_message(signals::PACKAGE_UPGRADE_PROGRESS)
+                                            .append1(*percent),
+                                    );
+                                }
+
+                                if let Some((stage, package)) = signals::AptStage::of(event) {
+                                    Self::send_signal_message(
+                                        connection,
+                                        Self::signal_message(signals::PACKAGE_UPGRADE_STAGE)
+                                            .append2(stage as u8, package),
+                                    );
+                                }
+
                                 Self::signal_message(signals::PACKAGE_UPGRADE)
                                     .append1(event.clone().into_dbus_map())
                             }
@@ -794,15 +1824,17 @@ impl Daemon {
         })
     }
 
-    /// Dismiss future desktop notifications.
-    ///
-    /// Only applicable for LTS releases.
-    fn dismiss_notification(&self, event: DismissEvent) -> Result<bool, String> {
+    /// Dismisses the notification of the next release, for the given user as well as for every
+    /// user if it is an LTS release, or clears the dismissal.
+    fn dismiss_notification(&self, uid: u32, event: DismissEvent) -> Result<bool, String> {
+        let kind = notification::Kind::ReleaseAvailable;
+
         if let DismissEvent::Unset = event {
             dismiss_file_remove()?;
+            update_notification_preferences(uid, |prefs| prefs.set_dismissed(kind, None))?;
             Ok(false)
         } else {
-            let status = self.release_check(false)?;
+            let status = self.release_check(false, false)?;
             if status.is_lts() && status.build.is_ok() {
                 dismiss_file_create(&status.next)?;
 
@@ -813,10 +1845,29 @@ impl Daemon {
                 }
             }
 
+            // Users may dismiss any release for themselves.
+            if event == DismissEvent::ByUser && status.build.is_ok() {
+                let next = Some(String::from(status.next));
+                update_notification_preferences(uid, |prefs| prefs.set_dismissed(kind, next))?;
+            }
+
             Ok(true)
         }
     }
 
+    /// The date on which the current release stops receiving updates.
+    fn end_of_life(&self) -> Result<EolDate, String> {
+        let eol = EolDate::fetch_from(&UpgradePaths::cached()).map_err(|why| format!("{:#}", why))?;
+
+        match eol.status() {
+            EolStatus::Exceeded => warn!("support for {} ended on {}", eol.version, eol.date()),
+            EolStatus::Imminent => warn!("support for {} ends on {}", eol.version, eol.date()),
+            _ => (),
+        }
+
+        Ok(eol)
+    }
+
     async fn fetch_updates<'a>(
         &'a mut self,
         additional_packages: &'a [String],
@@ -849,8 +1900,14 @@ impl Daemon {
     }
 
     fn cancel(&mut self) {
-        info!("cancelling a process which is in progress");
+        if self.status.load(Ordering::SeqCst) == DaemonStatus::Inactive {
+            info!("no process is in progress to cancel");
+            return;
+        }
 
+        // The flag remains set until the worker finishes the process, so that every stage of it
+        // which checks for cancellation will abort.
+        info!("cancelling a process which is in progress");
         self.cancel.store(true, Ordering::SeqCst);
     }
 
@@ -900,34 +1957,201 @@ impl Daemon {
         crate::release::refresh_os(flag).map_err(|ref why| format_error(why))
     }
 
-    fn release_check(&self, development: bool) -> Result<ReleaseStatus, String> {
+    fn release_check(&self, development: bool, force: bool) -> Result<ReleaseStatus, String> {
         info!("performing a release check");
 
-        let status = release::check::next(development).map_err(|ref why| format_error(why))?;
+        let arch = detect_arch().map_err(|ref why| format_error(why))?;
+        let status = release::check::next(development, force, arch)
+            .map_err(|ref why| format_error(why))?;
 
         let mut buffer = String::new();
 
         info!(
-            "Release {{ current: \"{}\", lts: \"{}\",  next: \"{}\", available: {} }}",
+            "Release {{ current: \"{}\", lts: \"{}\",  next: \"{}\", arch: \"{}\", \
+             available: {} }}",
             status.current,
             status.is_lts(),
             status.next,
+            status.arch,
             misc::format_build_number(status.build.status_code(), &mut buffer)
         );
 
         Ok(status)
     }
 
-    fn release_upgrade(&mut self, how: u8, from: &str, to: &str) -> anyhow::Result<()> {
+    /// Fetches the packages of the next release in the background, if configured to. The worker
+    /// checks for the next release, which is not fetched while the battery is low or the
+    /// connection is metered.
+    ///
+    /// Users are notified of new releases by the `pop-upgrade-notify` timer of their session.
+    fn prefetch_release(&self) {
+        if !Config::load_or_default().release.prefetch
+            || self.prefetching.load(Ordering::SeqCst)
+            || release::upgrade_in_progress()
+        {
+            return;
+        }
+
+        self.prefetching.store(true, Ordering::SeqCst);
+        if self.submit_event(Event::Prefetch).is_err() {
+            self.prefetching.store(false, Ordering::SeqCst);
+        }
+    }
+
+    /// The release notes of a release, as markdown.
+    ///
+    /// If `version` is empty, the notes of the next release are fetched.
+    fn release_notes(&self, version: &str) -> Result<String, String> {
+        info!("fetching release notes for {:?}", version);
+
+        let next;
+        let version = if version.is_empty() {
+            next = self.release_check(crate::development_releases_enabled(), false)?.next;
+            &next
+        } else {
+            version
+        };
+
+        let arch = detect_arch().map_err(|ref why| format_error(why))?;
+        release::notes::fetch(version, arch).map_err(|ref why| format_error(why))
+    }
+
+    /// The releases to upgrade through, in order, to get from `from` to `to`.
+    ///
+    /// If `from` is empty, the plan starts from the current release.
+    fn release_path(&self, from: &str, to: &str) -> Result<Vec<String>, String> {
+        info!("planning an upgrade path from {:?} to {}", from, to);
+
+        let paths = UpgradePaths::cached();
+
+        let current;
+        let from = if from.is_empty() {
+            let version = Version::detect().map_err(|ref why| format_error(why))?;
+            current = release::check::release_str(&paths, version.major, version.minor)
+                .map_err(|ref why| format_error(why))?
+                .to_owned();
+            &current
+        } else {
+            from
+        };
+
+        let hops = paths
+            .plan(from, to, crate::development_releases_enabled())
+            .map_err(|ref why| format_error(why))?;
+
+        let lts_only = Config::load_or_default().release.channel == Channel::Lts;
+
+        // On the LTS channel, interim releases are skipped by upgrading from LTS to LTS.
+        Ok(hops
+            .into_iter()
+            .map(|hop| &hop.next)
+            .filter(|&next| {
+                !lts_only || next == to || paths.get(next).map_or(false, |path| path.lts)
+            })
+            .cloned()
+            .collect())
+    }
+
+    /// The releases which upgrading from the current release would pass through, beginning with
+    /// the current release, with whether each is an LTS, and whether the upgrade to it is offered.
+    fn upgrade_path(&self) -> Result<Vec<(String, bool, u8)>, String> {
+        let paths = UpgradePaths::cached();
+
+        let version = Version::detect().map_err(|ref why| format_error(why))?;
+        let current = release::check::release_str(&paths, version.major, version.minor)
+            .map_err(|ref why| format_error(why))?;
+
+        let lts_only = Config::load_or_default().release.channel == Channel::Lts;
+
+        Ok(paths
+            .route(current, crate::development_releases_enabled(), lts_only)
+            .into_iter()
+            .map(|stop| (stop.version.to_owned(), stop.lts, stop.upgrade as u8))
+            .collect())
+    }
+
+    /// Third party sources which do not publish `version`, with the URIs of the repositories
+    /// lacking it.
+    ///
+    /// If `version` is empty, the sources are checked against the next release.
+    fn repo_compatibility(&self, version: &str) -> Result<Vec<(String, Vec<String>)>, String> {
+        info!("checking third party sources for compatibility with {:?}", version);
+
+        let next;
+        let version = if version.is_empty() {
+            next = self.release_check(crate::development_releases_enabled(), false)?.next;
+            &next
+        } else {
+            version
+        };
+
+        let current = Version::detect().map_err(|ref why| format_error(why))?;
+        let current = Codename::try_from(current)
+            .map_err(|_| String::from("the current release does not have a codename"))?;
+
+        let report = release::ppa::incompatible(
+            <&'static str>::from(current),
+            release::codename_from_version(version),
+        )
+        .map_err(|ref why| format_error(why))?;
+
+        Ok(report
+            .into_iter()
+            .map(|source| (source.path.display().to_string(), source.uris))
+            .collect())
+    }
+
+    fn release_upgrade(
+        &mut self,
+        how: u8,
+        from: &str,
+        to: &str,
+        lift_holds: bool,
+        conffiles: u8,
+    ) -> anyhow::Result<()> {
         info!("upgrading release from {} to {}, with {}", from, to, how);
 
         let how = ReleaseUpgradeMethod::from_u8(how)
             .context("provided upgrade `how` value is out of range")?;
 
-        let event = Event::ReleaseUpgrade { how, from: from.into(), to: to.into() };
+        // The configured policy is used unless the caller overrides it.
+        let conffiles = match conffiles {
+            0 => Config::load_or_default().release.conffiles,
+            policy => ConffilePolicy::from_u8(policy)
+                .context("provided `conffiles` policy is out of range")?,
+        };
+
+        let event = Event::ReleaseUpgrade {
+            how,
+            from: from.into(),
+            to: to.into(),
+            lift_holds,
+            conffiles,
+            resume: None,
+        };
+
         self.submit_event(event)
     }
 
+    /// Resumes the release upgrade which was interrupted, returning the releases it is between.
+    fn release_resume(&mut self) -> anyhow::Result<(String, String)> {
+        let state = release::resume::UpgradeState::load()
+            .context("there is no interrupted release upgrade to resume")?;
+
+        info!("resuming release upgrade from {} to {}", state.from, state.to);
+
+        let release::resume::UpgradeState { method, from, to, lift_holds, phase, .. } = state;
+        let releases = (from.clone(), to.clone());
+
+        // The upgrade resumes with the policy that it was started with.
+        let conffiles = ConffilePolicy::recorded()
+            .unwrap_or_else(|| Config::load_or_default().release.conffiles);
+
+        let event =
+            Event::ReleaseUpgrade { how: method, from, to, lift_holds, conffiles, resume: phase };
+        self.submit_event(event).map(|_| releases)
+    }
+
     fn release_upgrade_finalize(&mut self) -> Result<(), String> {
         match self.release_upgrade.as_ref() {
             Some(ReleaseUpgradeState { action, from, to }) => {
@@ -940,45 +2164,192 @@ impl Daemon {
         }
     }
 
-    async fn release_repair(&mut self) -> anyhow::Result<()> {
-        crate::repair::repair().await?;
+    async fn release_repair(&mut self) -> anyhow::Result<Vec<String>> {
+        if self.status.load(Ordering::SeqCst) != DaemonStatus::Inactive {
+            return Err(anyhow!("the system cannot be repaired while the daemon is busy"));
+        }
 
-        Ok(())
+        Ok(crate::repair::repair().await?)
+    }
+
+    /// Removes the files which pop-upgrade leaves behind, returning the category, the number of
+    /// files removed, and the bytes reclaimed for each category which was cleaned.
+    async fn clean(&mut self) -> Result<(Vec<(&'static str, u32, u64)>,), String> {
+        info!("removing files which pop-upgrade left behind");
+
+        if self.status.load(Ordering::SeqCst) != DaemonStatus::Inactive {
+            return Err("files cannot be cleaned while the daemon is busy".into());
+        }
+
+        let cleaned = clean::clean(self.prefetching.load(Ordering::SeqCst))
+            .await
+            .into_iter()
+            .map(|cleaned| (cleaned.category.id(), cleaned.removed.len() as u32, cleaned.bytes))
+            .collect();
+
+        Ok((cleaned,))
     }
 
     async fn reset(&mut self) -> Result<(), String> {
         info!("resetting daemon");
 
         self.status.store(DaemonStatus::Inactive, Ordering::SeqCst);
+        operation::end();
         self.sub_status.store(0, Ordering::SeqCst);
         self.fetching_state.store((0, 0), Ordering::SeqCst);
+        let _ = self.dbus_tx.send(SignalEvent::StatusChanged(DaemonStatus::Inactive, 0));
         self.release_upgrade = None;
+        self.last_error = None;
+        self.initiator = None;
 
-        release::cleanup().await;
+        if let Some(after) = release::cleanup().await {
+            self.submit_event(Event::AfterUpgrade(after)).map_err(|why| why.to_string())?;
+        }
 
         Ok(())
     }
 
-    fn send_signal_message(connection: &Connection, message: Message) {
+    fn send_signal_message(connection: &SyncConnection, message: Message) {
         if let Err(()) = connection.send(message) {
             error!("failed to send dbus signal message");
         }
     }
 
-    fn set_status<T, E, F>(&mut self, status: DaemonStatus, mut func: F) -> Result<T, E>
+    fn set_status<T, E, F>(&mut self, ctx: &Context, status: DaemonStatus, func: F) -> Result<T, E>
     where
         F: FnMut(&mut Self, bool) -> Result<T, E>,
+        E: From<LockError>,
     {
-        let already_active = self.status.swap(status, Ordering::SeqCst) == status;
+        let bus = self.bus.clone();
+        let initiator = || ctx.message().sender().map(|sender| describe_client(&bus, &sender));
+        self.set_status_by(initiator, status, func)
+    }
+
+    /// Sets the status on behalf of the initiator, which is only described if the status is new.
+    fn set_status_by<T, E, I, F>(
+        &mut self,
+        initiator: I,
+        status: DaemonStatus,
+        mut func: F,
+    ) -> Result<T, E>
+    where
+        I: FnOnce() -> Option<String>,
+        F: FnMut(&mut Self, bool) -> Result<T, E>,
+        E: From<LockError>,
+    {
+        let already_active = self.status.load(Ordering::SeqCst) == status;
+        if !already_active {
+            operation::begin(status)?;
+            self.status.store(status, Ordering::SeqCst);
+            self.initiator = initiator();
+            self.signal_status(status);
+        }
+
         match func(self, already_active) {
             Ok(value) => Ok(value),
             Err(why) => {
                 self.status.store(DaemonStatus::Inactive, Ordering::SeqCst);
+                if !already_active {
+                    operation::end();
+                    self.signal_status(DaemonStatus::Inactive);
+                }
+
                 Err(why)
             }
         }
     }
 
+    /// Schedules a release upgrade to the next release, to begin at the given time.
+    fn schedule_upgrade(&mut self, timestamp: u64) -> Result<(), String> {
+        if timestamp < schedule::now() {
+            return Err("the time to schedule the upgrade for has already passed".into());
+        }
+
+        // The release is not known until the upgrade begins, when the policy is checked again.
+        if let Some(policy) = Policy::load().map_err(|ref why| format_error(why))? {
+            let at = chrono::Local
+                .timestamp_opt(timestamp as i64, 0)
+                .single()
+                .ok_or("the time to schedule the upgrade for is out of range")?;
+
+            policy.permits(None, at.naive_local()).map_err(|ref why| format_error(why))?;
+        }
+
+        info!("scheduling a release upgrade to begin at {}", timestamp);
+
+        schedule::store(timestamp)
+            .map_err(|why| format!("failed to record the scheduled upgrade: {}", why))?;
+
+        self.scheduled = Some(timestamp);
+        Ok(())
+    }
+
+    /// Cancels the scheduled release upgrade, returning whether one was scheduled.
+    fn schedule_cancel(&mut self) -> bool {
+        info!("cancelling the scheduled release upgrade");
+        schedule::remove();
+        self.scheduled.take().is_some()
+    }
+
+    /// Begins the scheduled release upgrade, to the next release, once `scheduled_checks` passed.
+    fn scheduled_upgrade(&mut self, status: ReleaseStatus) -> Result<(), String> {
+        let conffiles = Config::load_or_default().release.conffiles;
+
+        let initiator = || Some("scheduled upgrade".to_owned());
+        self.set_status_by(initiator, DaemonStatus::ReleaseUpgrade, move |daemon, _active| {
+            daemon
+                .release_upgrade(
+                    ReleaseUpgradeMethod::Offline as u8,
+                    &status.current,
+                    &status.next,
+                    false,
+                    conffiles as u8,
+                )
+                .map_err(|ref why| format_error(why.as_ref()))
+        })
+    }
+
+    fn snapshot_restore(&self, name: &str) -> Result<String, String> {
+        info!("restoring snapshot {}", name);
+
+        if self.status.load(Ordering::SeqCst) != DaemonStatus::Inactive {
+            return Err("snapshots cannot be restored while the daemon is busy".into());
+        }
+
+        snapshot::restore(name)
+            .map(|backend| <&'static str>::from(backend).to_owned())
+            .map_err(|ref why| format_error(why))
+    }
+
+    /// Stages the updates fetched by a download-only `FetchUpdates` to be installed by
+    /// systemd on the next boot.
+    fn stage_offline_update(&mut self) -> Result<(), String> {
+        info!("staging updates to be installed on the next boot");
+
+        if self.status.load(Ordering::SeqCst) != DaemonStatus::Inactive {
+            return Err("updates cannot be staged while the daemon is busy".into());
+        }
+
+        if let Err(ref why) = self.last_known.fetch {
+            return Err(format!("updates were not fetched: {}", format_error(why)));
+        }
+
+        release::systemd::update_set().map_err(|ref why| format_error(why))
+    }
+
+    /// Records the error of an operation which failed, to be reported by the `StatusDetails` method.
+    fn record_error<E: std::error::Error + 'static>(&mut self, error: Option<&E>) {
+        if let Some(why) = error {
+            self.last_error = Some(format_error(why));
+        }
+    }
+
+    /// Signals a change of status, so that clients need not poll for it.
+    fn signal_status(&self, status: DaemonStatus) {
+        let sub_status = self.sub_status.load(Ordering::SeqCst);
+        let _ = self.dbus_tx.send(SignalEvent::StatusChanged(status, sub_status));
+    }
+
     fn signal_message(name: &'static str) -> Message {
         Message::new_signal(DBUS_PATH, DBUS_NAME, name).unwrap()
     }
@@ -991,47 +2362,528 @@ impl Daemon {
             return Err(anyhow::anyhow!("{}", desc));
         }
 
-        let _ = self.event_tx.send(event);
+        let _ = self.event_tx.send((event, self.initiator.clone()));
         Ok(())
     }
+}
+
+/// Submits a query to the background thread, which replies to its method call once it has been
+/// answered, so that the loop which handles DBus messages is not blocked while apt is waited on.
+fn submit_query(ctx: Context, daemon: &mut Daemon, query: Query) -> Option<Context> {
+    let refused = if daemon.status.load(Ordering::SeqCst) != DaemonStatus::Inactive {
+        "the daemon is busy"
+    } else if daemon.event_tx.is_full() {
+        "too many requests sent -- refusing additional requests"
+    } else {
+        let _ = daemon.event_tx.send((Event::Query(query, ctx), None));
+        return None;
+    };
+
+    warn!("refusing {:?}: {}", query, refused);
+    reply::<()>(ctx, Err(MethodErr::failed(&refused)))
+}
+
+/// Handles a method call with the daemon, which replies to it unless it returns no context.
+fn with_daemon<F>(ctx: Context, cr: &mut Crossroads, handle: F) -> Option<Context>
+where
+    F: FnOnce(Context, &mut Daemon) -> Option<Context>,
+{
+    match cr.data_mut::<Daemon>(ctx.path()) {
+        Some(daemon) => handle(ctx, daemon),
+        None => {
+            let why = MethodErr::no_path(ctx.path());
+            reply::<()>(ctx, Err(why))
+        }
+    }
+}
 
-    async fn update_and_restart(&mut self) -> u8 {
-        info!("updating apt sources");
-        let _ = AptGet::new().update().await;
+/// Asks polkit to authorize the caller of a method on a thread of its own, as the user may take
+/// some time to authenticate, and then resumes handling the call on the loop which handles DBus
+/// messages. Callers which are not authorized are replied to with the error.
+fn authorize_then<F>(
+    ctx: Context,
+    cr: &mut Crossroads,
+    action: &'static str,
+    resume: F,
+) -> Option<Context>
+where
+    F: FnOnce(Context, &mut Daemon) -> Option<Context> + Send + 'static,
+{
+    authorize_check_then(ctx, cr, action, || Ok(()), resume)
+}
+
+/// As `authorize_then`, but the `checks` which precede the method are also run off the loop,
+/// once the caller has been authorized, as they may have to reach the network.
+fn authorize_check_then<C, F>(
+    ctx: Context,
+    cr: &mut Crossroads,
+    action: &'static str,
+    checks: C,
+    resume: F,
+) -> Option<Context>
+where
+    C: FnOnce() -> Result<(), MethodErr> + Send + 'static,
+    F: FnOnce(Context, &mut Daemon) -> Option<Context> + Send + 'static,
+{
+    with_daemon(ctx, cr, move |ctx, daemon| {
+        let fg_tx = daemon.fg_tx.clone();
+        let sender = ctx.message().sender().map(|sender| sender.into_static());
+
+        std::thread::spawn(move || {
+            let event = match authorize(sender, action).and_then(|()| checks()) {
+                Ok(()) => FgEvent::Resume(ctx, Box::new(resume)),
+                Err(why) => {
+                    let mut ctx = ctx;
+                    ctx.reply::<()>(Err(why));
+                    FgEvent::Reply(ctx)
+                }
+            };
 
-        if let Ok(true) = upgrade_required().await {
-            if async_fs::File::create(RESTART_SCHEDULED).await.is_ok() {
-                info!("installing latest version of `pop-upgrade`, which will restart the daemon");
-                self.perform_upgrade = true;
-                return 1;
+            let _ = fg_tx.send(event);
+        });
+
+        None
+    })
+}
+
+/// The next release, if one is available, and it may be fetched in the background now.
+fn prefetch_target() -> Result<String, String> {
+    let arch = detect_arch().map_err(|ref why| format_error(why))?;
+    let status = release::check::next(false, false, arch).map_err(|ref why| format_error(why))?;
+    if !status.build.is_ok() {
+        return Err(format!("no release is available to upgrade to from {}", status.current));
+    }
+
+    crate::power::check().map_err(|why| why.to_string())?;
+    crate::network::deferral().map_err(|why| why.to_string())?;
+    crate::network::check().map_err(|why| why.to_string())?;
+
+    Ok(status.next.into())
+}
+
+/// The next release, if the scheduled upgrade to it may begin. Nobody may be present to override
+/// the checks of the battery and connection, or to lift the holds on packages, so the upgrade
+/// fails if any check fails.
+fn scheduled_checks() -> Result<ReleaseStatus, String> {
+    crate::power::check().map_err(|why| why.to_string())?;
+    crate::network::deferral().map_err(|why| why.to_string())?;
+    crate::network::check().map_err(|why| why.to_string())?;
+
+    let arch = detect_arch().map_err(|ref why| format_error(why))?;
+    let status = release::check::next(false, false, arch).map_err(|ref why| format_error(why))?;
+    if !status.build.is_ok() {
+        return Err(format!("no release is available to upgrade to from {}", status.current));
+    }
+
+    if let Some(policy) = Policy::load().map_err(|ref why| format_error(why))? {
+        let now = chrono::Local::now().naive_local();
+        policy.permits(Some(&*status.next), now).map_err(|ref why| format_error(why))?;
+    }
+
+    let codename = release::codename_from_version(&status.next);
+    let failed = preflight::run(&preflight::checks(Some(codename)))
+        .0
+        .into_iter()
+        .filter(|check| check.status == preflight::Status::Fail)
+        .map(|check| fomat!((check.name) ": " (check.message)))
+        .collect::<Vec<_>>();
+
+    if !failed.is_empty() {
+        return Err(fomat!("preflight checks failed: " (failed.join("; "))));
+    }
+
+    let held = async_io::block_on(release::holds::held()).map_err(|ref why| format_error(why))?;
+    if !held.is_empty() {
+        return Err(fomat!("packages are held, which the upgrade cannot lift: " (held.join(", "))));
+    }
+
+    Ok(status)
+}
+
+/// Removes every kernel except for the running kernel, the `keep` most recent of the others,
+/// and a kernel older than the running kernel, returning the versions of the kept and removed
+/// kernels, and the removed loader entries.
+async fn clean_kernels(
+    keep: usize,
+    dry_run: bool,
+) -> anyhow::Result<(Vec<String>, Vec<String>, Vec<String>)> {
+    let plan = kernels::plan(kernels::installed().await?, &kernels::running()?, keep);
+
+    let entries = if dry_run { Vec::new() } else { kernels::remove(&plan.removed).await? };
+
+    let versions = |kernels: Vec<kernels::Kernel>| {
+        kernels.into_iter().map(|kernel| kernel.version).collect::<Vec<_>>()
+    };
+
+    Ok((versions(plan.kept), versions(plan.removed), entries))
+}
+
+/// Simulates the upgrade to the release, or to the next release if it is empty.
+async fn release_simulate(version: String) -> anyhow::Result<release::preview::Simulation> {
+    info!("simulating the upgrade to {:?}", version);
+
+    let version = release_or_next(version)?;
+    Ok(release::preview::simulation(release::codename_from_version(&version)).await?)
+}
+
+/// Packages which an upgrade to `version` would remove or downgrade.
+///
+/// If `version` is empty, the upgrade to the next release is previewed.
+async fn release_preview(version: String) -> anyhow::Result<release::preview::Preview> {
+    info!("previewing the upgrade to {:?}", version);
+
+    let version = release_or_next(version)?;
+    Ok(release::preview::preview(release::codename_from_version(&version)).await?)
+}
+
+/// The release, or the next release if it is empty.
+fn release_or_next(version: String) -> anyhow::Result<String> {
+    if version.is_empty() {
+        let development = crate::development_releases_enabled();
+        Ok(release::check::next(development, false, detect_arch()?)?.next.into())
+    } else {
+        Ok(version)
+    }
+}
+
+/// Runs the checks which precede an upgrade, returning the name, status, and message of each.
+fn preflight_check() -> Vec<(String, u8, String)> {
+    info!("performing preflight checks");
+
+    // Packages are only checked against the next release if one is available.
+    let next = detect_arch()
+        .map_err(|ref why| format_error(why))
+        .and_then(|arch| {
+            let development = crate::development_releases_enabled();
+            release::check::next(development, false, arch).map_err(|ref why| format_error(why))
+        })
+        .map_err(|why| warn!("skipping the checks of the next release: {}", why))
+        .ok()
+        .filter(|status| status.build.is_ok())
+        .map(|status| release::codename_from_version(&status.next).to_owned());
+
+    preflight::run(&preflight::checks(next.as_deref()))
+        .0
+        .into_iter()
+        .map(|check| (check.name, check.status as u8, check.message))
+        .collect()
+}
+
+/// The options of a release upgrade, which `ReleaseUpgradeWithOptions` accepts by name.
+#[derive(Clone, Copy, Debug, Default)]
+struct UpgradeOptions {
+    lift_holds: bool,
+    /// A `ConffilePolicy`, or `0` for the configured policy.
+    conffiles:  u8,
+    /// `UpgradeFlags` which override the checks that precede the upgrade.
+    flags:      u8,
+}
+
+impl UpgradeOptions {
+    const CONFFILES: &'static str = "conffiles";
+    const FLAGS: &'static str = "flags";
+    const LIFT_HOLDS: &'static str = "lift-holds";
+
+    /// Parses the options, refusing those which are unknown or of the wrong type, rather than
+    /// upgrading without them.
+    fn parse(options: &PropMap) -> Result<Self, MethodErr> {
+        fn get<T: Copy + Default + 'static>(options: &PropMap, key: &str) -> Result<T, MethodErr> {
+            match options.get(key) {
+                Some(_) => prop_cast::<T>(options, key)
+                    .copied()
+                    .ok_or_else(|| invalid_args(fomat!("option " (key) " is of the wrong type"))),
+                None => Ok(T::default()),
             }
         }
 
-        0
+        let known = [Self::CONFFILES, Self::FLAGS, Self::LIFT_HOLDS];
+        if let Some(unknown) = options.keys().find(|key| !known.contains(&key.as_str())) {
+            return Err(invalid_args(fomat!("unknown option " (unknown))));
+        }
+
+        Ok(Self {
+            lift_holds: get(options, Self::LIFT_HOLDS)?,
+            conffiles:  get(options, Self::CONFFILES)?,
+            flags:      get(options, Self::FLAGS)?,
+        })
     }
 }
 
-pub async fn upgrade_required() -> anyhow::Result<bool> {
-    let (_, mut policies) = apt_cmd::AptCache::new().policy(&["pop-upgrade"]).await?;
+/// Refuses a method call whose arguments are invalid.
+fn invalid_args(why: String) -> MethodErr {
+    MethodErr::from(("org.freedesktop.DBus.Error.InvalidArgs", why))
+}
 
-    if let Some(policy) = policies.next().await {
-        if policy.installed != policy.candidate {
-            return Ok(true);
+/// Begins a release upgrade once its caller has been authorized, and its checks have passed.
+fn release_upgrade(
+    ctx: Context,
+    cr: &mut Crossroads,
+    how: u8,
+    from: String,
+    to: String,
+    options: UpgradeOptions,
+) -> Option<Context> {
+    let UpgradeOptions { lift_holds, conffiles, flags } = options;
+    let flags = UpgradeFlags::from_bits_truncate(flags);
+
+    let target = to.clone();
+    let checks = move || {
+        if !flags.contains(UpgradeFlags::IGNORE_BATTERY) {
+            require_power()?;
         }
+
+        if !flags.contains(UpgradeFlags::ALLOW_METERED) {
+            require_unmetered()?;
+        }
+
+        require_network()?;
+        require_policy(Some(target.as_str()), flags)
+    };
+
+    authorize_check_then(ctx, cr, polkit::RELEASE_UPGRADE, checks, move |ctx, daemon| {
+        let result = daemon.set_status(&ctx, DaemonStatus::ReleaseUpgrade, |daemon, active| {
+            if !active {
+                daemon
+                    .release_upgrade(how, &from, &to, lift_holds, conffiles)
+                    .map_err(|ref why| format_error(why.as_ref()))
+                    .map_err(|why| MethodErr::failed(&why))?;
+            }
+
+            Ok(())
+        });
+
+        reply(ctx, result)
+    })
+}
+
+/// Replies to a method call with the result.
+fn reply<OA: AppendAll>(mut ctx: Context, result: Result<OA, MethodErr>) -> Option<Context> {
+    ctx.reply(result);
+    Some(ctx)
+}
+
+fn method_error(why: anyhow::Error) -> MethodErr { MethodErr::failed(&format_error(why.as_ref())) }
+
+/// Updates the apt sources, and prepares to update pop-upgrade if a newer version is available,
+/// returning `1` if the daemon will update itself and restart. The client with the bus name must
+/// be authorized to install the update, which it is only asked for if there is one.
+async fn update_check(sender: Option<String>) -> anyhow::Result<u8> {
+    info!("updating apt sources");
+
+    // Clients check for an update before every command, which should work offline.
+    if let Err(why) = AptGet::new().update().await {
+        warn!("failed to update the apt sources: {}", why);
+    }
+
+    if !upgrade_required().await? {
+        return Ok(0);
     }
 
-    Ok(false)
+    if let Err(why) = polkit::authorize(sender.as_deref(), polkit::PACKAGE_UPGRADE, true) {
+        warn!("not updating pop-upgrade: {}", format_error(&why));
+        return Ok(0);
+    }
+
+    async_fs::File::create(RESTART_SCHEDULED)
+        .await
+        .with_context(|| fomat!("failed to create " (RESTART_SCHEDULED)))?;
+
+    info!("installing latest version of `pop-upgrade`, which will restart the daemon");
+    Ok(1)
 }
 
-pub fn result_signal<E: ::std::fmt::Display>(result: Result<&(), &E>) -> (u8, String) {
+pub async fn upgrade_required() -> anyhow::Result<bool> {
+    Ok(match self_versions().await? {
+        Some((installed, candidate)) => is_newer(&installed, &candidate).await,
+        None => false,
+    })
+}
+
+/// Whether the candidate version of a package is newer than the installed version, by dpkg's
+/// ordering of versions, so that a local build which is newer than the candidate is kept.
+async fn is_newer(installed: &str, candidate: &str) -> bool {
+    if installed == "(none)" || candidate == "(none)" || candidate.is_empty() {
+        return false;
+    }
+
+    async_process::Command::new("dpkg")
+        .args(&["--compare-versions", installed, "lt", candidate])
+        .status()
+        .await
+        .map_or(false, |status| status.success())
+}
+
+/// The installed and candidate versions of pop-upgrade, according to the apt cache.
+async fn self_versions() -> anyhow::Result<Option<(String, String)>> {
+    let (_, mut policies) = apt_cmd::AptCache::new().policy(&["pop-upgrade"]).await?;
+
+    Ok(policies
+        .next()
+        .await
+        .map(|policy| (policy.installed.to_string(), policy.candidate.to_string())))
+}
+
+/// Updates the apt sources, and reports the installed version of pop-upgrade, along with the
+/// candidate version if it is newer, without installing the candidate.
+async fn self_update_check() -> anyhow::Result<(String, String)> {
+    info!("updating apt sources to check for a newer pop-upgrade");
+    AptGet::new().update().await.context("failed to update the apt sources")?;
+
+    let (installed, candidate) =
+        self_versions().await?.context("the apt cache has no policy for pop-upgrade")?;
+
+    let candidate = if is_newer(&installed, &candidate).await { candidate } else { String::new() };
+    Ok((installed, candidate))
+}
+
+pub fn result_signal<E: OperationError>(result: Result<&(), &E>) -> (u8, String) {
     let status = match result {
-        Ok(_) => 0u8,
-        Err(_) => 1,
+        Ok(_) => ResultStatus::Success,
+        Err(why) if why.is_cancelled() => ResultStatus::Cancelled,
+        Err(_) => ResultStatus::Failure,
     };
 
     let why: String = result.err().map(|why| fomat!((why))).unwrap_or_default();
 
-    (status, why)
+    (status as u8, why)
+}
+
+/// Names the process behind a client's unique bus name, falling back to the bus name itself.
+fn describe_client(bus: &SyncConnection, sender: &str) -> String {
+    let pid: Result<(u32,), dbus::Error> = bus
+        .with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", Duration::from_secs(5))
+        .method_call("org.freedesktop.DBus", "GetConnectionUnixProcessID", (sender,));
+
+    match pid {
+        Ok((pid,)) => match fs::read_to_string(["/proc/", &pid.to_string(), "/comm"].concat()) {
+            Ok(comm) => fomat!((comm.trim()) " (pid " (pid) ")"),
+            Err(_) => fomat!("pid " (pid)),
+        },
+        Err(_) => sender.to_owned(),
+    }
+}
+
+/// The ID of the user behind the client which sent the message.
+fn caller_uid(ctx: &Context, bus: &SyncConnection) -> Result<u32, MethodErr> {
+    let sender = ctx.message().sender().ok_or_else(|| MethodErr::failed(&"no sender"))?;
+
+    let (uid,): (u32,) = bus
+        .with_proxy("org.freedesktop.DBus", "/org/freedesktop/DBus", Duration::from_secs(5))
+        .method_call("org.freedesktop.DBus", "GetConnectionUnixUser", (&*sender,))
+        .map_err(|why| MethodErr::failed(&format_error(&why)))?;
+
+    Ok(uid)
+}
+
+/// Changes the notification preferences of the given user.
+fn update_notification_preferences<F: FnOnce(&mut notification::Preferences)>(
+    uid: u32,
+    func: F,
+) -> Result<(), String> {
+    let mut prefs = notification::Preferences::load(uid);
+    func(&mut prefs);
+    prefs.store(uid).map_err(|why| fomat!("failed to store notification preferences: " (why)))
+}
+
+/// The percentage of a task which has completed.
+fn percent(done: u64, total: u64) -> u8 {
+    if total == 0 {
+        0
+    } else {
+        (done.min(total) * 100 / total) as u8
+    }
+}
+
+/// Refuses to perform an action unless polkit authorizes the client with the given bus name,
+/// with an error that clients may recognize by its name. The user may be prompted to
+/// authenticate, and so this is not to be called on the loop which handles DBus messages.
+fn authorize(
+    sender: Option<dbus::strings::BusName>,
+    action: &'static str,
+) -> Result<(), MethodErr> {
+    check_authorization(sender, action, true)
+}
+
+/// Refuses to perform an action unless polkit authorizes the client without prompting the user,
+/// which polkit answers at once.
+fn authorize_now(
+    sender: Option<dbus::strings::BusName>,
+    action: &'static str,
+) -> Result<(), MethodErr> {
+    check_authorization(sender, action, false)
+}
+
+fn check_authorization(
+    sender: Option<dbus::strings::BusName>,
+    action: &'static str,
+    interactive: bool,
+) -> Result<(), MethodErr> {
+    crate::polkit::authorize(sender.as_deref(), action, interactive).map_err(|why| {
+        warn!("refusing {} from {}: {}", action, sender.as_deref().unwrap_or("unknown"), why);
+        MethodErr::from((crate::polkit::NOT_AUTHORIZED_ERROR, format_error(&why)))
+    })
+}
+
+/// Replies with an error that clients may recognize by its name when an operation is refused
+/// because another holds the lock.
+impl From<LockError> for MethodErr {
+    fn from(why: LockError) -> Self {
+        match why {
+            LockError::Held(_) => MethodErr::from((crate::lock::BUSY_ERROR, why.to_string())),
+            LockError::Open(_) => MethodErr::failed(&format_error(&why)),
+        }
+    }
+}
+
+/// Refuses to begin an upgrade which could be interrupted by the battery running out, with an
+/// error that clients may recognize by its name.
+fn require_power() -> Result<(), MethodErr> {
+    match crate::power::check() {
+        Ok(_) => Ok(()),
+        Err(why) => {
+            warn!("refusing to upgrade: {}", why);
+            Err(MethodErr::from((crate::power::LOW_BATTERY_ERROR, why.to_string())))
+        }
+    }
+}
+
+/// Refuses to begin downloading an upgrade if the upgrade servers are unreachable, or if a
+/// captive portal intercepts requests to them, with an error that clients may recognize.
+fn require_network() -> Result<(), MethodErr> {
+    crate::network::check().map_err(|why| {
+        warn!("refusing to upgrade: {}", why);
+        MethodErr::from((why.dbus_name(), why.to_string()))
+    })
+}
+
+/// Refuses to begin an upgrade which the upgrade policy of a managed machine does not permit,
+/// including one which overrides the checks of the battery or connection, with an error that
+/// clients may recognize.
+fn require_policy(to: Option<&str>, flags: UpgradeFlags) -> Result<(), MethodErr> {
+    let refuse = |why: PolicyError| {
+        warn!("refusing to upgrade: {}", format_error(&why));
+        MethodErr::from((POLICY_ERROR, why.to_string()))
+    };
+
+    let policy = match Policy::load().map_err(refuse)? {
+        Some(policy) => policy,
+        None => return Ok(()),
+    };
+
+    if !flags.is_empty() {
+        return Err(refuse(PolicyError::Override));
+    }
+
+    policy.permits(to, chrono::Local::now().naive_local()).map_err(refuse)
+}
+
+/// Defers downloading an upgrade while the connection is metered, unless the configuration
+/// allows it, with an error that clients may recognize.
+fn require_unmetered() -> Result<(), MethodErr> {
+    crate::network::deferral().map_err(|why| {
+        info!("deferring the download: {}", why);
+        MethodErr::from((why.dbus_name(), why.to_string()))
+    })
 }
 
 // Creates the notification dismissal file.
@@ -1040,9 +2892,16 @@ fn dismiss_file_create(next: &str) -> Result<(), String> {
         .map_err(|why| format!("failed to write '{}' to '{}': {}", next, DISMISSED, why))
 }
 
-/// Removes the notification dismissal file.
-fn dismiss_file_remove() -> Result<(), String> {
-    fs::remove_file(DISMISSED).map_err(|why| format!("failed to remove '{}': {}", DISMISSED, why))
+/// Removes the notification dismissal file, which only LTS releases are dismissed with.
+fn dismiss_file_remove() -> Result<(), String> { remove_dismiss_file(Path::new(DISMISSED)) }
+
+fn remove_dismiss_file(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(why) if why.kind() != io::ErrorKind::NotFound => {
+            Err(format!("failed to remove '{}': {}", path.display(), why))
+        }
+        _ => Ok(()),
+    }
 }
 
 /// Creates the file which is used by clients to know that a release was dismissed by timestamp.
@@ -1061,3 +2920,21 @@ async fn self_upgrade(packages: &[&str]) {
 
     std::process::exit(1);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_dismiss_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dismissed");
+
+        // A release which was only dismissed by a user leaves no file behind.
+        assert_eq!(remove_dismiss_file(&path), Ok(()));
+
+        fs::write(&path, "21.04").unwrap();
+        assert_eq!(remove_dismiss_file(&path), Ok(()));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,65 @@
+//! Records the operation which the daemon is performing, so that an operation which was
+//! interrupted by a crash or reboot can be reported once the daemon starts again, and holds the
+//! lock which keeps other processes from beginning operations of their own meanwhile.
+
+use super::DaemonStatus;
+use crate::{
+    lock::{self, LockError},
+    logging,
+};
+use num_traits::FromPrimitive;
+use std::fs;
+
+/// Records the status of the operation in progress, as a number.
+const OPERATION: &str = "/var/lib/pop-upgrade/operation";
+
+/// Records that an operation has begun, and attributes the entries logged from now on to it,
+/// unless another operation holds the lock.
+pub fn begin(status: DaemonStatus) -> Result<(), LockError> {
+    lock::acquire(status.phase(0))?;
+    logging::begin_operation(status);
+
+    if let Err(why) = fs::write(OPERATION, (status as u8).to_string()) {
+        warn!("failed to record the operation in progress: {}", why);
+    }
+
+    Ok(())
+}
+
+/// Records the phase which the operation in progress has reached.
+pub fn set_phase(status: DaemonStatus, sub_status: u8) {
+    logging::set_phase(status, sub_status);
+    lock::record(status.phase(sub_status));
+}
+
+/// Records that the operation in progress has ended.
+pub fn end() {
+    logging::end_operation();
+    lock::release();
+    let _ = fs::remove_file(OPERATION);
+}
+
+/// The operation which was in progress when the daemon last stopped, if it never ended.
+pub fn interrupted() -> Option<DaemonStatus> {
+    let status = parse(&fs::read_to_string(OPERATION).ok()?);
+    end();
+    status
+}
+
+fn parse(operation: &str) -> Option<DaemonStatus> {
+    DaemonStatus::from_u8(operation.trim().parse().ok()?)
+        .filter(|&status| status != DaemonStatus::Inactive)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operations() {
+        assert_eq!(parse("3"), Some(DaemonStatus::ReleaseUpgrade));
+        assert_eq!(parse("2\n"), Some(DaemonStatus::RecoveryUpgrade));
+        assert_eq!(parse("0"), None);
+        assert_eq!(parse(""), None);
+    }
+}
@@ -1,5 +1,26 @@
-pub struct DaemonRuntime;
+use crate::apt_lock::{self, LockHolder};
+use std::{io, sync::Arc};
+
+pub struct DaemonRuntime {
+    /// Reports each process that the runtime waits on to release the package manager's locks.
+    on_lock_wait: Arc<dyn Fn(&LockHolder) + Send + Sync>,
+    /// Whether the operation in progress has been cancelled.
+    cancel:       Arc<dyn Fn() -> bool + Send + Sync>,
+}
 
 impl DaemonRuntime {
-    pub fn new() -> Self { Self }
+    pub fn new(
+        on_lock_wait: Arc<dyn Fn(&LockHolder) + Send + Sync>,
+        cancel: Arc<dyn Fn() -> bool + Send + Sync>,
+    ) -> Self {
+        Self { on_lock_wait, cancel }
+    }
+
+    /// Whether the operation in progress has been cancelled.
+    pub fn cancelled(&self) -> bool { (self.cancel)() }
+
+    /// Waits for the package manager's locks to be released, up to the configured timeout.
+    pub async fn apt_lock_wait(&self) -> io::Result<()> {
+        apt_lock::wait(&*self.on_lock_wait).await
+    }
 }
@@ -0,0 +1,18 @@
+//! Records when a release upgrade is scheduled to begin, so that the schedule survives reboots.
+//! An upgrade whose time passed while the system was off begins once the daemon starts.
+
+use std::{
+    fs, io,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Records the time which the upgrade is scheduled for, in seconds since the Unix epoch.
+const SCHEDULE: &str = "/var/lib/pop-upgrade/schedule";
+
+pub fn load() -> Option<u64> { fs::read_to_string(SCHEDULE).ok()?.trim().parse().ok() }
+
+pub fn store(timestamp: u64) -> io::Result<()> { fs::write(SCHEDULE, timestamp.to_string()) }
+
+pub fn remove() { let _ = fs::remove_file(SCHEDULE); }
+
+pub fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs()) }
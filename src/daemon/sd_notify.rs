@@ -0,0 +1,75 @@
+//! Notifies systemd of the daemon's readiness and status, and services its watchdog.
+//!
+//! The watchdog is serviced by a thread of its own, rather than by the loop which handles DBus
+//! messages, because method calls which wait on apt or on the user may block that loop for longer
+//! than the watchdog's timeout, and systemd would kill the daemon in the middle of dpkg.
+
+use std::{env, os::unix::net::UnixDatagram, thread, time::Duration};
+
+/// Tells systemd that the daemon has started, and is listening on the bus.
+pub fn ready() { notify("READY=1"); }
+
+/// Describes what the daemon is doing, as shown by `systemctl status`.
+pub fn status(status: &str) { notify(&["STATUS=", status].concat()); }
+
+/// Services the watchdog from a thread of its own, if systemd enabled one for this process, to
+/// keep systemd from restarting the daemon while it is running.
+pub fn watchdog() {
+    let usec = env::var("WATCHDOG_USEC").ok();
+    let pid = env::var("WATCHDOG_PID").ok();
+
+    if let Some(interval) = interval(usec.as_deref(), pid.as_deref(), std::process::id()) {
+        thread::spawn(move || loop {
+            notify("WATCHDOG=1");
+            thread::sleep(interval);
+        });
+    }
+}
+
+/// The interval to service the watchdog at, which is half of its timeout, so that a thread which
+/// is slow to be scheduled does not trip it.
+fn interval(usec: Option<&str>, pid: Option<&str>, own_pid: u32) -> Option<Duration> {
+    // The watchdog may be meant for another process, such as the parent of this one.
+    if let Some(pid) = pid {
+        if pid.parse::<u32>().ok()? != own_pid {
+            return None;
+        }
+    }
+
+    let usec = usec?.parse::<u64>().ok().filter(|&usec| usec != 0)?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+fn notify(state: &str) {
+    let socket = match env::var_os("NOTIFY_SOCKET") {
+        Some(socket) => socket,
+        None => return,
+    };
+
+    // The standard library cannot address sockets in the abstract namespace.
+    if socket.to_string_lossy().starts_with('@') {
+        debug!("abstract notify sockets are not supported");
+        return;
+    }
+
+    let result =
+        UnixDatagram::unbound().and_then(|datagram| datagram.send_to(state.as_bytes(), &socket));
+
+    if let Err(why) = result {
+        warn!("failed to notify systemd of {}: {}", state, why);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intervals() {
+        assert_eq!(interval(Some("300000000"), None, 7), Some(Duration::from_secs(150)));
+        assert_eq!(interval(Some("300000000"), Some("7"), 7), Some(Duration::from_secs(150)));
+        assert_eq!(interval(Some("300000000"), Some("8"), 7), None);
+        assert_eq!(interval(Some("0"), None, 7), None);
+        assert_eq!(interval(None, None, 7), None);
+    }
+}
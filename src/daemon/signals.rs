@@ -1,38 +1,109 @@
+use super::DaemonStatus;
 use crate::{
+    apt_lock::{self, LockHolder},
     recovery::{RecoveryError, RecoveryEvent},
-    release::{ReleaseError, UpgradeEvent},
+    release::{FetchProgress, ReleaseError, UpgradeEvent},
 };
 use apt_cmd::AptUpgradeEvent;
 use std::fmt::{self, Display, Formatter};
 
 // Signals supported by the daemon.
+pub const PACKAGE_FETCH_PROGRESS: &str = "PackageFetchProgress";
 pub const PACKAGE_FETCH_RESULT: &str = "PackageFetchResult";
 pub const PACKAGE_FETCHING: &str = "PackageFetching";
 pub const PACKAGE_FETCHED: &str = "PackageFetched";
 
+pub const PACKAGE_LOCK_WAIT: &str = "PackageLockWait";
+
 pub const PACKAGE_UPGRADE: &str = "PackageUpgrade";
+pub const PACKAGE_UPGRADE_PROGRESS: &str = "PackageUpgradeProgress";
+pub const PACKAGE_UPGRADE_STAGE: &str = "PackageUpgradeStage";
 
 pub const RECOVERY_DOWNLOAD_PROGRESS: &str = "RecoveryDownloadProgress";
 pub const RECOVERY_EVENT: &str = "RecoveryUpgradeEvent";
 pub const RECOVERY_RESULT: &str = "RecoveryUpgradeResult";
+pub const RECOVERY_SYNC_PROGRESS: &str = "RecoverySyncProgress";
+pub const RECOVERY_VERIFY_PROGRESS: &str = "RecoveryVerifyProgress";
 
 pub const RELEASE_EVENT: &str = "ReleaseUpgradeEvent";
+pub const RELEASE_PROGRESS: &str = "ReleaseUpgradeProgress";
 pub const RELEASE_RESULT: &str = "ReleaseUpgradeResult";
 
 pub const REPO_COMPAT_ERROR: &str = "RepoCompatError";
 
 pub const NO_CONNECTION: &str = "NoConnection";
 
+pub const STATUS_CHANGED: &str = "StatusChanged";
+
+/// How an operation ended, as signaled by the status of its result.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum ResultStatus {
+    Success = 0,
+    Failure = 1,
+    Cancelled = 2,
+}
+
+/// The errors of operations, which distinguish their cancellation from their failure.
+pub trait OperationError: Display {
+    fn is_cancelled(&self) -> bool;
+}
+
+impl OperationError for ReleaseError {
+    fn is_cancelled(&self) -> bool { matches!(self, ReleaseError::Cancelled) }
+}
+
+impl OperationError for RecoveryError {
+    fn is_cancelled(&self) -> bool { matches!(self, RecoveryError::Cancelled) }
+}
+
+/// The stage of a package upgrade, which is signaled with the package that entered it.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
+pub enum AptStage {
+    Unpacking = 1,
+    SettingUp = 2,
+    Processing = 3,
+}
+
+impl AptStage {
+    /// The stage which an upgrade event begins, and the package which entered it.
+    pub fn of(event: &AptUpgradeEvent) -> Option<(Self, &str)> {
+        match event {
+            AptUpgradeEvent::Unpacking { package, .. } => Some((AptStage::Unpacking, &**package)),
+            AptUpgradeEvent::SettingUp { package } => Some((AptStage::SettingUp, &**package)),
+            AptUpgradeEvent::Processing { package } => Some((AptStage::Processing, &**package)),
+            _ => None,
+        }
+    }
+}
+
+impl From<AptStage> for &'static str {
+    fn from(stage: AptStage) -> Self {
+        match stage {
+            AptStage::Unpacking => "unpacking",
+            AptStage::SettingUp => "setting up",
+            AptStage::Processing => "processing triggers",
+        }
+    }
+}
+
 #[derive(Debug)]
 pub enum SignalEvent {
+    FetchProgress(FetchProgress),
     FetchResult(Result<(), ReleaseError>),
     Fetched(String, u32, u32),
     Fetching(String),
     NoConnection,
+    PackageLockWait(LockHolder),
     RecoveryDownloadProgress(u64, u64),
+    RecoverySyncProgress(u8),
+    RecoveryVerifyProgress(u8),
     RecoveryUpgradeEvent(RecoveryEvent),
     RecoveryUpgradeResult(Result<(), RecoveryError>),
     ReleaseUpgradeEvent(UpgradeEvent),
+    ReleaseUpgradeProgress(u8),
+    StatusChanged(DaemonStatus, u8),
     Upgrade(AptUpgradeEvent),
 }
 
@@ -40,15 +111,26 @@ impl Display for SignalEvent {
     fn fmt(&self, fmt: &mut Formatter) -> fmt::Result {
         use self::SignalEvent::*;
         match self {
+            FetchProgress(progress) => write!(
+                fmt,
+                "fetching {}: {}/{} bytes",
+                progress.package, progress.fetched, progress.size
+            ),
             FetchResult(result) => write!(fmt, "fetch result: {:?}", result),
             Fetched(package, progress, total) => {
                 write!(fmt, "fetched {}/{}: {}", progress, total, package)
             }
             Fetching(package) => write!(fmt, "fetching {}", package),
             NoConnection => write!(fmt, "internet connection required, but not available"),
+            PackageLockWait(holder) => {
+                let holder = apt_lock::describe(holder);
+                write!(fmt, "waiting for {} to release the package manager", holder)
+            }
             RecoveryDownloadProgress(progress, total) => {
                 write!(fmt, "recovery download: {}/{} MiB", progress / 1024, total / 1024)
             }
+            RecoverySyncProgress(percent) => write!(fmt, "recovery sync: {}%", percent),
+            RecoveryVerifyProgress(percent) => write!(fmt, "recovery verify: {}%", percent),
             RecoveryUpgradeEvent(event) => {
                 write!(fmt, "recovery upgrade: {}", <&'static str>::from(*event))
             }
@@ -56,6 +138,10 @@ impl Display for SignalEvent {
             ReleaseUpgradeEvent(event) => {
                 write!(fmt, "release upgrade: {}", <&'static str>::from(*event))
             }
+            ReleaseUpgradeProgress(percent) => write!(fmt, "release upgrade: {}%", percent),
+            StatusChanged(status, sub_status) => {
+                write!(fmt, "status changed: {} ({})", status, sub_status)
+            }
             Upgrade(event) => write!(fmt, "package upgrade: {}", event),
         }
     }
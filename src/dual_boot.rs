@@ -0,0 +1,100 @@
+use crate::loader::{self, Entry, ESP};
+use std::{path::Path, process::Command};
+
+/// The entry which systemd-boot generates for the Windows Boot Manager when it finds it.
+pub const AUTO_WINDOWS: &str = "auto-windows";
+
+/// The Windows Boot Manager, relative to the EFI system partition.
+const WINDOWS_LOADER: &str = "EFI/Microsoft/Boot/bootmgfw.efi";
+
+/// An operating system installed alongside Pop!_OS.
+#[derive(Clone, Debug, PartialEq)]
+pub struct OtherOs {
+    pub name:     String,
+    /// The device or path which the operating system is booted from.
+    pub location: String,
+}
+
+impl OtherOs {
+    pub fn is_windows(&self) -> bool {
+        self.name.contains("Windows") || self.location.to_lowercase().contains("/microsoft/")
+    }
+}
+
+/// The operating systems installed alongside Pop!_OS, which are found by `os-prober` if it is
+/// installed, and by scanning the EFI system partition.
+pub fn detect() -> Vec<OtherOs> {
+    let mut found = Command::new("os-prober")
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .map_or_else(Vec::new, |output| parse_os_prober(&String::from_utf8_lossy(&output.stdout)));
+
+    let windows = Path::new(ESP).join(WINDOWS_LOADER);
+    if windows.exists() && !found.iter().any(OtherOs::is_windows) {
+        found.push(OtherOs {
+            name:     "Windows Boot Manager".into(),
+            location: windows.display().to_string(),
+        });
+    }
+
+    found
+}
+
+/// Whether the loader entry with the given ID boots Windows.
+pub fn boots_windows(id: &str) -> bool {
+    id == AUTO_WINDOWS || loader::entry(id).map_or(false, |entry| entry_boots_windows(&entry))
+}
+
+fn entry_boots_windows(entry: &Entry) -> bool {
+    entry.get("efi").map_or(false, |efi| efi.to_lowercase().contains("/microsoft/"))
+        || entry.title().map_or(false, |title| title.contains("Windows"))
+}
+
+/// Parses the `location:name:label:type` lines which `os-prober` outputs.
+fn parse_os_prober(output: &str) -> Vec<OtherOs> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split(':');
+            let location = fields.next()?;
+            let name = fields.next()?;
+
+            Some(OtherOs { name: name.into(), location: location.into() })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn os_prober() {
+        let output = "/dev/nvme0n1p1@/EFI/Microsoft/Boot/bootmgfw.efi:Windows Boot \
+                      Manager:Windows:efi\n/dev/sda2:Ubuntu 20.04 LTS (20.04):Ubuntu:linux\n";
+
+        let found = parse_os_prober(output);
+        assert_eq!(found, vec![
+            OtherOs {
+                name:     "Windows Boot Manager".into(),
+                location: "/dev/nvme0n1p1@/EFI/Microsoft/Boot/bootmgfw.efi".into(),
+            },
+            OtherOs { name: "Ubuntu 20.04 LTS (20.04)".into(), location: "/dev/sda2".into() },
+        ]);
+
+        assert!(found[0].is_windows());
+        assert!(!found[1].is_windows());
+    }
+
+    #[test]
+    fn windows_entries() {
+        let windows = "title Windows 10\nefi /EFI/Microsoft/Boot/bootmgfw.efi\n";
+        assert!(entry_boots_windows(&Entry::parse("windows", windows)));
+
+        let pop = "title Pop!_OS\nlinux /EFI/Pop_OS-4b8d6b8e/vmlinuz.efi\n";
+        assert!(!entry_boots_windows(&Entry::parse(loader::CURRENT_ENTRY, pop)));
+
+        assert!(boots_windows(AUTO_WINDOWS));
+    }
+}
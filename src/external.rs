@@ -1,21 +1,308 @@
-use async_process::{Command, Stdio};
-use futures::{io::BufReader, prelude::*};
-use std::{io, path::Path};
+//! External commands, which are run by absolute path rather than through `$PATH`, with a minimal
+//! environment, none of the daemon's file descriptors, and no way of gaining privileges.
 
-pub async fn findmnt_uuid<P: AsRef<Path>>(path: P) -> io::Result<String> {
+use crate::config::{CommandConfig, Config};
+use as_result::MapResult;
+use async_process::{unix::CommandExt, Command, Stdio};
+use futures::prelude::*;
+use std::{
+    ffi::OsStr,
+    io,
+    path::{Path, PathBuf},
+};
+
+/// The only `$PATH` which commands are given, for any programs which they run in turn.
+const SAFE_PATH: &str = "/usr/sbin:/usr/bin:/sbin:/bin";
+
+/// Creates a restricted command for the program at the configured path, or at its default path.
+fn command(configured: fn(&CommandConfig) -> &Option<PathBuf>, default: &str) -> Command {
+    let config = Config::load_or_default().commands;
+    let program = configured(&config).clone().unwrap_or_else(|| PathBuf::from(default));
+    restricted(program, config.seccomp)
+}
+
+/// Creates a restricted command for the program at `program`.
+fn restricted(program: PathBuf, seccomp: bool) -> Command {
+    // The filter is built before forking, as the child may not allocate.
+    let mut filter = if seccomp { seccomp::filter() } else { None };
+
+    let mut cmd = Command::new(program);
+    cmd.env_clear().env("PATH", SAFE_PATH).env("LC_ALL", "C");
+
+    unsafe {
+        cmd.pre_exec(move || restrict(filter.as_deref_mut()));
+    }
+
+    cmd
+}
+
+/// Runs in the child before it executes the program.
+fn restrict(filter: Option<&mut [libc::sock_filter]>) -> io::Result<()> {
+    close_inherited_fds();
+
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    if let Some(filter) = filter {
+        seccomp::apply(filter)?;
+    }
+
+    Ok(())
+}
+
+/// Marks every descriptor beyond stdio as close-on-exec. They are not closed outright, because
+/// the standard library reports a failure to execute the program over one of them.
+fn close_inherited_fds() {
+    let max = match unsafe { libc::sysconf(libc::_SC_OPEN_MAX) } {
+        max if max > 0 => max.min(65536) as libc::c_int,
+        _ => 1024,
+    };
+
+    for fd in 3..max {
+        unsafe {
+            libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC);
+        }
+    }
+}
+
+mod seccomp {
+    use std::io;
+
+    const SECCOMP_MODE_FILTER: libc::c_ulong = 2;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH_X86_64: u32 = 0xc000_003e;
+    #[cfg(target_arch = "x86_64")]
+    const SECCOMP_RET_ALLOW: u32 = 0x7fff_0000;
+    #[cfg(target_arch = "x86_64")]
+    const SECCOMP_RET_ERRNO: u32 = 0x0005_0000;
+
+    #[cfg(target_arch = "x86_64")]
+    const BPF_LD_W_ABS: u16 = 0x20;
+    #[cfg(target_arch = "x86_64")]
+    const BPF_JMP_JEQ_K: u16 = 0x15;
+    #[cfg(target_arch = "x86_64")]
+    const BPF_RET_K: u16 = 0x06;
+
+    /// System calls which none of the commands make, and which would let a compromised command
+    /// tamper with the kernel or with other processes.
+    #[cfg(target_arch = "x86_64")]
+    const DENIED: &[libc::c_long] = &[
+        libc::SYS_bpf,
+        libc::SYS_delete_module,
+        libc::SYS_finit_module,
+        libc::SYS_init_module,
+        libc::SYS_kexec_file_load,
+        libc::SYS_kexec_load,
+        libc::SYS_process_vm_writev,
+        libc::SYS_ptrace,
+        libc::SYS_reboot,
+        libc::SYS_swapoff,
+        libc::SYS_swapon,
+    ];
+
+    #[cfg(target_arch = "x86_64")]
+    fn statement(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code, jt, jf, k }
+    }
+
+    /// A filter which denies the system calls with `EPERM`, along with every system call of
+    /// other architectures.
+    #[cfg(target_arch = "x86_64")]
+    pub fn filter() -> Option<Vec<libc::sock_filter>> {
+        let deny = SECCOMP_RET_ERRNO | libc::EPERM as u32;
+
+        let mut filter = Vec::with_capacity(DENIED.len() * 2 + 5);
+        filter.push(statement(BPF_LD_W_ABS, 4, 0, 0));
+        filter.push(statement(BPF_JMP_JEQ_K, AUDIT_ARCH_X86_64, 1, 0));
+        filter.push(statement(BPF_RET_K, deny, 0, 0));
+        filter.push(statement(BPF_LD_W_ABS, 0, 0, 0));
+
+        for &syscall in DENIED {
+            filter.push(statement(BPF_JMP_JEQ_K, syscall as u32, 0, 1));
+            filter.push(statement(BPF_RET_K, deny, 0, 0));
+        }
+
+        filter.push(statement(BPF_RET_K, SECCOMP_RET_ALLOW, 0, 0));
+        Some(filter)
+    }
+
+    #[cfg(not(target_arch = "x86_64"))]
+    pub fn filter() -> Option<Vec<libc::sock_filter>> {
+        warn!("system call filtering of commands is only supported on x86_64");
+        None
+    }
+
+    pub fn apply(filter: &mut [libc::sock_filter]) -> io::Result<()> {
+        let program = libc::sock_fprog { len: filter.len() as u16, filter: filter.as_mut_ptr() };
+
+        let result = unsafe {
+            libc::prctl(libc::PR_SET_SECCOMP, SECCOMP_MODE_FILTER, &program as *const _, 0, 0)
+        };
+
+        if result == 0 {
+            Ok(())
+        } else {
+            Err(io::Error::last_os_error())
+        }
+    }
+}
+
+/// Runs the program at `program` with `args`, returning its standard output if it succeeds.
+pub async fn output(program: &str, args: &[&str]) -> io::Result<String> {
+    let mut cmd = restricted(PathBuf::from(program), Config::load_or_default().commands.seccomp);
+    cmd.args(args);
+    stdout(program, &mut cmd).await
+}
+
+/// The value of a `column` of the filesystem mounted at `path`, such as its `FSTYPE` or `UUID`.
+pub async fn findmnt<P: AsRef<Path>>(path: P, column: &str) -> io::Result<String> {
+    let path = path.as_ref();
+    let mut cmd = cascade::cascade! {
+        command(|config| &config.findmnt, "/usr/bin/findmnt");
+        ..args(&["-n", "-o", column]);
+        ..arg(path);
+    };
+
+    let value = stdout("findmnt", &mut cmd).await?.trim().to_owned();
+
+    if value.is_empty() {
+        let why = format!("findmnt: no {} found for {}", column, path.display());
+        return Err(io::Error::new(io::ErrorKind::NotFound, why));
+    }
+
+    Ok(value)
+}
+
+/// The device which the filesystem mounted at `path` resides on.
+pub async fn mount_source<P: AsRef<Path>>(path: P) -> io::Result<String> {
+    let source = findmnt(path, "SOURCE").await?;
+
+    // Btrfs subvolumes are listed as `/dev/mapper/data-root[/@]`.
+    Ok(source.split('[').next().unwrap_or("").to_owned())
+}
+
+async fn stdout(program: &str, cmd: &mut Command) -> io::Result<String> {
+    let output = cmd.output().await?;
+
+    if !output.status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("{} exited with {}", program, output.status),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Verifies a detached GPG signature of `file` against the keys in `keyring`.
+pub async fn gpgv(keyring: &Path, signature: &Path, file: &Path) -> io::Result<()> {
+    let mut cmd = cascade::cascade! {
+        command(|config| &config.gpgv, "/usr/bin/gpgv");
+        ..arg("--keyring");
+        ..arg(keyring);
+        ..arg(signature);
+        ..arg(file);
+    };
+
+    cmd.status().await.map_result()
+}
+
+/// Verifies the signature of data which the caller has already read, passing it to gpgv over
+/// its standard input, so that it cannot be replaced in between. Returns whether it is signed by
+/// a key in the keyring.
+pub async fn gpgv_data(keyring: &Path, signature: &Path, data: &[u8]) -> io::Result<bool> {
+    let mut child = cascade::cascade! {
+        command(|config| &config.gpgv, "/usr/bin/gpgv");
+        ..args(&["--quiet", "--keyring"]);
+        ..arg(keyring);
+        ..arg(signature);
+        ..arg("-");
+        ..stdin(Stdio::piped());
+        ..stdout(Stdio::null());
+        ..stderr(Stdio::null());
+    }
+    .spawn()?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(data).await?;
+    }
+
+    child.status().await.map(|status| status.success())
+}
+
+/// Runs rsync with the given arguments, passing the overall percentage of the transfer to
+/// `progress` as rsync reports it.
+pub async fn rsync<I, S>(args: I, progress: &dyn Fn(u8)) -> io::Result<()>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
     let mut cmd = cascade::cascade! {
-        Command::new("findmnt");
+        command(|config| &config.rsync, "/usr/bin/rsync");
+        ..args(args);
+        ..args(&["--info=progress2", "--no-inc-recursive"]);
         ..stdout(Stdio::piped());
-        ..args(&["-n", "-o", "UUID"]);
-        ..arg(path.as_ref());
     };
 
-    let mut child = cmd.spawn().map_err(|why| io::Error::new(io::ErrorKind::NotFound, why))?;
+    // Each file which is transferred is logged when tracing.
+    let verbose = log_enabled!(log::Level::Trace);
+    if verbose {
+        cmd.arg("--verbose");
+    }
+
+    let mut child = cmd.spawn()?;
+    let mut stdout = child.stdout.take().unwrap();
+
+    // Progress updates are separated by carriage returns rather than newlines.
+    let mut buf = [0u8; 4 * 1024];
+    let mut line = Vec::with_capacity(256);
+    loop {
+        let read = stdout.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+
+        for &byte in &buf[..read] {
+            if byte == b'\r' || byte == b'\n' {
+                let text = String::from_utf8_lossy(&line);
+                if let Some(percent) = rsync_percent(&text) {
+                    progress(percent);
+                } else if verbose && !text.trim().is_empty() {
+                    trace!("rsync: {}", text.trim());
+                }
+
+                line.clear();
+            } else {
+                line.push(byte);
+            }
+        }
+    }
+
+    child.status().await.map_result()
+}
+
+/// Parses the overall percentage from a line of `rsync --info=progress2` output.
+fn rsync_percent(line: &str) -> Option<u8> {
+    line.split_ascii_whitespace()
+        .find(|field| field.ends_with('%'))
+        .and_then(|field| field[..field.len() - 1].parse::<u8>().ok())
+        .filter(|&percent| percent <= 100)
+}
 
-    let reader = BufReader::new(child.stdout.take().unwrap());
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    match reader.lines().next().await {
-        Some(Ok(line)) => Ok(line),
-        _ => Err(io::Error::new(io::ErrorKind::NotFound, "findmnt: uuid not found for device"))?,
+    #[test]
+    fn rsync_progress2() {
+        assert_eq!(
+            rsync_percent("    557,056,000  45%   83.33MB/s    0:00:06 (xfr#4, to-chk=12/31)"),
+            Some(45)
+        );
+        assert_eq!(rsync_percent("  2,600,000,000 100%  101.20MB/s    0:00:24"), Some(100));
+        assert_eq!(rsync_percent("casper/filesystem.squashfs"), None);
+        assert_eq!(rsync_percent("sending incremental file list"), None);
     }
 }
@@ -1,33 +1,106 @@
+use crate::apt_lock;
+
 use anyhow::Context;
-use apt_cmd::{lock::apt_lock_wait, request::Request as AptRequest, AptGet};
+use as_result::MapResult;
+use async_process::{Command, Stdio};
 use std::collections::HashSet;
 
-pub async fn fetch_uris(packages: Option<&[&str]>) -> anyhow::Result<HashSet<AptRequest>> {
-    apt_lock_wait().await;
-    let mut uris = AptGet::new()
-        .noninteractive()
-        .fetch_uris(&["full-upgrade"])
+/// A package which apt would fetch, as printed by `apt-get --print-uris`.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct PackageUri {
+    pub uri:      String,
+    pub name:     String,
+    pub size:     u64,
+    /// The checksum of the package, tagged with its algorithm, as in `sha256:<digest>`.
+    pub checksum: String,
+}
+
+impl PackageUri {
+    /// Parses a line of the form `'<uri>' <name> <size> <algorithm>:<digest>`.
+    fn parse(line: &str) -> Option<Self> {
+        let mut fields = line.split_whitespace();
+
+        let uri = fields.next()?.trim_matches('\'').to_owned();
+        let name = fields.next()?.to_owned();
+        let size = fields.next()?.parse::<u64>().ok()?;
+
+        let checksum = fields.next()?;
+        let pos = checksum.find(':')?;
+        let algorithm = match checksum[..pos].to_ascii_lowercase().as_str() {
+            "md5sum" => "md5".to_owned(),
+            other => other.to_owned(),
+        };
+
+        Some(Self { uri, name, size, checksum: [&*algorithm, &checksum[pos..]].concat() })
+    }
+}
+
+pub async fn fetch_uris(packages: Option<&[&str]>) -> anyhow::Result<HashSet<PackageUri>> {
+    apt_lock::wait(&|_| ()).await.context("failed to wait for the package manager")?;
+    let mut uris = print_uris(&["full-upgrade"])
         .await
-        .context("failed to exec `apt-get full-upgrade --print-uris`")?
         .context("failed to fetch package URIs from apt-get full-upgrade")?;
 
     if let Some(packages) = packages {
-        apt_lock_wait().await;
-        let install_uris = AptGet::new()
-            .noninteractive()
-            .fetch_uris(&{
-                let mut args = vec!["install"];
-                args.extend_from_slice(packages);
-                args
-            })
-            .await
-            .context("failed to exec `apt-get install --print-uris`")?
-            .context("failed to fetch package URIs from `apt-get install`")?;
+        apt_lock::wait(&|_| ()).await.context("failed to wait for the package manager")?;
+        let install_uris = print_uris(&{
+            let mut args = vec!["install"];
+            args.extend_from_slice(packages);
+            args
+        })
+        .await
+        .context("failed to fetch package URIs from `apt-get install`")?;
 
-        for uri in install_uris {
-            uris.insert(uri);
-        }
+        uris.extend(install_uris);
     }
 
     Ok(uris)
 }
+
+/// The packages which `apt-get` would fetch to carry out the given command.
+async fn print_uris(args: &[&str]) -> anyhow::Result<HashSet<PackageUri>> {
+    let output = Command::new("apt-get")
+        .env("LANG", "C")
+        .env("DEBIAN_FRONTEND", "noninteractive")
+        .args(&["-qq", "--print-uris"])
+        .args(args)
+        .stdin(Stdio::null())
+        .stderr(Stdio::inherit())
+        .output()
+        .await
+        .context("failed to exec `apt-get --print-uris`")?;
+
+    output.status.map_result().context("`apt-get --print-uris` failed")?;
+
+    Ok(String::from_utf8_lossy(&output.stdout).lines().filter_map(PackageUri::parse).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_uris() {
+        assert_eq!(
+            PackageUri::parse(
+                "'http://apt.pop-os.org/release/pool/main/p/pop-upgrade/pop-upgrade_2.0_amd64.deb' \
+                 pop-upgrade_2.0_amd64.deb 1024 SHA256:abcd"
+            ),
+            Some(PackageUri {
+                uri:      "http://apt.pop-os.org/release/pool/main/p/pop-upgrade/\
+                           pop-upgrade_2.0_amd64.deb"
+                    .into(),
+                name:     "pop-upgrade_2.0_amd64.deb".into(),
+                size:     1024,
+                checksum: "sha256:abcd".into(),
+            })
+        );
+
+        assert_eq!(
+            PackageUri::parse("'http://a/b.deb' b.deb 1 MD5Sum:0123").map(|uri| uri.checksum),
+            Some("md5:0123".into())
+        );
+
+        assert_eq!(PackageUri::parse("'http://a/b.deb' b.deb"), None);
+    }
+}
@@ -0,0 +1,340 @@
+//! C-compatible functions for checking for, and upgrading to, new releases through the daemon,
+//! so that plugins written in C need not marshal its DBus messages themselves.
+//!
+//! Functions which may fail return `0` on success, and `-1` on failure, after which
+//! `pop_upgrade_last_error` describes the failure. Panics are caught at the boundary, and
+//! reported as failures.
+
+use crate::{
+    client::{self, Client, Continue, Signal},
+    daemon::DaemonStatus,
+    misc::format_error,
+    release::{UpgradeFlags, UpgradeMethod},
+};
+use std::{
+    cell::RefCell,
+    ffi::{CStr, CString},
+    os::raw::{c_char, c_int, c_void},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = RefCell::new(None);
+}
+
+/// A connection to the daemon.
+pub struct PopUpgradeClient(Client);
+
+/// The release which the system may be upgraded to, as found by a release check.
+#[repr(C)]
+pub struct PopUpgradeReleaseInfo {
+    pub current:           *mut c_char,
+    pub next:              *mut c_char,
+    /// The build of the next release, or a negative error code if none is available.
+    pub build:             i16,
+    /// The build which the upgrade is urgent for, or `-1`.
+    pub urgent:            i32,
+    pub is_lts:            bool,
+    /// Size of the ISO to download, in bytes.
+    pub size:              u64,
+    /// A link to the notes of the next release, or null.
+    pub release_notes_url: *mut c_char,
+}
+
+/// The kinds of events which are passed to a `PopUpgradeEventCallback`.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PopUpgradeEventKind {
+    /// `value` is the status of the daemon, and `total` its sub-status.
+    StatusChanged = 1,
+    /// `value` is the `UpgradeEvent`, which `message` describes.
+    ReleaseEvent = 2,
+    /// `value` is the bytes of packages fetched, of `total` bytes.
+    FetchProgress = 3,
+    /// `value` is the number of packages fetched, of `total` packages, the last of which was
+    /// `message`.
+    Fetched = 4,
+    /// The apt repositories are unreachable.
+    NoConnection = 5,
+    /// `value` is `0` if the upgrade succeeded, and `message` describes why it failed otherwise.
+    /// No more events follow it.
+    ReleaseResult = 6,
+}
+
+/// An event of an upgrade in progress. The message is only valid for the duration of the
+/// callback, and may be null.
+#[repr(C)]
+pub struct PopUpgradeEvent {
+    pub kind:    PopUpgradeEventKind,
+    pub value:   u64,
+    pub total:   u64,
+    pub message: *const c_char,
+}
+
+pub type PopUpgradeEventCallback =
+    extern "C" fn(event: *const PopUpgradeEvent, user_data: *mut c_void);
+
+/// Describes the last failure of a function on this thread, or returns null. The description
+/// is valid until the next failure.
+#[no_mangle]
+pub extern "C" fn pop_upgrade_last_error() -> *const c_char {
+    guarded(ptr::null(), || {
+        LAST_ERROR.with(|last| last.borrow().as_ref().map_or(ptr::null(), |why| why.as_ptr()))
+    })
+}
+
+/// Connects to the daemon, or returns null on failure.
+#[no_mangle]
+pub extern "C" fn pop_upgrade_client_new() -> *mut PopUpgradeClient {
+    guarded(ptr::null_mut(), || match Client::new() {
+        Ok(client) => Box::into_raw(Box::new(PopUpgradeClient(client))),
+        Err(why) => {
+            set_error(&why);
+            ptr::null_mut()
+        }
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn pop_upgrade_client_free(client: *mut PopUpgradeClient) {
+    guarded((), || {
+        if !client.is_null() {
+            drop(unsafe { Box::from_raw(client) });
+        }
+    })
+}
+
+/// Checks for a release to upgrade to, writing it to `info`, whose strings must be freed with
+/// `pop_upgrade_release_info_free`.
+#[no_mangle]
+pub extern "C" fn pop_upgrade_client_release_check(
+    client: *const PopUpgradeClient,
+    development: bool,
+    force: bool,
+    info: *mut PopUpgradeReleaseInfo,
+) -> c_int {
+    guarded(-1, || release_check(client, development, force, info))
+}
+
+fn release_check(
+    client: *const PopUpgradeClient,
+    development: bool,
+    force: bool,
+    info: *mut PopUpgradeReleaseInfo,
+) -> c_int {
+    let (client, info) = match unsafe { (client.as_ref(), info.as_mut()) } {
+        (Some(client), Some(info)) => (client, info),
+        _ => return null_argument(),
+    };
+
+    match client.0.release_check(development, force) {
+        Ok(release) => {
+            let notes = release.release_notes.as_deref();
+            *info = PopUpgradeReleaseInfo {
+                current:           into_c_string(&release.current),
+                next:              into_c_string(&release.next),
+                build:             release.build,
+                urgent:            release.urgent.map_or(-1, i32::from),
+                is_lts:            release.is_lts,
+                size:              release.size,
+                release_notes_url: notes.map_or(ptr::null_mut(), into_c_string),
+            };
+
+            0
+        }
+        Err(why) => failure(&why),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn pop_upgrade_release_info_free(info: *mut PopUpgradeReleaseInfo) {
+    guarded((), || {
+        if let Some(info) = unsafe { info.as_mut() } {
+            for string in &mut [&mut info.current, &mut info.next, &mut info.release_notes_url] {
+                if !string.is_null() {
+                    drop(unsafe { CString::from_raw(**string) });
+                    **string = ptr::null_mut();
+                }
+            }
+        }
+    })
+}
+
+/// Writes the status of the daemon, and the sub-status of its operation, which stand for the
+/// `DaemonStatus`, and the `RecoveryEvent` or `UpgradeEvent`, respectively.
+#[no_mangle]
+pub extern "C" fn pop_upgrade_client_status(
+    client: *const PopUpgradeClient,
+    status: *mut u8,
+    sub_status: *mut u8,
+) -> c_int {
+    guarded(-1, || client_status(client, status, sub_status))
+}
+
+fn client_status(client: *const PopUpgradeClient, status: *mut u8, sub_status: *mut u8) -> c_int {
+    let (client, status, sub_status) =
+        match unsafe { (client.as_ref(), status.as_mut(), sub_status.as_mut()) } {
+            (Some(client), Some(status), Some(sub_status)) => (client, status, sub_status),
+            _ => return null_argument(),
+        };
+
+    match client.0.status() {
+        Ok(current) => {
+            *status = current.status;
+            *sub_status = current.sub_status;
+            0
+        }
+        Err(why) => failure(&why),
+    }
+}
+
+/// Upgrades from the `from` release to the `to` release, with the `UpgradeFlags` of `flags`,
+/// and blocks until the upgrade is ready to install on the next boot, passing each of its events
+/// to `callback` meanwhile, unless it is null.
+#[no_mangle]
+pub extern "C" fn pop_upgrade_client_release_upgrade(
+    client: *const PopUpgradeClient,
+    from: *const c_char,
+    to: *const c_char,
+    flags: u8,
+    callback: Option<PopUpgradeEventCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    guarded(-1, || release_upgrade(client, from, to, flags, callback, user_data))
+}
+
+fn release_upgrade(
+    client: *const PopUpgradeClient,
+    from: *const c_char,
+    to: *const c_char,
+    flags: u8,
+    callback: Option<PopUpgradeEventCallback>,
+    user_data: *mut c_void,
+) -> c_int {
+    let client = match unsafe { client.as_ref() } {
+        Some(client) => &client.0,
+        None => return null_argument(),
+    };
+
+    let (from, to) = match (from_c_str(from), from_c_str(to)) {
+        (Some(from), Some(to)) => (from, to),
+        _ => return null_argument(),
+    };
+
+    let flags = UpgradeFlags::from_bits_truncate(flags);
+    if let Err(why) = client.release_upgrade(UpgradeMethod::Offline, from, to, false, None, flags)
+    {
+        return failure(&why);
+    }
+
+    let emit = |kind, value, total, message: Option<&str>| {
+        let callback = match callback {
+            Some(callback) => callback,
+            None => return,
+        };
+
+        let message = message.and_then(|message| CString::new(message).ok());
+        let event = PopUpgradeEvent {
+            kind,
+            value,
+            total,
+            message: message.as_ref().map_or(ptr::null(), |message| message.as_ptr()),
+        };
+
+        callback(&event, user_data);
+    };
+
+    let result = client.event_listen(
+        DaemonStatus::ReleaseUpgrade,
+        Client::release_upgrade_status,
+        |status| {
+            emit(PopUpgradeEventKind::ReleaseResult, status.status.into(), 0, Some(&*status.why))
+        },
+        |_client, signal| {
+            match signal {
+                Signal::StatusChanged(status) => emit(
+                    PopUpgradeEventKind::StatusChanged,
+                    status.status.into(),
+                    status.sub_status.into(),
+                    None,
+                ),
+                Signal::ReleaseEvent(event) => emit(
+                    PopUpgradeEventKind::ReleaseEvent,
+                    event as u64,
+                    0,
+                    Some(<&'static str>::from(event)),
+                ),
+                Signal::PackageFetchProgress(progress) => emit(
+                    PopUpgradeEventKind::FetchProgress,
+                    progress.total_fetched,
+                    progress.total,
+                    None,
+                ),
+                Signal::PackageFetched(fetched) => emit(
+                    PopUpgradeEventKind::Fetched,
+                    fetched.completed.into(),
+                    fetched.total.into(),
+                    Some(&*fetched.package),
+                ),
+                Signal::NoConnection => emit(PopUpgradeEventKind::NoConnection, 0, 0, None),
+                Signal::ReleaseResult(status) => {
+                    emit(
+                        PopUpgradeEventKind::ReleaseResult,
+                        status.status.into(),
+                        0,
+                        Some(&*status.why),
+                    );
+
+                    return Ok(Continue(false));
+                }
+                _ => (),
+            }
+
+            Ok(Continue(true))
+        },
+    );
+
+    match result {
+        Ok(()) => 0,
+        Err(why) => failure(&why),
+    }
+}
+
+/// Runs the body of an exported function, returning `on_panic` if it panics, as unwinding
+/// into C is undefined behavior.
+fn guarded<T>(on_panic: T, func: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(func)).unwrap_or_else(|_| {
+        let message = CString::new("pop-upgrade panicked").ok();
+        LAST_ERROR.with(|last| *last.borrow_mut() = message);
+        on_panic
+    })
+}
+
+fn set_error(why: &client::Error) {
+    let message = CString::new(format_error(why)).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+}
+
+fn failure(why: &client::Error) -> c_int {
+    set_error(why);
+    -1
+}
+
+fn null_argument() -> c_int {
+    let message = CString::new("a required argument was null, or not valid UTF-8").ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = message);
+    -1
+}
+
+fn from_c_str<'a>(string: *const c_char) -> Option<&'a str> {
+    if string.is_null() {
+        None
+    } else {
+        unsafe { CStr::from_ptr(string) }.to_str().ok()
+    }
+}
+
+fn into_c_string(string: &str) -> *mut c_char {
+    CString::new(string).map_or(ptr::null_mut(), CString::into_raw)
+}
@@ -0,0 +1,167 @@
+//! A record of the operations which the daemon has performed, so that administrators and support
+//! may audit what happened to a machine. Each entry is a line of JSON, and only the most recent
+//! entries are kept.
+
+use crate::{misc::format_error, notification};
+use serde_derive::{Deserialize, Serialize};
+use std::{convert::TryFrom, error::Error as StdError, fs, io};
+
+const HISTORY: &str = "/var/lib/pop-upgrade/history";
+
+/// How many entries are kept, beyond which the oldest are discarded.
+const MAX_ENTRIES: usize = 200;
+
+/// Operations are recorded by their names, which are also those that the daemon reports.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(into = "&'static str", try_from = "String")]
+pub enum Operation {
+    FetchUpdates,
+    PackageUpgrade,
+    RecoveryUpgrade,
+    ReleaseUpgrade,
+    /// The installation of a staged release upgrade on boot.
+    ReleaseInstall,
+}
+
+const OPERATIONS: [Operation; 5] = [
+    Operation::FetchUpdates,
+    Operation::PackageUpgrade,
+    Operation::RecoveryUpgrade,
+    Operation::ReleaseUpgrade,
+    Operation::ReleaseInstall,
+];
+
+impl From<Operation> for &'static str {
+    fn from(operation: Operation) -> Self {
+        match operation {
+            Operation::FetchUpdates => "fetch-updates",
+            Operation::PackageUpgrade => "package-upgrade",
+            Operation::RecoveryUpgrade => "recovery-upgrade",
+            Operation::ReleaseUpgrade => "release-upgrade",
+            Operation::ReleaseInstall => "release-install",
+        }
+    }
+}
+
+impl TryFrom<String> for Operation {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, Self::Error> {
+        OPERATIONS
+            .iter()
+            .copied()
+            .find(|&operation| <&'static str>::from(operation) == name)
+            .ok_or_else(|| fomat!("unknown operation: " (name)))
+    }
+}
+
+/// An operation which has ended.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Entry {
+    pub operation: Operation,
+    /// When the operation began, in seconds since the Unix epoch.
+    pub started:   u64,
+    /// When the operation ended, in seconds since the Unix epoch.
+    pub ended:     u64,
+    /// The release or recovery version which was upgraded from, if known.
+    pub from:      Option<String>,
+    /// The release or recovery version which was upgraded to, if known.
+    pub to:        Option<String>,
+    /// The client which requested the operation, such as `gnome-control-c (pid 2048)`.
+    pub client:    Option<String>,
+    /// Why the operation failed, if it did.
+    pub error:     Option<String>,
+}
+
+impl Entry {
+    /// An operation which began at `started`, and which has just ended with the result.
+    pub fn new<E: StdError + 'static>(
+        operation: Operation,
+        started: u64,
+        client: Option<String>,
+        result: Result<&(), &E>,
+    ) -> Self {
+        Self {
+            operation,
+            started,
+            ended: notification::now(),
+            from:  None,
+            to:    None,
+            client,
+            error: result.err().map(|why| format_error(why)),
+        }
+    }
+}
+
+/// The recorded entries, oldest first. Entries which cannot be parsed are skipped.
+pub fn load() -> Vec<Entry> {
+    fs::read_to_string(HISTORY).map_or_else(|_| Vec::new(), |history| parse(&history))
+}
+
+/// Records that an operation has ended.
+pub fn record(entry: &Entry) {
+    if let Err(why) = append(entry) {
+        warn!("failed to record the operation in {}: {}", HISTORY, why);
+    }
+}
+
+/// Rewrites the history with the entry appended, through a temporary file, so that a partial
+/// write never truncates the history.
+fn append(entry: &Entry) -> io::Result<()> {
+    let mut entries = load();
+    entries.push(entry.clone());
+
+    let skip = entries.len().saturating_sub(MAX_ENTRIES);
+    let mut history = String::new();
+    for entry in &entries[skip..] {
+        history.push_str(&serde_json::to_string(entry)?);
+        history.push('\n');
+    }
+
+    let temporary = [HISTORY, ".tmp"].concat();
+    fs::write(&temporary, history)?;
+    fs::rename(&temporary, HISTORY)
+}
+
+fn parse(history: &str) -> Vec<Entry> {
+    history.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries() {
+        let upgrade = Entry {
+            from: Some("21.10".into()),
+            to:   Some("22.04".into()),
+            ..Entry::new::<io::Error>(Operation::ReleaseUpgrade, 1650000000, None, Ok(&()))
+        };
+
+        let why = io::Error::new(io::ErrorKind::Other, "no space left");
+        let recovery = Entry::new(
+            Operation::RecoveryUpgrade,
+            1650000100,
+            Some("pop-upgrade (pid 42)".into()),
+            Err(&why),
+        );
+
+        assert_eq!(upgrade.error, None);
+        assert_eq!(recovery.error.as_deref(), Some("no space left"));
+
+        let history = [&upgrade, &recovery]
+            .iter()
+            .map(|entry| serde_json::to_string(entry).unwrap())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        assert!(history.contains("\"operation\":\"release-upgrade\""));
+        for &operation in &OPERATIONS {
+            let name = serde_json::to_string(&operation).unwrap();
+            assert_eq!(serde_json::from_str::<Operation>(&name).unwrap(), operation);
+        }
+
+        assert_eq!(parse(&[&history, "\nnot an entry\n"].concat()), vec![upgrade, recovery]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Scripts which administrators place in `/etc/pop-upgrade/hooks/pre.d` and `post.d`, to be run
+//! before and after release and recovery upgrades, such as to quiesce services or to re-register
+//! agents. They are run as root, in the order of their names, and are given these variables:
+//!
+//! - `POP_UPGRADE_HOOK`: `pre` or `post`
+//! - `POP_UPGRADE_OPERATION`: `release-upgrade` or `recovery-upgrade`
+//! - `POP_UPGRADE_FROM`: the release being upgraded from, if known
+//! - `POP_UPGRADE_TO`: the release being upgraded to, if known
+//! - `POP_UPGRADE_RESULT`: `success` or `failure`, for post hooks
+//!
+//! Offline release upgrades are installed by systemd on the next boot, so their post hooks run
+//! once the upgrade has been fetched and staged, rather than once it has been installed.
+
+use crate::{
+    config::{Config, FailurePolicy},
+    misc::format_error,
+};
+use as_result::MapResult;
+use async_io::Timer;
+use async_process::{unix::CommandExt, Command, Stdio};
+use futures::future::{self, Either};
+use std::{
+    ffi::OsStr,
+    fs, io,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use thiserror::Error;
+
+pub const HOOKS: &str = "/etc/pop-upgrade/hooks";
+
+/// How long each hook may run for, unless configured otherwise.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+const SAFE_PATH: &str = "/usr/sbin:/usr/bin:/sbin:/bin";
+
+#[derive(Debug, Error)]
+pub enum HookError {
+    #[error("hook {} failed", _0.display())]
+    Failed(PathBuf, #[source] io::Error),
+
+    #[error("hook {} did not finish within {} seconds", _0.display(), _1)]
+    Timeout(PathBuf, u64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stage {
+    Pre,
+    /// After the operation, or after it was staged if it is installed on the next boot.
+    Post,
+}
+
+impl Stage {
+    fn id(self) -> &'static str {
+        match self {
+            Stage::Pre => "pre",
+            Stage::Post => "post",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    ReleaseUpgrade,
+    RecoveryUpgrade,
+}
+
+impl Operation {
+    fn id(self) -> &'static str {
+        match self {
+            Operation::ReleaseUpgrade => "release-upgrade",
+            Operation::RecoveryUpgrade => "recovery-upgrade",
+        }
+    }
+}
+
+/// What the hooks are run around.
+#[derive(Clone, Copy, Debug)]
+pub struct Context<'a> {
+    pub operation: Operation,
+    pub from:      Option<&'a str>,
+    pub to:        Option<&'a str>,
+    /// Whether the operation succeeded, which post hooks are told.
+    pub succeeded: Option<bool>,
+}
+
+/// Runs the hooks of the stage in the order of their names. If a hook fails whose policy is to
+/// abort, the hooks after it are not run, and its error is returned.
+pub async fn run(stage: Stage, context: &Context<'_>) -> Result<(), HookError> {
+    let config = Config::load_or_default().hooks;
+
+    for path in scripts(&Path::new(HOOKS).join(fomat!((stage.id()) ".d"))) {
+        let name = path.file_name().map(OsStr::to_string_lossy).unwrap_or_default();
+        let script = config.scripts.get(&*name);
+
+        let timeout = script
+            .and_then(|script| script.timeout)
+            .or(config.timeout)
+            .map_or(DEFAULT_TIMEOUT, Duration::from_secs);
+
+        let policy = script.and_then(|script| script.on_failure).unwrap_or(config.on_failure);
+
+        info!("running the {} hook {}", stage.id(), path.display());
+        match run_script(&path, stage, context, timeout).await {
+            Ok(()) => (),
+            Err(why) if policy == FailurePolicy::Continue => warn!("{}", format_error(&why)),
+            Err(why) => return Err(why),
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_script(
+    path: &Path,
+    stage: Stage,
+    context: &Context<'_>,
+    timeout: Duration,
+) -> Result<(), HookError> {
+    let mut cmd = Command::new(path);
+    cmd.env_clear()
+        .env("PATH", SAFE_PATH)
+        .env("POP_UPGRADE_HOOK", stage.id())
+        .env("POP_UPGRADE_OPERATION", context.operation.id())
+        .stdin(Stdio::null());
+
+    // The hook leads its own process group, so that the processes it spawns are killed with it.
+    unsafe {
+        cmd.pre_exec(|| match libc::setpgid(0, 0) {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        });
+    }
+
+    if let Some(from) = context.from {
+        cmd.env("POP_UPGRADE_FROM", from);
+    }
+
+    if let Some(to) = context.to {
+        cmd.env("POP_UPGRADE_TO", to);
+    }
+
+    if let Some(succeeded) = context.succeeded {
+        cmd.env("POP_UPGRADE_RESULT", if succeeded { "success" } else { "failure" });
+    }
+
+    let mut child = cmd.spawn().map_err(|why| HookError::Failed(path.to_owned(), why))?;
+
+    let status = {
+        let status = child.status();
+        let expired = Timer::after(timeout);
+
+        futures::pin_mut!(status, expired);
+
+        match future::select(status, expired).await {
+            Either::Left((status, _)) => Some(status),
+            Either::Right(_) => None,
+        }
+    };
+
+    match status {
+        Some(status) => status.map_result().map_err(|why| HookError::Failed(path.to_owned(), why)),
+        None => {
+            unsafe {
+                libc::killpg(child.id() as libc::pid_t, libc::SIGKILL);
+            }
+
+            let _ = child.status().await;
+            Err(HookError::Timeout(path.to_owned(), timeout.as_secs()))
+        }
+    }
+}
+
+/// The executable files in the directory, sorted by name. Hidden files, and the backups which
+/// editors and dpkg leave behind, are skipped.
+fn scripts(dir: &Path) -> Vec<PathBuf> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+
+    let mut scripts = entries
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_script(path))
+        .collect::<Vec<_>>();
+
+    scripts.sort();
+    scripts
+}
+
+fn is_script(path: &Path) -> bool {
+    let name = match path.file_name().and_then(OsStr::to_str) {
+        Some(name) => name,
+        None => return false,
+    };
+
+    if name.starts_with('.') || name.ends_with('~') || name.contains(".dpkg-") {
+        return false;
+    }
+
+    let executable = |meta: fs::Metadata| meta.is_file() && meta.permissions().mode() & 0o111 != 0;
+    fs::metadata(path).map_or(false, executable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ordering() {
+        let dir = tempfile::tempdir().unwrap();
+
+        let create = |name: &str, mode: u32| {
+            let path = dir.path().join(name);
+            fs::write(&path, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(mode)).unwrap();
+        };
+
+        create("20-agent", 0o755);
+        create("10-services", 0o755);
+        create("30-disabled", 0o644);
+        create(".hidden", 0o755);
+        create("10-services~", 0o755);
+        create("10-services.dpkg-old", 0o755);
+
+        let names = scripts(dir.path())
+            .into_iter()
+            .map(|path| path.file_name().unwrap().to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+        assert_eq!(names, vec!["10-services", "20-agent"]);
+        assert!(scripts(&dir.path().join("missing")).is_empty());
+    }
+}
@@ -0,0 +1,119 @@
+use crate::config::Config;
+use isahc::{
+    config::{CaCertificate, Configurable},
+    http::Uri,
+    HttpClient, HttpClientBuilder,
+};
+use once_cell::sync::OnceCell;
+use std::process::Command;
+
+/// The proxies which apt is configured with, which are read once.
+static APT_PROXIES: OnceCell<AptProxies> = OnceCell::new();
+
+/// The proxies which apt is configured to fetch packages through, such as an apt-cacher-ng server.
+#[derive(Debug, Default, PartialEq)]
+pub struct AptProxies {
+    /// `Acquire::http::Proxy`
+    http:  Option<Uri>,
+    /// `Acquire::https::Proxy`, which is `Some(None)` if HTTPS is fetched directly, rather than
+    /// through the HTTP proxy.
+    https: Option<Option<Uri>>,
+}
+
+impl AptProxies {
+    /// The proxy which apt would fetch the URL through, by its scheme.
+    pub fn proxy(&self, url: &str) -> Option<&Uri> {
+        match self.https {
+            Some(ref https) if url.starts_with("https:") => https.as_ref(),
+            _ => self.http.as_ref(),
+        }
+    }
+}
+
+/// Creates a HTTP client builder for fetching `url`, with the TLS constraints, proxy, and
+/// bandwidth limit of the system configuration applied. The proxy that apt is configured to
+/// fetch the URL through is used if none is.
+pub fn builder(url: &str) -> HttpClientBuilder {
+    let mut builder = HttpClient::builder();
+    let config = Config::load_or_default().network;
+
+    if let Some(path) = config.ca_certificate {
+        info!("restricting trusted certificate authorities to {}", path.display());
+        builder = builder.ssl_ca_certificate(CaCertificate::file(path));
+    }
+
+    // The configuration was validated when loaded, so its proxy parses.
+    if let Some(proxy) = config.proxy.and_then(|proxy| proxy.parse::<Uri>().ok()) {
+        info!("using the configured proxy: {}", proxy);
+        builder = builder.proxy(Some(proxy));
+    } else if let Some(proxy) = apt_proxies().proxy(url) {
+        info!("using the proxy configured for apt: {}", proxy);
+        builder = builder.proxy(Some(proxy.clone()));
+    }
+
+    if let Some(limit) = config.bandwidth_limit {
+        builder = builder.max_download_speed(limit * 1024);
+    }
+
+    builder
+}
+
+/// Creates a HTTP client for fetching `url`, with the TLS constraints of the system
+/// configuration applied.
+pub fn client(url: &str) -> Result<HttpClient, isahc::Error> { builder(url).build() }
+
+/// The proxies which apt is configured with, as `apt-config` reported them when first asked.
+pub fn apt_proxies() -> &'static AptProxies {
+    APT_PROXIES.get_or_init(|| {
+        Command::new("apt-config")
+            .args(&["shell", "HTTP", "Acquire::http::Proxy", "HTTPS", "Acquire::https::Proxy"])
+            .output()
+            .map(|output| parse_apt_proxies(&String::from_utf8_lossy(&output.stdout)))
+            .unwrap_or_default()
+    })
+}
+
+/// Parses the output of `apt-config shell`.
+fn parse_apt_proxies(shell: &str) -> AptProxies {
+    let value = |name: &str| {
+        shell
+            .lines()
+            .filter_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+            .map(|value| value.trim().trim_matches('\''))
+            .find(|value| !value.is_empty())
+    };
+
+    let proxy = |value: &str| match value {
+        "DIRECT" | "false" => None,
+        value => value.parse::<Uri>().ok(),
+    };
+
+    AptProxies { http: value("HTTP").and_then(proxy), https: value("HTTPS").map(proxy) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apt_proxies() {
+        let proxy = |shell: &str, url: &str| parse_apt_proxies(shell).proxy(url).cloned();
+        let uri = |uri| Some(Uri::from_static(uri));
+
+        let shell = "HTTP='http://10.0.0.2:3142/'\nHTTPS='http://10.0.0.3:3142/'\n";
+        assert_eq!(proxy(shell, "http://a.com/"), uri("http://10.0.0.2:3142/"));
+        assert_eq!(proxy(shell, "https://a.com/"), uri("http://10.0.0.3:3142/"));
+
+        // HTTPS is fetched through the HTTP proxy, unless it is fetched directly.
+        let shell = "HTTP='http://10.0.0.2:3142/'\n";
+        assert_eq!(proxy(shell, "https://a.com/"), uri("http://10.0.0.2:3142/"));
+
+        let shell = "HTTP='http://10.0.0.2:3142/'\nHTTPS='DIRECT'\n";
+        assert_eq!(proxy(shell, "https://a.com/"), None);
+
+        let shell = "HTTP='DIRECT'\nHTTPS='http://10.0.0.3:3142/'\n";
+        assert_eq!(proxy(shell, "http://a.com/"), None);
+
+        assert_eq!(parse_apt_proxies(""), AptProxies::default());
+    }
+}
@@ -0,0 +1,249 @@
+use crate::{
+    apt_lock,
+    loader::{self, CURRENT_ENTRY, ESP},
+};
+use anyhow::Context;
+use apt_cmd::AptGet;
+use async_process::Command;
+use std::{fs, path::Path};
+
+/// Packages which are installed for each kernel version, such as `linux-modules-<version>`.
+const KERNEL_PACKAGES: &[&str] =
+    &["linux-image-", "linux-modules-", "linux-modules-extra-", "linux-headers-"];
+
+/// A kernel, and the packages installed for it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    /// The release of the kernel, such as `5.11.0-7614-generic`.
+    pub version:  String,
+    pub packages: Vec<String>,
+}
+
+/// The kernels which are kept, and the kernels which are removed.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct KernelPlan {
+    pub kept:    Vec<Kernel>,
+    pub removed: Vec<Kernel>,
+}
+
+/// The installed kernels, from the most recent to the oldest.
+pub async fn installed() -> anyhow::Result<Vec<Kernel>> {
+    let output = Command::new("dpkg-query")
+        .args(&["-W", "-f", "${db:Status-Abbrev} ${Package}\\n"])
+        .output()
+        .await
+        .context("failed to list installed packages")?;
+
+    if !output.status.success() {
+        return Err(anyhow!("`dpkg-query` exited with {}", output.status));
+    }
+
+    Ok(parse_installed(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Plans to keep the running kernel, the `keep` most recent of the other kernels, and to remove
+/// the rest. A kernel older than the running kernel is always kept, to fall back to if a newer
+/// kernel fails to boot.
+///
+/// Nothing is removed if the running kernel is not among the installed kernels, such as when it
+/// is held or was not installed from a package, as there would be no kernel to fall back to.
+pub fn plan(installed: Vec<Kernel>, running: &str, keep: usize) -> KernelPlan {
+    let mut plan = KernelPlan::default();
+    let running_at = match installed.iter().position(|kernel| kernel.version == running) {
+        Some(running_at) => running_at,
+        None => {
+            warn!("keeping every kernel, as the running kernel {} is not installed", running);
+            plan.kept = installed;
+            return plan;
+        }
+    };
+
+    let mut others = 0;
+    let mut kept_older = false;
+
+    for (position, kernel) in installed.into_iter().enumerate() {
+        let older = position > running_at;
+
+        if kernel.version == running {
+            plan.kept.push(kernel);
+        } else if others < keep || (older && !kept_older) {
+            others += 1;
+            kept_older |= older;
+            plan.kept.push(kernel);
+        } else {
+            plan.removed.push(kernel);
+        }
+    }
+
+    plan
+}
+
+/// The release of the kernel which is running.
+pub fn running() -> anyhow::Result<String> {
+    fs::read_to_string("/proc/sys/kernel/osrelease")
+        .map(|release| release.trim().to_owned())
+        .context("failed to read the release of the running kernel")
+}
+
+/// Purges the packages of the kernels, and then removes the loader entries which booted them.
+/// Returns the IDs of the loader entries which were removed.
+pub async fn remove(kernels: &[Kernel]) -> anyhow::Result<Vec<String>> {
+    if kernels.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let packages = kernels
+        .iter()
+        .flat_map(|kernel| kernel.packages.iter().map(String::as_str))
+        .collect::<Vec<_>>();
+
+    info!("purging {}", packages.join(", "));
+
+    apt_lock::wait(&|_| ()).await.context("failed to wait on the package manager lock")?;
+
+    let status = AptGet::new()
+        .noninteractive()
+        .arg("purge")
+        .args(&packages)
+        .status()
+        .await
+        .context("failed to purge the kernels")?;
+
+    if !status.success() {
+        return Err(anyhow!("`apt-get purge` exited with {}", status));
+    }
+
+    remove_stale_entries()
+}
+
+/// Removes the loader entries which boot kernels that no longer exist on the ESP.
+fn remove_stale_entries() -> anyhow::Result<Vec<String>> {
+    if !Path::new(loader::ENTRIES).exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut removed = Vec::new();
+
+    for entry in loader::entries()? {
+        // The current entry is regenerated by kernelstub, rather than removed.
+        if entry.id == CURRENT_ENTRY || entry.missing_files(Path::new(ESP)).is_empty() {
+            continue;
+        }
+
+        info!("removing loader entry {}, as its kernel was removed", entry.id);
+        loader::remove(&entry.id)?;
+        removed.push(entry.id);
+    }
+
+    Ok(removed)
+}
+
+/// Groups the packages of each installed kernel by the kernel's version, from the most recent.
+fn parse_installed(output: &str) -> Vec<Kernel> {
+    let packages = output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let status = fields.next()?;
+            let package = fields.next()?;
+            if status.starts_with("ii") {
+                Some(package)
+            } else {
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    // Metapackages, such as `linux-image-generic`, do not have a version.
+    let mut kernels = packages
+        .iter()
+        .filter_map(|package| package.strip_prefix("linux-image-"))
+        .filter(|version| version.starts_with(|c: char| c.is_ascii_digit()))
+        .map(|version| Kernel {
+            version:  version.to_owned(),
+            packages: KERNEL_PACKAGES
+                .iter()
+                .map(|prefix| [*prefix, version].concat())
+                .filter(|package| packages.contains(&package.as_str()))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+
+    kernels.sort_by(|a, b| version_key(&b.version).cmp(&version_key(&a.version)));
+    kernels
+}
+
+/// Orders kernel versions by their numeric components.
+fn version_key(version: &str) -> Vec<u64> {
+    version.split(|c: char| !c.is_ascii_digit()).filter_map(|number| number.parse().ok()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DPKG: &str = "ii  linux-image-5.8.0-7642-generic
+ii  linux-modules-5.8.0-7642-generic
+ii  linux-image-5.11.0-7614-generic
+ii  linux-modules-5.11.0-7614-generic
+ii  linux-headers-5.11.0-7614-generic
+ii  linux-image-5.11.0-7612-generic
+ii  linux-modules-5.11.0-7612-generic
+rc  linux-image-5.4.0-7634-generic
+ii  linux-image-generic
+ii  linux-system76
+";
+
+    #[test]
+    fn installed_kernels() {
+        let kernels = parse_installed(DPKG);
+        let versions = kernels.iter().map(|kernel| kernel.version.as_str()).collect::<Vec<_>>();
+
+        assert_eq!(versions, vec![
+            "5.11.0-7614-generic",
+            "5.11.0-7612-generic",
+            "5.8.0-7642-generic"
+        ]);
+
+        assert_eq!(kernels[0].packages, vec![
+            "linux-image-5.11.0-7614-generic",
+            "linux-modules-5.11.0-7614-generic",
+            "linux-headers-5.11.0-7614-generic",
+        ]);
+    }
+
+    #[test]
+    fn plans() {
+        let versions = |kernels: &[Kernel]| {
+            kernels.iter().map(|kernel| kernel.version.clone()).collect::<Vec<_>>()
+        };
+
+        let plan = plan(parse_installed(DPKG), "5.8.0-7642-generic", 1);
+        assert_eq!(versions(&plan.kept), vec!["5.11.0-7614-generic", "5.8.0-7642-generic"]);
+        assert_eq!(versions(&plan.removed), vec!["5.11.0-7612-generic"]);
+
+        // The running kernel is not counted, and an older kernel is kept to fall back to.
+        let plan = super::plan(parse_installed(DPKG), "5.11.0-7614-generic", 1);
+        assert_eq!(versions(&plan.kept), vec!["5.11.0-7614-generic", "5.11.0-7612-generic"]);
+        assert_eq!(versions(&plan.removed), vec!["5.8.0-7642-generic"]);
+
+        let plan = super::plan(parse_installed(DPKG), "5.11.0-7614-generic", 0);
+        assert_eq!(versions(&plan.kept), vec!["5.11.0-7614-generic", "5.11.0-7612-generic"]);
+
+        let plan = super::plan(parse_installed(DPKG), "5.11.0-7612-generic", 2);
+        assert_eq!(versions(&plan.kept), vec![
+            "5.11.0-7614-generic",
+            "5.11.0-7612-generic",
+            "5.8.0-7642-generic"
+        ]);
+
+        // A running kernel which was not installed from a package leaves nothing to fall back to.
+        let plan = super::plan(parse_installed(DPKG), "5.12.0-custom", 0);
+        assert_eq!(versions(&plan.kept), vec![
+            "5.11.0-7614-generic",
+            "5.11.0-7612-generic",
+            "5.8.0-7642-generic"
+        ]);
+        assert!(plan.removed.is_empty());
+    }
+}
@@ -5,9 +5,6 @@
 #[macro_use]
 extern crate anyhow;
 
-#[macro_use]
-extern crate bitflags;
-
 #[macro_use]
 extern crate cascade;
 
@@ -23,24 +20,83 @@ extern crate log;
 #[macro_use]
 extern crate num_derive;
 
+/// Waiting on the locks of apt and dpkg, which other package managers may hold
+pub mod apt_lock;
+
 /// Changelogs for each Pop!_OS release
 pub mod changelogs;
 
 /// Validate the SHA256 checksum of a file
 pub mod checksum;
 
+/// Removal of files which pop-upgrade leaves behind
+pub mod clean;
+
+/// System-wide configuration for pop-upgrade
+pub mod config;
+
 /// Features specific to the client for the upgrade daemon
 pub mod client;
 
 /// Features specific to the upgrade daemon
 pub mod daemon;
 
+/// C-compatible functions for clients of the daemon, such as the GNOME Settings plugin
+#[cfg(feature = "ffi")]
+pub mod ffi;
+
+/// Detection of other operating systems which are installed alongside Pop!_OS
+pub mod dual_boot;
+
+/// A record of the operations which the daemon has performed, for auditing
+pub mod history;
+
+/// Scripts which administrators configure to run before and after upgrades
+pub mod hooks;
+
 /// Functions for determining when the OS was installed
 pub mod install;
 
+/// Removal of old kernels, and of the loader entries which booted them
+pub mod kernels;
+
+/// Translations of the messages which are shown to users
+#[macro_use]
+pub mod localize;
+
+/// A lock which keeps upgrade operations of separate processes from running at once
+pub mod lock;
+
+/// Logging to the terminal, and from the daemon to the journal and to rotated log files
+pub mod logging;
+
+/// Validation of full-disk encryption before upgrading
+pub mod luks;
+
+/// Management of systemd-boot loader entries
+pub mod loader;
+
 /// Miscellaneous functions used throughout the library.
 pub mod misc;
 
+/// Desktop notifications which the daemon sends to the users of active sessions
+pub mod notification;
+
+/// Reachability of the upgrade servers, and detection of captive portals which intercept them
+pub mod network;
+
+/// Managed mode, in which a signed policy restricts the upgrades of a fleet of machines
+pub mod policy;
+
+/// Authorization of daemon methods through polkit, which may prompt the user to authenticate
+pub mod polkit;
+
+/// Detection of the battery charge and AC power, which upgrades are refused without
+pub mod power;
+
+/// Checks which determine whether the system is ready to be upgraded
+pub mod preflight;
+
 /// Functions for upgrading the recovery partition
 pub mod recovery;
 
@@ -56,26 +112,67 @@ pub mod release_architecture;
 /// Functions for repairing the OS
 pub mod repair;
 
+/// Downloads performed by an unprivileged helper process, rather than by the daemon as root
+pub mod sandbox;
+
+/// Detection of Secure Boot, and of third party modules which it would prevent from loading
+pub mod secure_boot;
+
+/// SMART health of the disks which upgrades are written to
+pub mod smart;
+
 /// Signal-handling capabilities for the daemon.
 pub mod sighandler;
 
+/// Validation of LVM and software RAID beneath the root filesystem before upgrading
+pub mod storage;
+
+/// Snapshots of the root filesystem, taken before release upgrades
+pub mod snapshot;
+
 /// Determine if the system is in legacy BIOS or EFI mode.
 pub mod system_environment;
 
+/// Opt-in reports of the outcomes of release upgrades
+pub mod telemetry;
+
 mod external;
 mod fetch;
 mod gnome_extensions;
+mod http;
 
-use std::path::Path;
+use std::{fs, io, path::Path};
 
-pub static DBUS_NAME: &str = "com.system76.PopUpgrade";
-pub static DBUS_PATH: &str = "/com/system76/PopUpgrade";
-pub static DBUS_IFACE: &str = "com.system76.PopUpgrade";
+pub use pop_upgrade_core::interface::{DBUS_IFACE, DBUS_NAME, DBUS_PATH};
 
 pub const DEVELOPMENT_RELEASE_FILE: &str = "/etc/pop-upgrade/devel";
 
 pub const VAR_LIB_DIR: &str = "/var/lib/pop-upgrade";
 pub const TRANSITIONAL_SNAPS: &str = "/var/lib/pop-upgrade/transitional_snaps";
+pub const SNAP_REFRESH_HOLD: &str = "/var/lib/pop-upgrade/snap_refresh_hold";
+pub const LIFTED_HOLDS: &str = "/var/lib/pop-upgrade/lifted_holds";
+pub const CONFFILE_POLICY: &str = "/var/lib/pop-upgrade/conffiles";
 pub const RESTART_SCHEDULED: &str = "/var/lib/pop-upgrade/restarting";
 
+/// Keyring containing the keys which release ISOs and the upgrade path table are signed with.
+pub const SIGNING_KEYRING: &str = "/etc/apt/trusted.gpg.d/pop-keyring-2017-archive.gpg";
+
 pub fn development_releases_enabled() -> bool { Path::new(DEVELOPMENT_RELEASE_FILE).exists() }
+
+/// Allows or disallows upgrades to development releases, which persists across reboots.
+pub fn set_development_releases(enabled: bool) -> io::Result<()> {
+    let path = Path::new(DEVELOPMENT_RELEASE_FILE);
+
+    if enabled {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::write(path, b"")
+    } else {
+        match fs::remove_file(path) {
+            Err(why) if why.kind() != io::ErrorKind::NotFound => Err(why),
+            _ => Ok(()),
+        }
+    }
+}
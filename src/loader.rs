@@ -0,0 +1,302 @@
+use std::{
+    fmt, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use thiserror::Error;
+
+/// Where the EFI system partition is mounted.
+pub const ESP: &str = "/boot/efi";
+
+/// Where systemd-boot reads its loader entries from.
+pub const ENTRIES: &str = "/boot/efi/loader/entries";
+
+/// The configuration of the loader, which defines the default entry.
+pub const LOADER_CONF: &str = "/boot/efi/loader/loader.conf";
+
+/// The entry which kernelstub maintains for the current kernel.
+pub const CURRENT_ENTRY: &str = "Pop_OS-current";
+
+/// The entry which boots directly into the offline upgrade.
+pub const UPGRADE_ENTRY: &str = "Pop_OS-upgrade";
+
+#[derive(Debug, Error)]
+pub enum LoaderError {
+    #[error("failed to read loader entries from {:?}", _0)]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("failed to write loader entry {:?}", _0)]
+    Write(PathBuf, #[source] io::Error),
+
+    #[error("failed to remove loader entry {:?}", _0)]
+    Remove(PathBuf, #[source] io::Error),
+
+    #[error("loader entry {} was not found", _0)]
+    NotFound(String),
+
+    #[error("failed to set {} as the entry to boot once", _0)]
+    OneShot(String, #[source] io::Error),
+
+    #[error("loader entry {} boots {:?}, which does not exist", _0, _1)]
+    MissingKernel(String, PathBuf),
+}
+
+/// A systemd-boot loader entry, which retains any keys and comments it does not manage.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    /// The name of the entry's file, without its `.conf` extension.
+    pub id: String,
+    lines:  Vec<String>,
+}
+
+impl Entry {
+    pub fn new(id: &str) -> Self { Entry { id: id.into(), lines: Vec::new() } }
+
+    pub fn parse(id: &str, contents: &str) -> Self {
+        Entry { id: id.into(), lines: contents.lines().map(String::from).collect() }
+    }
+
+    /// The value of the first occurrence of a key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| split(line))
+            .find(|(name, _)| *name == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Replaces the value of the first occurrence of a key, or appends it.
+    pub fn set(&mut self, key: &str, value: &str) {
+        let line = [key, " ", value].concat();
+
+        match self.lines.iter().position(|line| split(line).map_or(false, |(k, _)| k == key)) {
+            Some(position) => self.lines[position] = line,
+            None => self.lines.push(line),
+        }
+    }
+
+    pub fn title(&self) -> Option<&str> { self.get("title") }
+
+    pub fn linux(&self) -> Option<&str> { self.get("linux") }
+
+    pub fn initrd(&self) -> Option<&str> { self.get("initrd") }
+
+    pub fn options(&self) -> Option<&str> { self.get("options") }
+
+    /// The kernel and initrd of the entry which do not exist within the `esp`.
+    pub fn missing_files(&self, esp: &Path) -> Vec<PathBuf> {
+        self.lines
+            .iter()
+            .filter_map(|line| split(line))
+            .filter(|(key, _)| *key == "linux" || *key == "initrd")
+            .map(|(_, path)| esp.join(path.trim_start_matches('/')))
+            .filter(|path| !path.exists())
+            .collect()
+    }
+
+    fn path(&self, dir: &Path) -> PathBuf { dir.join([&*self.id, ".conf"].concat()) }
+
+    /// Writes the entry to the loader's entries, replacing the existing entry of the same ID.
+    pub fn store(&self) -> Result<(), LoaderError> { self.store_in(Path::new(ENTRIES)) }
+
+    fn store_in(&self, dir: &Path) -> Result<(), LoaderError> {
+        let path = self.path(dir);
+        let temporary = dir.join([".", &*self.id, ".conf.tmp"].concat());
+
+        fs::write(&temporary, self.to_string())
+            .and_then(|_| fs::rename(&temporary, &path))
+            .map_err(|why| LoaderError::Write(path, why))
+    }
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            writeln!(fmt, "{}", line)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The loader entries, sorted by their IDs.
+pub fn entries() -> Result<Vec<Entry>, LoaderError> { entries_in(Path::new(ENTRIES)) }
+
+fn entries_in(dir: &Path) -> Result<Vec<Entry>, LoaderError> {
+    let error = |why| LoaderError::Read(dir.to_path_buf(), why);
+
+    let mut entries = Vec::new();
+
+    for entry in fs::read_dir(dir).map_err(error)? {
+        let path = entry.map_err(error)?.path();
+
+        let id = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) if name.ends_with(".conf") && !name.starts_with('.') => {
+                name.trim_end_matches(".conf")
+            }
+            _ => continue,
+        };
+
+        let contents = fs::read_to_string(&path).map_err(error)?;
+        entries.push(Entry::parse(id, &contents));
+    }
+
+    entries.sort_by(|a, b| a.id.cmp(&b.id));
+
+    Ok(entries)
+}
+
+/// Fetches the loader entry with the given ID.
+pub fn entry(id: &str) -> Result<Entry, LoaderError> {
+    entries()?
+        .into_iter()
+        .find(|entry| entry.id == id)
+        .ok_or_else(|| LoaderError::NotFound(id.into()))
+}
+
+/// Removes a loader entry, if it exists.
+pub fn remove(id: &str) -> Result<(), LoaderError> {
+    let path = Entry::new(id).path(Path::new(ENTRIES));
+
+    match fs::remove_file(&path) {
+        Err(why) if why.kind() != io::ErrorKind::NotFound => Err(LoaderError::Remove(path, why)),
+        _ => Ok(()),
+    }
+}
+
+/// Boots the given entry on the next boot only, without changing the default entry.
+pub fn set_oneshot(id: &str) -> Result<(), LoaderError> {
+    let error = |why| LoaderError::OneShot(id.into(), why);
+
+    let status = Command::new("bootctl")
+        .args(&["set-oneshot", &[id, ".conf"].concat()])
+        .status()
+        .map_err(error)?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(error(io::Error::new(io::ErrorKind::Other, format!("bootctl exited with {}", status))))
+    }
+}
+
+/// The ID of the default entry in the loader's configuration.
+pub fn default_id() -> String {
+    let conf = fs::read_to_string(LOADER_CONF).unwrap_or_default();
+
+    conf.lines()
+        .filter_map(split)
+        .find(|(key, _)| *key == "default")
+        .map_or(CURRENT_ENTRY, |(_, id)| id.trim_end_matches(".conf"))
+        .to_owned()
+}
+
+/// Verifies that the kernel and initrd of the default entry exist.
+pub fn verify_default() -> Result<(), LoaderError> { verify(&default_id()) }
+
+/// Verifies that the kernel and initrd of the entry with the given ID exist.
+pub fn verify(id: &str) -> Result<(), LoaderError> {
+    let entry = entry(id)?;
+
+    match entry.missing_files(Path::new(ESP)).into_iter().next() {
+        Some(path) => Err(LoaderError::MissingKernel(entry.id, path)),
+        None => Ok(()),
+    }
+}
+
+/// Creates the entry for the offline upgrade from the current entry, which boots directly into
+/// the `system-update` target, and sets it to be booted once.
+pub fn add_upgrade_entry() -> Result<Entry, LoaderError> {
+    let entry = upgrade_entry(&entry(CURRENT_ENTRY)?);
+
+    entry.store()?;
+    set_oneshot(&entry.id)?;
+
+    Ok(entry)
+}
+
+fn upgrade_entry(current: &Entry) -> Entry {
+    let mut entry = current.clone();
+    entry.id = UPGRADE_ENTRY.into();
+
+    entry.set("title", &[current.title().unwrap_or("Pop!_OS"), " (Upgrade)"].concat());
+
+    let mut options = current.options().unwrap_or("").to_owned();
+    if !options.contains("systemd.unit=") {
+        options.push_str(" systemd.unit=system-update.target");
+    }
+
+    entry.set("options", options.trim());
+
+    entry
+}
+
+/// Splits a line into its key and value, skipping comments and blank lines.
+fn split(line: &str) -> Option<(&str, &str)> {
+    let line = line.trim();
+
+    if line.is_empty() || line.starts_with('#') {
+        return None;
+    }
+
+    let mut fields = line.splitn(2, char::is_whitespace);
+    Some((fields.next()?, fields.next().unwrap_or("").trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CURRENT: &str = "# Managed by kernelstub
+title Pop!_OS
+linux /EFI/Pop_OS-4b8d6b8e/vmlinuz.efi
+initrd /EFI/Pop_OS-4b8d6b8e/initrd.img
+options root=UUID=4b8d6b8e ro quiet loglevel=0 splash
+";
+
+    #[test]
+    fn keys() {
+        let mut entry = Entry::parse(CURRENT_ENTRY, CURRENT);
+        assert_eq!(entry.to_string(), CURRENT);
+        assert_eq!(entry.linux(), Some("/EFI/Pop_OS-4b8d6b8e/vmlinuz.efi"));
+
+        entry.set("title", "Pop!_OS 21.04");
+        entry.set("version", "5.11.0");
+        assert_eq!(entry.title(), Some("Pop!_OS 21.04"));
+        assert!(entry.to_string().ends_with("splash\nversion 5.11.0\n"));
+    }
+
+    #[test]
+    fn upgrade() {
+        let entry = upgrade_entry(&Entry::parse(CURRENT_ENTRY, CURRENT));
+
+        assert_eq!(entry.id, UPGRADE_ENTRY);
+        assert_eq!(entry.title(), Some("Pop!_OS (Upgrade)"));
+        assert_eq!(
+            entry.options(),
+            Some("root=UUID=4b8d6b8e ro quiet loglevel=0 splash systemd.unit=system-update.target")
+        );
+    }
+
+    #[test]
+    fn store_and_verify() {
+        let esp = tempfile::tempdir().unwrap();
+        let esp = esp.path();
+        let entries = esp.join("loader/entries");
+        fs::create_dir_all(&entries).unwrap();
+
+        Entry::parse(CURRENT_ENTRY, CURRENT).store_in(&entries).unwrap();
+        let stored = entries_in(&entries).unwrap();
+        assert_eq!(stored, vec![Entry::parse(CURRENT_ENTRY, CURRENT)]);
+
+        assert_eq!(stored[0].missing_files(esp), vec![
+            esp.join("EFI/Pop_OS-4b8d6b8e/vmlinuz.efi"),
+            esp.join("EFI/Pop_OS-4b8d6b8e/initrd.img"),
+        ]);
+
+        fs::create_dir_all(esp.join("EFI/Pop_OS-4b8d6b8e")).unwrap();
+        fs::write(esp.join("EFI/Pop_OS-4b8d6b8e/vmlinuz.efi"), b"").unwrap();
+        fs::write(esp.join("EFI/Pop_OS-4b8d6b8e/initrd.img"), b"").unwrap();
+        assert!(stored[0].missing_files(esp).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+use i18n_embed::{
+    fluent::{fluent_language_loader, FluentLanguageLoader},
+    unic_langid::LanguageIdentifier,
+    DefaultLocalizer, DesktopLanguageRequester, LanguageLoader, Localizer,
+};
+use once_cell::sync::Lazy;
+use rust_embed::RustEmbed;
+
+#[derive(RustEmbed)]
+#[folder = "i18n/"]
+struct Localizations;
+
+pub static LANGUAGE_LOADER: Lazy<FluentLanguageLoader> = Lazy::new(|| {
+    let loader: FluentLanguageLoader = fluent_language_loader!();
+
+    loader.load_fallback_language(&Localizations).expect("Error while loading fallback language");
+
+    loader
+});
+
+#[macro_export]
+macro_rules! fl {
+    ($message_id:literal) => {{
+        i18n_embed_fl::fl!($crate::localize::LANGUAGE_LOADER, $message_id)
+    }};
+
+    ($message_id:literal, $($args:expr),*) => {{
+        i18n_embed_fl::fl!($crate::localize::LANGUAGE_LOADER, $message_id, $($args), *)
+    }};
+}
+
+// Get the `Localizer` to be used for localizing this library.
+pub fn localizer() -> Box<dyn Localizer> {
+    Box::from(DefaultLocalizer::new(&*LANGUAGE_LOADER, &Localizations))
+}
+
+/// Selects the languages which the locale of the process requests, falling back to English.
+pub fn init() {
+    let requested = DesktopLanguageRequester::requested_languages();
+    if let Err(why) = localizer().select(&requested) {
+        warn!("failed to load translations: {}", why);
+    }
+}
+
+/// Loads the translations for another process's locale, such as `de_DE.UTF-8`, falling back to
+/// English.
+pub fn loader(locale: &str) -> FluentLanguageLoader {
+    let loader: FluentLanguageLoader = fluent_language_loader!();
+
+    loader.load_fallback_language(&Localizations).expect("Error while loading fallback language");
+
+    if let Some(language) = language(locale) {
+        if let Err(why) = i18n_embed::select(&loader, &Localizations, &[language]) {
+            warn!("failed to load translations for {}: {}", locale, why);
+        }
+    }
+
+    loader
+}
+
+/// The language of a locale, without its encoding or modifier.
+fn language(locale: &str) -> Option<LanguageIdentifier> {
+    let language = locale.split(|c| c == '.' || c == '@').next()?;
+    language.replace('_', "-").parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages() {
+        assert_eq!(language("de_DE.UTF-8"), "de-DE".parse().ok());
+        assert_eq!(language("sr_RS@latin"), "sr-RS".parse().ok());
+        assert_eq!(language("pt_BR"), "pt-BR".parse().ok());
+        assert_eq!(language("C.UTF-8"), None);
+    }
+}
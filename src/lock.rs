@@ -0,0 +1,128 @@
+//! A lock which is held for the duration of an upgrade operation, so that operations which are
+//! begun by separate processes, such as a second instance of the daemon, never run at once.
+//!
+//! The lock file records the process which holds it, and the phase which its operation is in, so
+//! that an operation which is refused can say what it is waiting on.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    mem::ManuallyDrop,
+    os::unix::io::{AsRawFd, FromRawFd, IntoRawFd},
+    process,
+    sync::atomic::{AtomicI32, Ordering},
+};
+use thiserror::Error;
+
+pub use pop_upgrade_core::interface::errors::BUSY_ERROR;
+
+/// Where the lock is kept, which is cleared on boot.
+pub const LOCK_DIR: &str = "/run/pop-upgrade";
+
+const LOCK: &str = "/run/pop-upgrade/lock";
+
+/// The descriptor of the lock file while this process holds the lock, or -1.
+static HELD: AtomicI32 = AtomicI32::new(-1);
+
+#[derive(Debug, Error)]
+pub enum LockError {
+    #[error("failed to open {}", LOCK)]
+    Open(#[source] io::Error),
+
+    #[error("another upgrade operation is in progress ({})", _0)]
+    Held(String),
+}
+
+impl From<LockError> for String {
+    fn from(why: LockError) -> Self { why.to_string() }
+}
+
+/// Takes the lock for an operation which is beginning in the given phase.
+pub fn acquire(phase: &str) -> Result<(), LockError> {
+    if HELD.load(Ordering::SeqCst) != -1 {
+        return Err(LockError::Held(holder()));
+    }
+
+    fs::create_dir_all(LOCK_DIR).map_err(LockError::Open)?;
+
+    let mut file = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .open(LOCK)
+        .map_err(LockError::Open)?;
+
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } != 0 {
+        let why = io::Error::last_os_error();
+        if why.raw_os_error() != Some(libc::EWOULDBLOCK) {
+            return Err(LockError::Open(why));
+        }
+
+        let mut contents = String::new();
+        let _ = file.read_to_string(&mut contents);
+        return Err(LockError::Held(describe(&contents)));
+    }
+
+    HELD.store(file.into_raw_fd(), Ordering::SeqCst);
+    record(phase);
+    Ok(())
+}
+
+/// Records the phase which the operation has reached, if this process holds the lock.
+pub fn record(phase: &str) {
+    let fd = HELD.load(Ordering::SeqCst);
+    if fd == -1 {
+        return;
+    }
+
+    // The descriptor remains open for as long as the lock is held.
+    let mut file = ManuallyDrop::new(unsafe { File::from_raw_fd(fd) });
+
+    let result = file
+        .set_len(0)
+        .and_then(|_| file.seek(SeekFrom::Start(0)))
+        .and_then(|_| write!(file, "{}\n{}\n", process::id(), phase));
+
+    if let Err(why) = result {
+        warn!("failed to record the phase of the operation in {}: {}", LOCK, why);
+    }
+}
+
+/// Releases the lock, if this process holds it.
+pub fn release() {
+    let fd = HELD.swap(-1, Ordering::SeqCst);
+    if fd != -1 {
+        // Closing the descriptor releases the lock.
+        let file = unsafe { File::from_raw_fd(fd) };
+        let _ = file.set_len(0);
+    }
+}
+
+/// Describes the process which holds the lock, and the phase of its operation.
+fn holder() -> String {
+    fs::read_to_string(LOCK).map_or_else(|_| describe(""), |contents| describe(&contents))
+}
+
+fn describe(contents: &str) -> String {
+    let mut lines = contents.lines();
+    let pid = lines.next().and_then(|pid| pid.trim().parse::<u32>().ok());
+    let phase = lines.next().map(str::trim).filter(|phase| !phase.is_empty());
+
+    match (pid, phase) {
+        (Some(pid), Some(phase)) => fomat!("pid " (pid) ", " (phase)),
+        (Some(pid), None) => fomat!("pid " (pid)),
+        _ => "unknown process".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holders() {
+        assert_eq!(describe("1234\nfetching packages\n"), "pid 1234, fetching packages");
+        assert_eq!(describe("1234\n"), "pid 1234");
+        assert_eq!(describe(""), "unknown process");
+    }
+}
@@ -1,9 +1,129 @@
-use fern::{Dispatch, InitError};
+use crate::daemon::DaemonStatus;
+use fern::{Dispatch, InitError, Output};
 use log::{Level, LevelFilter, Record};
-use std::io;
+use num_traits::FromPrimitive;
+use std::{
+    env,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    os::unix::net::UnixDatagram,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU16, AtomicU64, Ordering},
+        Mutex,
+    },
+};
 use yansi::Paint;
 
+/// Where the daemon writes its log files.
+pub const LOG_DIR: &str = "/var/log/pop-upgrade";
+
+/// The log file which is written to. Once rotated, it is suffixed with `.1`, `.2`, and so on.
+const LOG_FILE: &str = "/var/log/pop-upgrade/pop-upgrade.log";
+
+/// Size beyond which the log file is rotated.
+const ROTATE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// Number of rotated log files which are kept.
+const ROTATIONS: u32 = 4;
+
+/// Receives entries in the journal's native protocol.
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+
+/// The ID of the operation which the daemon is performing, or zero if it is inactive.
+static OPERATION: AtomicU64 = AtomicU64::new(0);
+
+/// The status and sub-status of the daemon, which make up the phase of the operation.
+static PHASE: AtomicU16 = AtomicU16::new(0);
+
+/// Attributes the entries which are logged from now on to a new operation.
+pub fn begin_operation(status: DaemonStatus) {
+    let id = rand::random::<u64>().max(1);
+    OPERATION.store(id, Ordering::SeqCst);
+    set_phase(status, 0);
+    info!("beginning operation {:016x}: {}", id, status);
+}
+
+/// Attributes the entries which are logged from now on to a phase of the operation.
+pub fn set_phase(status: DaemonStatus, sub_status: u8) {
+    PHASE.store(u16::from(status as u8) << 8 | u16::from(sub_status), Ordering::SeqCst);
+}
+
+/// Stops attributing entries to the operation which has ended.
+pub fn end_operation() {
+    OPERATION.store(0, Ordering::SeqCst);
+    PHASE.store(0, Ordering::SeqCst);
+}
+
+/// The level to log at, which each `-v` raises from the default, and `--quiet` lowers to errors.
+pub fn verbosity(default: LevelFilter, verbose: u64, quiet: bool) -> LevelFilter {
+    if quiet {
+        return LevelFilter::Error;
+    }
+
+    match (default as u64).saturating_add(verbose) {
+        0 => LevelFilter::Off,
+        1 => LevelFilter::Error,
+        2 => LevelFilter::Warn,
+        3 => LevelFilter::Info,
+        4 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Logs to the terminal.
 pub fn setup_logging(filter: LevelFilter) -> Result<(), InitError> {
+    dispatch(filter).chain(terminal()).apply()?;
+    Ok(())
+}
+
+/// Logs to the journal, with the operation and phase of each entry as fields, and to a log file
+/// of JSON entries which is rotated as it grows. Entries are written to the terminal too, unless
+/// the terminal is the journal.
+pub fn setup_daemon_logging(filter: LevelFilter) -> Result<(), InitError> {
+    let mut dispatch = dispatch(filter);
+
+    let journal = UnixDatagram::unbound()
+        .and_then(|socket| socket.connect(JOURNAL_SOCKET).map(|_| socket))
+        .ok();
+
+    if journal.is_none() || env::var_os("JOURNAL_STREAM").is_none() {
+        dispatch = dispatch.chain(terminal());
+    }
+
+    if let Some(socket) = journal {
+        dispatch = dispatch.chain(Output::call(move |record| {
+            let _ = socket.send(&journal_entry(record));
+        }));
+    }
+
+    match RotatingFile::open(PathBuf::from(LOG_FILE), ROTATE_SIZE) {
+        Ok(file) => {
+            let file = Mutex::new(file);
+            dispatch = dispatch.chain(Output::call(move |record| {
+                let _ = file.lock().unwrap().write_entry(&file_entry(record));
+            }));
+        }
+        Err(why) => eprintln!("pop-upgrade: failed to open {}: {}", LOG_FILE, why),
+    }
+
+    dispatch.apply()?;
+    Ok(())
+}
+
+fn dispatch(filter: LevelFilter) -> Dispatch {
+    // Crates that we use are only logged when tracing, such as the requests of the HTTP client.
+    let dependencies = if filter == LevelFilter::Trace { filter } else { LevelFilter::Off };
+
+    Dispatch::new()
+        .level(dependencies)
+        // Include only the logs for relevant crates of interest
+        .level_for("pop_upgrade", filter)
+        .level_for("pop_upgrade_gtk", LevelFilter::Trace)
+        .level_for("apt_fetcher", filter)
+}
+
+fn terminal() -> Dispatch {
     let location = |record: &Record| {
         let mut target = record.target();
         if let Some(pos) = target.find(':') {
@@ -30,12 +150,6 @@ pub fn setup_logging(filter: LevelFilter) -> Result<(), InitError> {
     };
 
     Dispatch::new()
-        // Exclude logs for crates that we use
-        .level(LevelFilter::Off)
-        // Include only the logs for relevant crates of interest
-        .level_for("pop_upgrade", filter)
-        .level_for("pop_upgrade_gtk", LevelFilter::Trace)
-        .level_for("apt_fetcher", filter)
         .format(move |out, message, record| {
             out.finish(format_args!(
                 "[{:5}] {}: {}",
@@ -45,6 +159,163 @@ pub fn setup_logging(filter: LevelFilter) -> Result<(), InitError> {
             ))
         })
         .chain(io::stderr())
-        .apply()?;
-    Ok(())
+}
+
+fn operation() -> Option<String> {
+    match OPERATION.load(Ordering::SeqCst) {
+        0 => None,
+        id => Some(format!("{:016x}", id)),
+    }
+}
+
+fn phase() -> Option<&'static str> {
+    let phase = PHASE.load(Ordering::SeqCst);
+    match DaemonStatus::from_u8((phase >> 8) as u8)? {
+        DaemonStatus::Inactive => None,
+        status => Some(status.phase(phase as u8)),
+    }
+}
+
+/// A line of JSON which records the time, operation, and phase of the entry.
+fn file_entry(record: &Record) -> String {
+    let entry = serde_json::json!({
+        "timestamp": chrono::Local::now().to_rfc3339(),
+        "level": record.level().to_string(),
+        "target": record.target(),
+        "operation": operation(),
+        "phase": phase(),
+        "message": record.args().to_string(),
+    });
+
+    [&*entry.to_string(), "\n"].concat()
+}
+
+fn journal_entry(record: &Record) -> Vec<u8> {
+    let priority = match record.level() {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    };
+
+    let mut entry = Vec::new();
+    journal_field(&mut entry, "MESSAGE", &record.args().to_string());
+    journal_field(&mut entry, "PRIORITY", priority);
+    journal_field(&mut entry, "SYSLOG_IDENTIFIER", "pop-upgrade");
+
+    if let (Some(file), Some(line)) = (record.file(), record.line()) {
+        journal_field(&mut entry, "CODE_FILE", file);
+        journal_field(&mut entry, "CODE_LINE", &line.to_string());
+    }
+
+    if let Some(operation) = operation() {
+        journal_field(&mut entry, "POP_UPGRADE_OPERATION", &operation);
+    }
+
+    if let Some(phase) = phase() {
+        journal_field(&mut entry, "POP_UPGRADE_PHASE", phase);
+    }
+
+    entry
+}
+
+/// Appends a field in the journal's native protocol, where values which span multiple lines are
+/// prefixed with their length instead of being separated by `=`.
+fn journal_field(entry: &mut Vec<u8>, key: &str, value: &str) {
+    entry.extend_from_slice(key.as_bytes());
+
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+/// A log file which is rotated once writing to it would exceed its size limit.
+struct RotatingFile {
+    path:  PathBuf,
+    file:  File,
+    size:  u64,
+    limit: u64,
+}
+
+impl RotatingFile {
+    fn open(path: PathBuf, limit: u64) -> io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+
+        Ok(Self { path, file, size, limit })
+    }
+
+    fn write_entry(&mut self, entry: &str) -> io::Result<()> {
+        if self.size != 0 && self.size + entry.len() as u64 > self.limit {
+            rotate(&self.path);
+            *self = Self::open(self.path.clone(), self.limit)?;
+        }
+
+        self.file.write_all(entry.as_bytes())?;
+        self.size += entry.len() as u64;
+        Ok(())
+    }
+}
+
+/// Shifts each log file to the next suffix, discarding the oldest.
+fn rotate(path: &Path) {
+    for n in (1..ROTATIONS).rev() {
+        let _ = fs::rename(rotated(path, n), rotated(path, n + 1));
+    }
+
+    let _ = fs::rename(path, rotated(path, 1));
+}
+
+fn rotated(path: &Path, n: u32) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(format!(".{}", n));
+    PathBuf::from(rotated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn journal_fields() {
+        let mut entry = Vec::new();
+        journal_field(&mut entry, "PRIORITY", "6");
+        journal_field(&mut entry, "MESSAGE", "a\nb");
+
+        assert_eq!(entry, b"PRIORITY=6\nMESSAGE\n\x03\0\0\0\0\0\0\0a\nb\n".to_vec());
+    }
+
+    #[test]
+    fn verbosity_levels() {
+        assert_eq!(verbosity(LevelFilter::Info, 0, false), LevelFilter::Info);
+        assert_eq!(verbosity(LevelFilter::Info, 1, false), LevelFilter::Debug);
+        assert_eq!(verbosity(LevelFilter::Info, 2, false), LevelFilter::Trace);
+        assert_eq!(verbosity(LevelFilter::Debug, 5, false), LevelFilter::Trace);
+        assert_eq!(verbosity(LevelFilter::Info, 2, true), LevelFilter::Error);
+    }
+
+    #[test]
+    fn rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pop-upgrade.log");
+
+        let mut file = RotatingFile::open(path.clone(), 8).unwrap();
+        for entry in &["first\n", "second\n", "third\n"] {
+            file.write_entry(entry).unwrap();
+        }
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "third\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 1)).unwrap(), "second\n");
+        assert_eq!(fs::read_to_string(rotated(&path, 2)).unwrap(), "first\n");
+    }
 }
@@ -0,0 +1,241 @@
+use crate::external;
+use envfile::EnvFile;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+const CRYPTTAB: &str = "/etc/crypttab";
+
+const LSBLK: &str = "/usr/bin/lsblk";
+
+/// Installed by `cryptsetup-initramfs`, and required for the initramfs to unlock the root volume.
+const CRYPTROOT_HOOK: &str = "/usr/share/initramfs-tools/hooks/cryptroot";
+
+/// Where the inclusion of cryptsetup in the initramfs may be disabled.
+const CONF_HOOK: &str = "/etc/cryptsetup-initramfs/conf-hook";
+
+const RECOVERY_CONF: &str = "/recovery/recovery.conf";
+
+#[derive(Debug, Error)]
+pub enum LuksError {
+    #[error("failed to inspect the root volume")]
+    Inspect(#[source] io::Error),
+
+    #[error("failed to read {}", _0)]
+    Read(&'static str, #[source] io::Error),
+
+    #[error(
+        "/etc/crypttab has no entry for {}, which unlocks the root volume, so the upgraded \
+         initramfs would be unable to unlock it",
+        _0
+    )]
+    MissingCrypttab(String),
+
+    #[error(
+        "/etc/crypttab unlocks {} from {}, but the encrypted partition is {}, so the upgraded \
+         initramfs would be unable to unlock it",
+        name,
+        entry,
+        device
+    )]
+    CrypttabMismatch { name: String, entry: String, device: String },
+
+    #[error(
+        "cryptsetup-initramfs is not installed, so the upgraded initramfs would be unable to \
+         unlock the root volume"
+    )]
+    MissingHooks,
+
+    #[error(
+        "cryptsetup is disabled in /etc/cryptsetup-initramfs/conf-hook, so the upgraded initramfs \
+         would be unable to unlock the root volume"
+    )]
+    HooksDisabled,
+
+    #[error(
+        "the recovery partition expects the encrypted partition to be UUID={}, but it is UUID={}, \
+         so the recovery partition would be unable to unlock it",
+        recorded,
+        actual
+    )]
+    RecoveryMismatch { recorded: String, actual: String },
+}
+
+impl LuksError {
+    /// Whether the error describes an inconsistency which would leave the disk unable to be
+    /// unlocked, rather than a failure to inspect the system.
+    pub fn is_inconsistent(&self) -> bool {
+        !matches!(self, LuksError::Inspect(_) | LuksError::Read(..))
+    }
+}
+
+/// An encrypted volume which the root filesystem resides on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CryptVolume {
+    /// The name of the device mapper target that the volume is unlocked as.
+    pub name:   String,
+    /// The partition which holds the LUKS header.
+    pub device: PathBuf,
+    /// The UUID of the LUKS header.
+    pub uuid:   String,
+}
+
+/// The encrypted volume which the root filesystem resides on, if it is encrypted.
+pub fn root_volume() -> Result<Option<CryptVolume>, LuksError> {
+    let output = async_io::block_on(async {
+        let source = external::mount_source("/").await?;
+        external::output(LSBLK, &["-s", "-P", "-o", "NAME,TYPE,UUID", &source]).await
+    })
+    .map_err(LuksError::Inspect)?;
+
+    Ok(parse_lsblk(&output))
+}
+
+/// Validates that the root volume would remain unlockable after upgrading, or by the recovery
+/// partition. Returns the volume if the root filesystem is encrypted.
+pub fn validate() -> Result<Option<CryptVolume>, LuksError> {
+    let volume = match root_volume()? {
+        Some(volume) => volume,
+        None => return Ok(None),
+    };
+
+    let crypttab = fs::read_to_string(CRYPTTAB).map_err(|why| LuksError::Read(CRYPTTAB, why))?;
+    check_crypttab(&crypttab, &volume)?;
+
+    if !Path::new(CRYPTROOT_HOOK).exists() {
+        return Err(LuksError::MissingHooks);
+    }
+
+    if let Ok(conf) = fs::read_to_string(CONF_HOOK) {
+        if hooks_disabled(&conf) {
+            return Err(LuksError::HooksDisabled);
+        }
+    }
+
+    if Path::new(RECOVERY_CONF).exists() {
+        let conf = EnvFile::new(Path::new(RECOVERY_CONF))
+            .map_err(|why| LuksError::Read(RECOVERY_CONF, why))?;
+
+        if let Some(recorded) = conf.get("LUKS_UUID") {
+            if recorded != volume.uuid {
+                return Err(LuksError::RecoveryMismatch {
+                    recorded: recorded.into(),
+                    actual:   volume.uuid,
+                });
+            }
+        }
+    }
+
+    Ok(Some(volume))
+}
+
+/// Finds the first encrypted volume in the dependencies of a block device, and the partition
+/// that it is unlocked from.
+fn parse_lsblk(output: &str) -> Option<CryptVolume> {
+    let devices = output.lines().map(parse_pairs).collect::<Vec<_>>();
+
+    let position = devices.iter().position(|device| device.get("TYPE") == Some(&"crypt"))?;
+    let name = devices[position].get("NAME")?;
+    let parent = devices.get(position + 1)?;
+
+    Some(CryptVolume {
+        name:   (*name).into(),
+        device: Path::new("/dev").join(parent.get("NAME")?),
+        uuid:   (*parent.get("UUID")?).into(),
+    })
+}
+
+/// Parses the `KEY="value"` pairs which `lsblk -P` outputs.
+pub(crate) fn parse_pairs(line: &str) -> HashMap<&str, &str> {
+    line.split_whitespace()
+        .filter_map(|pair| {
+            let mut fields = pair.splitn(2, '=');
+            Some((fields.next()?, fields.next()?.trim_matches('"')))
+        })
+        .collect()
+}
+
+fn check_crypttab(crypttab: &str, volume: &CryptVolume) -> Result<(), LuksError> {
+    let source = crypttab
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| line.split_whitespace())
+        .find_map(|mut fields| match (fields.next(), fields.next()) {
+            (Some(name), Some(source)) if name == volume.name => Some(source),
+            _ => None,
+        })
+        .ok_or_else(|| LuksError::MissingCrypttab(volume.name.clone()))?;
+
+    let matches = if let Some(uuid) = source.strip_prefix("UUID=") {
+        uuid == volume.uuid
+    } else if source.starts_with('/') {
+        fs::canonicalize(source).unwrap_or_else(|_| source.into()) == volume.device
+    } else {
+        // Sources such as `PARTUUID=` and `LABEL=` are not verified.
+        true
+    };
+
+    if matches {
+        Ok(())
+    } else {
+        Err(LuksError::CrypttabMismatch {
+            name:   volume.name.clone(),
+            entry:  source.into(),
+            device: fomat!((volume.device.display()) " (UUID=" (volume.uuid) ")"),
+        })
+    }
+}
+
+fn hooks_disabled(conf: &str) -> bool {
+    conf.lines().map(str::trim).any(|line| line == "CRYPTSETUP=n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LSBLK: &str = r#"NAME="data-root" TYPE="lvm" UUID="0d5b4e3c-7a0e-4b4e-9d4b-1c2f3e4d5a6b"
+NAME="cryptdata" TYPE="crypt" UUID="kWb3Xr-1nYq-3oXq-aB2c-dE4f-gH5i-jK6l7m"
+NAME="nvme0n1p3" TYPE="part" UUID="ed9e7007-b02b-48a6-b4ce-2207ee5fefd6"
+NAME="nvme0n1" TYPE="disk" UUID=""
+"#;
+
+    fn volume() -> CryptVolume {
+        CryptVolume {
+            name:   "cryptdata".into(),
+            device: PathBuf::from("/dev/nvme0n1p3"),
+            uuid:   "ed9e7007-b02b-48a6-b4ce-2207ee5fefd6".into(),
+        }
+    }
+
+    #[test]
+    fn encrypted_root() {
+        assert_eq!(parse_lsblk(LSBLK), Some(volume()));
+        assert_eq!(parse_lsblk(r#"NAME="nvme0n1p3" TYPE="part" UUID="ed9e7007""#), None);
+    }
+
+    #[test]
+    fn crypttab() {
+        let good = "cryptdata UUID=ed9e7007-b02b-48a6-b4ce-2207ee5fefd6 none luks\n";
+        assert!(check_crypttab(good, &volume()).is_ok());
+
+        let stale = "cryptdata UUID=00000000-b02b-48a6-b4ce-2207ee5fefd6 none luks\n";
+        assert!(matches!(
+            check_crypttab(stale, &volume()),
+            Err(LuksError::CrypttabMismatch { .. })
+        ));
+
+        let renamed = "cryptroot UUID=ed9e7007-b02b-48a6-b4ce-2207ee5fefd6 none luks\n";
+        assert!(matches!(check_crypttab(renamed, &volume()), Err(LuksError::MissingCrypttab(_))));
+    }
+
+    #[test]
+    fn conf_hook() {
+        assert!(hooks_disabled("#CRYPTSETUP=\nCRYPTSETUP=n\n"));
+        assert!(!hooks_disabled("#CRYPTSETUP=n\n"));
+    }
+}
@@ -14,11 +14,15 @@ extern crate shrinkwraprs;
 extern crate thiserror;
 
 mod cli;
-mod logging;
 mod notify;
 
-use crate::{cli::Client, logging::setup_logging};
-use pop_upgrade::{daemon::Daemon, sighandler};
+use crate::cli::Client;
+use pop_upgrade::{
+    daemon::Daemon,
+    fl, localize,
+    logging::{setup_daemon_logging, setup_logging, verbosity},
+    sandbox, sighandler,
+};
 
 pub mod error {
     use pop_upgrade::{
@@ -52,28 +56,138 @@ pub mod error {
     }
 }
 
-use clap::{App, AppSettings, Arg, ArgMatches, SubCommand};
-use std::process::exit;
+use clap::{App, AppSettings, Arg, ArgMatches, Shell, SubCommand};
+use std::{io, process::exit};
 
 use self::error::InitError;
 
 pub fn main() {
-    let _ = setup_logging(::log::LevelFilter::Debug);
+    let matches = app().get_matches();
 
-    let clap = App::new("pop-upgrade")
+    let verbose = matches.occurrences_of("verbose");
+    let quiet = matches.is_present("quiet");
+    cli::set_quiet(quiet);
+    cli::set_non_interactive(matches.is_present("assume-yes"));
+
+    let _ = if matches.subcommand_name() == Some("daemon") {
+        setup_daemon_logging(verbosity(::log::LevelFilter::Debug, verbose, quiet))
+    } else {
+        setup_logging(verbosity(::log::LevelFilter::Info, verbose, quiet))
+    };
+
+    localize::init();
+
+    if let Err(why) = main_(&matches) {
+        // Outcomes such as there being no update were already described by the output.
+        if !why.to_string().is_empty() {
+            eprintln!("pop-upgrade: {}", why);
+        }
+
+        let mut source = why.source();
+        while let Some(why) = source {
+            eprintln!("  {}", fl!("error-caused-by", why = why.to_string()));
+            source = why.source();
+        }
+
+        exit(cli::ExitCode::of(&why) as i32);
+    }
+}
+
+/// The command-line interface, from which shell completions are generated too.
+fn app() -> App<'static, 'static> {
+    App::new("pop-upgrade")
         .about("Pop!_OS Upgrade Utility")
         .global_setting(AppSettings::ColoredHelp)
         .global_setting(AppSettings::UnifiedHelpMessage)
         .setting(AppSettings::SubcommandRequiredElseHelp)
+        .arg(
+            Arg::with_name("json")
+                .help(
+                    "print the output of the release check, recovery check, status, preflight, \
+                     clean, history, and release report subcommands as JSON documents, which are \
+                     stable across versions",
+                )
+                .long("json")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("verbose")
+                .help("log in more detail: once for debugging, and twice for HTTP and rsync detail")
+                .short("v")
+                .long("verbose")
+                .multiple(true)
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("assume-yes")
+                .help(
+                    "answer every prompt with yes, and never retry when the network is \
+                     unreachable",
+                )
+                .short("y")
+                .long("assume-yes")
+                .visible_alias("non-interactive")
+                .global(true),
+        )
+        .arg(
+            Arg::with_name("quiet")
+                .help("only print errors, and the results of operations which fail")
+                .short("q")
+                .long("quiet")
+                .conflicts_with("verbose")
+                .global(true),
+        )
         // Recovery partition tools.
         .subcommand(
             SubCommand::with_name("cancel")
-                .about("cancels any process which is currently in progress"),
+                .about(
+                    "cancels any process which is currently in progress, and waits for the \
+                     daemon to clean up after it",
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("clean")
+                .about(
+                    "remove stale ISO caches, orphaned casper directories, leftover upgrade \
+                     state files, and old logs",
+                )
+                .subcommand(
+                    SubCommand::with_name("kernels")
+                        .about(
+                            "remove every kernel except for the running kernel and the most \
+                             recent kernels, and the boot entries of removed kernels",
+                        )
+                        .arg(
+                            Arg::with_name("keep")
+                                .help(
+                                    "the number of the most recent kernels to keep, besides the \
+                                     running kernel",
+                                )
+                                .long("keep")
+                                .takes_value(true)
+                                .default_value("1"),
+                        )
+                        .arg(
+                            Arg::with_name("dry-run")
+                                .help("list the kernels which would be removed, without removing")
+                                .long("dry-run"),
+                        ),
+                ),
         )
         .subcommand(
             SubCommand::with_name("daemon")
                 .about("launch a daemon for integration with control centers like GNOME's"),
         )
+        .subcommand(
+            SubCommand::with_name(sandbox::HELPER)
+                .about("download a URL to stdout, on behalf of the daemon")
+                .setting(AppSettings::Hidden)
+                .arg(Arg::with_name("URL").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("history")
+                .about("show the upgrades which the daemon has performed, and how they ended"),
+        )
         .subcommand(
             SubCommand::with_name("recovery")
                 .about("tools for managing the recovery partition")
@@ -110,6 +224,19 @@ pub fn main() {
                                             "fetches the next release's ISO if VERSION is not set",
                                         )
                                         .long("next"),
+                                )
+                                .arg(
+                                    Arg::with_name("ignore-battery")
+                                        .help(
+                                            "upgrade even if the system is running on a low \
+                                             battery",
+                                        )
+                                        .long("ignore-battery"),
+                                )
+                                .arg(
+                                    Arg::with_name("allow-metered")
+                                        .help("download the ISO even if the connection is metered")
+                                        .long("allow-metered"),
                                 ),
                         ),
                 )
@@ -124,11 +251,97 @@ pub fn main() {
                 .about("check for new distribution releases, or upgrade to a new release")
                 .setting(AppSettings::SubcommandRequiredElseHelp)
                 .subcommand(
-                    SubCommand::with_name("check").about("check for a new distribution release"),
+                    SubCommand::with_name("check")
+                        .about("check for a new distribution release")
+                        .arg(
+                            Arg::with_name("preview")
+                                .help(
+                                    "list the packages which upgrading to the new release would \
+                                     remove or downgrade",
+                                )
+                                .long("preview"),
+                        )
+                        .arg(
+                            Arg::with_name("simulate")
+                                .help(
+                                    "resolve the upgrade to the new release against a temporary \
+                                     copy of its sources, and list the packages it would install, \
+                                     upgrade, remove, or downgrade, and the size of its download, \
+                                     without changing the system",
+                                )
+                                .long("simulate")
+                                .conflicts_with("preview"),
+                        ),
                 )
                 .subcommand(
                     SubCommand::with_name("dismiss")
-                        .about("dismiss the current release notification (LTS only)"),
+                        .about("dismiss the current release notification"),
+                )
+                .subcommand(
+                    SubCommand::with_name("notes")
+                        .about("show what is new in a release")
+                        .arg(
+                            Arg::with_name("VERSION")
+                                .help("the release to show notes for, instead of the next release"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("path")
+                        .about("show the releases to upgrade through to reach a release")
+                        .arg(Arg::with_name("VERSION").help(
+                            "the release to upgrade to, instead of showing each release which \
+                             upgrades would pass through",
+                        ))
+                        .arg(
+                            Arg::with_name("from")
+                                .help("plan from this release instead of the current release")
+                                .long("from")
+                                .takes_value(true)
+                                .requires("VERSION"),
+                        )
+                        .arg(
+                            Arg::with_name("lift-holds")
+                                .help(
+                                    "lift the holds on held packages until the upgrade completes, \
+                                     instead of refusing to upgrade",
+                                )
+                                .long("lift-holds"),
+                        )
+                        .arg(
+                            Arg::with_name("ignore-battery")
+                                .help("upgrade even if the system is running on a low battery")
+                                .long("ignore-battery"),
+                        )
+                        .arg(
+                            Arg::with_name("allow-metered")
+                                .help("download the upgrade even if the connection is metered")
+                                .long("allow-metered"),
+                        )
+                        .arg(
+                            Arg::with_name("conffiles")
+                                .help(
+                                    "how conflicts with modified configuration files are \
+                                     resolved, in place of the configured policy: keep the old \
+                                     file, or install the new file",
+                                )
+                                .long("conffiles")
+                                .takes_value(true)
+                                .possible_values(&["old", "new"]),
+                        )
+                        .arg(
+                            Arg::with_name("upgrade")
+                                .help(
+                                    "begin upgrading to the first release in the path; each \
+                                     upgrade requires a reboot before the next may begin",
+                                )
+                                .short("u")
+                                .long("upgrade")
+                                .requires("VERSION"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("preflight")
+                        .about("check whether the system is ready to be upgraded"),
                 )
                 .subcommand(
                     SubCommand::with_name("update")
@@ -140,6 +353,15 @@ pub fn main() {
                                 )
                                 .short("d")
                                 .long("download-only"),
+                        )
+                        .arg(
+                            Arg::with_name("offline")
+                                .help(
+                                    "fetch updates, and install them on the next boot instead of \
+                                     on the running system",
+                                )
+                                .long("offline")
+                                .conflicts_with("download-only"),
                         ),
                 )
                 .subcommand(
@@ -148,13 +370,85 @@ pub fn main() {
                         .subcommand(SubCommand::with_name("disable"))
                         .subcommand(SubCommand::with_name("enable")),
                 )
+                .subcommand(
+                    SubCommand::with_name("reclaim").about(
+                        "remove packages which are no longer required, and clear the package cache",
+                    ),
+                )
                 .subcommand(
                     SubCommand::with_name("repair")
                         .about("search for issues in the system, and repair them"),
                 )
+                .subcommand(
+                    SubCommand::with_name("report")
+                        .about("show what the last release upgrade changed on the system"),
+                )
+                .subcommand(
+                    SubCommand::with_name("resume")
+                        .about("resume a release upgrade which was interrupted"),
+                )
+                .subcommand(
+                    SubCommand::with_name("unschedule")
+                        .about("cancel a release upgrade which was scheduled to begin later"),
+                )
                 .subcommand(
                     SubCommand::with_name("upgrade")
                         .about("update the system, and fetch the packages for the next release")
+                        .arg(
+                            Arg::with_name("force")
+                                .help(
+                                    "upgrade even if the release has not been rolled out to this \
+                                     system yet",
+                                )
+                                .long("force"),
+                        )
+                        .arg(
+                            Arg::with_name("lift-holds")
+                                .help(
+                                    "lift the holds on held packages until the upgrade completes, \
+                                     instead of refusing to upgrade",
+                                )
+                                .long("lift-holds"),
+                        )
+                        .arg(
+                            Arg::with_name("ignore-battery")
+                                .help("upgrade even if the system is running on a low battery")
+                                .long("ignore-battery"),
+                        )
+                        .arg(
+                            Arg::with_name("allow-metered")
+                                .help("download the upgrade even if the connection is metered")
+                                .long("allow-metered"),
+                        )
+                        .arg(
+                            Arg::with_name("conffiles")
+                                .help(
+                                    "how conflicts with modified configuration files are \
+                                     resolved, in place of the configured policy: keep the old \
+                                     file, or install the new file",
+                                )
+                                .long("conffiles")
+                                .takes_value(true)
+                                .possible_values(&["old", "new"]),
+                        )
+                        .arg(
+                            Arg::with_name("schedule")
+                                .help(
+                                    "begin the upgrade later, at HH:MM or YYYY-MM-DD HH:MM in \
+                                     local time, or at the configured time, instead of now",
+                                )
+                                .long("schedule")
+                                .value_name("TIME")
+                                .takes_value(true)
+                                .min_values(0)
+                                .conflicts_with_all(&[
+                                    "force",
+                                    "lift-holds",
+                                    "ignore-battery",
+                                    "allow-metered",
+                                    "conffiles",
+                                ]),
+                        )
                         .arg(
                             Arg::with_name("force-next")
                                 .help(
@@ -167,51 +461,77 @@ pub fn main() {
                         ),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("snapshot")
+                .about("manage the snapshots of the system taken before release upgrades")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("list").about("list snapshots taken by pop-upgrade"),
+                )
+                .subcommand(
+                    SubCommand::with_name("restore")
+                        .about("restore the system to a snapshot")
+                        .arg(
+                            Arg::with_name("NAME")
+                                .help("the snapshot to restore, as shown by `snapshot list`")
+                                .required(true),
+                        ),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("status").about("get the status of the pop upgrade daemon"),
-        );
-
-    if let Err(why) = main_(&clap.get_matches()) {
-        eprintln!("pop-upgrade: {}", why);
+        )
+        .subcommand(
+            SubCommand::with_name("tui")
+                .about("upgrade to the next release in a full-screen terminal interface"),
+        )
+        .subcommand(
+            SubCommand::with_name("completions")
+                .about("print the completions of pop-upgrade for a shell")
+                .arg(
+                    Arg::with_name("SHELL")
+                        .help("the shell to complete for")
+                        .possible_values(&Shell::variants())
+                        .required(true),
+                ),
+        )
+}
 
-        let mut source = why.source();
-        while let Some(why) = source {
-            eprintln!("  caused by: {}", why);
-            source = why.source();
-        }
+fn main_(matches: &ArgMatches) -> anyhow::Result<()> {
+    // The helper runs without the privileges which are needed to initialize.
+    if let (sandbox::HELPER, Some(matches)) = matches.subcommand() {
+        return async_io::block_on(sandbox::serve(matches.value_of("URL").unwrap()));
+    }
 
-        exit(1);
+    if let ("completions", Some(matches)) = matches.subcommand() {
+        let shell = matches.value_of("SHELL").unwrap();
+        let shell = shell.parse::<Shell>().map_err(|why| anyhow!(why))?;
+        app().gen_completions_to("pop-upgrade", shell, &mut io::stdout());
+        return Ok(());
     }
-}
 
-fn main_(matches: &ArgMatches) -> anyhow::Result<()> {
     init()?;
 
     match matches.subcommand() {
-        ("cancel", _) => Client::new()?.cancel()?,
+        ("cancel", Some(matches)) => Client::new()?.cancel(matches)?,
         ("daemon", _) => Daemon::init()?,
         (other, Some(matches)) => {
-            let mut client = Client::new()?;
-
-            println!("checking if pop-upgrade requires an update");
-            if client.update_and_restart()? {
-                println!("waiting for daemon to update and restart");
-
-                let file = std::path::Path::new(pop_upgrade::RESTART_SCHEDULED);
-                while file.exists() {
-                    std::thread::sleep(std::time::Duration::from_secs(1));
-                }
+            let client = Client::new()?;
 
-                std::thread::sleep(std::time::Duration::from_secs(1));
+            // Scripts expect nothing but the requested output.
+            let chatty = !matches.is_present("quiet") && !matches.is_present("json");
 
-                println!("reconnecting to pop-upgrade daemon");
-                client = Client::new()?;
-            }
+            let upgrading = other == "release" && matches.subcommand_name() == Some("upgrade");
+            client.self_update(upgrading, chatty)?;
 
             let func = match other {
+                "clean" => Client::clean,
+                "history" => Client::history,
                 "recovery" => Client::recovery,
                 "release" => Client::release,
+                "snapshot" => Client::snapshot,
                 "status" => Client::status,
+                "tui" => Client::tui,
                 _ => unreachable!(),
             };
 
@@ -38,6 +38,24 @@ pub fn format_build_number(value: i16, buffer: &mut String) -> &str {
     }
 }
 
+/// Formats a size in bytes for display, using decimal units.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "kB", "MB", "GB", "TB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1000.0 && unit + 1 < UNITS.len() {
+        size /= 1000.0;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{} {}", bytes, UNITS[0])
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
 pub fn format_error(source: &(dyn std::error::Error + 'static)) -> String {
     let mut out = fomat!((source));
 
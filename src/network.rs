@@ -0,0 +1,173 @@
+use crate::{config::Config, release::mirrors};
+use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+use isahc::{
+    config::Configurable,
+    http::{header, StatusCode, Uri},
+    HttpClient,
+};
+use std::time::Duration;
+use thiserror::Error;
+
+pub use pop_upgrade_core::interface::errors::{
+    CAPTIVE_PORTAL_ERROR, METERED_ERROR, NO_CONNECTION_ERROR,
+};
+
+/// Captive portals are detected by whether requests to this URL are redirected to another host.
+const CONNECTIVITY_CHECK: &str = "http://connectivity-check.ubuntu.com/";
+
+/// The archive which the packages of Pop!_OS are fetched from.
+const ARCHIVE: &str = "http://apt.pop-os.org/";
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Error)]
+pub enum NetworkError {
+    #[error(
+        "the network requires logging in before the internet can be reached{}",
+        _0.as_ref().map_or_else(String::new, |portal| format!(": visit {}", portal))
+    )]
+    CaptivePortal(Option<String>),
+
+    #[error(
+        "the connection is metered, and downloading the upgrade may incur charges: connect to an \
+         unmetered network, or allow downloads on metered connections"
+    )]
+    Metered,
+
+    #[error("failed to create an HTTP client")]
+    Client(#[source] isahc::Error),
+
+    #[error("unable to reach {}", _0.join(", "))]
+    Unreachable(Vec<String>),
+}
+
+impl NetworkError {
+    /// The name of the DBus error which describes this error to clients.
+    pub fn dbus_name(&self) -> &'static str {
+        match self {
+            NetworkError::CaptivePortal(_) => CAPTIVE_PORTAL_ERROR,
+            NetworkError::Metered => METERED_ERROR,
+            _ => NO_CONNECTION_ERROR,
+        }
+    }
+}
+
+/// Verifies that the release API and archives are reachable before downloading from them, and
+/// that requests are not being intercepted by the captive portal of a public network.
+pub fn check() -> Result<(), NetworkError> {
+    // Redirects are not followed, so that those of a captive portal are seen.
+    let client = |url: &str| {
+        crate::http::builder(url).timeout(TIMEOUT).build().map_err(NetworkError::Client)
+    };
+
+    // If the connectivity check itself is blocked, the servers below are checked regardless.
+    if let Ok(response) = client(CONNECTIVITY_CHECK)?.head(CONNECTIVITY_CHECK) {
+        redirected(CONNECTIVITY_CHECK, response.status(), location(response.headers()))?;
+    }
+
+    let hosts = [crate::release_api::endpoint(), ARCHIVE.into(), mirrors::DEFAULT_MIRROR.into()];
+
+    let mut unreachable = Vec::new();
+    for host in &hosts {
+        if let Err(why) = client(host.as_str())?.head(host.as_str()) {
+            warn!("unable to reach {}: {}", host, why);
+            unreachable.push(host.clone());
+        }
+    }
+
+    if unreachable.is_empty() {
+        Ok(())
+    } else {
+        Err(NetworkError::Unreachable(unreachable))
+    }
+}
+
+/// Defers multi-gigabyte downloads while the connection is metered, unless the configuration
+/// allows downloading on metered connections.
+pub fn deferral() -> Result<(), NetworkError> {
+    if Config::load_or_default().network.allow_metered || !metered() {
+        Ok(())
+    } else {
+        Err(NetworkError::Metered)
+    }
+}
+
+/// Whether NetworkManager reports, or guesses, that the primary connection is metered. If the
+/// metered status is unknown, the connection is assumed to be unmetered.
+pub fn metered() -> bool {
+    match nm_metered() {
+        Ok(status) => is_metered(status),
+        Err(why) => {
+            warn!("unable to determine if the connection is metered: {}", why);
+            false
+        }
+    }
+}
+
+fn nm_metered() -> Result<u32, dbus::Error> {
+    let connection = Connection::new_system()?;
+
+    connection
+        .with_proxy(
+            "org.freedesktop.NetworkManager",
+            "/org/freedesktop/NetworkManager",
+            Duration::from_secs(5),
+        )
+        .get("org.freedesktop.NetworkManager", "Metered")
+}
+
+/// Interprets an `NMMetered` value: `1` is metered, and `3` is guessed to be metered.
+fn is_metered(status: u32) -> bool { status == 1 || status == 3 }
+
+fn location(headers: &isahc::http::HeaderMap) -> Option<String> {
+    headers.get(header::LOCATION)?.to_str().ok().map(String::from)
+}
+
+/// A request is intercepted if it is redirected to another host, which a captive portal does to
+/// every request made before logging in.
+fn redirected(
+    host: &str,
+    status: StatusCode,
+    location: Option<String>,
+) -> Result<(), NetworkError> {
+    if !status.is_redirection() {
+        return Ok(());
+    }
+
+    let authority = |url: &str| {
+        url.parse::<Uri>().ok().and_then(|uri| uri.host().map(str::to_ascii_lowercase))
+    };
+
+    match location {
+        Some(location) if authority(&location).map_or(false, |to| Some(to) != authority(host)) => {
+            Err(NetworkError::CaptivePortal(Some(location)))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PORTAL: &str = "http://portal.hotel.example/login?continue=http%3A%2F%2Fapt.pop-os.org";
+
+    #[test]
+    fn metered_statuses() {
+        let statuses = (0..=4).map(is_metered).collect::<Vec<_>>();
+        assert_eq!(statuses, [false, true, false, true, false]);
+    }
+
+    #[test]
+    fn redirects() {
+        let https = Some("https://apt.pop-os.org/".into());
+        assert!(redirected(ARCHIVE, StatusCode::MOVED_PERMANENTLY, https).is_ok());
+        assert!(redirected(ARCHIVE, StatusCode::OK, None).is_ok());
+        assert!(redirected(CONNECTIVITY_CHECK, StatusCode::NO_CONTENT, None).is_ok());
+
+        assert!(matches!(
+            redirected(CONNECTIVITY_CHECK, StatusCode::FOUND, Some(PORTAL.into())),
+            Err(NetworkError::CaptivePortal(Some(_)))
+        ));
+    }
+}
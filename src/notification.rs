@@ -0,0 +1,361 @@
+use crate::{config::Config, localize};
+use dbus::blocking::{stdintf::org_freedesktop_dbus::Properties, Connection};
+use i18n_embed::fluent::FluentLanguageLoader;
+use std::{
+    collections::HashMap,
+    fmt, fs, io,
+    path::PathBuf,
+    process::Command,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Records the notifications which each user has dismissed or snoozed, in a file per user ID.
+const PREFERENCES: &str = "/var/lib/pop-upgrade/notifications";
+
+/// How long a notification is hidden for once it has been snoozed, unless configured otherwise.
+const SNOOZE: Duration = Duration::from_secs(24 * 60 * 60);
+
+const LOGIND: &str = "org.freedesktop.login1";
+
+/// The kinds of notifications which are sent to users.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+    ReleaseAvailable,
+    UpgradeDownloaded,
+}
+
+impl Kind {
+    fn id(self) -> &'static str {
+        match self {
+            Kind::ReleaseAvailable => "release-available",
+            Kind::UpgradeDownloaded => "upgrade-downloaded",
+        }
+    }
+
+    fn from_id(id: &str) -> Option<Self> {
+        match id {
+            "release-available" => Some(Kind::ReleaseAvailable),
+            "upgrade-downloaded" => Some(Kind::UpgradeDownloaded),
+            _ => None,
+        }
+    }
+}
+
+/// A notification for the users of the active sessions, which is translated to the language of
+/// each user's session when it is shown.
+#[derive(Clone, Debug)]
+pub struct Notice {
+    pub kind: Kind,
+    /// Identifies what the notification is about, such as a release, so that dismissing it does
+    /// not dismiss later notifications of the same kind.
+    pub key:  String,
+}
+
+impl Notice {
+    pub fn release_available(next: &str) -> Self {
+        Self { kind: Kind::ReleaseAvailable, key: next.to_owned() }
+    }
+
+    pub fn upgrade_downloaded(to: &str) -> Self {
+        Self { kind: Kind::UpgradeDownloaded, key: to.to_owned() }
+    }
+
+    /// The summary and body of the notification, in the language of the loader.
+    fn text(&self, loader: &FluentLanguageLoader) -> (String, String) {
+        let version = &*self.key;
+        match self.kind {
+            Kind::ReleaseAvailable => (
+                i18n_embed_fl::fl!(loader, "release-available", version = version),
+                i18n_embed_fl::fl!(loader, "release-available-body"),
+            ),
+            Kind::UpgradeDownloaded => (
+                i18n_embed_fl::fl!(loader, "upgrade-downloaded"),
+                i18n_embed_fl::fl!(loader, "upgrade-downloaded-body", version = version),
+            ),
+        }
+    }
+}
+
+/// The notifications which a user has dismissed or snoozed.
+#[derive(Debug, Default, PartialEq)]
+pub struct Preferences {
+    /// The key of the notification of each kind which was dismissed.
+    dismissed: HashMap<Kind, String>,
+    /// When notifications of each kind may be shown again, in seconds since the Unix epoch.
+    snoozed:   HashMap<Kind, u64>,
+}
+
+impl Preferences {
+    pub fn load(uid: u32) -> Self {
+        fs::read_to_string(path(uid)).map(|prefs| Self::parse(&prefs)).unwrap_or_default()
+    }
+
+    pub fn store(&self, uid: u32) -> io::Result<()> {
+        fs::create_dir_all(PREFERENCES)?;
+        fs::write(path(uid), self.to_string())
+    }
+
+    /// Hides the notice, and later notices of the same kind about the same thing.
+    pub fn dismiss(&mut self, notice: &Notice) {
+        self.dismissed.insert(notice.kind, notice.key.clone());
+    }
+
+    /// Hides notices of the same kind for the period.
+    pub fn snooze(&mut self, kind: Kind, now: u64, period: Duration) {
+        self.set_snoozed_until(kind, Some(now + period.as_secs()));
+    }
+
+    /// The key of the notification of this kind which was dismissed.
+    pub fn dismissed(&self, kind: Kind) -> Option<&str> {
+        self.dismissed.get(&kind).map(String::as_str)
+    }
+
+    /// Dismisses the notification of this kind with the given key, or clears the dismissal.
+    pub fn set_dismissed(&mut self, kind: Kind, key: Option<String>) {
+        match key {
+            Some(key) => self.dismissed.insert(kind, key),
+            None => self.dismissed.remove(&kind),
+        };
+    }
+
+    /// When notifications of this kind may be shown again, in seconds since the Unix epoch.
+    pub fn snoozed_until(&self, kind: Kind) -> Option<u64> { self.snoozed.get(&kind).cloned() }
+
+    /// Hides notices of this kind until the given time, or clears the snooze.
+    pub fn set_snoozed_until(&mut self, kind: Kind, until: Option<u64>) {
+        match until {
+            Some(until) => self.snoozed.insert(kind, until),
+            None => self.snoozed.remove(&kind),
+        };
+    }
+
+    /// Whether the user has dismissed or snoozed the notice.
+    pub fn suppresses(&self, notice: &Notice, now: u64) -> bool {
+        self.dismissed.get(&notice.kind) == Some(&notice.key)
+            || self.snoozed.get(&notice.kind).map_or(false, |&until| now < until)
+    }
+
+    fn parse(prefs: &str) -> Self {
+        let mut parsed = Self::default();
+
+        for line in prefs.lines() {
+            let mut fields = line.split_whitespace();
+            let (action, kind, value) = match (fields.next(), fields.next(), fields.next()) {
+                (Some(action), Some(kind), Some(value)) => (action, kind, value),
+                _ => continue,
+            };
+
+            let kind = match Kind::from_id(kind) {
+                Some(kind) => kind,
+                None => continue,
+            };
+
+            match action {
+                "dismissed" => {
+                    parsed.dismissed.insert(kind, value.to_owned());
+                }
+                "snoozed" => {
+                    if let Ok(until) = value.parse::<u64>() {
+                        parsed.snoozed.insert(kind, until);
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        parsed
+    }
+}
+
+impl fmt::Display for Preferences {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for (kind, key) in &self.dismissed {
+            writeln!(fmt, "dismissed {} {}", kind.id(), key)?;
+        }
+
+        for (kind, until) in &self.snoozed {
+            writeln!(fmt, "snoozed {} {}", kind.id(), until)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Shows the notice to the user of each active graphical session, unless they dismissed or
+/// snoozed it, or notifications are disabled. Each notification is shown from a thread, which
+/// records the user's response.
+pub fn dispatch(notice: &Notice) {
+    if !Config::load_or_default().notifications.enabled {
+        info!("notifications are disabled: {} {}", notice.kind.id(), notice.key);
+        return;
+    }
+
+    let users = match active_users() {
+        Ok(users) => users,
+        Err(why) => {
+            warn!("failed to find the users of active sessions: {}", why);
+            return;
+        }
+    };
+
+    let now = now();
+
+    for (uid, user) in users {
+        if Preferences::load(uid).suppresses(notice, now) {
+            info!("{} has dismissed or snoozed the {} notification", user, notice.kind.id());
+            continue;
+        }
+
+        let notice = notice.clone();
+        std::thread::spawn(move || show(uid, &user, &notice));
+    }
+}
+
+/// The IDs and names of the users who have active graphical sessions.
+fn active_users() -> Result<Vec<(u32, String)>, dbus::Error> {
+    let connection = Connection::new_system()?;
+    let timeout = Duration::from_secs(5);
+
+    let (sessions,): (Vec<(String, u32, String, String, dbus::Path)>,) = connection
+        .with_proxy(LOGIND, "/org/freedesktop/login1", timeout)
+        .method_call("org.freedesktop.login1.Manager", "ListSessions", ())?;
+
+    let mut users = Vec::new();
+
+    for (_id, uid, user, _seat, path) in sessions {
+        let session = connection.with_proxy(LOGIND, path, timeout);
+        let active: bool = session.get("org.freedesktop.login1.Session", "Active")?;
+        let kind: String = session.get("org.freedesktop.login1.Session", "Type")?;
+
+        if active && (kind == "x11" || kind == "wayland") && !users.iter().any(|&(u, _)| u == uid) {
+            users.push((uid, user));
+        }
+    }
+
+    Ok(users)
+}
+
+/// Shows a notification on the user's session bus, in the language of their session, and waits
+/// for them to act on it.
+fn show(uid: u32, user: &str, notice: &Notice) {
+    let loader = localize::loader(&session_locale(uid, user).unwrap_or_default());
+
+    let (summary, body) = notice.text(&loader);
+
+    let output = Command::new("runuser")
+        .args(&["-u", user, "--", "env"])
+        .arg(fomat!("DBUS_SESSION_BUS_ADDRESS=unix:path=/run/user/" (uid) "/bus"))
+        .arg("notify-send")
+        .arg(fomat!("--app-name=" (i18n_embed_fl::fl!(loader, "notification-app-name"))))
+        .arg("--icon=distributor-logo")
+        .arg(fomat!("--action=dismiss=" (i18n_embed_fl::fl!(loader, "notification-dismiss"))))
+        .arg(fomat!("--action=snooze=" (i18n_embed_fl::fl!(loader, "notification-snooze"))))
+        .arg("--wait")
+        .arg(&summary)
+        .arg(&body)
+        .output();
+
+    let action = match output {
+        Ok(output) if output.status.success() => {
+            String::from_utf8_lossy(&output.stdout).into_owned()
+        }
+        Ok(output) => {
+            warn!("failed to notify {}: notify-send exited with {}", user, output.status);
+            return;
+        }
+        Err(why) => {
+            warn!("failed to notify {}: {}", user, why);
+            return;
+        }
+    };
+
+    let mut prefs = Preferences::load(uid);
+
+    match action.trim() {
+        "dismiss" => prefs.dismiss(notice),
+        "snooze" => prefs.snooze(notice.kind, now(), snooze_period()),
+        _ => return,
+    }
+
+    if let Err(why) = prefs.store(uid) {
+        warn!("failed to record the notification preferences of {}: {}", user, why);
+    }
+}
+
+/// The locale of the user's session, as the session exported it to their service manager.
+fn session_locale(uid: u32, user: &str) -> Option<String> {
+    let output = Command::new("runuser")
+        .args(&["-u", user, "--", "env"])
+        .arg(fomat!("XDG_RUNTIME_DIR=/run/user/" (uid)))
+        .args(&["systemctl", "--user", "show-environment"])
+        .output()
+        .map_err(|why| warn!("failed to read the environment of {}: {}", user, why))
+        .ok()
+        .filter(|output| output.status.success())?;
+
+    locale(&String::from_utf8_lossy(&output.stdout)).map(String::from)
+}
+
+/// The locale which messages are shown in, of an environment of `KEY=VALUE` lines.
+fn locale(environment: &str) -> Option<&str> {
+    let variable = |name: &str| {
+        environment
+            .lines()
+            .filter_map(|line| line.strip_prefix(name)?.strip_prefix('='))
+            .find(|value| !value.is_empty())
+    };
+
+    variable("LC_ALL").or_else(|| variable("LC_MESSAGES")).or_else(|| variable("LANG"))
+}
+
+fn path(uid: u32) -> PathBuf { [PREFERENCES, &uid.to_string()].iter().collect() }
+
+/// How long a notification is hidden for once it has been snoozed.
+fn snooze_period() -> Duration {
+    Config::load_or_default()
+        .notifications
+        .snooze_hours
+        .map_or(SNOOZE, |hours| Duration::from_secs(hours * 60 * 60))
+}
+
+pub fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |now| now.as_secs()) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn preferences() {
+        let available = Notice::release_available("22.04");
+        let mut prefs = Preferences::default();
+        assert!(!prefs.suppresses(&available, 0));
+
+        prefs.dismiss(&available);
+        assert!(prefs.suppresses(&available, 0));
+        assert!(!prefs.suppresses(&Notice::release_available("24.04"), 0));
+
+        let downloaded = Notice::upgrade_downloaded("22.04");
+        prefs.snooze(Kind::UpgradeDownloaded, 1000, SNOOZE);
+        assert!(prefs.suppresses(&downloaded, 1000 + SNOOZE.as_secs() - 1));
+        assert!(!prefs.suppresses(&downloaded, 1000 + SNOOZE.as_secs()));
+
+        assert_eq!(Preferences::parse(&prefs.to_string()), prefs);
+
+        prefs.set_dismissed(Kind::ReleaseAvailable, Some("24.04".into()));
+        assert_eq!(prefs.dismissed(Kind::ReleaseAvailable), Some("24.04"));
+        assert!(!prefs.suppresses(&available, 0));
+
+        prefs.set_snoozed_until(Kind::UpgradeDownloaded, None);
+        assert_eq!(prefs.snoozed_until(Kind::UpgradeDownloaded), None);
+        assert!(!prefs.suppresses(&downloaded, 1000));
+        assert_eq!(Preferences::parse("snoozed unknown 5\ndismissed"), Preferences::default());
+    }
+
+    #[test]
+    fn locales() {
+        let environment = "HOME=/home/user\nLANG=de_DE.UTF-8\nLC_MESSAGES=\n";
+        assert_eq!(locale(environment), Some("de_DE.UTF-8"));
+        assert_eq!(locale("LANG=de_DE.UTF-8\nLC_ALL=fr_FR.UTF-8\n"), Some("fr_FR.UTF-8"));
+        assert_eq!(locale("HOME=/home/user\n"), None);
+    }
+}
@@ -0,0 +1,279 @@
+//! Managed mode, in which the upgrade policy of a fleet of machines is set by a policy file that
+//! configuration management places at `/etc/pop-upgrade/policy.toml`. The policy must be signed
+//! by a key in `/etc/pop-upgrade/policy.gpg`. While it is present, the daemon refuses to begin
+//! or schedule upgrades which the policy does not permit, and requests to override its checks,
+//! whoever makes them. If the policy cannot be verified, every upgrade is refused.
+//!
+//! ```toml
+//! allowed_release = "22.04"
+//! defer_until = "2022-06-01"
+//!
+//! [[windows]]
+//! days = ["sat", "sun"]
+//! start = "22:00"
+//! end = "04:00"
+//! ```
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Weekday};
+use serde_derive::Deserialize;
+use std::{
+    convert::TryFrom,
+    fs,
+    io,
+    path::Path,
+    str::FromStr,
+};
+use thiserror::Error;
+
+pub use pop_upgrade_core::interface::errors::POLICY_ERROR;
+
+pub const POLICY: &str = "/etc/pop-upgrade/policy.toml";
+pub const SIGNATURE: &str = "/etc/pop-upgrade/policy.toml.sig";
+pub const KEYRING: &str = "/etc/pop-upgrade/policy.gpg";
+
+/// Whether the machine is managed, even if its policy cannot be verified.
+pub fn managed() -> bool { Path::new(POLICY).exists() }
+
+#[derive(Debug, Error)]
+pub enum PolicyError {
+    #[error("failed to read {}", POLICY)]
+    Read(#[source] io::Error),
+
+    #[error("failed to verify the signature of {} with gpgv", POLICY)]
+    Verify(#[source] io::Error),
+
+    #[error("{} is not signed by a key in {}", POLICY, KEYRING)]
+    Unsigned,
+
+    #[error("failed to parse {}", POLICY)]
+    Parse(#[source] toml::de::Error),
+
+    #[error("invalid {} in {}: {}", _0, POLICY, _1)]
+    Invalid(&'static str, String),
+
+    #[error("the upgrade policy only permits upgrading to {}", _0)]
+    Release(String),
+
+    #[error("the upgrade policy defers upgrades until {}", _0)]
+    Deferred(NaiveDate),
+
+    #[error("the upgrade policy only permits upgrades within its maintenance windows")]
+    Window,
+
+    #[error("the upgrade policy does not permit overriding the checks of the battery or network")]
+    Override,
+}
+
+/// The upgrade policy of a managed machine.
+#[derive(Debug, Default, PartialEq)]
+pub struct Policy {
+    /// The only release which may be upgraded to.
+    pub allowed_release: Option<String>,
+    /// Upgrades may not begin before this date.
+    pub defer_until:     Option<NaiveDate>,
+    /// Upgrades may only begin within these windows, if there are any.
+    pub windows:         Vec<Window>,
+}
+
+/// A maintenance window, which recurs on the given days of the week, or every day if none are
+/// given. A window which ends before it starts ends on the following day.
+#[derive(Debug, PartialEq)]
+pub struct Window {
+    pub days:  Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end:   NaiveTime,
+}
+
+impl Policy {
+    /// Loads and verifies the policy, if the machine is managed.
+    pub fn load() -> Result<Option<Self>, PolicyError> {
+        let policy = match fs::read(POLICY) {
+            Ok(policy) => policy,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(why) => return Err(PolicyError::Read(why)),
+        };
+
+        // The bytes which were read are verified, so the file may not be replaced in between.
+        verify(&policy)?;
+
+        String::from_utf8(policy)
+            .map_err(|_| PolicyError::Invalid("encoding", "expected UTF-8".into()))?
+            .parse::<Policy>()
+            .map(Some)
+    }
+
+    /// Whether the policy permits an upgrade to begin at the given local time. The release is
+    /// not checked if it is not yet known.
+    pub fn permits(&self, to: Option<&str>, at: NaiveDateTime) -> Result<(), PolicyError> {
+        if let (Some(allowed), Some(to)) = (self.allowed_release.as_ref(), to) {
+            if allowed != to {
+                return Err(PolicyError::Release(allowed.clone()));
+            }
+        }
+
+        if let Some(date) = self.defer_until {
+            if at.date() < date {
+                return Err(PolicyError::Deferred(date));
+            }
+        }
+
+        if !self.windows.is_empty() && !self.windows.iter().any(|window| window.contains(at)) {
+            return Err(PolicyError::Window);
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Policy {
+    type Err = PolicyError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        toml::from_str::<PolicyFile>(input)
+            .map_err(PolicyError::Parse)
+            .and_then(Policy::try_from)
+    }
+}
+
+impl Window {
+    /// Whether the window contains the given local time.
+    pub fn contains(&self, at: NaiveDateTime) -> bool {
+        let on = |day: Weekday| self.days.is_empty() || self.days.contains(&day);
+        let (date, time) = (at.date(), at.time());
+
+        if self.start <= self.end {
+            on(date.weekday()) && self.start <= time && time < self.end
+        } else {
+            (on(date.weekday()) && self.start <= time)
+                || (on((date - Duration::days(1)).weekday()) && time < self.end)
+        }
+    }
+}
+
+/// Verifies the signature of the policy with gpgv, against only the policy keyring.
+fn verify(policy: &[u8]) -> Result<(), PolicyError> {
+    let (keyring, signature) = (Path::new(KEYRING), Path::new(SIGNATURE));
+    let verified = async_io::block_on(crate::external::gpgv_data(keyring, signature, policy))
+        .map_err(PolicyError::Verify)?;
+
+    if verified {
+        Ok(())
+    } else {
+        Err(PolicyError::Unsigned)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PolicyFile {
+    allowed_release: Option<String>,
+    defer_until:     Option<String>,
+    windows:         Vec<WindowFile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct WindowFile {
+    #[serde(default)]
+    days:  Vec<String>,
+    start: String,
+    end:   String,
+}
+
+impl TryFrom<PolicyFile> for Policy {
+    type Error = PolicyError;
+
+    fn try_from(file: PolicyFile) -> Result<Self, Self::Error> {
+        let invalid = |field, why: &str| PolicyError::Invalid(field, why.to_owned());
+
+        let defer_until = match file.defer_until {
+            Some(date) => Some(
+                NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .map_err(|_| invalid("defer_until", "expected a date as YYYY-MM-DD"))?,
+            ),
+            None => None,
+        };
+
+        let time = |field, time: &str| {
+            NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| invalid(field, "expected a time as HH:MM"))
+        };
+
+        let mut windows = Vec::with_capacity(file.windows.len());
+        for window in file.windows {
+            let days = window
+                .days
+                .iter()
+                .map(|day| day.parse::<Weekday>())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|_| invalid("windows.days", "expected days such as \"mon\""))?;
+
+            let start = time("windows.start", &window.start)?;
+            let end = time("windows.end", &window.end)?;
+            if start == end {
+                return Err(invalid("windows", "expected a window which ends after it starts"));
+            }
+
+            windows.push(Window { days, start, end });
+        }
+
+        Ok(Policy { allowed_release: file.allowed_release, defer_until, windows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!("".parse::<Policy>().unwrap(), Policy::default());
+
+        let policy = "allowed_release = \"22.04\"\ndefer_until = \"2022-06-01\"\n\
+                      [[windows]]\ndays = [\"sat\", \"sun\"]\nstart = \"22:00\"\nend = \"04:00\"";
+        let policy = policy.parse::<Policy>().unwrap();
+        assert_eq!(policy.allowed_release.as_deref(), Some("22.04"));
+        assert_eq!(policy.defer_until, Some(NaiveDate::from_ymd(2022, 6, 1)));
+        assert_eq!(policy.windows[0].days, vec![Weekday::Sat, Weekday::Sun]);
+
+        assert!("defer_until = \"June\"".parse::<Policy>().is_err());
+        assert!("[[windows]]\nstart = \"25:00\"\nend = \"04:00\"".parse::<Policy>().is_err());
+        assert!("[[windows]]\ndays = [\"someday\"]\nstart = \"01:00\"\nend = \"04:00\""
+            .parse::<Policy>()
+            .is_err());
+        assert!("allow_release = \"22.04\"".parse::<Policy>().is_err());
+    }
+
+    #[test]
+    fn permits() {
+        let policy = "allowed_release = \"22.04\"\ndefer_until = \"2022-06-01\"\n\
+                      [[windows]]\ndays = [\"sat\"]\nstart = \"22:00\"\nend = \"04:00\"";
+        let policy = policy.parse::<Policy>().unwrap();
+
+        // 2022-06-04 is a Saturday.
+        assert!(policy.permits(Some("22.04"), at("2022-06-04 23:00")).is_ok());
+        assert!(policy.permits(Some("22.04"), at("2022-06-05 03:59")).is_ok());
+        assert!(policy.permits(None, at("2022-06-04 22:00")).is_ok());
+
+        assert!(matches!(
+            policy.permits(Some("22.10"), at("2022-06-04 23:00")),
+            Err(PolicyError::Release(_))
+        ));
+        assert!(matches!(
+            policy.permits(Some("22.04"), at("2022-05-28 23:00")),
+            Err(PolicyError::Deferred(_))
+        ));
+        assert!(matches!(
+            policy.permits(Some("22.04"), at("2022-06-05 04:00")),
+            Err(PolicyError::Window)
+        ));
+        assert!(matches!(
+            policy.permits(Some("22.04"), at("2022-06-04 03:00")),
+            Err(PolicyError::Window)
+        ));
+    }
+}
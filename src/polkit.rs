@@ -0,0 +1,86 @@
+use dbus::{
+    arg::{RefArg, Variant},
+    blocking::Connection,
+};
+use std::{collections::HashMap, time::Duration};
+use thiserror::Error;
+
+pub use pop_upgrade_core::interface::errors::NOT_AUTHORIZED_ERROR;
+
+/// Upgrading to a new release, and resuming or finalizing one which was interrupted.
+pub const RELEASE_UPGRADE: &str = "com.system76.PopUpgrade.release-upgrade";
+
+/// Replacing the contents of the recovery partition.
+pub const RECOVERY_UPGRADE: &str = "com.system76.PopUpgrade.recovery-upgrade";
+
+/// Changing the entry which systemd-boot boots by default, such as to refresh the OS.
+pub const DEFAULT_BOOT: &str = "com.system76.PopUpgrade.default-boot";
+
+/// Updating the apt sources, and installing or removing packages.
+pub const PACKAGE_UPGRADE: &str = "com.system76.PopUpgrade.package-upgrade";
+
+/// Restoring the system to a snapshot taken before an upgrade.
+pub const SNAPSHOT_RESTORE: &str = "com.system76.PopUpgrade.snapshot-restore";
+
+/// Allowing or disallowing upgrades to development releases.
+pub const DEVELOPMENT_RELEASES: &str = "com.system76.PopUpgrade.development-releases";
+
+/// Permits polkit to prompt the caller to authenticate, rather than denying them outright.
+const ALLOW_USER_INTERACTION: u32 = 1;
+
+/// Authentication waits on the user, who may take some time to enter their password.
+const TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Without authentication, polkit answers at once.
+const NONINTERACTIVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum PolkitError {
+    #[error("the caller's bus name is unknown")]
+    NoSender,
+
+    #[error("failed to check authorization with polkit")]
+    Check(#[source] dbus::Error),
+
+    #[error("not authorized to perform {}", _0)]
+    NotAuthorized(&'static str),
+}
+
+/// Asks polkit if the client with the given unique bus name may perform an action. If
+/// `interactive`, the user is prompted to authenticate if the action's policy requires it, which
+/// blocks until they have; otherwise, only callers that are already authorized are.
+pub fn authorize(
+    sender: Option<&str>,
+    action: &'static str,
+    interactive: bool,
+) -> Result<(), PolkitError> {
+    let sender = sender.ok_or(PolkitError::NoSender)?;
+    let connection = Connection::new_system().map_err(PolkitError::Check)?;
+
+    let mut subject = HashMap::<&str, Variant<Box<dyn RefArg>>>::new();
+    subject.insert("name", Variant(Box::new(sender.to_owned())));
+
+    let details = HashMap::<&str, &str>::new();
+    let (flags, timeout) =
+        if interactive { (ALLOW_USER_INTERACTION, TIMEOUT) } else { (0, NONINTERACTIVE_TIMEOUT) };
+
+    let ((authorized, _challenge, _details),): ((bool, bool, HashMap<String, String>),) =
+        connection
+            .with_proxy(
+                "org.freedesktop.PolicyKit1",
+                "/org/freedesktop/PolicyKit1/Authority",
+                timeout,
+            )
+            .method_call(
+                "org.freedesktop.PolicyKit1.Authority",
+                "CheckAuthorization",
+                (("system-bus-name", subject), action, details, flags, ""),
+            )
+            .map_err(PolkitError::Check)?;
+
+    if authorized {
+        Ok(())
+    } else {
+        Err(PolkitError::NotAuthorized(action))
+    }
+}
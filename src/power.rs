@@ -0,0 +1,127 @@
+use crate::config::Config;
+use std::{fs, path::Path};
+use thiserror::Error;
+
+pub use pop_upgrade_core::interface::errors::LOW_BATTERY_ERROR;
+
+/// Percentage of charge below which upgrades are refused without AC power, if not configured.
+pub const DEFAULT_MIN_BATTERY: u8 = 30;
+
+pub const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum PowerError {
+    #[error(
+        "the battery is at {}%, which may run out before the upgrade completes: connect AC \
+         power, or charge the battery to {}%",
+        charge,
+        threshold
+    )]
+    LowBattery { charge: u8, threshold: u8 },
+}
+
+/// The sources of power which the system is running from.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PowerSupply {
+    /// Whether AC power is connected.
+    pub ac:      bool,
+    /// The charge of the system's least charged battery, if it has any, as a percentage.
+    pub battery: Option<u8>,
+}
+
+impl PowerSupply {
+    /// Whether the system is running from a battery whose charge is below the threshold.
+    pub fn check(self, threshold: u8) -> Result<(), PowerError> {
+        match self.battery {
+            Some(charge) if !self.ac && charge < threshold => {
+                Err(PowerError::LowBattery { charge, threshold })
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The configured percentage of charge below which upgrades are refused without AC power.
+pub fn min_battery() -> u8 {
+    Config::load_or_default().power.min_battery.unwrap_or(DEFAULT_MIN_BATTERY)
+}
+
+/// Refuses to begin an upgrade, which could be interrupted by the battery running out, if the
+/// system is on battery power below the configured threshold.
+pub fn check() -> Result<PowerSupply, PowerError> {
+    let supply = detect(Path::new(POWER_SUPPLY));
+    supply.check(min_battery())?;
+    Ok(supply)
+}
+
+/// Detects the power supplies which are listed in sysfs. Batteries of peripherals, such as
+/// wireless mice, are ignored.
+pub fn detect(power_supply: &Path) -> PowerSupply {
+    let mut supply = PowerSupply::default();
+    let mut charges = Vec::new();
+
+    let entries = match fs::read_dir(power_supply) {
+        Ok(entries) => entries,
+        Err(_) => return supply,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let read = |file: &str| {
+            fs::read_to_string(path.join(file)).map(|value| value.trim().to_owned()).ok()
+        };
+
+        match read("type").as_deref() {
+            Some("Mains") | Some("USB") => supply.ac |= read("online").as_deref() == Some("1"),
+            Some("Battery") if read("scope").as_deref() != Some("Device") => {
+                charges.extend(read("capacity").and_then(|value| value.parse::<u8>().ok()));
+            }
+            _ => (),
+        }
+    }
+
+    supply.battery = charges.into_iter().min();
+    supply
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn supply(dir: &Path, name: &str, attributes: &[(&str, &str)]) {
+        let path = dir.join(name);
+        fs::create_dir_all(&path).unwrap();
+        for (attribute, value) in attributes {
+            fs::write(path.join(attribute), format!("{}\n", value)).unwrap();
+        }
+    }
+
+    #[test]
+    fn detection() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = dir.path();
+
+        assert_eq!(detect(dir), PowerSupply { ac: false, battery: None });
+
+        supply(dir, "AC", &[("type", "Mains"), ("online", "0")]);
+        supply(dir, "BAT0", &[("type", "Battery"), ("scope", "System"), ("capacity", "30")]);
+        supply(dir, "BAT1", &[("type", "Battery"), ("capacity", "50")]);
+        let mouse = [("type", "Battery"), ("scope", "Device"), ("capacity", "5")];
+        supply(dir, "hidpp_battery_0", &mouse);
+
+        assert_eq!(detect(dir), PowerSupply { ac: false, battery: Some(30) });
+
+        supply(dir, "AC", &[("online", "1")]);
+        assert_eq!(detect(dir), PowerSupply { ac: true, battery: Some(30) });
+    }
+
+    #[test]
+    fn thresholds() {
+        let battery = PowerSupply { ac: false, battery: Some(20) };
+        assert_eq!(battery.check(50), Err(PowerError::LowBattery { charge: 20, threshold: 50 }));
+        assert_eq!(battery.check(20), Ok(()));
+
+        assert_eq!(PowerSupply { ac: true, battery: Some(20) }.check(50), Ok(()));
+        assert_eq!(PowerSupply { ac: false, battery: None }.check(50), Ok(()));
+    }
+}
@@ -0,0 +1,622 @@
+use crate::{
+    dual_boot, loader, luks,
+    power::{self, PowerSupply},
+    secure_boot::{self, SecureBoot as SecureBootState},
+    smart::{self, DiskHealth},
+    storage,
+    release::{dkms, foreign, health, preview::SharedLists},
+};
+use std::{
+    collections::HashMap,
+    ffi::CString,
+    fs, io,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt},
+    path::{Path, PathBuf},
+    process::Command,
+    sync::Arc,
+};
+
+pub use pop_upgrade_core::preflight::{
+    run, Check, CheckResult, Report, Status, APT_CHECK, POWER_CHECK,
+};
+
+const GIB: u64 = 1024 * 1024 * 1024;
+const MIB: u64 = 1024 * 1024;
+
+/// Space which a release upgrade requires on each of these paths. Paths which share a
+/// filesystem have their requirements combined.
+const DISK_SPACE: &[(&str, u64)] = &[
+    ("/", 2 * GIB),
+    ("/var/cache/apt/archives", 3 * GIB),
+    ("/boot", 256 * MIB),
+    ("/boot/efi", 64 * MIB),
+];
+
+const REBOOT_REQUIRED: &str = "/var/run/reboot-required";
+const REBOOT_REQUIRED_PKGS: &str = "/var/run/reboot-required.pkgs";
+
+/// The checks which are performed before an upgrade to the `release` codename, if it is known.
+pub fn checks(release: Option<&str>) -> Vec<Box<dyn Check>> {
+    let mut checks: Vec<Box<dyn Check>> = Vec::new();
+
+    for (path, required) in disk_requirements() {
+        checks.push(Box::new(DiskSpace { path, required }));
+    }
+
+    checks.push(Box::new(AcPower));
+    checks.push(Box::new(Network));
+    checks.push(Box::new(AptHealth));
+    checks.push(Box::new(Encryption));
+    checks.push(Box::new(Storage));
+    checks.push(Box::new(Smart));
+    checks.push(Box::new(SecureBoot));
+    checks.push(Box::new(PendingReboot));
+    checks.push(Box::new(DualBoot));
+
+    if let Some(release) = release {
+        // The package lists of the release are fetched once, for both checks.
+        let lists = Arc::new(SharedLists::new(release));
+        checks.push(Box::new(ForeignPackages { lists: lists.clone() }));
+        checks.push(Box::new(Dkms { lists }));
+    }
+
+    checks
+}
+
+/// Requires that a filesystem has enough space available for the upgrade.
+pub struct DiskSpace {
+    pub path:     PathBuf,
+    pub required: u64,
+}
+
+impl Check for DiskSpace {
+    fn name(&self) -> String { fomat!("disk space (" (self.path.display()) ")") }
+
+    fn run(&self) -> (Status, String) {
+        match available(&self.path) {
+            Ok(available) => space_status(available, self.required),
+            Err(why) => (Status::Warn, fomat!("failed to get available space: " (why))),
+        }
+    }
+}
+
+/// Requires that the system is not running on a battery which may be exhausted mid-upgrade.
+pub struct AcPower;
+
+impl Check for AcPower {
+    fn name(&self) -> String { POWER_CHECK.into() }
+
+    fn run(&self) -> (Status, String) {
+        power_status(power::detect(Path::new(power::POWER_SUPPLY)), power::min_battery())
+    }
+}
+
+/// Requires that the servers which upgrades are fetched from are reachable.
+pub struct Network;
+
+impl Check for Network {
+    fn name(&self) -> String { "network".into() }
+
+    fn run(&self) -> (Status, String) {
+        match crate::network::check() {
+            Ok(()) => (Status::Pass, "upgrade servers are reachable".into()),
+            Err(why) => (Status::Fail, why.to_string()),
+        }
+    }
+}
+
+/// Requires that dpkg has no packages left in a broken state, and that apt's dependencies are
+/// satisfied.
+pub struct AptHealth;
+
+impl Check for AptHealth {
+    fn name(&self) -> String { APT_CHECK.into() }
+
+    fn run(&self) -> (Status, String) {
+        match health::dpkg_audit() {
+            Ok(Some(audit)) => return (Status::Fail, audit),
+            Ok(None) => (),
+            Err(why) => return (Status::Warn, fomat!("failed to audit dpkg: " (why))),
+        }
+
+        match Command::new("apt-get").args(&["-qq", "check"]).output() {
+            Ok(output) if output.status.success() => {
+                (Status::Pass, "packages are in a consistent state".into())
+            }
+            Ok(output) => (Status::Fail, String::from_utf8_lossy(&output.stderr).trim().into()),
+            Err(why) => (Status::Warn, fomat!("failed to run apt-get check: " (why))),
+        }
+    }
+}
+
+/// Requires that an encrypted root volume would remain unlockable after upgrading.
+pub struct Encryption;
+
+impl Check for Encryption {
+    fn name(&self) -> String { "encryption".into() }
+
+    fn run(&self) -> (Status, String) {
+        match luks::validate() {
+            Ok(Some(volume)) => (Status::Pass, fomat!("root volume is unlocked by " (volume.name))),
+            Ok(None) => (Status::Pass, "root volume is not encrypted".into()),
+            Err(why) if why.is_inconsistent() => (Status::Fail, why.to_string()),
+            Err(why) => (Status::Warn, crate::misc::format_error(&why)),
+        }
+    }
+}
+
+/// Requires that the initramfs of the new release would be able to set up the LVM volumes and
+/// RAID arrays which the root filesystem resides on.
+pub struct Storage;
+
+impl Check for Storage {
+    fn name(&self) -> String { "storage".into() }
+
+    fn run(&self) -> (Status, String) {
+        match storage::validate() {
+            Ok(storage) if storage.layouts.is_empty() => {
+                (Status::Pass, "root filesystem is on a plain partition".into())
+            }
+            Ok(storage) => {
+                let layouts =
+                    storage.layouts.iter().map(ToString::to_string).collect::<Vec<_>>().join(", ");
+                (Status::Pass, fomat!("root filesystem is on " (layouts)))
+            }
+            Err(why) if why.is_inconsistent() => (Status::Fail, why.to_string()),
+            Err(why) => (Status::Warn, crate::misc::format_error(&why)),
+        }
+    }
+}
+
+/// Warns when the disks which the upgrade is written to report failed sectors, and fails when
+/// they predict their own failure, so that the system is not upgraded onto dying media.
+pub struct Smart;
+
+impl Check for Smart {
+    fn name(&self) -> String { "disk health".into() }
+
+    fn run(&self) -> (Status, String) {
+        if !smart::available() {
+            return (Status::Pass, "smartmontools is not installed to assess disks".into());
+        }
+
+        let recovery = Path::new("/recovery");
+        let mut mounts = vec![Path::new("/")];
+        if recovery.exists() {
+            mounts.push(recovery);
+        }
+
+        match smart::assess(&mounts) {
+            Ok(disks) => smart_status(&disks),
+            Err(why) => (Status::Warn, crate::misc::format_error(&why)),
+        }
+    }
+}
+
+/// Warns when Secure Boot would prevent third party modules from loading once they are rebuilt
+/// for the kernel of the new release.
+pub struct SecureBoot;
+
+impl Check for SecureBoot {
+    fn name(&self) -> String { "secure boot".into() }
+
+    fn run(&self) -> (Status, String) {
+        match secure_boot::inspect() {
+            Ok(state) => secure_boot_status(&state),
+            Err(why) => (Status::Warn, fomat!("failed to inspect secure boot: " (why))),
+        }
+    }
+}
+
+/// Warns when updates which have already been installed are waiting on a reboot.
+pub struct PendingReboot;
+
+impl Check for PendingReboot {
+    fn name(&self) -> String { "pending reboot".into() }
+
+    fn run(&self) -> (Status, String) {
+        if !Path::new(REBOOT_REQUIRED).exists() {
+            return (Status::Pass, "no reboot is pending".into());
+        }
+
+        let packages = fs::read_to_string(REBOOT_REQUIRED_PKGS).unwrap_or_default();
+        let packages = packages.lines().collect::<Vec<_>>();
+
+        (
+            Status::Warn,
+            if packages.is_empty() {
+                "a reboot is required".into()
+            } else {
+                fomat!("a reboot is required by " (packages.join(", ")))
+            },
+        )
+    }
+}
+
+/// Requires that the packages installed for foreign architectures, such as i386, are available in
+/// the new release, as they would otherwise be removed or prevent the upgrade from resolving.
+pub struct ForeignPackages {
+    pub lists: Arc<SharedLists>,
+}
+
+impl Check for ForeignPackages {
+    fn name(&self) -> String { "foreign architectures".into() }
+
+    fn run(&self) -> (Status, String) {
+        match async_io::block_on(foreign::check(&self.lists)) {
+            Ok(packages) => foreign_status(&packages, self.lists.release()),
+            Err(why) => (Status::Warn, crate::misc::format_error(&why)),
+        }
+    }
+}
+
+/// Warns when DKMS modules, such as VirtualBox or out-of-tree Wi-Fi drivers, may fail to build
+/// for the kernel of the new release, which would leave the system without working drivers.
+pub struct Dkms {
+    pub lists: Arc<SharedLists>,
+}
+
+impl Check for Dkms {
+    fn name(&self) -> String { "DKMS modules".into() }
+
+    fn run(&self) -> (Status, String) {
+        match async_io::block_on(dkms::check(&self.lists)) {
+            Ok(modules) => dkms_status(&modules, self.lists.release()),
+            Err(why) => (Status::Warn, crate::misc::format_error(&why)),
+        }
+    }
+}
+
+/// Warns when another operating system is installed, and Windows is the default boot target, as
+/// the system would boot Windows after the upgrade has been installed.
+pub struct DualBoot;
+
+impl Check for DualBoot {
+    fn name(&self) -> String { "dual boot".into() }
+
+    fn run(&self) -> (Status, String) {
+        let default = loader::default_id();
+        dual_boot_status(&dual_boot::detect(), dual_boot::boots_windows(&default))
+    }
+}
+
+/// Combines the requirements of paths which are on the same filesystem.
+fn disk_requirements() -> Vec<(PathBuf, u64)> {
+    let mut requirements: Vec<(PathBuf, u64)> = Vec::new();
+    let mut devices = HashMap::new();
+
+    for &(path, required) in DISK_SPACE {
+        let device = match fs::metadata(path) {
+            Ok(metadata) => metadata.dev(),
+            Err(_) => continue,
+        };
+
+        match devices.get(&device) {
+            Some(&index) => requirements[index].1 += required,
+            None => {
+                devices.insert(device, requirements.len());
+                requirements.push((PathBuf::from(path), required));
+            }
+        }
+    }
+
+    requirements
+}
+
+/// The space which is available to unprivileged users on the filesystem of the path.
+pub(crate) fn available(path: &Path) -> io::Result<u64> {
+    let path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Fails below the required space, and warns below twice the required space.
+fn space_status(available: u64, required: u64) -> (Status, String) {
+    let message = fomat!((available / MIB) " MiB available, " (required / MIB) " MiB required");
+
+    let status = if available < required {
+        Status::Fail
+    } else if available < required * 2 {
+        Status::Warn
+    } else {
+        Status::Pass
+    };
+
+    (status, message)
+}
+
+/// Fails when the upgrade cannot be resolved and the new release lacks some of the packages, and
+/// warns when any of the packages would be removed or left without updates.
+fn foreign_status(packages: &foreign::ForeignPackages, release: &str) -> (Status, String) {
+    if packages.installed.is_empty() {
+        return (Status::Pass, "no foreign-architecture packages are installed".into());
+    }
+
+    if packages.unresolvable {
+        let message = fomat!("the upgrade to " (release) " cannot be resolved");
+
+        // The upgrade may be unable to resolve for reasons which are unrelated to these packages.
+        return if packages.unavailable.is_empty() {
+            (Status::Warn, message)
+        } else {
+            let unavailable = packages.unavailable.join(", ");
+            (Status::Fail, fomat!((message) ": remove " (unavailable) ", which it lacks"))
+        };
+    }
+
+    let obsolete = packages
+        .unavailable
+        .iter()
+        .filter(|package| !packages.removed.contains(package))
+        .map(String::as_str)
+        .collect::<Vec<_>>();
+
+    let mut issues = Vec::new();
+
+    if !packages.removed.is_empty() {
+        issues.push(fomat!("the upgrade will remove " (packages.removed.join(", "))));
+    }
+
+    if !obsolete.is_empty() {
+        issues.push(fomat!(
+            (release) " does not provide " (obsolete.join(", ")) ", which will no longer be updated"
+        ));
+    }
+
+    if issues.is_empty() {
+        let count = packages.installed.len();
+        (Status::Pass, fomat!((count) " foreign-architecture packages are available in " (release)))
+    } else {
+        (Status::Warn, issues.join("; "))
+    }
+}
+
+fn dual_boot_status(others: &[dual_boot::OtherOs], default_windows: bool) -> (Status, String) {
+    if others.is_empty() {
+        return (Status::Pass, "no other operating systems were found".into());
+    }
+
+    let names = others.iter().map(|os| os.name.as_str()).collect::<Vec<_>>().join(", ");
+
+    if default_windows {
+        (
+            Status::Warn,
+            fomat!(
+                "Windows is the default boot target, so the system will boot it once the upgrade \
+                 has been installed: select Pop!_OS from the boot menu to complete the upgrade \
+                 (found " (names) ")"
+            ),
+        )
+    } else {
+        (Status::Pass, fomat!("found " (names) ", which the upgrade will not change"))
+    }
+}
+
+/// Warns when modules were not installed by a package, or the new release lacks their packages.
+fn dkms_status(modules: &dkms::DkmsModules, release: &str) -> (Status, String) {
+    if modules.installed.is_empty() {
+        return (Status::Pass, "no DKMS modules are installed".into());
+    }
+
+    let ids = |modules: &[dkms::DkmsModule]| {
+        modules.iter().map(dkms::DkmsModule::id).collect::<Vec<_>>().join(", ")
+    };
+
+    let mut issues = Vec::new();
+
+    if !modules.unavailable.is_empty() {
+        issues.push(fomat!((release) " does not provide " (ids(&modules.unavailable))));
+    }
+
+    if !modules.unpackaged.is_empty() {
+        issues.push(fomat!((ids(&modules.unpackaged)) " were not installed by a package"));
+    }
+
+    if issues.is_empty() {
+        let count = modules.installed.len();
+        (Status::Pass, fomat!((count) " DKMS modules are provided by " (release)))
+    } else {
+        let issues = issues.join("; ");
+        (Status::Warn, fomat!((issues) ": they may fail to build for the new kernel"))
+    }
+}
+
+fn secure_boot_status(state: &SecureBootState) -> (Status, String) {
+    if !state.enabled {
+        return (Status::Pass, "secure boot is disabled".into());
+    }
+
+    if !state.modules_unloadable() {
+        return (Status::Pass, "third party modules are signed by an enrolled key".into());
+    }
+
+    let names = |modules: &[PathBuf]| {
+        modules
+            .iter()
+            .filter_map(|module| module.file_stem())
+            .map(|name| name.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let message = if state.unsigned.is_empty() {
+        fomat!(
+            "the key which signs these modules is not enrolled, so they will fail to load once \
+             rebuilt for the new kernel: " (names(&state.signed))
+        )
+    } else {
+        fomat!(
+            "these modules are not signed, and will fail to load with the new kernel while \
+             secure boot is enabled: " (names(&state.unsigned))
+        )
+    };
+
+    (Status::Warn, message)
+}
+
+fn smart_status(disks: &[DiskHealth]) -> (Status, String) {
+    if disks.is_empty() {
+        return (Status::Pass, "no disks report their SMART health".into());
+    }
+
+    let describe = |disks: Vec<&DiskHealth>| {
+        disks.iter().map(ToString::to_string).collect::<Vec<_>>().join("; ")
+    };
+
+    let failing = disks.iter().filter(|disk| disk.is_failing()).collect::<Vec<_>>();
+    if !failing.is_empty() {
+        let failing = describe(failing);
+        return (
+            Status::Fail,
+            fomat!((failing) ": back up your files, and replace the disk before upgrading"),
+        );
+    }
+
+    let degraded = disks.iter().filter(|disk| disk.is_degraded()).collect::<Vec<_>>();
+    if !degraded.is_empty() {
+        return (
+            Status::Warn,
+            fomat!((describe(degraded)) ": the disk may be failing, so back up your files first"),
+        );
+    }
+
+    (Status::Pass, fomat!((disks.len()) " disks are healthy"))
+}
+
+fn power_status(supply: PowerSupply, threshold: u8) -> (Status, String) {
+    match supply.battery {
+        None => (Status::Pass, "no battery is present".into()),
+        Some(_) if supply.ac => (Status::Pass, "connected to AC power".into()),
+        Some(charge) if charge < threshold => (
+            Status::Fail,
+            fomat!("running on battery at " (charge) "%: connect to AC power to upgrade"),
+        ),
+        Some(charge) => (Status::Warn, fomat!("running on battery at " (charge) "%")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disk_space() {
+        assert_eq!(space_status(GIB, 2 * GIB).0, Status::Fail);
+        assert_eq!(space_status(3 * GIB, 2 * GIB).0, Status::Warn);
+        assert_eq!(space_status(4 * GIB, 2 * GIB).0, Status::Pass);
+    }
+
+    #[test]
+    fn disk_health() {
+        let disk = |passed, reallocated| DiskHealth {
+            disk: "/dev/sda".into(),
+            passed: Some(passed),
+            reallocated,
+            ..DiskHealth::default()
+        };
+
+        assert_eq!(smart_status(&[]).0, Status::Pass);
+        assert_eq!(smart_status(&[disk(true, 0)]).0, Status::Pass);
+        assert_eq!(smart_status(&[disk(true, 0), disk(true, 8)]).0, Status::Warn);
+        assert_eq!(smart_status(&[disk(true, 8), disk(false, 0)]).0, Status::Fail);
+    }
+
+    #[test]
+    fn power() {
+        let status = |ac, battery| power_status(PowerSupply { ac, battery }, 30).0;
+
+        assert_eq!(status(false, None), Status::Pass);
+        assert_eq!(status(true, Some(10)), Status::Pass);
+        assert_eq!(status(false, Some(80)), Status::Warn);
+        assert_eq!(status(false, Some(10)), Status::Fail);
+    }
+
+    #[test]
+    fn foreign_packages() {
+        let packages = |unavailable: &[&str], removed: &[&str], unresolvable| {
+            foreign::ForeignPackages {
+                installed:    vec!["libc6:i386".into(), "libfoo1:i386".into()],
+                unavailable:  unavailable.iter().map(|&p| p.into()).collect(),
+                removed:      removed.iter().map(|&p| p.into()).collect(),
+                unresolvable,
+            }
+        };
+
+        let status = |packages| foreign_status(&packages, "hirsute").0;
+
+        assert_eq!(status(foreign::ForeignPackages::default()), Status::Pass);
+        assert_eq!(status(packages(&[], &[], false)), Status::Pass);
+        assert_eq!(status(packages(&["libfoo1:i386"], &[], false)), Status::Warn);
+        assert_eq!(status(packages(&[], &["libfoo1:i386"], false)), Status::Warn);
+        assert_eq!(status(packages(&[], &[], true)), Status::Warn);
+        assert_eq!(status(packages(&["libfoo1:i386"], &[], true)), Status::Fail);
+    }
+
+    #[test]
+    fn dual_boot() {
+        let windows = dual_boot::OtherOs {
+            name:     "Windows Boot Manager".into(),
+            location: "/boot/efi/EFI/Microsoft/Boot/bootmgfw.efi".into(),
+        };
+
+        assert_eq!(dual_boot_status(&[], false).0, Status::Pass);
+        assert_eq!(dual_boot_status(&[windows.clone()], false).0, Status::Pass);
+        assert_eq!(dual_boot_status(&[windows], true).0, Status::Warn);
+    }
+
+    #[test]
+    fn secure_boot() {
+        let module = || vec![PathBuf::from("/lib/modules/5.11.0-7614-generic/updates/dkms/wl.ko")];
+
+        let state = SecureBootState { enabled: true, ..SecureBootState::default() };
+        assert_eq!(secure_boot_status(&state).0, Status::Pass);
+
+        let state = SecureBootState { unsigned: module(), ..state };
+        assert_eq!(
+            secure_boot_status(&state),
+            (
+                Status::Warn,
+                "these modules are not signed, and will fail to load with the new kernel while \
+                 secure boot is enabled: wl"
+                    .into()
+            )
+        );
+    }
+
+    #[test]
+    fn dkms_modules() {
+        let module = |name: &str, package: Option<&str>| dkms::DkmsModule {
+            name:    name.into(),
+            version: "1.0".into(),
+            package: package.map(String::from),
+        };
+
+        let virtualbox = module("virtualbox", Some("virtualbox-dkms"));
+        let wifi = module("rtl8821ce", None);
+
+        let status = |modules| dkms_status(&modules, "hirsute");
+
+        assert_eq!(status(dkms::DkmsModules::default()).0, Status::Pass);
+
+        let modules = dkms::DkmsModules {
+            installed:   vec![virtualbox.clone(), wifi.clone()],
+            unpackaged:  vec![wifi],
+            unavailable: vec![virtualbox],
+        };
+
+        assert_eq!(
+            status(modules),
+            (
+                Status::Warn,
+                "hirsute does not provide virtualbox/1.0; rtl8821ce/1.0 were not installed by a \
+                 package: they may fail to build for the new kernel"
+                    .into()
+            )
+        );
+    }
+}
@@ -29,6 +29,18 @@ pub enum RecoveryError {
     #[error("fetching from {} failed: {}", url, source)]
     Fetch { url: String, source: anyhow::Error },
 
+    #[error("a hook which runs before the upgrade failed")]
+    Hook(#[source] crate::hooks::HookError),
+
+    #[error("failed to read the ISO's checksum list at {:?}", _0)]
+    IsoChecksumList(PathBuf, #[source] io::Error),
+
+    #[error("the ISO does not contain a checksum list for its contents")]
+    IsoChecksumListNotFound,
+
+    #[error("ISO file at {:?} is corrupted: {}", path, source)]
+    IsoContents { path: PathBuf, source: ValidateError },
+
     #[error("ISO does not exist at path")]
     IsoNotFound,
 
@@ -38,6 +50,12 @@ pub enum RecoveryError {
     #[error("no build was found to fetch")]
     NoBuildAvailable,
 
+    #[error("failed to fetch the ISO signature from {}: {}", url, source)]
+    SignatureFetch { url: String, source: anyhow::Error },
+
+    #[error("the ISO signature could not be verified with the Pop!_OS keyring")]
+    SignatureInvalid(#[source] io::Error),
+
     #[error("failed to create temporary directory for ISO")]
     TempDir(#[source] io::Error),
 
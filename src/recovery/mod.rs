@@ -2,51 +2,48 @@ mod errors;
 mod version;
 
 use anyhow::Context;
-use async_process::Command;
-use futures::prelude::*;
+use futures::{future::Either, prelude::*};
 use std::{
-    io::SeekFrom,
+    ffi::OsStr,
+    os::unix::fs::symlink,
     path::{Path, PathBuf},
-    time::Instant,
+    time::{Duration, Instant},
 };
 use sys_mount::{Mount, MountFlags, Unmount, UnmountFlags};
-use tempfile::{tempdir, TempDir};
+use tempfile::TempDir;
 
 use crate::{
-    checksum::validate_checksum, external::findmnt_uuid, release_api::Release,
-    release_architecture::detect_arch, system_environment::SystemEnvironment,
+    checksum::{self, Algorithm, Hasher},
+    external::findmnt,
+    release_api::Release,
+    release_architecture::detect_arch,
+    system_environment::SystemEnvironment,
 };
 
 pub use self::{
     errors::{RecResult, RecoveryError},
     version::{recovery_file, version, RecoveryVersion, RecoveryVersionError, RECOVERY_VERSION},
 };
+pub use pop_upgrade_core::recovery::{RecoveryEvent, ReleaseFlags};
 
-bitflags! {
-    pub struct ReleaseFlags: u8 {
-        const NEXT = 1;
-    }
-}
+/// Number of times an ISO will be downloaded again after failing its checksum.
+const CHECKSUM_RETRIES: u8 = 1;
 
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
-pub enum RecoveryEvent {
-    Fetching = 1,
-    Verifying = 2,
-    Syncing = 3,
-    Complete = 4,
-}
+/// How often a checksum validation which is in progress checks if it has been cancelled.
+const CANCEL_INTERVAL: Duration = Duration::from_millis(250);
 
-impl From<RecoveryEvent> for &'static str {
-    fn from(event: RecoveryEvent) -> Self {
-        match event {
-            RecoveryEvent::Fetching => "fetching recovery files",
-            RecoveryEvent::Syncing => "syncing recovery files with recovery partition",
-            RecoveryEvent::Verifying => "verifying checksums of fetched files",
-            RecoveryEvent::Complete => "recovery partition upgrade completed",
-        }
-    }
-}
+/// Arguments passed to rsync when syncing ISO contents to the recovery partition.
+///
+/// `-L` is required for the symlinked sources to be copied as the directories they point to.
+const RSYNC_ARGS: &[&str] = &["-KLavc", "--inplace", "--delete"];
+
+/// Directories in the ISO which are synced to the same name on the recovery partition.
+///
+/// The casper directory is mapped to `casper-{UUID}` separately.
+const SYNC_MAPPINGS: &[(&str, &str)] = &[(".disk", ".disk"), ("dists", "dists"), ("pool", "pool")];
+
+/// Prefixes the temporary directories which remote ISOs are downloaded to.
+pub const ISO_DIR_PREFIX: &str = "pop-upgrade-iso-";
 
 #[derive(Debug, Clone)]
 pub enum UpgradeMethod {
@@ -54,14 +51,18 @@ pub enum UpgradeMethod {
     FromRelease { version: Option<String>, arch: Option<String>, flags: ReleaseFlags },
 }
 
-pub async fn recovery<'a, F, E>(
+pub async fn recovery<'a, F, S, V, E>(
     cancel: &'a (dyn Fn() -> bool + Send + Sync),
     action: &'a UpgradeMethod,
     progress: F,
+    sync_progress: S,
+    verify_progress: V,
     event: E,
 ) -> RecResult<()>
 where
     F: Fn(u64, u64) + 'static + Send + Sync,
+    S: Fn(u8) + 'static,
+    V: Fn(u8) + 'static,
     E: Fn(RecoveryEvent) + 'static,
 {
     if SystemEnvironment::detect() != SystemEnvironment::Efi {
@@ -90,9 +91,19 @@ where
             .unwrap_or(false)
     }
 
-    if let Some((version, build)) =
-        fetch_iso(cancel, verify, &action, &progress, &event, "/recovery").await?
-    {
+    let fetched = fetch_iso(
+        cancel,
+        verify,
+        &action,
+        &progress,
+        &sync_progress,
+        &verify_progress,
+        &event,
+        "/recovery",
+    )
+    .await?;
+
+    if let Some((version, build)) = fetched {
         let data = fomat!((version) " " (build));
         async_fs::write(RECOVERY_VERSION, data.as_bytes())
             .await
@@ -115,11 +126,14 @@ pub fn recovery_exists() -> Result<bool, RecoveryError> {
     Ok(false)
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
     cancel: &'a (dyn Fn() -> bool + Send + Sync),
     verify: fn(&str, u16) -> bool,
     action: &'a UpgradeMethod,
     progress: &'a F,
+    sync_progress: &'a dyn Fn(u8),
+    verify_progress: &'a dyn Fn(u8),
     event: &'a dyn Fn(RecoveryEvent),
     recovery_path: P,
 ) -> RecResult<Option<(Box<str>, u16)>> {
@@ -137,7 +151,7 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
     }
 
     let recovery_uuid =
-        findmnt_uuid(recovery_path).await.context("cannot find UUID of recover partition")?;
+        findmnt(recovery_path, "UUID").await.context("cannot find UUID of recover partition")?;
 
     let casper = ["casper-", &recovery_uuid].concat();
     let recovery = ["Recovery-", &recovery_uuid].concat();
@@ -150,10 +164,13 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
     let (build, version, iso) = match action {
         UpgradeMethod::FromRelease { ref version, ref arch, flags } => {
             let version_ = version.as_ref().map(String::as_str);
-            let arch = arch.as_ref().map(String::as_str);
+            let arch = match arch {
+                Some(ref arch) => arch.as_str(),
+                None => detect_arch()?,
+            };
 
             let (version, build) =
-                crate::release::check::current(version_).context("no build available")?;
+                crate::release::check::current(version_, arch).context("no build available")?;
 
             cancellation_check(&cancel)?;
 
@@ -164,6 +181,8 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
 
             cancellation_check(&cancel)?;
 
+            crate::smart::warn_unhealthy(&[Path::new("/recovery")]);
+
             let iso =
                 from_release(cancel, &mut temp_iso_dir, progress, event, &version, arch, *flags)
                     .await?;
@@ -176,40 +195,44 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
 
     cancellation_check(&cancel)?;
 
-    (*event)(RecoveryEvent::Syncing);
     let tempdir = tempfile::tempdir().map_err(RecoveryError::TempDir)?;
     let _iso_mount = Mount::new(iso, tempdir.path(), "iso9660", MountFlags::RDONLY, None)
         .context("failed to mount recovery ISO")?
         .into_unmount_drop(UnmountFlags::DETACH);
 
-    let disk = tempdir.path().join(".disk");
-    let dists = tempdir.path().join("dists");
-    let pool = tempdir.path().join("pool");
-    let casper_p = tempdir.path().join("casper/");
+    (*event)(RecoveryEvent::Verifying);
+    validate_iso_contents(cancel, tempdir.path(), verify_progress).await?;
+
+    (*event)(RecoveryEvent::Syncing);
 
     let efi_initrd = efi_recovery.join("initrd.gz");
     let efi_vmlinuz = efi_recovery.join("vmlinuz.efi");
     let casper_initrd = recovery_path.join([&casper, "/initrd.gz"].concat());
     let casper_vmlinuz = recovery_path.join([&casper, "/vmlinuz.efi"].concat());
-    let recovery_str = recovery_path.to_str().unwrap();
-
-    let mut cmd = cascade! {
-        Command::new("rsync");
-        ..args(&[&disk, &dists, &pool]);
-        ..arg(recovery_str);
-        ..args(&["-KLavc", "--inplace", "--delete"]);
-    };
-
-    cmd.status().await.context("rsync failed to copy")?;
 
-    let mut cmd = cascade! {
-        Command::new("rsync");
-        ..args(&[&casper_p]);
-        ..arg(&[recovery_str, "/", &casper].concat());
-        ..args(&["-KLavc", "--inplace", "--delete"]);
-    };
+    // Map each ISO directory to its destination name on the recovery partition through a
+    // directory of symlinks, so that a single rsync pass can copy everything at once.
+    let links = tempfile::tempdir().map_err(RecoveryError::TempDir)?;
+    let mut sources = Vec::with_capacity(SYNC_MAPPINGS.len() + 1);
+
+    let mappings = SYNC_MAPPINGS.iter().cloned().chain(std::iter::once(("casper", &*casper)));
+    for (src, dst) in mappings {
+        let link = links.path().join(dst);
+        symlink(tempdir.path().join(src), &link)
+            .with_context(|| fomat!("failed to map " (src) " to " (dst) " for syncing"))?;
+        sources.push(link);
+    }
 
-    cmd.status().await.context("rsync failed to copy casper")?;
+    crate::external::rsync(
+        sources
+            .iter()
+            .map(|path| path.as_os_str())
+            .chain(std::iter::once(recovery_path.as_os_str()))
+            .chain(RSYNC_ARGS.iter().map(OsStr::new)),
+        sync_progress,
+    )
+    .await
+    .context("rsync failed to sync the recovery partition")?;
 
     let cp1 = crate::misc::cp(&casper_initrd, &efi_initrd);
     let cp2 = crate::misc::cp(&casper_vmlinuz, &efi_vmlinuz);
@@ -221,6 +244,68 @@ async fn fetch_iso<'a, P: AsRef<Path>, F: Fn(u64, u64) + 'static + Send + Sync>(
     Ok(Some((version, build)))
 }
 
+/// Validates the files that will be synced to the recovery partition against the checksum list
+/// embedded in the mounted ISO, reporting the percentage of files which have been validated.
+async fn validate_iso_contents(
+    cancel: &(dyn Fn() -> bool + Send + Sync),
+    iso_root: &Path,
+    progress: &dyn Fn(u8),
+) -> RecResult<()> {
+    let (list_path, sha256) = if iso_root.join("SHA256SUMS").exists() {
+        (iso_root.join("SHA256SUMS"), true)
+    } else if iso_root.join("md5sum.txt").exists() {
+        (iso_root.join("md5sum.txt"), false)
+    } else {
+        return Err(RecoveryError::IsoChecksumListNotFound);
+    };
+
+    info!("validating contents of the ISO against {}", list_path.display());
+
+    let list = async_fs::read_to_string(&list_path)
+        .await
+        .map_err(|why| RecoveryError::IsoChecksumList(list_path.clone(), why))?;
+
+    let synced = |path: &str| {
+        SYNC_MAPPINGS
+            .iter()
+            .map(|&(src, _)| src)
+            .chain(std::iter::once("casper"))
+            .any(|dir| path.starts_with(dir) && path[dir.len()..].starts_with('/'))
+    };
+
+    let files = checksum::parse_checksum_list(&list)
+        .filter(|&(_, path)| synced(path))
+        .collect::<Vec<_>>();
+
+    for (validated, &(checksum, path)) in files.iter().enumerate() {
+        cancellation_check(cancel)?;
+
+        let path = iso_root.join(path);
+
+        let validate = async {
+            match crate::misc::open(&path).await {
+                Ok(ref mut file) if sha256 => checksum::validate_sha256(file, checksum).await,
+                Ok(ref mut file) => checksum::validate_md5(file, checksum).await,
+                Err(why) => Err(why.into()),
+            }
+        };
+
+        // The squashfs is several gigabytes, so cancellation must not wait for it to be hashed.
+        let cancelled = cancelled(cancel);
+        futures::pin_mut!(validate, cancelled);
+
+        let result = match future::select(validate, cancelled).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => return Err(RecoveryError::Cancelled),
+        };
+
+        result.map_err(|source| RecoveryError::IsoContents { path, source })?;
+        (*progress)(((validated + 1) * 100 / files.len()) as u8);
+    }
+
+    Ok(())
+}
+
 /// Fetches the release ISO remotely from api.pop-os.org.
 async fn from_release<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     cancel: &'a (dyn Fn() -> bool + Send + Sync),
@@ -228,25 +313,68 @@ async fn from_release<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     progress: &'a F,
     event: &'a dyn Fn(RecoveryEvent),
     version: &'a str,
-    arch: Option<&'a str>,
+    arch: &'a str,
     _flags: ReleaseFlags,
 ) -> RecResult<PathBuf> {
-    let arch = match arch {
-        Some(ref arch) => arch,
-        None => detect_arch()?,
+    let release = Release::get_release(version, arch).map_err(RecoveryError::ApiError)?;
+    let checksum = release.checksum();
+
+    let mut retries = 0;
+    let iso_path = loop {
+        match from_remote(cancel, temp, progress, event, &release.url, &checksum).await {
+            // The temporary directory holding the corrupted ISO is dropped on failure, so the
+            // bad file is already gone by the time we attempt to fetch it again.
+            Err(RecoveryError::Checksum { path, source }) if retries < CHECKSUM_RETRIES => {
+                warn!(
+                    "discarding {} due to checksum mismatch ({}): downloading it again",
+                    path.display(),
+                    source
+                );
+
+                retries += 1;
+                cancellation_check(cancel)?;
+                (*event)(RecoveryEvent::Fetching);
+            }
+            Err(RecoveryError::Cancelled) => return Err(RecoveryError::Cancelled),
+            result => break result.map_err(|why| RecoveryError::Download(Box::new(why)))?,
+        }
     };
 
-    let release = Release::get_release(version, arch).map_err(RecoveryError::ApiError)?;
-    let iso_path = from_remote(cancel, temp, progress, event, &release.url, &release.sha_sum)
-        .await
-        .map_err(|why| RecoveryError::Download(Box::new(why)))?;
+    match release.signature_url {
+        Some(ref url) => verify_signature(&iso_path, url).await?,
+        None => warn!("release API did not provide a signature for the ISO"),
+    }
 
     Ok(iso_path)
 }
 
+/// Fetches the detached signature of an ISO, and verifies it against the Pop!_OS keyring.
+async fn verify_signature(iso: &Path, url: &str) -> RecResult<()> {
+    info!("verifying signature of ISO from {}", url);
+
+    let signature = iso.with_extension("iso.sig");
+
+    (async {
+        let mut download = crate::sandbox::download(url).await?;
+
+        let mut data = Vec::new();
+        download.body.read_to_end(&mut data).await?;
+        download.finish().await?;
+        async_fs::write(&signature, &data).await?;
+
+        Ok::<(), anyhow::Error>(())
+    })
+    .await
+    .map_err(|source| RecoveryError::SignatureFetch { url: url.to_owned(), source })?;
+
+    crate::external::gpgv(Path::new(crate::SIGNING_KEYRING), &signature, iso)
+        .await
+        .map_err(RecoveryError::SignatureInvalid)
+}
+
 /// Downloads the ISO from a remote location, to a temporary local directory.
 ///
-/// Once downloaded, the ISO will be verfied against the given checksum.
+/// The ISO is hashed as it is written, and verified against the given checksum once downloaded.
 async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     cancel: &'a (dyn Fn() -> bool + Send + Sync),
     temp_dir: &'a mut Option<TempDir>,
@@ -256,7 +384,10 @@ async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     checksum: &'a str,
 ) -> RecResult<PathBuf> {
     info!("downloading ISO from remote at {}", url);
-    let temp = tempdir().map_err(RecoveryError::TempDir)?;
+    let temp = tempfile::Builder::new()
+        .prefix(ISO_DIR_PREFIX)
+        .tempdir()
+        .map_err(RecoveryError::TempDir)?;
     let path = temp.path().join("new.iso");
 
     let mut file = async_fs::OpenOptions::new()
@@ -268,45 +399,31 @@ async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
         .await
         .context("failed to create ISO file for writing")?;
 
+    let (algorithm, checksum) = Algorithm::detect(checksum)
+        .map_err(|source| RecoveryError::Checksum { path: path.clone(), source })?;
+
     let mut total = 0;
+    let mut hasher = Hasher::new(algorithm);
 
     (async {
-        use isahc::config::Configurable;
-
-        let req = isahc::HttpClient::builder()
-            .low_speed_timeout(1, std::time::Duration::from_secs(15))
-            .build()
-            .expect("failed to build HTTP client")
-            .get_async(url)
-            .await?;
-
-        let status = req.status();
-        if !status.is_success() {
-            return Err(anyhow!("request failed due to status code {}", status));
-        }
+        // The ISO is fetched by an unprivileged helper, which streams it back over a pipe.
+        let mut download = crate::sandbox::download(url).await?;
 
-        total = req
-            .headers()
-            .get("content-length")
-            .and_then(|v| v.to_str().ok())
-            .and_then(|v| v.parse::<u64>().ok())
-            .unwrap_or(0)
-            / 1024;
+        total = download.length.unwrap_or(0) / 1024;
 
         let mut buf = vec![0u8; 8 * 1024];
         let mut p = 0;
 
-        let mut body = req.into_body();
-
         let mut last = Instant::now();
 
         loop {
-            let read = body.read(&mut buf).await?;
+            let read = download.body.read(&mut buf).await?;
             if read == 0 {
                 break;
             }
 
             file.write_all(&buf[..read]).await?;
+            hasher.update(&buf[..read]);
 
             p += read;
 
@@ -318,25 +435,25 @@ async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
             cancellation_check(cancel)?;
         }
 
-        Ok(())
+        download.finish().await?;
+
+        Ok::<(), anyhow::Error>(())
     })
     .await
-    .map_err(|source| RecoveryError::Fetch { url: url.to_owned(), source })?;
-
-    cancellation_check(cancel)?;
+    .map_err(|source| match cancellation_check(cancel) {
+        // The partially-downloaded ISO is removed with its temporary directory.
+        Err(cancelled) => cancelled,
+        Ok(()) => RecoveryError::Fetch { url: url.to_owned(), source },
+    })?;
 
     (*progress)(total, total);
     (*event)(RecoveryEvent::Verifying);
 
-    async {
-        file.flush().await?;
-        file.seek(SeekFrom::Start(0)).await
-    }
-    .await
-    .context("failed to write recovery ISO")?;
+    file.flush().await.context("failed to write recovery ISO")?;
 
-    validate_checksum(&mut file, checksum)
-        .await
+    // The ISO was hashed as it was downloaded, so there is no need to read it back.
+    hasher
+        .validate(checksum)
         .map_err(|source| RecoveryError::Checksum { path: path.clone(), source })?;
 
     cancellation_check(cancel)?;
@@ -345,6 +462,13 @@ async fn from_remote<'a, F: Fn(u64, u64) + 'static + Send + Sync>(
     Ok(path)
 }
 
+/// Resolves once the process has been cancelled.
+async fn cancelled(cancel: &(dyn Fn() -> bool + Send + Sync)) {
+    while !cancel() {
+        async_io::Timer::after(CANCEL_INTERVAL).await;
+    }
+}
+
 fn cancellation_check(cancel: &(dyn Fn() -> bool + Send + Sync)) -> RecResult<()> {
     if cancel() {
         Err(RecoveryError::Cancelled)
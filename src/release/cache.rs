@@ -0,0 +1,87 @@
+use super::check::{Build, BuildStatus, ReleaseStatus};
+use crate::config::Channel;
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Location where the result of the last successful release check is stored.
+const STATE_FILE: &str = "/var/cache/pop-upgrade/state.json";
+
+#[derive(Deserialize, Serialize)]
+struct State {
+    /// Seconds since the Unix epoch at which the check was performed.
+    checked:     u64,
+    development: bool,
+    #[serde(default)]
+    channel:     Channel,
+    current:     String,
+    next:        String,
+    arch:        String,
+    is_lts:      bool,
+    /// The build that was available, or `None` if the upgrade was not offered.
+    build:       Option<Build>,
+}
+
+/// The result of a previous release check.
+pub struct Cached {
+    pub status: ReleaseStatus,
+    pub age:    Duration,
+}
+
+/// Loads the last release check, if it was performed with the same parameters.
+pub fn load(current: &str, development: bool, channel: Channel, arch: &str) -> Option<Cached> {
+    let state = serde_json::from_slice::<State>(&fs::read(STATE_FILE).ok()?).ok()?;
+
+    if state.current != current
+        || state.development != development
+        || state.channel != channel
+        || state.arch != arch
+    {
+        return None;
+    }
+
+    Some(Cached {
+        age:    Duration::from_secs(now().saturating_sub(state.checked)),
+        status: ReleaseStatus {
+            current: state.current.into(),
+            next:    state.next.into(),
+            arch:    state.arch.into(),
+            build:   state.build.map_or(BuildStatus::Blacklisted, BuildStatus::Build),
+            is_lts:  state.is_lts,
+        },
+    })
+}
+
+/// Stores the result of a release check, if it was successful.
+pub fn store(status: &ReleaseStatus, development: bool, channel: Channel) -> io::Result<()> {
+    let build = match status.build {
+        BuildStatus::Build(ref build) => Some(build.clone()),
+        BuildStatus::Blacklisted => None,
+        _ => return Ok(()),
+    };
+
+    let state = State {
+        checked: now(),
+        development,
+        channel,
+        current: status.current.to_string(),
+        next:    status.next.to_string(),
+        arch:    status.arch.to_string(),
+        is_lts:  status.is_lts,
+        build,
+    };
+
+    let path = Path::new(STATE_FILE);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, serde_json::to_vec(&state)?)?;
+    fs::rename(&temporary, path)
+}
+
+fn now() -> u64 { SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs()) }
@@ -1,152 +1,248 @@
-use crate::release_api::{ApiError, Release};
+use super::{
+    cache,
+    paths::{Availability, UpgradePaths},
+    rollout,
+};
+use crate::{
+    config::{Channel, Config},
+    misc::format_error,
+    release_api::{ApiError, Release},
+};
 use anyhow::Context;
+use std::time::Duration;
+use thiserror::Error;
 use ubuntu_version::{Version, VersionError};
 
-#[derive(Debug)]
-pub enum BuildStatus {
-    Blacklisted,
-    Build(u16),
-    ConnectionIssue(isahc::Error),
-    InternalIssue(ApiError),
-    ServerStatus(isahc::http::StatusCode),
-}
+pub use pop_upgrade_core::release::{Build, BuildStatus, ReleaseStatus};
 
-impl BuildStatus {
-    pub fn is_ok(&self) -> bool {
-        if let BuildStatus::Build(_) = *self {
-            true
-        } else {
-            false
-        }
-    }
+/// Seconds for which the result of a release check is reused, unless configured otherwise.
+const DEFAULT_CHECK_TTL: u64 = 60 * 60;
 
-    pub fn status_code(&self) -> i16 {
-        match *self {
-            BuildStatus::ConnectionIssue(_) => -3,
-            BuildStatus::ServerStatus(_) => -2,
-            BuildStatus::InternalIssue(_) => -1,
-            BuildStatus::Build(build) => build as i16,
-            BuildStatus::Blacklisted => -4,
-        }
-    }
+#[derive(Debug, Error)]
+pub enum CheckError {
+    #[error("failed to detect the current release")]
+    Version(#[from] VersionError),
+
+    #[error(transparent)]
+    Unsupported(#[from] UnsupportedRelease),
 }
 
-impl From<Result<u16, ApiError>> for BuildStatus {
-    fn from(result: Result<u16, ApiError>) -> Self {
-        match result {
-            Err(ApiError::Get(why)) => BuildStatus::ConnectionIssue(why),
-            Err(ApiError::Status(why)) => BuildStatus::ServerStatus(why),
-            Err(otherwise) => BuildStatus::InternalIssue(otherwise),
-            Ok(build) => BuildStatus::Build(build),
-        }
-    }
+/// The installed release is not in the table of supported releases.
+#[derive(Debug, Error)]
+#[error("this version of pop-upgrade does not support release {}.{:02}", major, minor)]
+pub struct UnsupportedRelease {
+    pub major: u8,
+    pub minor: u8,
 }
 
-impl PartialEq for BuildStatus {
-    fn eq(&self, other: &BuildStatus) -> bool {
-        match (self, other) {
-            (BuildStatus::Blacklisted, BuildStatus::Blacklisted)
-            | (BuildStatus::ConnectionIssue(_), BuildStatus::ConnectionIssue(_))
-            | (BuildStatus::InternalIssue(_), BuildStatus::InternalIssue(_))
-            | (BuildStatus::ServerStatus(_), BuildStatus::ServerStatus(_)) => true,
-            (BuildStatus::Build(a), BuildStatus::Build(b)) => a == b,
-            _ => false,
-        }
+/// The status of the build which the release API responded with.
+fn build_status(result: Result<Release, ApiError>) -> BuildStatus {
+    match result {
+        Err(ApiError::Get(why)) => BuildStatus::ConnectionIssue(format_error(&why)),
+        Err(ApiError::Status(code)) => BuildStatus::ServerStatus(code.as_u16()),
+        Err(ApiError::Throttled(wait)) => BuildStatus::Throttled(wait),
+        Err(otherwise) => BuildStatus::InternalIssue(format_error(&otherwise)),
+        Ok(release) => BuildStatus::Build(Build {
+            build:         release.build,
+            size:          release.size,
+            release_notes: release.release_notes,
+        }),
     }
 }
 
-#[derive(Debug, PartialEq)]
-pub struct ReleaseStatus {
-    pub current: &'static str,
-    pub next:    &'static str,
-    pub build:   BuildStatus,
-    pub is_lts:  bool,
-}
+/// Checks for the next release, reusing the result of a recent check if there is one.
+///
+/// If the release API is unavailable, the result of the last check is used regardless of age.
+/// Releases which are being phased in are only offered to the machines they have been rolled
+/// out to, unless `force` is set.
+pub fn next(development: bool, force: bool, arch: &str) -> Result<ReleaseStatus, CheckError> {
+    let current = Version::detect()?;
+    let version = format!("{}.{:02}", current.major, current.minor);
+    let config = Config::load_or_default().release;
+    let ttl = config.check_ttl.unwrap_or(DEFAULT_CHECK_TTL);
+    let channel = config.channel;
+
+    let cached = match cache::load(&version, development, channel, arch) {
+        Some(cached) if cached.age < Duration::from_secs(ttl) => {
+            info!("using the release check from {:?} ago", cached.age);
+            return Ok(cached.status);
+        }
+        cached => cached,
+    };
+
+    let status = next_(&UpgradePaths::load(), current, development, channel, arch, |build| {
+        match Release::get_release(build, arch) {
+            Ok(Release { rollout: Some(percentage), .. })
+                if !force && !rollout::includes(build, percentage) =>
+            {
+                info!("{} is rolled out to {}% of machines, excluding this one", build, percentage);
+                BuildStatus::NotYetRolledOut(percentage)
+            }
+            result => build_status(result),
+        }
+    })?;
 
-impl ReleaseStatus {
-    pub fn is_lts(&self) -> bool { self.is_lts }
-}
+    match status.build {
+        // Forced checks ignore the rollout, so they must not be reused by checks which do not.
+        BuildStatus::Build(_) | BuildStatus::Blacklisted if !force => {
+            if let Err(why) = cache::store(&status, development, channel) {
+                warn!("failed to cache release check: {}", why);
+            }
+        }
+        BuildStatus::ConnectionIssue(_)
+        | BuildStatus::ServerStatus(_)
+        | BuildStatus::Throttled(_) => {
+            if let Some(cached) = cached {
+                warn!(
+                    "release API is unavailable: using the release check from {:?} ago",
+                    cached.age
+                );
+                return Ok(cached.status);
+            }
+        }
+        _ => (),
+    }
 
-pub fn next(development: bool) -> Result<ReleaseStatus, VersionError> {
-    Version::detect().map(|current| {
-        next_(current, development, |build| Release::build_exists(build, "intel").into())
-    })
+    Ok(status)
 }
 
-pub fn current(version: Option<&str>) -> anyhow::Result<(Box<str>, u16)> {
-    info!("Checking for current release of {:?}", version);
+pub fn current(version: Option<&str>, arch: &str) -> anyhow::Result<(Box<str>, u16)> {
+    info!("Checking for current release of {:?} ({})", version, arch);
 
     if let Some(version) = version {
-        let build = Release::build_exists(version, "intel")
+        let build = Release::build_exists(version, arch)
             .with_context(|| fomat!("failed to find build for "(version)))?;
 
         return Ok((version.into(), build));
     }
 
     let current = Version::detect().context("cannot detect current version of Pop")?;
-    let release_str = release_str(current.major, current.minor);
+    let paths = UpgradePaths::load();
+    let release_str = release_str(&paths, current.major, current.minor)?;
 
-    let build = Release::build_exists(release_str, "intel")
+    let build = Release::build_exists(release_str, arch)
         .with_context(|| fomat!("failed to find build for "(release_str)))?;
 
     Ok((release_str.into(), build))
 }
 
-pub fn release_str(major: u8, minor: u8) -> &'static str {
-    match (major, minor) {
-        (18, 4) => "18.04",
-        (19, 10) => "18.10",
-        (20, 4) => "20.04",
-        (20, 10) => "20.10",
-        (21, 4) => "21.04",
-        _ => panic!("this version of pop-upgrade is not supported on this release"),
-    }
+pub fn release_str(paths: &UpgradePaths, major: u8, minor: u8) -> Result<&str, UnsupportedRelease> {
+    paths
+        .find(major, minor)
+        .map(|path| path.version.as_str())
+        .ok_or(UnsupportedRelease { major, minor })
 }
 
 fn next_(
+    paths: &UpgradePaths,
     current: Version,
     development: bool,
+    channel: Channel,
+    arch: &str,
     release_check: impl Fn(&str) -> BuildStatus,
-) -> ReleaseStatus {
-    let next: &str;
-    match (current.major, current.minor) {
-        (18, 4) => {
-            // next = if development { "20.10" } else { "20.04" };
-            next = "20.04";
-
-            ReleaseStatus { build: release_check(next), current: "18.04", is_lts: true, next }
-        }
-
-        (19, 10) => {
-            next = "20.04";
-
-            ReleaseStatus { build: release_check(next), current: "19.10", is_lts: false, next }
-        }
-
-        (20, 4) => {
-            next = "20.10";
-
-            ReleaseStatus { build: release_check(next), current: "20.04", is_lts: true, next }
+) -> Result<ReleaseStatus, UnsupportedRelease> {
+    let (major, minor) = (current.major, current.minor);
+    let path = paths.find(major, minor).ok_or(UnsupportedRelease { major, minor })?;
+
+    let (next, build) = match channel {
+        Channel::Latest => {
+            let build = match path.upgrade {
+                Availability::Enabled => release_check(&path.next),
+                Availability::Development if development => release_check(&path.next),
+                Availability::Development | Availability::Disabled => BuildStatus::Blacklisted,
+            };
+
+            (path.next.as_str(), build)
         }
+        // Interim releases are skipped by upgrading directly to the next LTS release, which is
+        // only offered once every upgrade leading up to it is.
+        Channel::Lts => match paths.next_lts(&path.version) {
+            Some(next) if paths.plan(&path.version, next, development).is_ok() => {
+                (next, release_check(next))
+            }
+            next => (next.unwrap_or(&path.next), BuildStatus::Blacklisted),
+        },
+    };
+
+    Ok(ReleaseStatus {
+        build,
+        current: path.version.as_str().into(),
+        is_lts:  path.lts,
+        next:    next.into(),
+        arch:    arch.into(),
+    })
+}
 
-        (20, 10) => {
-            next = "21.04";
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
 
-            ReleaseStatus {
-                build: if development { release_check(next) } else { BuildStatus::Blacklisted },
-                current: "20.10",
-                is_lts: false,
-                next,
-            }
-        }
+    fn available(version: &str) -> BuildStatus {
+        BuildStatus::Build(Build { build: 1, size: 0, release_notes: Some(version.into()) })
+    }
 
-        (21, 4) => ReleaseStatus {
-            build:   BuildStatus::Blacklisted,
-            current: "21.04",
-            is_lts:  false,
-            next:    "21.10",
-        },
+    #[test]
+    fn lts_channel() {
+        let paths = UpgradePaths::bundled();
+        let check = |major, minor, channel| {
+            let version = Version { major, minor, patch: 0 };
+            next_(&paths, version, true, channel, "intel", available).unwrap()
+        };
+
+        let status = check(19, 10, Channel::Lts);
+        assert_eq!(&*status.next, "20.04");
+        assert_eq!(status.build, available("20.04"));
+
+        let status = check(20, 4, Channel::Lts);
+        assert_eq!(&*status.next, "20.10");
+        assert_eq!(status.build, BuildStatus::Blacklisted);
+
+        let status = check(20, 4, Channel::Latest);
+        assert_eq!(&*status.next, "20.10");
+        assert_eq!(status.build, available("20.10"));
+    }
 
-        _ => panic!("this version of pop-upgrade is not supported on this release"),
+    #[test]
+    fn serialize() {
+        let status = ReleaseStatus {
+            current: "20.04".into(),
+            next:    "20.10".into(),
+            arch:    "intel".into(),
+            build:   BuildStatus::Build(Build {
+                build:         11,
+                size:          2_500_000_000,
+                release_notes: None,
+            }),
+            is_lts:  true,
+        };
+
+        assert_eq!(
+            serde_json::to_value(&status).unwrap(),
+            json!({
+                "current": "20.04",
+                "next": "20.10",
+                "arch": "intel",
+                "build": {
+                    "status": "available",
+                    "build": 11,
+                    "size": 2_500_000_000u64,
+                    "release_notes": null,
+                },
+                "is_lts": true,
+            })
+        );
+
+        let throttled = BuildStatus::Throttled(Duration::from_secs(30));
+        assert_eq!(
+            serde_json::to_value(&throttled).unwrap(),
+            json!({ "status": "throttled", "retry_after": 30 })
+        );
+
+        let phased = BuildStatus::NotYetRolledOut(25);
+        assert_eq!(
+            serde_json::to_value(&phased).unwrap(),
+            json!({ "status": "not_yet_rolled_out", "rollout": 25 })
+        );
     }
 }
@@ -0,0 +1,155 @@
+//! Checks whether the DKMS modules which are installed, such as VirtualBox or out-of-tree Wi-Fi
+//! drivers, are provided for a new release.
+//!
+//! Modules are rebuilt for the kernel of the new release from the source that is installed, so
+//! modules which the release does not provide an updated package for may fail to build.
+
+use super::preview::{PreviewError, SharedLists};
+use async_process::Command;
+use std::{fs, io, path::Path};
+
+const DKMS_TREE: &str = "/var/lib/dkms";
+
+/// A module which DKMS builds for each kernel.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DkmsModule {
+    pub name:    String,
+    pub version: String,
+    /// The package which installed the source of the module, if it was installed by a package.
+    pub package: Option<String>,
+}
+
+impl DkmsModule {
+    /// The module as `name/version`, which is how DKMS refers to it.
+    pub fn id(&self) -> String { [&*self.name, "/", &*self.version].concat() }
+}
+
+/// The DKMS modules which may fail to build after a release upgrade.
+#[derive(Debug, Default, PartialEq)]
+pub struct DkmsModules {
+    /// Every installed module.
+    pub installed:   Vec<DkmsModule>,
+    /// Modules whose source was not installed by a package, and therefore will not be updated.
+    pub unpackaged:  Vec<DkmsModule>,
+    /// Modules whose package the new release does not provide.
+    pub unavailable: Vec<DkmsModule>,
+}
+
+/// Checks the installed DKMS modules against the package lists of the new release.
+pub async fn check(lists: &SharedLists) -> Result<DkmsModules, PreviewError> {
+    let installed = installed(Path::new(DKMS_TREE)).map_err(PreviewError::Installed)?;
+
+    let mut modules = Vec::with_capacity(installed.len());
+    for (name, version) in installed {
+        let package = owner(&["/usr/src/", &*name, "-", &*version].concat()).await;
+        modules.push(DkmsModule { name, version, package });
+    }
+
+    let (unpackaged, packaged): (Vec<_>, Vec<_>) =
+        modules.iter().cloned().partition(|module| module.package.is_none());
+
+    let mut unavailable = Vec::new();
+
+    if !packaged.is_empty() {
+        info!("checking {} DKMS modules against {}", packaged.len(), lists.release());
+
+        let packages =
+            packaged.iter().filter_map(|module| module.package.clone()).collect::<Vec<_>>();
+        let available = lists.get().await?.available(&packages).await?;
+
+        unavailable.extend(packaged.into_iter().filter(|module| {
+            module.package.as_ref().map_or(false, |package| !available.contains(package))
+        }));
+    }
+
+    Ok(DkmsModules { installed: modules, unpackaged, unavailable })
+}
+
+/// The names and versions of the modules which have been added to the DKMS tree.
+fn installed(tree: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut modules = Vec::new();
+
+    let entries = match fs::read_dir(tree) {
+        Ok(entries) => entries,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(modules),
+        Err(why) => return Err(why),
+    };
+
+    for module in entries.filter_map(Result::ok) {
+        if !module.path().is_dir() {
+            continue;
+        }
+
+        let name = module.file_name().to_string_lossy().into_owned();
+
+        for version in fs::read_dir(module.path())?.filter_map(Result::ok) {
+            let version = version.file_name().to_string_lossy().into_owned();
+
+            // Each kernel that the module was built for is linked as `kernel-<release>-<arch>`.
+            let source = module.path().join(&version).join("source");
+            if version.starts_with("kernel-") || !source.exists() {
+                continue;
+            }
+
+            modules.push((name.clone(), version));
+        }
+    }
+
+    modules.sort();
+    Ok(modules)
+}
+
+/// The package which installed a path, if any did.
+async fn owner(path: &str) -> Option<String> {
+    let output = Command::new("dpkg-query").args(&["-S", path]).output().await.ok()?;
+
+    if !output.status.success() {
+        return None;
+    }
+
+    parse_owner(&String::from_utf8_lossy(&output.stdout))
+}
+
+/// Parses the first package of `dpkg-query -S`, which outputs `package[, package]: path`.
+fn parse_owner(output: &str) -> Option<String> {
+    let packages = output.lines().next()?.split(": ").next()?;
+    let package = packages.split(", ").next()?.trim();
+
+    if package.is_empty() {
+        None
+    } else {
+        Some(package.to_owned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::symlink;
+
+    #[test]
+    fn dkms_tree() {
+        let tree = tempfile::tempdir().unwrap();
+        let module = tree.path().join("virtualbox");
+
+        fs::create_dir_all(module.join("6.1.16/source")).unwrap();
+        fs::create_dir_all(module.join("6.1.10")).unwrap();
+        symlink("6.1.16/5.8.0-7642-generic", module.join("kernel-5.8.0-7642-generic-x86_64"))
+            .unwrap();
+        fs::write(tree.path().join("dkms_dbversion"), "2.0.0\n").unwrap();
+
+        let modules = installed(tree.path()).unwrap();
+        assert_eq!(modules, vec![(String::from("virtualbox"), String::from("6.1.16"))]);
+
+        assert!(installed(&tree.path().join("missing")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn owners() {
+        let output = "virtualbox-dkms: /usr/src/virtualbox-6.1.16\n";
+        assert_eq!(parse_owner(output), Some("virtualbox-dkms".into()));
+
+        let output = "rtl8821ce-dkms, rtl8821ce-dkms-extra: /usr/src/rtl8821ce-5.5.2\n";
+        assert_eq!(parse_owner(output), Some("rtl8821ce-dkms".into()));
+    }
+}
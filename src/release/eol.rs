@@ -1,14 +1,10 @@
+use super::paths::UpgradePaths;
 use anyhow::Context;
-use chrono::{Date, NaiveDate, Utc};
+use chrono::{Date, Datelike, NaiveDate, Utc};
 use std::convert::TryFrom;
 use ubuntu_version::{Codename, Version};
 
-#[derive(Debug, PartialEq)]
-pub enum EolStatus {
-    Ok,
-    Imminent,
-    Exceeded,
-}
+pub use pop_upgrade_core::release::EolStatus;
 
 pub struct EolDate {
     pub version: Version,
@@ -22,9 +18,20 @@ impl From<Codename> for EolDate {
 }
 
 impl EolDate {
-    pub fn fetch() -> anyhow::Result<Self> {
+    /// The EOL date of the current release, according to the bundled upgrade path table.
+    pub fn fetch() -> anyhow::Result<Self> { Self::fetch_from(&UpgradePaths::bundled()) }
+
+    /// The EOL date of the current release.
+    ///
+    /// The date in the upgrade path table takes precedence over the date known to
+    /// `ubuntu-version`, which is only updated when pop-upgrade is.
+    pub fn fetch_from(paths: &UpgradePaths) -> anyhow::Result<Self> {
         let version = Version::detect().context("failed to detect current Ubuntu release")?;
 
+        if let Some(eol) = paths.find(version.major, version.minor).and_then(|path| path.eol) {
+            return Ok(Self { version, ymd: (eol.year() as u32, eol.month(), eol.day()) });
+        }
+
         let codename = match Codename::try_from(version) {
             Ok(codename) => codename,
             Err(()) => return Err(anyhow!("Invalid Ubuntu version: {}", version)),
@@ -33,6 +40,12 @@ impl EolDate {
         Ok(Self { version, ymd: codename.eol_date() })
     }
 
+    /// The EOL date, formatted as `YYYY-MM-DD`.
+    pub fn date(&self) -> String {
+        let (year, month, day) = self.ymd;
+        format!("{}-{:02}-{:02}", year, month, day)
+    }
+
     #[inline]
     pub fn status(&self) -> EolStatus { self.status_from(Utc::now().date()) }
 
@@ -80,6 +93,12 @@ mod tests {
         assert_eq!(disco.status_from(ymd_to_utc(2020, 1, 17)), EolStatus::Imminent);
     }
 
+    #[test]
+    fn date() {
+        let eol = EolDate { version: Codename::Disco.into(), ymd: (2020, 1, 3) };
+        assert_eq!(eol.date(), "2020-01-03");
+    }
+
     #[test]
     fn eol_ok() {
         let disco = EolDate::from(Codename::Disco);
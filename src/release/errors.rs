@@ -16,12 +16,18 @@ pub enum ReleaseError {
     #[error("failed to back up system sources")]
     BackupPPAs(#[source] anyhow::Error),
 
+    #[error("process has been cancelled")]
+    Cancelled,
+
     #[error("unable to upgrade to next release: {:?}", _0)]
     Check(#[source] anyhow::Error),
 
     #[error("failed to launch command")]
     Command(#[source] io::Error),
 
+    #[error("failed to record the conffile policy for the upgrade")]
+    ConffilePolicy(#[source] io::Error),
+
     #[error("conflicting and/or deprecated packages could not be removed")]
     ConflictRemoval(#[source] io::Error),
 
@@ -34,12 +40,37 @@ pub enum ReleaseError {
     #[error("status for `dpkg --configure -a` failed")]
     DpkgConfigure(#[source] io::Error),
 
+    #[error("the encrypted root volume would be unable to be unlocked")]
+    Encryption(#[source] crate::luks::LuksError),
+
     #[error("status for `apt-get install -f` failed")]
     FixBroken(#[source] io::Error),
 
+    #[error(
+        "packages are held, which may prevent the upgrade: {}; their holds may be lifted for the \
+         duration of the upgrade",
+        _0.join(", ")
+    )]
+    Held(Vec<String>),
+
+    #[error("failed to list held packages")]
+    HeldPackages(#[source] io::Error),
+
+    #[error("failed to lift holds on packages")]
+    HoldsLift(#[source] io::Error),
+
+    #[error("failed to record packages whose holds were lifted")]
+    HoldsRecord(#[source] io::Error),
+
     #[error("failed to hold the pop-upgrade package")]
     HoldPopUpgrade(#[source] io::Error),
 
+    #[error("a hook which runs before the upgrade failed")]
+    Hook(#[source] crate::hooks::HookError),
+
+    #[error("unable to add the boot entry for the upgrade")]
+    Loader(#[source] crate::loader::LoaderError),
+
     #[error("unable to hold apt/dpkg lock files")]
     Lock(#[source] io::Error),
 
@@ -0,0 +1,168 @@
+//! Updates the system's Flatpak runtimes and applications once a release upgrade has completed,
+//! so that they are built against the same platform as the new release.
+
+use super::UpgradeEvent;
+use crate::config::Config;
+use anyhow::Context;
+use async_process::{Command, Output, Stdio};
+use futures::{io::BufReader, prelude::*};
+use std::path::Path;
+
+const FLATPAK: &str = "/usr/bin/flatpak";
+
+const FLATHUB: &str = "flathub";
+
+/// Describes the Flathub remote, and is used to add it back if it was removed.
+const FLATHUB_REPO: &str = "https://flathub.org/repo/flathub.flatpakrepo";
+
+/// Errors which `flatpak update` reports when the Flathub remote no longer offers the branches
+/// of the installed runtimes, or was left disabled, and must be repaired before updating.
+const REMOTE_ERRORS: &[&str] = &[
+    "No such ref",
+    "No remote refs found",
+    "Unable to load summary",
+    "is disabled",
+    "not found in remote",
+    "No entry for",
+];
+
+/// Updates Flatpaks once a release upgrade has completed, if enabled in the configuration.
+pub async fn after_upgrade(logger: &dyn Fn(UpgradeEvent), progress: &dyn Fn(u8)) {
+    if !Config::load_or_default().release.flatpak || !Path::new(FLATPAK).exists() {
+        return;
+    }
+
+    if let Err(why) = update(logger, progress).await {
+        error!("failed to update flatpaks: {}", crate::misc::format_error(why.as_ref()));
+    }
+}
+
+/// Updates the system-wide Flatpak runtimes and applications. If the update fails because the
+/// Flathub remote lacks the branches of the installed runtimes, the remote is repaired, and the
+/// update is attempted once more. The percentage of the operations of the update which have
+/// completed is passed to `progress`.
+pub async fn update(logger: &dyn Fn(UpgradeEvent), progress: &dyn Fn(u8)) -> anyhow::Result<()> {
+    (*logger)(UpgradeEvent::UpdatingFlatpaks);
+
+    let output = flatpak_update(progress).await?;
+    if output.status.success() {
+        return Ok(());
+    }
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !needs_repair(&stderr) {
+        return Err(anyhow!("`flatpak update` exited with {}: {}", output.status, stderr.trim()));
+    }
+
+    warn!("repairing the {} remote: {}", FLATHUB, stderr.trim());
+    (*logger)(UpgradeEvent::RepairingFlatpakRemote);
+    repair_flathub().await?;
+
+    (*logger)(UpgradeEvent::UpdatingFlatpaks);
+
+    let output = flatpak_update(progress).await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`flatpak update` exited with {} after repairing {}: {}",
+            output.status,
+            FLATHUB,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(())
+}
+
+/// Runs `flatpak update`, passing the progress which it reports in its output to `progress`.
+async fn flatpak_update(progress: &dyn Fn(u8)) -> anyhow::Result<Output> {
+    let mut child = Command::new(FLATPAK)
+        .args(&["update", "--system", "--noninteractive", "-y"])
+        .env("LANG", "C")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run `flatpak update`")?;
+
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next().await {
+            let line = line.context("failed to read the output of `flatpak update`")?;
+            if let Some(percent) = operation_progress(&line) {
+                progress(percent);
+            }
+        }
+    }
+
+    child.output().await.context("failed to run `flatpak update`")
+}
+
+/// The progress of an update by the operation which it reports beginning, such as
+/// `Updating 2/5…`, where the operations before it have completed.
+fn operation_progress(line: &str) -> Option<u8> {
+    line.split_whitespace().find_map(|word| {
+        let mut operation = word.trim_matches(|c: char| !c.is_ascii_digit()).splitn(2, '/');
+        let current = operation.next()?.parse::<u64>().ok()?;
+        let total = operation.next()?.parse::<u64>().ok()?;
+
+        if current == 0 || current > total {
+            return None;
+        }
+
+        Some(((current - 1) * 100 / total) as u8)
+    })
+}
+
+/// Adds the Flathub remote back if it is missing, enables it, refreshes its summary, and repairs
+/// the installation of any refs which it no longer offers.
+async fn repair_flathub() -> anyhow::Result<()> {
+    let commands: &[&[&str]] = &[
+        &["remote-add", "--system", "--if-not-exists", FLATHUB, FLATHUB_REPO],
+        &["remote-modify", "--system", "--enable", FLATHUB],
+        &["update", "--system", "--noninteractive", "--appstream", FLATHUB],
+        &["repair", "--system"],
+    ];
+
+    for args in commands {
+        let status = Command::new(FLATPAK)
+            .args(*args)
+            .status()
+            .await
+            .with_context(|| format!("failed to run `flatpak {}`", args[0]))?;
+
+        if !status.success() {
+            return Err(anyhow!("`flatpak {}` exited with {}", args[0], status));
+        }
+    }
+
+    Ok(())
+}
+
+fn needs_repair(stderr: &str) -> bool {
+    stderr.contains(FLATHUB) && REMOTE_ERRORS.iter().any(|error| stderr.contains(error))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs() {
+        let branch =
+            "error: No such ref 'runtime/org.gnome.Platform/x86_64/3.36' in remote flathub";
+        assert!(needs_repair(branch));
+
+        let disabled = "error: Remote \"flathub\" is disabled, ignoring org.gimp.GIMP update";
+        assert!(needs_repair(disabled));
+
+        let space = "error: Failed to install org.gimp.GIMP from flathub: No space left on device";
+        assert!(!needs_repair(space));
+    }
+
+    #[test]
+    fn operations() {
+        assert_eq!(operation_progress("Updating 1/4…"), Some(0));
+        assert_eq!(operation_progress("Updating 3/4…"), Some(50));
+        assert_eq!(operation_progress("Updating runtime/org.gnome.Platform/x86_64/3.38"), None);
+        assert_eq!(operation_progress("Nothing to do."), None);
+    }
+}
@@ -0,0 +1,112 @@
+//! Checks whether the packages installed for foreign architectures, such as i386 multiarch
+//! libraries, are available in a new release.
+//!
+//! Foreign-architecture packages which a release no longer provides are removed by the upgrade,
+//! or otherwise leave apt unable to resolve it.
+
+use super::preview::{self, PreviewError, SharedLists};
+use crate::external;
+use std::io;
+
+/// The foreign-architecture packages which would be affected by a release upgrade.
+#[derive(Debug, Default, PartialEq)]
+pub struct ForeignPackages {
+    /// Every installed package of a foreign architecture, as `package:arch`.
+    pub installed:    Vec<String>,
+    /// Installed packages which the new release does not provide.
+    pub unavailable:  Vec<String>,
+    /// Installed packages which the upgrade would remove.
+    pub removed:      Vec<String>,
+    /// Whether apt was unable to resolve the upgrade.
+    pub unresolvable: bool,
+}
+
+/// Checks the installed foreign-architecture packages against the package lists of the new
+/// release.
+pub async fn check(lists: &SharedLists) -> Result<ForeignPackages, PreviewError> {
+    let to = lists.release();
+    let installed = installed().await.map_err(PreviewError::Installed)?;
+
+    if installed.is_empty() {
+        return Ok(ForeignPackages::default());
+    }
+
+    info!("checking {} foreign-architecture packages against {}", installed.len(), to);
+
+    let lists = lists.get().await?;
+
+    let available = lists.available(&installed).await?;
+    let unavailable = installed.iter().filter(|package| !available.contains(package)).cloned();
+    let unavailable = unavailable.collect::<Vec<_>>();
+
+    let (removed, unresolvable) = match preview::simulate(&lists).await {
+        Ok(preview) => {
+            let removed = installed
+                .iter()
+                .filter(|package| preview.removed.contains(package))
+                .cloned()
+                .collect::<Vec<_>>();
+
+            (removed, false)
+        }
+        Err(why) => {
+            warn!("failed to resolve the upgrade to {}: {}", to, why);
+            (Vec::new(), true)
+        }
+    };
+
+    Ok(ForeignPackages { installed, unavailable, removed, unresolvable })
+}
+
+/// The installed packages of foreign architectures, as `package:arch`.
+pub async fn installed() -> io::Result<Vec<String>> {
+    let architectures =
+        external::output("/usr/bin/dpkg", &["--print-foreign-architectures"]).await?;
+    let architectures = architectures.split_whitespace().collect::<Vec<_>>();
+
+    if architectures.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let packages = external::output("/usr/bin/dpkg-query", &[
+        "-W",
+        "-f",
+        "${db:Status-Abbrev} ${Package}:${Architecture}\\n",
+    ])
+    .await?;
+
+    Ok(parse_installed(&packages, &architectures))
+}
+
+fn parse_installed(output: &str, architectures: &[&str]) -> Vec<String> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let status = fields.next()?;
+            let package = fields.next()?;
+            let arch = package.rsplit(':').next()?;
+
+            if status.starts_with("ii") && architectures.contains(&arch) {
+                Some(package.to_owned())
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn installed_packages() {
+        let output = "ii  libc6:amd64\n\
+                      ii  libc6:i386\n\
+                      rc  libfoo1:i386\n\
+                      ii  steam-libs-i386:i386\n";
+
+        assert_eq!(parse_installed(output, &["i386"]), vec!["libc6:i386", "steam-libs-i386:i386"]);
+    }
+}
@@ -0,0 +1,237 @@
+//! Checks the health of the system once a release upgrade has been installed, and rolls the
+//! system back to the previous release if the upgrade left it broken.
+
+use super::{repos, systemd::BootConf};
+use crate::{
+    loader::{self, LoaderError},
+    misc,
+    snapshot,
+};
+use anyhow::Context;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+    time::SystemTime,
+};
+use thiserror::Error;
+
+/// Records the release upgrade which is to be installed on the next boot, as
+/// `<from> <to> <kernel>`, so that its outcome may be checked afterwards.
+const UPGRADE_RECORD: &str = "/var/lib/pop-upgrade/upgrade_record";
+
+/// The boot entry which kernelstub maintains for the previous kernel.
+const OLD_KERNEL_ENTRY: &str = "Pop_OS-oldkern";
+
+#[derive(Debug, PartialEq)]
+pub struct UpgradeRecord {
+    pub from:   String,
+    pub to:     String,
+    /// The kernel which was running before the upgrade.
+    pub kernel: String,
+}
+
+impl UpgradeRecord {
+    pub fn load() -> Option<Self> { Self::parse(&fs::read_to_string(UPGRADE_RECORD).ok()?) }
+
+    /// Records an upgrade from the `from` release to the `to` release, from the running kernel.
+    pub fn store(from: &str, to: &str) -> io::Result<()> {
+        let kernel = fs::read_to_string("/proc/sys/kernel/osrelease")?;
+        fs::write(UPGRADE_RECORD, [from, " ", to, " ", kernel.trim()].concat())
+    }
+
+    pub fn remove() { let _ = fs::remove_file(UPGRADE_RECORD); }
+
+    /// When the upgrade was staged to be installed.
+    pub fn staged() -> Option<SystemTime> {
+        fs::metadata(UPGRADE_RECORD).and_then(|meta| meta.modified()).ok()
+    }
+
+    fn parse(record: &str) -> Option<Self> {
+        let mut fields = record.split_whitespace();
+        Some(Self {
+            from:   fields.next()?.into(),
+            to:     fields.next()?.into(),
+            kernel: fields.next()?.into(),
+        })
+    }
+}
+
+/// Why a staged upgrade did not leave the system on the new release.
+#[derive(Debug, Error)]
+pub enum InstallError {
+    #[error("the upgrade was not installed on boot")]
+    NotInstalled,
+
+    #[error("the upgrade failed its health check, and was rolled back")]
+    RolledBack,
+}
+
+#[derive(Debug, Error, PartialEq)]
+pub enum HealthIssue {
+    #[error("packages were left in a broken state by dpkg:\n{}", _0)]
+    Dpkg(String),
+
+    #[error("the default kernel is missing {:?}", _0)]
+    Kernel(PathBuf),
+}
+
+/// Checks for issues which leave the system unusable after an upgrade.
+pub fn check() -> Vec<HealthIssue> {
+    let mut issues = Vec::new();
+
+    issues.extend(dpkg_issues());
+    issues.extend(kernel_issues(Path::new("/")));
+
+    if Path::new(loader::ENTRIES).exists() {
+        if let Err(LoaderError::MissingKernel(_, path)) = loader::verify_default() {
+            issues.push(HealthIssue::Kernel(path));
+        }
+    }
+
+    issues
+}
+
+/// Packages which dpkg left unconfigured, such as when the installation was interrupted, are
+/// configured before they are deemed broken, so that the upgrade is only rolled back if they
+/// cannot be configured.
+fn dpkg_issues() -> Option<HealthIssue> {
+    let audit = |when: &str| match dpkg_audit() {
+        Ok(audit) => audit,
+        Err(why) => {
+            error!("failed to audit dpkg {}: {}", when, why);
+            None
+        }
+    };
+
+    let unconfigured = audit("after the upgrade")?;
+    warn!("configuring the packages which dpkg left unconfigured:\n{}", unconfigured);
+
+    let status = Command::new("dpkg")
+        .args(&["--configure", "-a", "--force-confdef", "--force-confold"])
+        .env("DEBIAN_FRONTEND", "noninteractive")
+        .status();
+
+    match status {
+        Ok(status) if status.success() => (),
+        Ok(status) => error!("dpkg --configure -a exited with {}", status),
+        Err(why) => error!("failed to run dpkg --configure -a: {}", why),
+    }
+
+    audit("after configuring packages").map(HealthIssue::Dpkg)
+}
+
+/// `dpkg --audit` lists packages which are unpacked, but not configured, or half-installed.
+pub(crate) fn dpkg_audit() -> io::Result<Option<String>> {
+    let output = Command::new("dpkg").arg("--audit").output()?;
+    let audit = String::from_utf8_lossy(&output.stdout);
+    let audit = audit.trim();
+
+    Ok(if audit.is_empty() { None } else { Some(audit.into()) })
+}
+
+/// The default kernel must have an initramfs, and modules to load.
+fn kernel_issues(root: &Path) -> Option<HealthIssue> {
+    let missing = |path: PathBuf| Some(HealthIssue::Kernel(path));
+
+    let vmlinuz = root.join("boot/vmlinuz");
+    let initrd = root.join("boot/initrd.img");
+
+    let kernel = match fs::canonicalize(&vmlinuz) {
+        Ok(kernel) => kernel,
+        Err(_) => return missing(vmlinuz),
+    };
+
+    if !initrd.exists() {
+        return missing(initrd);
+    }
+
+    let version = kernel.file_name()?.to_str()?.trim_start_matches("vmlinuz-");
+
+    let modules = root.join("lib/modules").join(version);
+    if !modules.exists() {
+        return missing(modules);
+    }
+
+    None
+}
+
+/// Rolls back a failed upgrade.
+///
+/// The snapshot taken before the upgrade is restored if there is one, and it can be restored
+/// from the running system. Otherwise, the apt sources of the previous release are restored, and
+/// the previous kernel is held and booted by default.
+pub fn rollback(record: &UpgradeRecord) -> anyhow::Result<()> {
+    let pre_upgrade = snapshot::list()
+        .ok()
+        .and_then(|snapshots| snapshots.into_iter().find(|snapshot| snapshot.pre_upgrade));
+
+    if let Some(snapshot) = pre_upgrade {
+        warn!("rolling back to the pre-upgrade snapshot {}", snapshot.name);
+        match snapshot::restore(&snapshot.name) {
+            Ok(_) => return Ok(()),
+            Err(ref why) => {
+                warn!("failed to restore the pre-upgrade snapshot: {}", misc::format_error(why))
+            }
+        }
+    }
+
+    warn!("rolling back the apt sources to {}", record.from);
+    repos::restore(&record.from).context("failed to restore the apt sources")?;
+
+    warn!("pinning the previous kernel, {}", record.kernel);
+    let package = ["linux-image-", &*record.kernel].concat();
+    let status = Command::new("apt-mark")
+        .args(&["hold", &*package])
+        .status()
+        .context("failed to hold the previous kernel")?;
+
+    if !status.success() {
+        warn!("failed to hold {}: apt-mark exited with {}", package, status);
+    }
+
+    BootConf::load()
+        .and_then(|mut conf| conf.set_default_boot_id(OLD_KERNEL_ENTRY))
+        .context("failed to boot the previous kernel by default")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record() {
+        assert_eq!(
+            UpgradeRecord::parse("focal groovy 5.8.0-7642-generic"),
+            Some(UpgradeRecord {
+                from:   "focal".into(),
+                to:     "groovy".into(),
+                kernel: "5.8.0-7642-generic".into(),
+            })
+        );
+
+        assert_eq!(UpgradeRecord::parse("focal groovy"), None);
+    }
+
+    #[test]
+    fn missing_kernel() {
+        let root = tempfile::tempdir().unwrap();
+        let root = root.path();
+
+        fs::create_dir_all(root.join("boot")).unwrap();
+        assert_eq!(kernel_issues(root), Some(HealthIssue::Kernel(root.join("boot/vmlinuz"))));
+
+        fs::write(root.join("boot/vmlinuz-5.11.0-7614-generic"), b"").unwrap();
+        std::os::unix::fs::symlink("vmlinuz-5.11.0-7614-generic", root.join("boot/vmlinuz"))
+            .unwrap();
+        fs::write(root.join("boot/initrd.img"), b"").unwrap();
+
+        assert_eq!(
+            kernel_issues(root),
+            Some(HealthIssue::Kernel(root.join("lib/modules/5.11.0-7614-generic")))
+        );
+
+        fs::create_dir_all(root.join("lib/modules/5.11.0-7614-generic")).unwrap();
+        assert_eq!(kernel_issues(root), None);
+    }
+}
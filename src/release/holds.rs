@@ -0,0 +1,148 @@
+use super::errors::ReleaseError;
+use apt_cmd::AptMark;
+use async_fs as fs;
+use async_process::Command;
+use std::{future::Future, io, path::Path};
+
+/// Packages which pop-upgrade holds itself, and are therefore not reported.
+const OWN_HOLDS: &[&str] = &["pop-upgrade"];
+
+/// Packages which are held by the system administrator.
+///
+/// Held packages are not upgraded, which frequently prevents a release upgrade from resolving.
+pub async fn held() -> Result<Vec<String>, ReleaseError> {
+    let output = Command::new("apt-mark")
+        .arg("showhold")
+        .output()
+        .await
+        .map_err(ReleaseError::HeldPackages)?;
+
+    if !output.status.success() {
+        return Err(ReleaseError::HeldPackages(io::Error::new(
+            io::ErrorKind::Other,
+            format!("apt-mark exited with {}", output.status),
+        )));
+    }
+
+    Ok(parse_held(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// Lifts the holds on packages for the duration of the upgrade.
+///
+/// The packages are recorded beforehand so that their holds are restored once the upgrade has
+/// completed, or if it was interrupted.
+pub async fn lift(packages: &[String]) -> Result<(), ReleaseError> {
+    let mut buffer = String::new();
+
+    for package in packages {
+        buffer.push_str(package);
+        buffer.push('\n');
+    }
+
+    fs::write(crate::LIFTED_HOLDS, buffer.as_bytes())
+        .await
+        .map_err(ReleaseError::HoldsRecord)?;
+
+    info!("lifting holds on {}", packages.join(", "));
+    AptMark::new().unhold(packages).await.map_err(ReleaseError::HoldsLift)
+}
+
+/// Holds the packages whose holds were lifted for an upgrade once again.
+pub async fn restore() { restore_from(Path::new(crate::LIFTED_HOLDS), hold).await }
+
+/// Restores the lifted holds if the upgrade which lifted them failed, or was cancelled.
+///
+/// A staged upgrade keeps its holds lifted until it has been installed.
+pub async fn restore_on_failure<T>(result: Result<T, ReleaseError>) -> Result<T, ReleaseError> {
+    restore_on_failure_from(Path::new(crate::LIFTED_HOLDS), result, hold).await
+}
+
+async fn restore_on_failure_from<T, F, R>(
+    record: &Path,
+    result: Result<T, ReleaseError>,
+    hold: F,
+) -> Result<T, ReleaseError>
+where
+    F: FnOnce(Vec<String>) -> R,
+    R: Future<Output = ()>,
+{
+    if result.is_err() {
+        restore_from(record, hold).await;
+    }
+
+    result
+}
+
+async fn restore_from<F, R>(record: &Path, hold: F)
+where
+    F: FnOnce(Vec<String>) -> R,
+    R: Future<Output = ()>,
+{
+    if let Ok(packages) = fs::read_to_string(record).await {
+        hold(packages.lines().map(String::from).collect()).await;
+    }
+
+    let _ = fs::remove_file(record).await;
+}
+
+async fn hold(packages: Vec<String>) {
+    info!("restoring holds on {}", packages.join(", "));
+    let _ = AptMark::new().hold(&packages).await;
+}
+
+fn parse_held(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(str::trim)
+        .filter(|package| !package.is_empty() && !OWN_HOLDS.contains(package))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn own_holds_are_hidden() {
+        assert_eq!(parse_held("linux-generic\npop-upgrade\nnvidia-driver-460\n"), vec![
+            "linux-generic",
+            "nvidia-driver-460"
+        ]);
+    }
+
+    #[test]
+    fn lifted_holds_are_restored_on_failure() {
+        let dir = tempfile::tempdir().unwrap();
+        let record = dir.path().join("lifted_holds");
+        std::fs::write(&record, "linux-generic\nnvidia-driver-460\n").unwrap();
+
+        let mut restored = Vec::new();
+        let failure = Err::<(), _>(ReleaseError::HoldsRecord(io::ErrorKind::Other.into()));
+        let result = async_io::block_on(restore_on_failure_from(&record, failure, |packages| {
+            restored = packages;
+            async {}
+        }));
+
+        assert!(result.is_err());
+        assert_eq!(restored, vec!["linux-generic", "nvidia-driver-460"]);
+        assert!(!record.exists());
+    }
+
+    #[test]
+    fn lifted_holds_are_kept_on_success() {
+        let dir = tempfile::tempdir().unwrap();
+        let record = dir.path().join("lifted_holds");
+        std::fs::write(&record, "linux-generic\n").unwrap();
+
+        let mut restored = false;
+        let result = async_io::block_on(restore_on_failure_from(&record, Ok(()), |_| {
+            restored = true;
+            async {}
+        }));
+
+        assert!(result.is_ok());
+        assert!(!restored);
+        assert!(record.exists());
+    }
+}
@@ -0,0 +1,159 @@
+//! Preserves custom kernel parameters across release upgrades, which may regenerate the boot
+//! entries without them.
+
+use crate::loader;
+use anyhow::Context;
+use serde_derive::Deserialize;
+use std::{fs, path::Path, process::Command};
+
+/// Records the kernel parameters which were configured before the upgrade.
+const KERNEL_OPTIONS: &str = "/var/lib/pop-upgrade/kernel_options";
+
+const KERNELSTUB: &str = "/usr/bin/kernelstub";
+
+/// The configuration of kernelstub, which holds the parameters that it writes to boot entries.
+const KERNELSTUB_CONFIG: &str = "/etc/kernelstub/configuration";
+
+/// Parameters which are specific to the boot they were given to, and are not to be reapplied.
+const TRANSIENT: &[&str] = &["BOOT_IMAGE=", "initrd=", "root=", "systemd.unit="];
+
+#[derive(Deserialize)]
+struct KernelstubConfig {
+    user: KernelstubUser,
+}
+
+#[derive(Deserialize)]
+struct KernelstubUser {
+    kernel_options: Vec<String>,
+}
+
+/// Records the configured kernel parameters before upgrading. Parameters which were only given
+/// to the current boot, such as from the boot menu, are not recorded.
+pub fn capture() -> anyhow::Result<()> {
+    let configured = match configured()? {
+        Some(configured) => configured,
+        None => return Ok(()),
+    };
+
+    let options = persistent(&configured).collect::<Vec<_>>().join(" ");
+
+    info!("recording kernel parameters: {}", options);
+    fs::write(KERNEL_OPTIONS, options).context("failed to record the kernel parameters")
+}
+
+/// Reapplies parameters which were recorded before the upgrade, but which the current boot
+/// entry no longer has, returning the parameters which were reapplied.
+///
+/// kernelstub is given the parameters when installed, so that they are retained whenever it
+/// regenerates the boot entries. Otherwise, they are added to the boot entry directly.
+pub fn reapply() -> anyhow::Result<Vec<String>> {
+    let captured = match fs::read_to_string(KERNEL_OPTIONS) {
+        Ok(captured) => captured,
+        Err(_) => return Ok(Vec::new()),
+    };
+
+    // The parameters are only reapplied once, whether or not that succeeds.
+    let _ = fs::remove_file(KERNEL_OPTIONS);
+
+    if !Path::new(loader::ENTRIES).exists() {
+        return Ok(Vec::new());
+    }
+
+    let mut entry = loader::entry(&loader::default_id())?;
+    let missing = missing(&captured, entry.options().unwrap_or(""));
+
+    if !missing.is_empty() {
+        let options = missing.join(" ");
+        info!("reapplying kernel parameters which were dropped by the upgrade: {}", options);
+
+        if Path::new(KERNELSTUB).exists() {
+            let status = Command::new(KERNELSTUB)
+                .args(&["--add-options", &*options])
+                .status()
+                .context("failed to run kernelstub")?;
+
+            if !status.success() {
+                return Err(anyhow!("kernelstub exited with {}", status));
+            }
+        } else {
+            let options = [entry.options().unwrap_or(""), " ", &*options].concat();
+            entry.set("options", options.trim());
+            entry.store()?;
+        }
+    }
+
+    Ok(missing)
+}
+
+/// The parameters which kernelstub is configured with, or else those of the default boot entry,
+/// if the system boots with systemd-boot.
+fn configured() -> anyhow::Result<Option<String>> {
+    if Path::new(KERNELSTUB_CONFIG).exists() {
+        let config =
+            fs::read(KERNELSTUB_CONFIG).context("failed to read the kernelstub configuration")?;
+        let options =
+            kernelstub_options(&config).context("failed to parse the kernelstub configuration")?;
+        return Ok(Some(options));
+    }
+
+    if !Path::new(loader::ENTRIES).exists() {
+        return Ok(None);
+    }
+
+    let entry = loader::entry(&loader::default_id())?;
+    Ok(Some(entry.options().unwrap_or("").to_owned()))
+}
+
+fn kernelstub_options(config: &[u8]) -> serde_json::Result<String> {
+    let config = serde_json::from_slice::<KernelstubConfig>(config)?;
+    Ok(config.user.kernel_options.join(" "))
+}
+
+fn persistent(options: &str) -> impl Iterator<Item = &str> {
+    options
+        .split_whitespace()
+        .filter(|option| !TRANSIENT.iter().any(|prefix| option.starts_with(prefix)))
+}
+
+/// Parameters of the `captured` options which the `current` options lack.
+fn missing(captured: &str, current: &str) -> Vec<String> {
+    let current = current.split_whitespace().collect::<Vec<_>>();
+
+    persistent(captured)
+        .filter(|option| !current.contains(option))
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_options() {
+        let cmdline = "initrd=\\EFI\\Pop_OS-4b8d6b8e\\initrd.img root=UUID=4b8d6b8e ro quiet \
+                       loglevel=0 splash nvidia-drm.modeset=1 mitigations=off";
+
+        assert_eq!(
+            missing(cmdline, "root=UUID=1d2c3b4a ro quiet loglevel=0 splash"),
+            vec!["nvidia-drm.modeset=1", "mitigations=off"]
+        );
+
+        let current = "ro quiet loglevel=0 splash nvidia-drm.modeset=1 mitigations=off";
+        assert!(missing(cmdline, current).is_empty());
+    }
+
+    #[test]
+    fn configured_options() {
+        let config = br#"{
+            "default": { "kernel_options": ["quiet", "splash"], "esp_path": "/boot/efi" },
+            "user": {
+                "kernel_options": ["quiet", "loglevel=0", "splash", "mitigations=off"],
+                "esp_path": "/boot/efi",
+                "config_rev": 3
+            }
+        }"#;
+
+        assert_eq!(kernelstub_options(config).unwrap(), "quiet loglevel=0 splash mitigations=off");
+    }
+}
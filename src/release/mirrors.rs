@@ -0,0 +1,220 @@
+//! Selects the fastest Ubuntu archive mirror to fetch the packages of a release upgrade from,
+//! which is reverted to the default mirror once the upgrade has completed.
+
+use super::repos::{MAIN_FILE, NEW_MAIN_FILE};
+use crate::config::Config;
+use anyhow::Context;
+use isahc::config::Configurable;
+use std::{
+    fs,
+    io::Read,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// The archive mirror which the sources of a new release are generated with.
+pub const DEFAULT_MIRROR: &str = "http://us.archive.ubuntu.com/ubuntu/";
+
+/// Lists the mirrors of the country which the request originates from.
+const MIRRORS_LIST: &str = "http://mirrors.ubuntu.com/mirrors.txt";
+
+/// Records the mirror which the sources were rewritten to for the upgrade.
+const MIRROR_RECORD: &str = "/var/lib/pop-upgrade/mirror";
+
+/// Limits the number of mirrors which are benchmarked.
+const MAX_CANDIDATES: usize = 8;
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The outcome of benchmarking a mirror.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Benchmark {
+    pub mirror:     String,
+    /// Time until the response headers were received.
+    pub latency:    Duration,
+    /// Bytes per second at which the response body was received.
+    pub throughput: u64,
+}
+
+/// Benchmarks the candidate mirrors, and rewrites the sources of the `release` to the fastest.
+///
+/// Does nothing unless mirror benchmarking is enabled in the configuration.
+pub fn select_fastest(release: &str) -> anyhow::Result<Option<Benchmark>> {
+    let config = Config::load_or_default().mirrors;
+    if !config.benchmark {
+        return Ok(None);
+    }
+
+    let candidates =
+        if config.candidates.is_empty() { candidates() } else { config.candidates };
+
+    info!("benchmarking {} mirrors for {}", candidates.len(), release);
+
+    let fastest = match fastest(release, &candidates) {
+        Some(fastest) => fastest,
+        None => {
+            warn!("no mirror could be reached: using {}", DEFAULT_MIRROR);
+            return Ok(None);
+        }
+    };
+
+    info!(
+        "selected {}, with {} ms latency and {} KiB/s throughput",
+        fastest.mirror,
+        fastest.latency.as_millis(),
+        fastest.throughput / 1024
+    );
+
+    if fastest.mirror != DEFAULT_MIRROR {
+        fs::write(MIRROR_RECORD, &fastest.mirror).context("failed to record the mirror")?;
+        rewrite_files(DEFAULT_MIRROR, &fastest.mirror)?;
+    }
+
+    Ok(Some(fastest))
+}
+
+/// Reverts the sources to the default mirror, if they were rewritten to another. Returns
+/// whether any sources were reverted.
+pub fn restore() -> anyhow::Result<bool> {
+    let mirror = match fs::read_to_string(MIRROR_RECORD) {
+        Ok(mirror) => mirror,
+        Err(_) => return Ok(false),
+    };
+
+    info!("reverting sources from {} to {}", mirror.trim(), DEFAULT_MIRROR);
+    let reverted = rewrite_files(mirror.trim(), DEFAULT_MIRROR)?;
+
+    fs::remove_file(MIRROR_RECORD).context("failed to remove the mirror record")?;
+
+    Ok(reverted)
+}
+
+/// Benchmarks each mirror, returning the mirror with the highest throughput.
+pub fn fastest(release: &str, candidates: &[String]) -> Option<Benchmark> {
+    candidates
+        .iter()
+        .filter_map(|mirror| match benchmark(mirror, release) {
+            Ok(benchmark) => Some(benchmark),
+            Err(why) => {
+                warn!("failed to benchmark {}: {}", mirror, why);
+                None
+            }
+        })
+        .max_by_key(|benchmark| benchmark.throughput)
+}
+
+/// Measures how quickly the mirror serves the `Release` file of the release.
+pub fn benchmark(mirror: &str, release: &str) -> anyhow::Result<Benchmark> {
+    let url = [mirror.trim_end_matches('/'), "/dists/", release, "/Release"].concat();
+    let client = crate::http::builder(&url).timeout(TIMEOUT).build()?;
+
+    let start = Instant::now();
+    let mut response = client.get(&url)?;
+    let latency = start.elapsed();
+
+    if !response.status().is_success() {
+        return Err(anyhow!("{} returned {}", url, response.status()));
+    }
+
+    let mut body = Vec::new();
+    let bytes = response.body_mut().read_to_end(&mut body)? as u64;
+    let elapsed = start.elapsed().as_millis().max(1) as u64;
+
+    Ok(Benchmark { mirror: mirror.into(), latency, throughput: bytes * 1000 / elapsed })
+}
+
+/// The default mirror, and the mirrors of the country which the system is in.
+fn candidates() -> Vec<String> {
+    let mut candidates = vec![String::from(DEFAULT_MIRROR)];
+
+    let listed = (|| -> anyhow::Result<String> {
+        let client = crate::http::builder(MIRRORS_LIST).timeout(TIMEOUT).build()?;
+        let mut listed = String::new();
+        client.get(MIRRORS_LIST)?.body_mut().read_to_string(&mut listed)?;
+        Ok(listed)
+    })();
+
+    match listed {
+        Ok(listed) => candidates.extend(parse_mirrors(&listed).take(MAX_CANDIDATES - 1)),
+        Err(why) => warn!("failed to fetch the list of mirrors: {}", why),
+    }
+
+    candidates
+}
+
+fn parse_mirrors(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("http://") || line.starts_with("https://"))
+        .filter(|line| *line != DEFAULT_MIRROR)
+        .map(String::from)
+}
+
+/// Rewrites the mirror of the system sources, returning whether any were rewritten.
+fn rewrite_files(from: &str, to: &str) -> anyhow::Result<bool> {
+    let mut rewritten = false;
+
+    for path in &[NEW_MAIN_FILE, MAIN_FILE] {
+        if !Path::new(path).exists() {
+            continue;
+        }
+
+        let contents =
+            fs::read_to_string(path).with_context(|| fomat!("failed to read "(path)))?;
+
+        let new = rewrite(&contents, from, to);
+        if new != contents {
+            fs::write(path, new).with_context(|| fomat!("failed to write "(path)))?;
+            rewritten = true;
+        }
+    }
+
+    Ok(rewritten)
+}
+
+/// Replaces the URI of the mirror in source entries, but not in comments or other fields.
+fn rewrite(sources: &str, from: &str, to: &str) -> String {
+    let mut rewritten = String::with_capacity(sources.len());
+
+    for line in sources.lines() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("deb") || trimmed.starts_with("URIs:") {
+            rewritten.push_str(&line.replace(from, to));
+        } else {
+            rewritten.push_str(line);
+        }
+
+        rewritten.push('\n');
+    }
+
+    rewritten
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::release::repos;
+
+    const MIRROR: &str = "http://mirror.example.org/ubuntu/";
+
+    #[test]
+    fn rewrite_sources() {
+        let sources = repos::new_system_sources("hirsute");
+        let rewritten = rewrite(&sources, DEFAULT_MIRROR, MIRROR);
+
+        assert!(rewritten.contains(&["URIs: ", MIRROR].concat()));
+        assert!(rewritten.contains(&["X-Repolib-Default-Mirror: ", DEFAULT_MIRROR].concat()));
+        assert_eq!(rewrite(&rewritten, MIRROR, DEFAULT_MIRROR), sources);
+
+        let sources = repos::default_sources("focal");
+        let rewritten = rewrite(&sources, DEFAULT_MIRROR, MIRROR);
+        assert!(!rewritten.contains(DEFAULT_MIRROR));
+        assert_eq!(rewrite(&rewritten, MIRROR, DEFAULT_MIRROR), sources);
+    }
+
+    #[test]
+    fn mirrors_list() {
+        let list = "http://mirror.example.org/ubuntu/\nhttp://us.archive.ubuntu.com/ubuntu/\n\n";
+        assert_eq!(parse_mirrors(list).collect::<Vec<_>>(), vec![MIRROR]);
+    }
+}
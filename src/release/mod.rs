@@ -1,38 +1,74 @@
 pub mod check;
+pub mod dkms;
 pub mod eol;
+pub mod flatpak;
+pub mod foreign;
+pub mod health;
+pub mod holds;
+pub mod kernelstub;
+pub mod mirrors;
+pub mod notes;
+pub mod paths;
+pub mod ppa;
+pub mod prefetch;
+pub mod preview;
+pub mod reclaim;
+pub mod report;
 pub mod repos;
+pub mod resume;
+pub mod rollout;
+pub mod sources;
 pub mod systemd;
 
+mod cache;
 mod errors;
 mod recovery;
 mod snapd;
 
-use self::systemd::LoaderEntry;
+use self::{
+    resume::{Phase, UpgradeState},
+    systemd::LoaderEntry,
+};
 
 pub use self::{
     check::{BuildStatus, ReleaseStatus},
     errors::{RelResult, ReleaseError},
 };
+pub use pop_upgrade_core::release::{
+    FetchProgress, RefreshOp, UpgradeEvent, UpgradeFlags, UpgradeMethod,
+};
 use crate::{
+    apt_lock,
+    checksum::{Algorithm, Hasher},
+    config::ConffilePolicy,
     daemon::DaemonRuntime,
+    fetch::apt::PackageUri,
+    history,
     repair::{self, RepairError},
+    telemetry::{self, Report},
 };
 
 use anyhow::Context;
-use apt_cmd::{
-    lock::apt_lock_wait, request::Request as AptRequest, AptGet, AptMark, AptUpgradeEvent, Dpkg,
-    DpkgQuery,
-};
+use apt_cmd::{AptGet, AptMark, AptUpgradeEvent, Dpkg, DpkgQuery};
 
-use futures::prelude::*;
+use async_io::Timer;
+use futures::{
+    future::{self, Either},
+    prelude::*,
+    stream,
+};
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     convert::TryFrom,
     fs::{self, File},
     os::unix::fs::symlink,
-    path::Path,
-    sync::Arc,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
 };
 use systemd_boot_conf::SystemdBootConf;
 
@@ -74,14 +110,6 @@ pub fn upgrade_in_progress() -> bool {
     Path::new(STARTUP_UPGRADE_FILE).exists() || Path::new(RELEASE_FETCH_FILE).exists()
 }
 
-#[repr(u8)]
-#[derive(Copy, Clone, Debug)]
-pub enum RefreshOp {
-    Status = 0,
-    Enable = 1,
-    Disable = 2,
-}
-
 /// Configure the system to refresh the OS in the recovery partition.
 pub fn refresh_os(op: RefreshOp) -> Result<bool, ReleaseError> {
     recovery::upgrade_prereq()?;
@@ -102,6 +130,8 @@ pub fn refresh_os(op: RefreshOp) -> Result<bool, ReleaseError> {
         RefreshOp::Enable => {
             info!("Enabling refresh OS");
 
+            validate_encryption()?;
+
             recovery::mode_set("refresh", conf.default_boot())
                 .map_err(|why| ReleaseError::RecoveryConf(why.into()))?;
 
@@ -118,157 +148,102 @@ pub fn refresh_os(op: RefreshOp) -> Result<bool, ReleaseError> {
     }
 }
 
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
-pub enum UpgradeMethod {
-    Offline = 1,
-}
-
-impl From<UpgradeMethod> for &'static str {
-    fn from(action: UpgradeMethod) -> Self {
-        match action {
-            UpgradeMethod::Offline => "offline upgrade",
-        }
-    }
-}
-
-#[repr(u8)]
-#[derive(Clone, Copy, Debug, FromPrimitive, PartialEq)]
-pub enum UpgradeEvent {
-    UpdatingPackageLists = 1,
-    FetchingPackages = 2,
-    UpgradingPackages = 3,
-    InstallingPackages = 4,
-    UpdatingSourceLists = 5,
-    FetchingPackagesForNewRelease = 6,
-    AttemptingLiveUpgrade = 7,
-    AttemptingSystemdUnit = 8,
-    AttemptingRecovery = 9,
-    Success = 10,
-    SuccessLive = 11,
-    Failure = 12,
-    AptFilesLocked = 13,
-    RemovingConflicts = 14,
-    Simulating = 15,
-}
-
-impl From<UpgradeEvent> for &'static str {
-    fn from(action: UpgradeEvent) -> Self {
-        match action {
-            UpgradeEvent::AptFilesLocked => "waiting on a process holding the apt lock files",
-            UpgradeEvent::AttemptingLiveUpgrade => "attempting live upgrade to the new release",
-            UpgradeEvent::AttemptingSystemdUnit => {
-                "setting up the system to perform an offline upgrade on the next boot"
-            }
-            UpgradeEvent::AttemptingRecovery => {
-                "setting up the recovery partition to install the new release"
-            }
-            UpgradeEvent::Failure => "an error occurred while setting up the release upgrade",
-            UpgradeEvent::FetchingPackages => "fetching updated packages for the current release",
-            UpgradeEvent::FetchingPackagesForNewRelease => "fetching packages for the new release",
-            UpgradeEvent::InstallingPackages => {
-                "ensuring that system-critical packages are installed"
-            }
-            UpgradeEvent::RemovingConflicts => "removing deprecated and/or conflicting packages",
-            UpgradeEvent::Success => "new release is ready to install",
-            UpgradeEvent::SuccessLive => "new release was successfully installed",
-            UpgradeEvent::UpdatingPackageLists => "updating package lists",
-            UpgradeEvent::UpdatingSourceLists => "updating the source lists",
-            UpgradeEvent::UpgradingPackages => "upgrading packages for the current release",
-            UpgradeEvent::Simulating => "simulating upgrade",
-        }
-    }
-}
-
 impl DaemonRuntime {
-    /// Get a list of APT URIs to fetch for this operation, and then fetch them.
+    /// Fetch the packages of this operation, each in an unprivileged helper process.
     pub async fn apt_fetch<'a>(
         self: &'a mut Self,
-        uris: HashSet<AptRequest>,
+        uris: HashSet<PackageUri>,
         func: Arc<dyn Fn(FetchEvent) + Send + Sync>,
     ) -> RelResult<()> {
         (*func)(FetchEvent::Init(uris.len()));
 
-        apt_lock_wait().await;
+        self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
         let _lock_files = hold_apt_locks()?;
 
-        const ARCHIVES: &str = "/var/cache/apt/archives/";
-        const PARTIAL: &str = "/var/cache/apt/archives/partial/";
-
         const CONCURRENT_FETCHES: usize = 4;
-        const DELAY_BETWEEN: u64 = 100;
-        const RETRIES: u32 = 3;
+        const PROGRESS_INTERVAL: Duration = Duration::from_millis(500);
 
-        let client = isahc::HttpClient::new().expect("failed to create HTTP Client");
+        let total = uris.iter().map(|uri| uri.size).sum::<u64>();
 
-        let (fetch_tx, fetch_rx) = flume::bounded(CONCURRENT_FETCHES);
+        // The sizes of the packages being fetched, and the bytes of packages already fetched.
+        let fetching = &Mutex::new(HashMap::<String, u64>::new());
+        let fetched = &AtomicU64::new(0);
 
-        use apt_cmd::fetch::{EventKind, PackageFetcher};
-
-        // The system which fetches packages we send requests to
-        let mut events = PackageFetcher::new(client)
-            .concurrent(CONCURRENT_FETCHES)
-            .delay_between(DELAY_BETWEEN)
-            .retries(RETRIES)
-            .fetch(fetch_rx.into_stream(), Arc::from(Path::new(PARTIAL)));
-
-        // The system which sends package-fetching requests
-        let sender = async move {
-            if !Path::new(PARTIAL).exists() {
-                async_fs::create_dir_all(PARTIAL)
-                    .await
-                    .context("failed to create partial debian directory")?;
-            }
-
-            let packages = AptGet::new()
-                .noninteractive()
-                .fetch_uris(&["full-upgrade"])
+        if !Path::new(PARTIAL).exists() {
+            async_fs::create_dir_all(PARTIAL)
                 .await
-                .context("failed to spawn apt-get command")?
-                .context("failed to fetch package URIs from apt-get")?;
-
-            for package in packages {
-                info!("sending package");
-                let _ = fetch_tx.send_async(Arc::new(package)).await;
-                info!("sending package");
-            }
-
-            Ok::<(), anyhow::Error>(())
-        };
-
-        // The system that handles events received from the package-fetcher
-        let receiver = async move {
-            info!("receiving packages");
-            while let Some(event) = events.next().await {
-                debug!("Package Fetch Event: {:#?}", event);
+                .context("failed to create partial debian directory")
+                .map_err(ReleaseError::PackageFetch)?;
+        }
 
-                match event.kind {
-                    EventKind::Fetching => {
-                        func(FetchEvent::Fetching((*event.package).clone()));
+        let fetch = {
+            let func = func.clone();
+            stream::iter(uris.into_iter().map(Ok)).try_for_each_concurrent(
+                CONCURRENT_FETCHES,
+                move |package| {
+                    let func = func.clone();
+                    async move {
+                        fetching.lock().unwrap().insert(package.name.clone(), package.size);
+                        func(FetchEvent::Fetching(package.name.clone()));
+
+                        fetch_package(&package).await?;
+
+                        fetching.lock().unwrap().remove(&package.name);
+                        let total_fetched =
+                            fetched.fetch_add(package.size, Ordering::SeqCst) + package.size;
+
+                        func(FetchEvent::Progress(FetchProgress {
+                            package: package.name.clone(),
+                            fetched: package.size,
+                            size: package.size,
+                            total_fetched,
+                            total,
+                        }));
+
+                        func(FetchEvent::Fetched(package.name));
+
+                        Ok::<(), anyhow::Error>(())
                     }
+                },
+            )
+        };
 
-                    EventKind::Validated(src) => {
-                        let dst = Path::new(ARCHIVES).join(&event.package.name);
-
-                        async_fs::rename(&src, &dst)
-                            .await
-                            .context("failed to rename fetched debian package")?;
-
-                        func(FetchEvent::Fetched((*event.package).clone()));
-                    }
+        // Periodically reports the progress of the packages which are being fetched, until the
+        // fetching is cancelled.
+        let runtime = &*self;
+        let progress = async move {
+            loop {
+                Timer::after(PROGRESS_INTERVAL).await;
 
-                    EventKind::Error(why) => {
-                        return Err(why).context("package fetching failed");
-                    }
+                if runtime.cancelled() {
+                    return;
+                }
 
-                    _ => (),
+                let fetched = fetched.load(Ordering::SeqCst);
+                let fetching = fetching.lock().unwrap().clone();
+                for progress in fetch_progress(&fetching, fetched, total) {
+                    func(FetchEvent::Progress(progress));
                 }
             }
+        };
 
-            Ok::<(), anyhow::Error>(())
+        let result = {
+            futures::pin_mut!(fetch, progress);
+
+            match future::select(fetch, progress).await {
+                Either::Left((result, _)) => Some(result),
+                Either::Right(_) => None,
+            }
         };
 
-        futures::try_join!(sender, receiver).map(|_| ()).map_err(ReleaseError::PackageFetch)
+        match result {
+            Some(result) => result.map_err(ReleaseError::PackageFetch),
+            None => {
+                info!("package fetching was cancelled");
+                remove_partial_packages();
+                Err(ReleaseError::Cancelled)
+            }
+        }
     }
 
     /// Check if release files can be upgraded, and then overwrite them with the new release.
@@ -290,16 +265,22 @@ impl DaemonRuntime {
         fs::write(RELEASE_FETCH_FILE, &format!("{} {}", current, new))
             .context("failed to create release fetch file")?;
 
+        let runtime = &*self;
         let update_sources = async move {
             (logger)(UpgradeEvent::AptFilesLocked);
 
-            apt_cmd::lock::apt_lock_wait().await;
+            runtime.apt_lock_wait().await.context("failed to wait for the package manager")?;
 
             (logger)(UpgradeEvent::UpdatingPackageLists);
 
             repos::create_new_sources_list(new)?;
 
-            apt_lock_wait().await;
+            if let Err(why) = mirrors::select_fastest(new) {
+                let why = crate::misc::format_error(why.as_ref());
+                warn!("failed to select the fastest mirror: {}", why);
+            }
+
+            runtime.apt_lock_wait().await.context("failed to wait for the package manager")?;
             AptGet::new().noninteractive().update().await.context("failed to update source lists")
         };
 
@@ -316,15 +297,20 @@ impl DaemonRuntime {
         Ok(())
     }
 
-    /// Upgrades packages for the current release.
-    pub async fn package_upgrade<C: Fn(AptUpgradeEvent)>(&mut self, callback: C) -> RelResult<()> {
+    /// Upgrades packages for the current release, resolving conffile conflicts by `conffiles`.
+    pub async fn package_upgrade<C: Fn(AptUpgradeEvent)>(
+        &mut self,
+        conffiles: ConffilePolicy,
+        callback: C,
+    ) -> RelResult<()> {
         let callback = &callback;
 
         let apt_upgrade = || async {
-            apt_lock_wait().await;
+            self.apt_lock_wait().await?;
             info!("upgrading packages");
-            let (mut child, mut upgrade_events) =
-                AptGet::new().noninteractive().allow_downgrades().force().stream_upgrade().await?;
+            let mut apt_get = AptGet::new().noninteractive().allow_downgrades().force();
+            apt_get.args(conffiles.apt_options());
+            let (mut child, mut upgrade_events) = apt_get.stream_upgrade().await?;
 
             while let Some(event) = upgrade_events.next().await {
                 callback(event)
@@ -333,18 +319,18 @@ impl DaemonRuntime {
             child.status().await
         };
 
-        apt_lock_wait().await;
+        self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
         info!("autoremoving packages");
         let _ =
             AptGet::new().noninteractive().allow_downgrades().force().autoremove().status().await;
 
         // If the first upgrade attempt fails, try to dpkg --configure -a and try again.
         if apt_upgrade().await.is_err() {
-            apt_lock_wait().await;
+            self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
             info!("dpkg --configure -a");
             let dpkg_configure = Dpkg::new().configure_all().status().await.is_err();
 
-            apt_lock_wait().await;
+            self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
             info!("checking for broken packages");
             AptGet::new()
                 .noninteractive()
@@ -356,7 +342,7 @@ impl DaemonRuntime {
                 .map_err(ReleaseError::FixBroken)?;
 
             if dpkg_configure {
-                apt_lock_wait().await;
+                self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
                 info!("dpkg --configure -a");
                 Dpkg::new().configure_all().status().await.map_err(ReleaseError::DpkgConfigure)?
             }
@@ -364,7 +350,7 @@ impl DaemonRuntime {
             apt_upgrade().await.map_err(ReleaseError::Upgrade)?;
         }
 
-        apt_lock_wait().await;
+        self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
         info!("autoremoving packages");
         let _ =
             AptGet::new().noninteractive().force().allow_downgrades().autoremove().status().await;
@@ -374,12 +360,21 @@ impl DaemonRuntime {
 
     /// Perform the release upgrade by updating release files, fetching packages required for the
     /// new release, and then setting the recovery partition as the default boot entry.
+    ///
+    /// When resuming an interrupted upgrade, `resume` is the last phase which it completed, and
+    /// phases up to that point are skipped.
+    ///
+    /// Conflicts with locally modified conffiles are resolved by `conffiles`, both while the
+    /// current release is upgraded, and while the new release is installed by the offline upgrade.
     #[allow(clippy::too_many_arguments)]
     pub async fn upgrade<'a>(
         &'a mut self,
         action: UpgradeMethod,
         from: &'a str,
         to: &'a str,
+        lift_holds: bool,
+        conffiles: ConffilePolicy,
+        resume: Option<Phase>,
         logger: &'a dyn Fn(UpgradeEvent),
         fetch: Arc<dyn Fn(FetchEvent) + Send + Sync>,
         upgrade: &'a dyn Fn(AptUpgradeEvent),
@@ -397,107 +392,176 @@ impl DaemonRuntime {
 
         let _ = AptMark::new().hold(&["pop-upgrade"]).await;
 
-        // Check the system and perform any repairs necessary for success.
-        (async move {
-            repair::crypttab::repair().map_err(RepairError::Crypttab)?;
-            repair::fstab::repair().map_err(RepairError::Fstab)?;
-            repair::packaging::repair().await.map_err(RepairError::Packaging)?;
+        let mut state = UpgradeState::new(action, from, to, lift_holds);
+        state.phase = resume;
 
-            Ok(())
-        })
-        .await
-        .map_err(ReleaseError::Repair)?;
+        match resume {
+            Some(phase) => {
+                info!("resuming release upgrade after {}", <&'static str>::from(phase));
 
-        let version = codename_from_version(from);
+                // A resumed upgrade began when it was first attempted.
+                if let Some(previous) = UpgradeState::load() {
+                    state.started = previous.started;
+                }
+            }
+            None => UpgradeState::remove(),
+        }
+
+        info!("resolving conffile conflicts with the {:?} policy", conffiles);
+        conffiles.record().map_err(ReleaseError::ConffilePolicy)?;
+
+        // Check the system and perform any repairs necessary for success.
+        if !state.completed(Phase::Prepared) {
+            (async move {
+                repair::crypttab::repair().map_err(RepairError::Crypttab)?;
+                repair::fstab::repair().map_err(RepairError::Fstab)?;
+                repair::packaging::repair().await.map_err(RepairError::Packaging)?;
+
+                Ok(())
+            })
+            .await
+            .map_err(ReleaseError::Repair)?;
+        }
 
-        info!("creating backup of source lists");
-        repos::backup(version).map_err(ReleaseError::BackupPPAs)?;
+        validate_encryption()?;
 
-        info!("disabling third party sources");
-        repos::disable_third_parties(version).map_err(ReleaseError::DisablePPAs)?;
+        // Held packages are not upgraded, so they must be lifted before upgrading.
+        let held = holds::held().await?;
+        if !held.is_empty() {
+            if !lift_holds {
+                return Err(ReleaseError::Held(held));
+            }
 
-        if repos::is_eol(from_codename) && repos::is_old_release(from_codename) {
-            info!("switching to old-releases repositories");
-            repos::replace_with_old_releases().map_err(ReleaseError::OldReleaseSwitch)?;
+            holds::lift(&held).await?;
         }
 
-        let conflicting = (async {
-            let (mut child, package_stream) =
-                DpkgQuery::new().show_installed(REMOVE_PACKAGES).await?;
+        // Holds are restored if the upgrade fails, or is cancelled, once they were lifted.
+        let result = async {
+            // Take a snapshot of the system that the upgrade may be rolled back to.
+            if !state.completed(Phase::Prepared) {
+                match crate::snapshot::create(to) {
+                    Ok(Some(snapshot)) => info!("created pre-upgrade snapshot {}", snapshot.name),
+                    Ok(None) => info!("snapshots are not supported on this system"),
+                    Err(ref why) => warn!(
+                        "failed to take a pre-upgrade snapshot; continuing without one: {}",
+                        crate::misc::format_error(why)
+                    ),
+                }
+
+                state.advance(Phase::Prepared);
+            }
+
+            // Snaps are refreshed once the upgrade has been installed, rather than during it.
+            if !state.completed(Phase::SnapRefreshPaused) {
+                (*logger)(UpgradeEvent::PausingSnapRefresh);
+                snapd::pause_refresh().await;
+                state.advance(Phase::SnapRefreshPaused);
+            }
 
-            futures_util::pin_mut!(package_stream);
+            let version = codename_from_version(from);
 
-            let mut packages = Vec::new();
+            info!("creating backup of source lists");
+            repos::backup(version).map_err(ReleaseError::BackupPPAs)?;
 
-            while let Some(package) = package_stream.next().await {
-                packages.push(package);
+            info!("disabling third party sources");
+            repos::disable_third_parties(version, codename_from_version(to))
+                .map_err(ReleaseError::DisablePPAs)?;
+
+            if repos::is_eol(from_codename) && repos::is_old_release(from_codename) {
+                info!("switching to old-releases repositories");
+                repos::replace_with_old_releases().map_err(ReleaseError::OldReleaseSwitch)?;
             }
 
-            // NOTE: This is okay to fail since it just means a package is not found
-            let _ = child.status().await;
+            // Upgrade the current release, unless this was done before the upgrade was interrupted.
+            if !state.completed(Phase::CurrentUpgraded) {
+                let conflicting = (async {
+                    let (mut child, package_stream) =
+                        DpkgQuery::new().show_installed(REMOVE_PACKAGES).await?;
 
-            Ok::<_, std::io::Error>(packages)
-        })
-        .await
-        .map_err(ReleaseError::ConflictRemoval)?;
+                    futures_util::pin_mut!(package_stream);
 
-        if !conflicting.is_empty() {
-            apt_lock_wait().await;
-            (logger)(UpgradeEvent::RemovingConflicts);
-            AptGet::new()
-                .noninteractive()
-                .force()
-                .remove(conflicting)
+                    let mut packages = Vec::new();
+
+                    while let Some(package) = package_stream.next().await {
+                        packages.push(package);
+                    }
+
+                    // NOTE: This is okay to fail since it just means a package is not found
+                    let _ = child.status().await;
+
+                    Ok::<_, std::io::Error>(packages)
+                })
                 .await
                 .map_err(ReleaseError::ConflictRemoval)?;
-        }
 
-        // Update the package lists for the current release.
-        apt_lock_wait().await;
-        (logger)(UpgradeEvent::UpdatingPackageLists);
-        AptGet::new().noninteractive().update().await.map_err(ReleaseError::CurrentUpdate)?;
+                if !conflicting.is_empty() {
+                    self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
+                    (logger)(UpgradeEvent::RemovingConflicts);
+                    AptGet::new()
+                        .noninteractive()
+                        .force()
+                        .remove(conflicting)
+                        .await
+                        .map_err(ReleaseError::ConflictRemoval)?;
+                }
+
+                // Update the package lists for the current release.
+                self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
+                (logger)(UpgradeEvent::UpdatingPackageLists);
+                AptGet::new()
+                    .noninteractive()
+                    .update()
+                    .await
+                    .map_err(ReleaseError::CurrentUpdate)?;
 
-        // Fetch required packages for upgrading the current release.
-        (*logger)(UpgradeEvent::FetchingPackages);
+                // Fetch required packages for upgrading the current release.
+                (*logger)(UpgradeEvent::FetchingPackages);
 
-        let uris = crate::fetch::apt::fetch_uris(Some(CORE_PACKAGES))
-            .await
-            .map_err(ReleaseError::AptList)?;
+                let uris = crate::fetch::apt::fetch_uris(Some(CORE_PACKAGES))
+                    .await
+                    .map_err(ReleaseError::AptList)?;
 
-        self.apt_fetch(uris, fetch.clone()).await?;
+                self.apt_fetch(uris, fetch.clone()).await?;
 
-        // Upgrade the current release to the latest packages.
-        (*logger)(UpgradeEvent::UpgradingPackages);
-        self.package_upgrade(upgrade).await?;
+                // Upgrade the current release to the latest packages.
+                (*logger)(UpgradeEvent::UpgradingPackages);
+                self.package_upgrade(conffiles, upgrade).await?;
 
-        apt_lock_wait().await;
-        (logger)(UpgradeEvent::InstallingPackages);
-        AptGet::new()
-            .noninteractive()
-            .allow_downgrades()
-            .force()
-            .install(CORE_PACKAGES)
-            .await
-            .map_err(ReleaseError::InstallCore)?;
+                self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
+                (logger)(UpgradeEvent::InstallingPackages);
+                AptGet::new()
+                    .noninteractive()
+                    .allow_downgrades()
+                    .force()
+                    .install(CORE_PACKAGES)
+                    .await
+                    .map_err(ReleaseError::InstallCore)?;
 
-        // Apply any fixes necessary before the upgrade.
-        repair::pre_upgrade().map_err(ReleaseError::PreUpgrade)?;
+                state.advance(Phase::CurrentUpgraded);
+            }
 
-        let _ = AptMark::new().unhold(&["pop-upgrade"]).await;
+            // Apply any fixes necessary before the upgrade.
+            repair::pre_upgrade().map_err(ReleaseError::PreUpgrade)?;
 
-        // Update the source lists to the new release,
-        // then fetch the packages required for the upgrade.
-        let _ = self.fetch_new_release_packages(logger, fetch, from, to).await?;
+            let _ = AptMark::new().unhold(&["pop-upgrade"]).await;
 
-        if let Err(why) = crate::gnome_extensions::disable() {
-            error!(
-                "failed to disable gnome-shell extensions: {}",
-                crate::misc::format_error(why.as_ref())
-            )
+            // Update the source lists to the new release,
+            // then fetch the packages required for the upgrade.
+            let _ = self.fetch_new_release_packages(logger, fetch, &mut state).await?;
+
+            if let Err(why) = crate::gnome_extensions::disable() {
+                error!(
+                    "failed to disable gnome-shell extensions: {}",
+                    crate::misc::format_error(why.as_ref())
+                )
+            }
+
+            (*logger)(UpgradeEvent::Success);
+            Ok::<_, ReleaseError>(())
         }
+        .await;
 
-        (*logger)(UpgradeEvent::Success);
-        Ok(())
+        holds::restore_on_failure(result).await
     }
 
     /// Search for any active processes which are incompatible with the upgrade daemon,
@@ -524,7 +588,7 @@ impl DaemonRuntime {
                         }
 
                         if exe == APPCENTER {
-                            eprintln!("killing {}", APPCENTER);
+                            info!("killing {}", APPCENTER);
                             unsafe {
                                 let _ = libc::kill(proc.pid(), libc::SIGKILL);
                             }
@@ -555,23 +619,27 @@ impl DaemonRuntime {
         &'b mut self,
         logger: &'b dyn Fn(UpgradeEvent),
         fetch: Arc<dyn Fn(FetchEvent) + Send + Sync>,
-        current: &'b str,
-        next: &'b str,
+        state: &'b mut UpgradeState,
     ) -> RelResult<()> {
         (*logger)(UpgradeEvent::UpdatingSourceLists);
 
+        let current = state.from.clone();
+        let to = state.to.clone();
+
         // Updates the source lists, with a handle for reverting the change.
-        self.release_upgrade(logger, &current, &next).await.map_err(ReleaseError::Check)?;
+        self.release_upgrade(logger, &current, &state.to).await.map_err(ReleaseError::Check)?;
+        state.advance(Phase::SourcesRewritten);
 
         // Use a closure to capture any early returns due to an error.
         let updated_list_ops = || async {
             info!("updated the package lists for the new release");
-            apt_lock_wait().await;
+            self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
             (logger)(UpgradeEvent::UpdatingPackageLists);
             AptGet::new().noninteractive().update().await.map_err(ReleaseError::ReleaseUpdate)?;
 
             snapd::hold_transitional_packages().await?;
 
+            prefetch::adopt(&to);
             self.attempt_fetch(logger, fetch).await?;
 
             info!("packages fetched successfully");
@@ -583,9 +651,12 @@ impl DaemonRuntime {
 
         // On any error, roll back the source lists.
         match updated_list_ops().await {
-            Ok(_) => Ok(()),
+            Ok(_) => {
+                state.advance(Phase::PackagesFetched);
+                Ok(())
+            }
             Err(why) => {
-                rollback(codename_from_version(current), &why);
+                rollback(codename_from_version(&current), &why);
 
                 Err(why)
             }
@@ -593,7 +664,7 @@ impl DaemonRuntime {
     }
 
     async fn simulate_upgrade(&self) -> RelResult<()> {
-        apt_lock_wait().await;
+        self.apt_lock_wait().await.map_err(ReleaseError::Lock)?;
         AptGet::new()
             .noninteractive()
             .allow_downgrades()
@@ -608,8 +679,14 @@ impl DaemonRuntime {
 /// Currently not a supported path
 pub fn upgrade_finalize(action: UpgradeMethod, from: &str, to: &str) -> RelResult<()> {
     match action {
-        UpgradeMethod::Offline => systemd::upgrade_set(from, to),
+        UpgradeMethod::Offline => systemd::upgrade_set(from, to)?,
     }
+
+    if let Some(mut state) = UpgradeState::load() {
+        state.advance(Phase::Staged);
+    }
+
+    Ok(())
 }
 
 fn rollback(release: &str, why: &(dyn std::error::Error + 'static)) {
@@ -624,13 +701,139 @@ fn rollback(release: &str, why: &(dyn std::error::Error + 'static)) {
 }
 
 pub enum FetchEvent {
-    Fetching(AptRequest),
-    Fetched(AptRequest),
+    Fetching(String),
+    Fetched(String),
     Init(usize),
+    Progress(FetchProgress),
+}
+
+/// Where packages are moved to once they have been fetched and validated.
+const ARCHIVES: &str = "/var/cache/apt/archives/";
+
+/// Where packages are written to while they are being fetched.
+const PARTIAL: &str = "/var/cache/apt/archives/partial/";
+
+/// Attempts which are made to fetch a package before the fetch fails.
+const FETCH_ATTEMPTS: u32 = 3;
+
+/// Fetches a package through the unprivileged download helper, validating it against its
+/// checksum as it is written, before moving it into apt's archives.
+async fn fetch_package(package: &PackageUri) -> anyhow::Result<()> {
+    let (algorithm, checksum) = Algorithm::detect(&package.checksum)
+        .with_context(|| fomat!("unsupported checksum for " (package.name)))?;
+
+    let partial = Path::new(PARTIAL).join(&package.name);
+
+    let mut attempt = 1;
+    loop {
+        let result = async {
+            let mut download = crate::sandbox::download(&package.uri).await?;
+            let mut file = async_fs::File::create(&partial).await?;
+            let mut hasher = Hasher::new(algorithm);
+            let mut buf = vec![0u8; 64 * 1024];
+
+            loop {
+                let read = download.body.read(&mut buf).await?;
+                if read == 0 {
+                    break;
+                }
+
+                file.write_all(&buf[..read]).await?;
+                hasher.update(&buf[..read]);
+            }
+
+            download.finish().await?;
+            file.flush().await?;
+            hasher.validate(checksum)?;
+
+            Ok::<(), anyhow::Error>(())
+        }
+        .await;
+
+        match result {
+            Ok(()) => break,
+            Err(why) if attempt < FETCH_ATTEMPTS => {
+                warn!(
+                    "attempt {} to fetch {} failed: {}",
+                    attempt,
+                    package.name,
+                    crate::misc::format_error(why.as_ref())
+                );
+                attempt += 1;
+            }
+            Err(why) => {
+                let _ = async_fs::remove_file(&partial).await;
+                return Err(why).with_context(|| fomat!("failed to fetch " (package.uri)));
+            }
+        }
+    }
+
+    async_fs::rename(&partial, Path::new(ARCHIVES).join(&package.name))
+        .await
+        .context("failed to rename fetched debian package")
+}
+
+/// Removes the packages which were left partially fetched by a cancelled fetch.
+fn remove_partial_packages() {
+    let entries = match fs::read_dir(PARTIAL) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_file() {
+            if let Err(why) = fs::remove_file(&path) {
+                warn!("failed to remove partial package at {}: {}", path.display(), why);
+            }
+        }
+    }
+}
+
+/// The progress of each package being fetched, as measured by the size of its partial file.
+fn fetch_progress(
+    fetching: &HashMap<String, u64>,
+    fetched: u64,
+    total: u64,
+) -> Vec<FetchProgress> {
+    let partial = |name: &str| {
+        fs::metadata(Path::new(PARTIAL).join(name)).map_or(0, |metadata| metadata.len())
+    };
+
+    let packages = fetching
+        .iter()
+        .map(|(name, &size)| (name, partial(name).min(size), size))
+        .collect::<Vec<_>>();
+
+    let total_fetched = fetched + packages.iter().map(|&(_, fetched, _)| fetched).sum::<u64>();
+
+    packages
+        .into_iter()
+        .map(|(name, fetched, size)| FetchProgress {
+            package: name.clone(),
+            fetched,
+            size,
+            total_fetched,
+            total,
+        })
+        .collect()
+}
+
+/// What remains to be done once an upgrade has been installed, which `cleanup` leaves to the
+/// daemon's worker, as refreshing snaps, reclaiming space, and updating Flatpaks take some time.
+#[derive(Debug)]
+pub struct AfterUpgrade {
+    /// The upgrade which was installed, and when it began, which is reported on.
+    installed:        Option<(health::UpgradeRecord, SystemTime)>,
+    disabled_sources: Vec<PathBuf>,
+    warnings:         Vec<String>,
 }
 
 /// Check if certain files exist at the time of starting this daemon.
-pub async fn cleanup() {
+///
+/// Returns what remains to be done if an upgrade was installed, which is passed to
+/// `after_upgrade`.
+pub async fn cleanup() -> Option<AfterUpgrade> {
     let _ = fs::remove_file(crate::RESTART_SCHEDULED);
 
     let _ = AptMark::new().unhold(&["pop-upgrade"]).await;
@@ -654,13 +857,136 @@ pub async fn cleanup() {
             }
 
             let _ = fs::remove_file(file);
-            apt_lock_wait().await;
-            let _ = AptGet::new().noninteractive().update().await;
+            if apt_lock::wait(&|_| ()).await.is_ok() {
+                let _ = AptGet::new().noninteractive().update().await;
+            }
             break;
         }
     }
 
+    // Sources which were rewritten to a faster mirror for the upgrade are reverted.
+    match mirrors::restore() {
+        Ok(true) => {
+            if apt_lock::wait(&|_| ()).await.is_ok() {
+                let _ = AptGet::new().noninteractive().update().await;
+            }
+        }
+        Ok(false) => (),
+        Err(why) => {
+            error!("failed to revert the mirror: {}", crate::misc::format_error(why.as_ref()))
+        }
+    }
+
+    let current = Version::detect()
+        .ok()
+        .and_then(|version| Codename::try_from(version).ok())
+        .map(<&'static str>::from);
+
+    // An interrupted upgrade may be resumed, once the sources of the current release have been
+    // restored above.
+    let mut began = None;
+    if let Some(mut state) = UpgradeState::load() {
+        if current == Some(codename_from_version(&state.to)) {
+            if state.started != 0 {
+                began = Some(UNIX_EPOCH + Duration::from_secs(state.started));
+            }
+
+            UpgradeState::remove();
+        } else {
+            state.rewind();
+        }
+    }
+
+    // Once an upgrade has been installed, roll it back if it left the system unusable. What the
+    // upgrade staged is cleaned up below either way, so that the rolled back system does not
+    // keep its holds, nor boot into the upgrade again.
+    let mut upgraded = false;
+    let mut rolled_back = false;
+    let mut installed = None;
+    if let Some(record) = health::UpgradeRecord::load() {
+        let staged = health::UpgradeRecord::staged();
+        health::UpgradeRecord::remove();
+
+        let result = if current == Some(record.to.as_str()) {
+            let issues = health::check();
+            if !issues.is_empty() {
+                for issue in &issues {
+                    error!("upgrade to {} failed its health check: {}", record.to, issue);
+                }
+
+                if let Err(why) = health::rollback(&record) {
+                    let why = crate::misc::format_error(why.as_ref());
+                    error!("failed to roll back the upgrade: {}", why);
+                }
+
+                rolled_back = true;
+                Err(health::InstallError::RolledBack)
+            } else {
+                upgraded = true;
+                Ok(())
+            }
+        } else {
+            error!("upgrade to {} was not installed", record.to);
+            Err(health::InstallError::NotInstalled)
+        };
+
+        // The outcome of the upgrade is only known now that it has been installed, or not.
+        telemetry::report(Report::new(&record.from, &record.to, None, result.as_ref()));
+
+        let started = staged
+            .and_then(|staged| staged.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |since| since.as_secs());
+
+        let operation = history::Operation::ReleaseInstall;
+        history::record(&history::Entry {
+            from: Some(record.from.clone()),
+            to:   Some(record.to.clone()),
+            ..history::Entry::new(operation, started, None, result.as_ref())
+        });
+
+        if result.is_ok() {
+            // Packages are removed before the upgrade is staged, so the report covers everything
+            // since it began, if that was recorded.
+            installed = began.or(staged).map(|since| (record, since));
+        }
+    }
+
+    // Problems which are reported once the upgrade has been installed.
+    let mut warnings = Vec::new();
+
+    // Boot entries which were regenerated by the upgrade may have dropped custom parameters. The
+    // entries of a rolled back system are left as the rollback set them.
+    if !rolled_back {
+        if let Err(why) = kernelstub::reapply() {
+            let why = crate::misc::format_error(why.as_ref());
+            let why = fomat!("failed to reapply kernel parameters: " (why));
+            error!("{}", why);
+            warnings.push(why);
+        }
+    }
+
+    // Once the upgrade has completed, sources which were disabled for it may be re-enabled.
+    let mut disabled_sources = Vec::new();
+    match ppa::Manifest::load() {
+        Ok(Some(manifest)) => {
+            if current == Some(manifest.to.as_str()) {
+                match manifest.reenable() {
+                    Ok(outcome) => disabled_sources = outcome.disabled,
+                    Err(why) => {
+                        let why = crate::misc::format_error(&why);
+                        let why = fomat!("failed to re-enable sources: " (why));
+                        error!("{}", why);
+                        warnings.push(why);
+                    }
+                }
+            }
+        }
+        Ok(None) => (),
+        Err(why) => error!("{}", crate::misc::format_error(&why)),
+    }
+
     let _ = fs::remove_file(SYSTEM_UPDATE);
+    let _ = crate::loader::remove(crate::loader::UPGRADE_ENTRY);
 
     if Path::new(crate::TRANSITIONAL_SNAPS).exists() {
         if let Ok(packages) = fs::read_to_string(crate::TRANSITIONAL_SNAPS) {
@@ -671,6 +997,69 @@ pub async fn cleanup() {
 
         let _ = fs::remove_file(crate::TRANSITIONAL_SNAPS);
     }
+
+    holds::restore().await;
+
+    if upgraded {
+        return Some(AfterUpgrade { installed, disabled_sources, warnings });
+    }
+
+    // Refreshes remain paused while an interrupted upgrade may yet be resumed.
+    if UpgradeState::load().is_none() {
+        snapd::resume_refresh(false).await;
+    }
+
+    None
+}
+
+/// Refreshes snaps, reclaims space, and updates Flatpaks once an upgrade has been installed,
+/// and then reports what the upgrade changed. The `progress` of each of them is reported as a
+/// percentage, where it is known.
+pub async fn after_upgrade(
+    after: AfterUpgrade,
+    logger: &dyn Fn(UpgradeEvent),
+    progress: &dyn Fn(u8),
+) {
+    let AfterUpgrade { installed, disabled_sources, warnings } = after;
+
+    // Refreshes remain paused while an interrupted upgrade may yet be resumed.
+    if UpgradeState::load().is_none() {
+        if Path::new(crate::SNAP_REFRESH_HOLD).exists() {
+            (*logger)(UpgradeEvent::RefreshingSnaps);
+        }
+
+        snapd::resume_refresh(true).await;
+    }
+
+    reclaim::after_upgrade(logger, progress).await;
+    flatpak::after_upgrade(logger, progress).await;
+
+    // Once everything which follows the upgrade is done, report what it changed.
+    if let Some((record, since)) = installed {
+        let report =
+            report::Report::collect(&record.from, &record.to, since, disabled_sources, warnings);
+
+        match report.store() {
+            Ok(path) => {
+                info!("{}", report.summary());
+                info!("the report of the upgrade was written to {}", path.display());
+            }
+            Err(why) => error!("failed to write the report of the upgrade: {}", why),
+        }
+    }
+}
+
+/// Aborts if the root volume would be unable to be unlocked afterwards. Failures to inspect the
+/// system are only logged, as they do not indicate that the volume is misconfigured.
+fn validate_encryption() -> RelResult<()> {
+    match crate::luks::validate() {
+        Ok(Some(volume)) => info!("root volume is encrypted, and unlocked by {}", volume.name),
+        Ok(None) => (),
+        Err(why) if why.is_inconsistent() => return Err(ReleaseError::Encryption(why)),
+        Err(why) => warn!("{}", crate::misc::format_error(&why)),
+    }
+
+    Ok(())
 }
 
 fn hold_apt_locks() -> RelResult<(File, File)> {
@@ -679,7 +1068,7 @@ fn hold_apt_locks() -> RelResult<(File, File)> {
         .map_err(ReleaseError::Lock)
 }
 
-fn codename_from_version(version: &str) -> &str {
+pub fn codename_from_version(version: &str) -> &str {
     version
         .parse::<Version>()
         .ok()
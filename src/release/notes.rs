@@ -0,0 +1,53 @@
+use crate::{
+    changelogs::CHANGELOGS,
+    release_api::{self, ApiError, Release},
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum NotesError {
+    #[error("failed to fetch the release notes of {}", _0)]
+    Fetch(String, #[source] ApiError),
+
+    #[error("there are no release notes for {}", _0)]
+    NotFound(String),
+}
+
+/// Fetches the release notes of a release, as markdown.
+///
+/// If the release API does not link to release notes for the release, the changelog which was
+/// bundled with pop-upgrade is used instead.
+pub fn fetch(version: &str, arch: &str) -> Result<String, NotesError> {
+    let url = match Release::get_release(version, arch) {
+        Ok(release) => release.release_notes,
+        Err(why) => {
+            warn!("failed to fetch the release manifest of {}: {}", version, why);
+            None
+        }
+    };
+
+    match url {
+        Some(url) => {
+            release_api::release_notes(&url).map_err(|why| NotesError::Fetch(version.into(), why))
+        }
+        None => bundled(version)
+            .map(String::from)
+            .ok_or_else(|| NotesError::NotFound(version.into())),
+    }
+}
+
+/// The changelog of a release which was bundled with pop-upgrade.
+fn bundled(version: &str) -> Option<&'static str> {
+    CHANGELOGS.iter().find(|(release, _)| *release == version).map(|(_, changelog)| *changelog)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled_changelogs() {
+        assert!(bundled("20.10").unwrap().contains("Pop Shell"));
+        assert!(bundled("17.10").is_none());
+    }
+}
@@ -0,0 +1,390 @@
+use crate::release_api::{self, ApiError};
+use chrono::NaiveDate;
+use once_cell::sync::Lazy;
+use serde::{Deserialize as _, Deserializer};
+use serde_derive::Deserialize;
+use std::{
+    io::{self, Write},
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+use thiserror::Error;
+
+/// The upgrade paths which were shipped with this version of pop-upgrade.
+const BUNDLED: &str = include_str!("upgrade-paths.toml");
+
+/// How long the table is used before it is fetched from the release API again.
+const TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The table which is in use, and when it was last fetched, or attempted to be.
+static LOADED: Lazy<Mutex<Option<(Instant, UpgradePaths)>>> = Lazy::new(Mutex::default);
+
+/// Whether the table is being fetched in the background.
+static REFRESHING: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("failed to fetch the upgrade paths")]
+    Table(#[source] ApiError),
+
+    #[error("failed to fetch the signature of the upgrade paths")]
+    Signature(#[source] ApiError),
+
+    #[error("failed to verify the signature of the upgrade paths")]
+    Verify(#[source] io::Error),
+
+    #[error("the upgrade paths are not signed by a key in {}", crate::SIGNING_KEYRING)]
+    Unsigned,
+
+    #[error("the upgrade paths are invalid")]
+    Parse(#[source] toml::de::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum PlanError {
+    #[error("release {} is not supported", _0)]
+    Unsupported(String),
+
+    #[error("the upgrade from {} to {} is not available", from, to)]
+    Unavailable { from: String, to: String },
+
+    #[error("there is no upgrade path from {} to {}", from, to)]
+    Unreachable { from: String, to: String },
+}
+
+/// Whether the upgrade to the next release will be offered, ordered from most to least offered.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Deserialize, Eq, FromPrimitive, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Availability {
+    Enabled = 1,
+    /// Only offered when development releases are enabled.
+    Development = 2,
+    Disabled = 3,
+}
+
+impl Default for Availability {
+    fn default() -> Self { Availability::Enabled }
+}
+
+/// A supported release, and the release that it may upgrade to.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UpgradePath {
+    pub version: String,
+    pub next:    String,
+    #[serde(default)]
+    pub lts:     bool,
+    #[serde(default)]
+    pub upgrade: Availability,
+    /// The date on which the release stops receiving updates.
+    #[serde(default, deserialize_with = "date")]
+    pub eol:     Option<NaiveDate>,
+}
+
+/// A release which the machine would pass through when upgrading.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stop<'a> {
+    pub version: &'a str,
+    pub lts:     bool,
+    /// Whether the upgrade to this release is offered, which is the least offered of the
+    /// upgrades that it skips over.
+    pub upgrade: Availability,
+}
+
+/// The table of supported releases.
+#[derive(Clone, Debug, Deserialize)]
+pub struct UpgradePaths {
+    #[serde(rename = "release")]
+    pub releases: Vec<UpgradePath>,
+}
+
+impl UpgradePaths {
+    /// The table published by the release API, which is fetched again once it is an hour old.
+    ///
+    /// If it cannot be fetched, or its signature cannot be verified, the table which was last
+    /// fetched is used, or else the bundled table.
+    pub fn load() -> Self {
+        if let Some((at, ref paths)) = *LOADED.lock().unwrap() {
+            if at.elapsed() < TTL {
+                return paths.clone();
+            }
+        }
+
+        let paths = match Self::fetch() {
+            Ok(paths) => paths,
+            Err(why) => {
+                let why = anyhow::Error::from(why);
+                match *LOADED.lock().unwrap() {
+                    Some((_, ref paths)) => {
+                        warn!("using the upgrade paths which were last fetched: {:#}", why);
+                        paths.clone()
+                    }
+                    None => {
+                        info!("using bundled upgrade paths: {:#}", why);
+                        Self::bundled()
+                    }
+                }
+            }
+        };
+
+        // A table which failed to be fetched is not fetched again until it is due.
+        *LOADED.lock().unwrap() = Some((Instant::now(), paths.clone()));
+        paths
+    }
+
+    /// As `load`, but never waits on the release API: once the table is due to be fetched again,
+    /// it is fetched in the background, and the table in use is returned meanwhile.
+    pub fn cached() -> Self {
+        let (paths, due) = match *LOADED.lock().unwrap() {
+            Some((at, ref paths)) => (paths.clone(), at.elapsed() >= TTL),
+            None => (Self::bundled(), true),
+        };
+
+        if due && !REFRESHING.swap(true, Ordering::SeqCst) {
+            std::thread::spawn(|| {
+                let _ = Self::load();
+                REFRESHING.store(false, Ordering::SeqCst);
+            });
+        }
+
+        paths
+    }
+
+    /// Fetches the table from the release API, once its signature is verified.
+    fn fetch() -> Result<Self, FetchError> {
+        let table = release_api::upgrade_paths().map_err(FetchError::Table)?;
+        let signature = release_api::upgrade_paths_signature().map_err(FetchError::Signature)?;
+        verify(&table, &signature)?;
+        table.parse().map_err(FetchError::Parse)
+    }
+
+    pub fn bundled() -> Self { BUNDLED.parse().expect("bundled upgrade paths are invalid") }
+
+    /// The upgrade path for the given release version, such as `20.04`.
+    pub fn get(&self, version: &str) -> Option<&UpgradePath> {
+        self.releases.iter().find(|path| path.version == version)
+    }
+
+    /// The upgrade path for a release by its major and minor version numbers.
+    pub fn find(&self, major: u8, minor: u8) -> Option<&UpgradePath> {
+        self.get(&format!("{}.{:02}", major, minor))
+    }
+
+    /// The first LTS release after the given release, following the upgrade paths.
+    pub fn next_lts(&self, version: &str) -> Option<&str> {
+        let mut path = self.get(version)?;
+
+        // A well-formed table never revisits a release, so it cannot take longer than this.
+        for _ in 0..self.releases.len() {
+            path = self.get(&path.next)?;
+            if path.lts {
+                return Some(&path.version);
+            }
+        }
+
+        None
+    }
+
+    /// The releases which upgrading from the given release would pass through, beginning with
+    /// the given release itself, and ending with the first upgrade which is not offered.
+    ///
+    /// If `lts_only` is set, interim releases are skipped by upgrading from LTS to LTS.
+    pub fn route(&self, from: &str, development: bool, lts_only: bool) -> Vec<Stop> {
+        let mut path = match self.get(from) {
+            Some(path) => path,
+            None => return Vec::new(),
+        };
+
+        let mut stops =
+            vec![Stop { version: &path.version, lts: path.lts, upgrade: Availability::Enabled }];
+        let mut upgrade = Availability::Enabled;
+
+        // A well-formed table never revisits a release, so a route cannot be longer than it.
+        for _ in 0..self.releases.len() {
+            upgrade = upgrade.max(path.upgrade);
+            let next = self.get(&path.next);
+            let lts = next.map_or(false, |next| next.lts);
+
+            if !lts_only || lts {
+                stops.push(Stop { version: &path.next, lts, upgrade });
+
+                let offered = match upgrade {
+                    Availability::Enabled => true,
+                    Availability::Development => development,
+                    Availability::Disabled => false,
+                };
+
+                if !offered {
+                    break;
+                }
+
+                upgrade = Availability::Enabled;
+            }
+
+            path = match next {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        stops
+    }
+
+    /// Plans the sequence of upgrades required to get from one release to another.
+    ///
+    /// Each hop in the returned plan upgrades from its `version` to its `next` release.
+    pub fn plan(
+        &self,
+        from: &str,
+        to: &str,
+        development: bool,
+    ) -> Result<Vec<&UpgradePath>, PlanError> {
+        if self.get(from).is_none() {
+            return Err(PlanError::Unsupported(from.to_owned()));
+        }
+
+        let mut hops = Vec::new();
+        let mut version = from;
+
+        while version != to {
+            // A well-formed table never revisits a release, so a plan cannot be longer than it.
+            let path = match self.get(version) {
+                Some(path) if hops.len() < self.releases.len() => path,
+                _ => {
+                    return Err(PlanError::Unreachable { from: from.to_owned(), to: to.to_owned() })
+                }
+            };
+
+            hops.push(path);
+            version = &path.next;
+        }
+
+        let unavailable = hops.iter().find(|hop| match hop.upgrade {
+            Availability::Enabled => false,
+            Availability::Development => !development,
+            Availability::Disabled => true,
+        });
+
+        if let Some(hop) = unavailable {
+            return Err(PlanError::Unavailable { from: hop.version.clone(), to: hop.next.clone() });
+        }
+
+        Ok(hops)
+    }
+}
+
+impl FromStr for UpgradePaths {
+    type Err = toml::de::Error;
+
+    fn from_str(table: &str) -> Result<Self, Self::Err> { toml::from_str(table) }
+}
+
+/// Verifies the detached signature of the table against the Pop!_OS keyring.
+fn verify(table: &str, signature: &str) -> Result<(), FetchError> {
+    let mut file = tempfile::NamedTempFile::new().map_err(FetchError::Verify)?;
+    file.write_all(signature.as_bytes()).map_err(FetchError::Verify)?;
+
+    let keyring = Path::new(crate::SIGNING_KEYRING);
+    let verified =
+        async_io::block_on(crate::external::gpgv_data(keyring, file.path(), table.as_bytes()))
+            .map_err(FetchError::Verify)?;
+
+    if verified {
+        Ok(())
+    } else {
+        Err(FetchError::Unsigned)
+    }
+}
+
+/// Dates in the table are written as `YYYY-MM-DD`.
+fn date<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<NaiveDate>, D::Error> {
+    let date = String::deserialize(deserializer)?;
+    NaiveDate::parse_from_str(&date, "%Y-%m-%d").map(Some).map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bundled() {
+        let paths = UpgradePaths::bundled();
+
+        let focal = paths.find(20, 4).unwrap();
+        assert_eq!(focal.next, "20.10");
+        assert!(focal.lts);
+        assert_eq!(focal.upgrade, Availability::Enabled);
+        assert_eq!(focal.eol, Some(NaiveDate::from_ymd(2025, 5, 29)));
+
+        assert_eq!(paths.get("20.10").unwrap().upgrade, Availability::Development);
+        assert!(paths.find(17, 10).is_none());
+    }
+
+    #[test]
+    fn eol() {
+        let paths = "[[release]]\nversion = \"20.04\"\nnext = \"20.10\"\neol = \"2025-04-31\"";
+        assert!(paths.parse::<UpgradePaths>().is_err());
+
+        let paths = "[[release]]\nversion = \"20.04\"\nnext = \"20.10\"";
+        assert_eq!(paths.parse::<UpgradePaths>().unwrap().releases[0].eol, None);
+    }
+
+    #[test]
+    fn next_lts() {
+        let paths = UpgradePaths::bundled();
+        assert_eq!(paths.next_lts("18.04"), Some("20.04"));
+        assert_eq!(paths.next_lts("19.10"), Some("20.04"));
+        assert_eq!(paths.next_lts("20.04"), None);
+    }
+
+    #[test]
+    fn route() {
+        use Availability::*;
+
+        let paths = UpgradePaths::bundled();
+        let route = |from, development, lts_only| {
+            paths
+                .route(from, development, lts_only)
+                .into_iter()
+                .map(|stop| (stop.version, stop.upgrade))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            route("18.04", false, false),
+            vec![("18.04", Enabled), ("20.04", Enabled), ("20.10", Enabled), ("21.04", Development)]
+        );
+        assert_eq!(
+            route("20.10", true, false),
+            vec![("20.10", Enabled), ("21.04", Development), ("21.10", Disabled)]
+        );
+        assert_eq!(route("19.10", false, true), vec![("19.10", Enabled), ("20.04", Enabled)]);
+        assert!(paths.route("17.10", true, false).is_empty());
+
+        let stops = paths.route("18.04", false, false);
+        assert!(stops[0].lts && stops[1].lts && !stops[2].lts);
+    }
+
+    #[test]
+    fn plan() {
+        let paths = UpgradePaths::bundled();
+        let versions = |from, to, development| {
+            paths
+                .plan(from, to, development)
+                .map(|hops| hops.iter().map(|hop| hop.next.as_str()).collect::<Vec<_>>())
+        };
+
+        assert_eq!(versions("18.04", "20.10", false).unwrap(), vec!["20.04", "20.10"]);
+        assert_eq!(versions("20.04", "20.04", false).unwrap(), Vec::<&str>::new());
+        assert_eq!(versions("18.04", "21.04", true).unwrap(), vec!["20.04", "20.10", "21.04"]);
+
+        assert!(matches!(versions("18.04", "21.04", false), Err(PlanError::Unavailable { .. })));
+        assert!(matches!(versions("20.04", "18.04", true), Err(PlanError::Unreachable { .. })));
+        assert!(matches!(versions("17.10", "20.04", true), Err(PlanError::Unsupported(_))));
+    }
+}
@@ -0,0 +1,325 @@
+//! Tracks the third party sources which were disabled for a release upgrade, so that they can be
+//! restored if the upgrade is interrupted, or re-enabled for the new release once it completes.
+
+use super::{
+    repos::PPA_DIR,
+    sources::{self, Format, SourcesFile, SyntaxError},
+};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+
+/// Location of the manifest of sources which were disabled for the current release upgrade.
+const MANIFEST: &str = "/var/lib/pop-upgrade/disabled-sources.json";
+
+#[derive(Debug, Error)]
+pub enum PpaError {
+    #[error("failed to read the manifest of disabled sources")]
+    Read(#[source] io::Error),
+
+    #[error("the manifest of disabled sources is malformed")]
+    Parse(#[source] serde_json::Error),
+
+    #[error("failed to serialize the manifest of disabled sources")]
+    Serialize(#[source] serde_json::Error),
+
+    #[error("failed to write the manifest of disabled sources")]
+    Write(#[source] io::Error),
+
+    #[error("failed to restore {:?}", _0)]
+    Restore(PathBuf, #[source] io::Error),
+
+    #[error("failed to read {:?}", _0)]
+    Source(PathBuf, #[source] io::Error),
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Manifest {
+    /// Codename of the release being upgraded from.
+    pub from:    String,
+    /// Codename of the release being upgraded to.
+    pub to:      String,
+    pub sources: Vec<DisabledSource>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct DisabledSource {
+    pub path:     PathBuf,
+    /// The contents of the file before it was disabled.
+    pub contents: String,
+    /// Codename of the release which the contents refer to, if it was left disabled by an earlier
+    /// upgrade, from a release before the one being upgraded from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub release:  Option<String>,
+}
+
+/// The outcome of re-enabling sources after an upgrade.
+#[derive(Debug, Default)]
+pub struct Reenabled {
+    pub enabled:  Vec<PathBuf>,
+    /// Sources which do not publish the new release yet, and thus remain disabled.
+    pub disabled: Vec<PathBuf>,
+}
+
+impl Manifest {
+    pub fn new(from: &str, to: &str) -> Self {
+        Self { from: from.into(), to: to.into(), sources: Vec::new() }
+    }
+
+    /// Loads the manifest of the last upgrade, if one was in progress.
+    pub fn load() -> Result<Option<Self>, PpaError> {
+        let data = match fs::read(MANIFEST) {
+            Ok(data) => data,
+            Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(why) => return Err(PpaError::Read(why)),
+        };
+
+        serde_json::from_slice(&data).map(Some).map_err(PpaError::Parse)
+    }
+
+    pub fn store(&self) -> Result<(), PpaError> {
+        let data = serde_json::to_vec(self).map_err(PpaError::Serialize)?;
+
+        if let Some(parent) = Path::new(MANIFEST).parent() {
+            fs::create_dir_all(parent).map_err(PpaError::Write)?;
+        }
+
+        fs::write(MANIFEST, data).map_err(PpaError::Write)
+    }
+
+    /// Carries over the sources of an earlier manifest, which were left disabled by an earlier
+    /// upgrade, or which an interrupted upgrade did not restore, so that their original contents
+    /// are kept.
+    pub fn carry_over(&mut self, earlier: Manifest) {
+        for mut source in earlier.sources {
+            let release = source.release.take().unwrap_or_else(|| earlier.from.clone());
+            source.release = if release == self.from { None } else { Some(release) };
+            self.sources.push(source);
+        }
+    }
+
+    /// Restores every disabled source to its state before the upgrade. Sources which were left
+    /// disabled by an earlier upgrade remain disabled, and are kept in the manifest.
+    pub fn restore(mut self) -> Result<(), PpaError> {
+        for source in &self.sources {
+            if source.release.is_none() {
+                info!("restoring disabled source at {}", source.path.display());
+                fs::write(&source.path, &source.contents)
+                    .map_err(|why| PpaError::Restore(source.path.clone(), why))?;
+            }
+        }
+
+        self.sources.retain(|source| source.release.is_some());
+        self.keep()
+    }
+
+    /// Re-enables each disabled source whose repositories publish suites for the new release,
+    /// with its suites rewritten to the new release.
+    ///
+    /// Sources which remain disabled are kept in the manifest, so that they are re-enabled once
+    /// their repositories publish the new release, or by the next upgrade.
+    pub fn reenable(mut self) -> Result<Reenabled, PpaError> {
+        let mut outcome = Reenabled::default();
+        let mut remaining = Vec::new();
+
+        for mut source in self.sources.drain(..) {
+            // The source may have been removed by the user since.
+            if !source.path.exists() {
+                continue;
+            }
+
+            let from = source.release.as_deref().unwrap_or(&self.from);
+            match reenabled(&source, from, &self.to, sources::published) {
+                Some(contents) => {
+                    info!("re-enabling {} for {}", source.path.display(), self.to);
+                    fs::write(&source.path, contents)
+                        .map_err(|why| PpaError::Restore(source.path.clone(), why))?;
+                    outcome.enabled.push(source.path);
+                }
+                None => {
+                    warn!("{} does not support {} yet", source.path.display(), self.to);
+                    outcome.disabled.push(source.path.clone());
+                    source.release = Some(from.to_owned());
+                    remaining.push(source);
+                }
+            }
+        }
+
+        self.sources = remaining;
+        self.keep().map(|_| outcome)
+    }
+
+    /// Stores the manifest if any sources remain in it, or else discards it.
+    fn keep(&self) -> Result<(), PpaError> {
+        if self.sources.is_empty() {
+            discard()
+        } else {
+            self.store()
+        }
+    }
+}
+
+/// A third party source with repositories which do not publish the release being upgraded to.
+#[derive(Debug)]
+pub struct Incompatible {
+    pub path: PathBuf,
+    /// The URIs of the repositories which lack the new release.
+    pub uris: Vec<String>,
+}
+
+/// Checks each enabled third party source for whether its repositories publish the `to` release,
+/// so that sources which would break after upgrading can be reported before upgrading.
+pub fn incompatible(from: &str, to: &str) -> Result<Vec<Incompatible>, PpaError> {
+    let mut report = Vec::new();
+
+    let dir = fs::read_dir(PPA_DIR).map_err(|why| PpaError::Source(PPA_DIR.into(), why))?;
+
+    let mut paths = dir
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| is_third_party(path))
+        .collect::<Vec<_>>();
+
+    paths.sort();
+
+    for path in paths {
+        let contents =
+            fs::read_to_string(&path).map_err(|why| PpaError::Source(path.clone(), why))?;
+
+        let uris = SourcesFile::parse(Format::List, &contents)
+            .and_then(|mut file| unsupported(&mut file, from, to, sources::published));
+
+        let uris = match uris {
+            Ok(uris) => uris,
+            Err(why) => {
+                warn!("skipping {}: {}", path.display(), why);
+                continue;
+            }
+        };
+
+        if !uris.is_empty() {
+            report.push(Incompatible { path, uris });
+        }
+    }
+
+    Ok(report)
+}
+
+/// Third party sources are the `.list` files which are disabled during an upgrade, except for
+/// those belonging to Pop!_OS.
+fn is_third_party(path: &Path) -> bool {
+    const POP_SOURCES: &[&str] = &["pop-os-ppa.list", "system76-ubuntu-pop"];
+
+    let name = path.file_name().and_then(|name| name.to_str()).unwrap_or("");
+
+    Format::of(path) == Some(Format::List)
+        && !POP_SOURCES.iter().any(|pop| name.starts_with(pop))
+}
+
+/// Rewrites the file for the `to` release, and returns the URIs which do not publish it.
+fn unsupported(
+    file: &mut SourcesFile,
+    from: &str,
+    to: &str,
+    published: impl Fn(&str, &str) -> bool,
+) -> Result<Vec<String>, SyntaxError> {
+    file.rewrite(from, to)?;
+
+    let mut uris: Vec<String> = Vec::new();
+    for (uri, suite) in file.sources() {
+        if !uris.iter().any(|known| known == uri) && !published(uri, suite) {
+            uris.push(uri.into());
+        }
+    }
+
+    Ok(uris)
+}
+
+/// Removes the manifest once its sources have been restored or re-enabled.
+pub fn discard() -> Result<(), PpaError> {
+    match fs::remove_file(MANIFEST) {
+        Err(why) if why.kind() != io::ErrorKind::NotFound => Err(PpaError::Write(why)),
+        _ => Ok(()),
+    }
+}
+
+/// The original contents of the source rewritten for the `to` release, if every repository it
+/// refers to publishes the rewritten suites.
+fn reenabled(
+    source: &DisabledSource,
+    from: &str,
+    to: &str,
+    published: impl Fn(&str, &str) -> bool,
+) -> Option<String> {
+    let mut file = SourcesFile::parse(Format::of(&source.path)?, &source.contents).ok()?;
+
+    if unsupported(&mut file, from, to, published).ok()?.is_empty() {
+        Some(file.to_string())
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "deb http://ppa.launchpad.net/example/ppa/ubuntu focal main\n";
+
+    #[test]
+    fn reenable() {
+        let source = DisabledSource {
+            path:     PathBuf::from("/etc/apt/sources.list.d/example.list"),
+            contents: SOURCE.into(),
+            release:  None,
+        };
+
+        assert_eq!(
+            reenabled(&source, "focal", "groovy", |_, suite| suite == "groovy"),
+            Some(SOURCE.replace("focal", "groovy"))
+        );
+
+        assert_eq!(reenabled(&source, "focal", "groovy", |_, suite| suite == "focal"), None);
+    }
+
+    #[test]
+    fn carry_over() {
+        let source = |path: &str, release: Option<&str>| DisabledSource {
+            path:     PathBuf::from(path),
+            contents: SOURCE.into(),
+            release:  release.map(String::from),
+        };
+
+        let mut earlier = Manifest::new("focal", "groovy");
+        earlier.sources.push(source("a.list", None));
+        earlier.sources.push(source("b.list", Some("bionic")));
+
+        let mut manifest = Manifest::new("groovy", "hirsute");
+        manifest.carry_over(earlier);
+        let releases = manifest.sources.iter().map(|source| source.release.as_deref());
+        assert_eq!(releases.collect::<Vec<_>>(), vec![Some("focal"), Some("bionic")]);
+
+        // Sources left disabled by an earlier upgrade stay so when an upgrade is retried.
+        let mut retried = Manifest::new("groovy", "hirsute");
+        retried.carry_over(manifest);
+        assert!(retried.sources[0].release.is_some());
+
+        let mut earlier = Manifest::new("groovy", "hirsute");
+        earlier.sources.push(source("a.list", None));
+        let mut manifest = Manifest::new("groovy", "hirsute");
+        manifest.carry_over(earlier);
+        assert_eq!(manifest.sources[0].release, None);
+    }
+
+    #[test]
+    fn third_party() {
+        let dir = Path::new(PPA_DIR);
+        assert!(is_third_party(&dir.join("example-ubuntu-ppa-focal.list")));
+        assert!(!is_third_party(&dir.join("system76-ubuntu-pop-focal.list")));
+        assert!(!is_third_party(&dir.join("pop-os-ppa.list")));
+        assert!(!is_third_party(&dir.join("system.sources")));
+    }
+}
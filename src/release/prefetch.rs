@@ -0,0 +1,132 @@
+//! Fetches the packages of a new release in the background, before its upgrade is requested, so
+//! that most of the upgrade is already on the system by the time that it is.
+//!
+//! Packages are fetched into a directory of their own, which apt does not clean, and are moved
+//! into apt's archives once the upgrade begins fetching the packages of the new release.
+
+use super::{
+    codename_from_version,
+    preview::{PreviewError, ReleaseLists},
+};
+use as_result::MapResult;
+use async_io::Timer;
+use futures::future::{self, Either};
+use std::{fs, io, path::Path, time::Duration};
+use thiserror::Error;
+
+/// Where the packages of the next release are fetched to.
+pub const PREFETCH: &str = "/var/cache/pop-upgrade/prefetch";
+
+/// Records the release which the fetched packages belong to.
+const RELEASE: &str = "/var/cache/pop-upgrade/prefetch/release";
+
+const ARCHIVES: &str = "/var/cache/apt/archives";
+
+/// How often a fetch which is in progress checks if it has been cancelled.
+const CANCEL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Error)]
+pub enum PrefetchError {
+    #[error("failed to create {}", PREFETCH)]
+    Create(#[source] io::Error),
+
+    #[error("failed to fetch the package lists of {}", _0)]
+    Lists(String, #[source] PreviewError),
+
+    #[error("failed to fetch the packages of {}", _0)]
+    Fetch(String, #[source] io::Error),
+
+    #[error("fetching the packages of {} was cancelled", _0)]
+    Cancelled(String),
+}
+
+/// Fetches the packages which an upgrade to the `to` release would install, until `cancel`
+/// returns true. Packages which were fetched for any other release are discarded.
+pub async fn prefetch(
+    to: &str,
+    cancel: &(dyn Fn() -> bool + Send + Sync),
+) -> Result<(), PrefetchError> {
+    let archives = Path::new(PREFETCH).join("archives");
+
+    if fs::read_to_string(RELEASE).ok().as_deref() != Some(to) {
+        remove();
+    }
+
+    fs::create_dir_all(archives.join("partial"))
+        .and_then(|_| fs::write(RELEASE, to))
+        .map_err(PrefetchError::Create)?;
+
+    let lists = ReleaseLists::fetch(codename_from_version(to))
+        .await
+        .map_err(|why| PrefetchError::Lists(to.into(), why))?;
+
+    info!("fetching the packages of {} in the background", to);
+
+    let mut child = lists
+        .command("apt-get")
+        .args(&["-qq", "-y", "--download-only", "-o"])
+        .arg(["Dir::Cache::Archives=", &*archives.to_string_lossy(), "/"].concat())
+        .arg("full-upgrade")
+        .spawn()
+        .map_err(|why| PrefetchError::Fetch(to.into(), why))?;
+
+    let status = {
+        let status = child.status();
+        let cancelled = async {
+            while !cancel() {
+                Timer::after(CANCEL_INTERVAL).await;
+            }
+        };
+
+        futures::pin_mut!(status, cancelled);
+
+        match future::select(status, cancelled).await {
+            Either::Left((status, _)) => Some(status),
+            Either::Right(_) => None,
+        }
+    };
+
+    match status {
+        Some(status) => {
+            status.map_result().map_err(|why| PrefetchError::Fetch(to.into(), why))?;
+            info!("fetched the packages of {}", to);
+            Ok(())
+        }
+        None => {
+            // Partially fetched packages are kept, for apt to resume the next time.
+            let _ = child.kill();
+            Err(PrefetchError::Cancelled(to.into()))
+        }
+    }
+}
+
+/// Moves the packages which were fetched for the `to` release into apt's archives, where the
+/// upgrade will find them instead of fetching them again.
+pub fn adopt(to: &str) {
+    if fs::read_to_string(RELEASE).ok().as_deref() == Some(to) {
+        let entries = fs::read_dir(Path::new(PREFETCH).join("archives"));
+        let mut adopted = 0;
+
+        for entry in entries.into_iter().flatten().filter_map(Result::ok) {
+            let path = entry.path();
+            if path.extension().map_or(true, |ext| ext != "deb") {
+                continue;
+            }
+
+            match fs::rename(&path, Path::new(ARCHIVES).join(entry.file_name())) {
+                Ok(()) => adopted += 1,
+                Err(why) => warn!("failed to move {} into apt's archives: {}", path.display(), why),
+            }
+        }
+
+        info!("{} packages of {} were fetched in advance", adopted, to);
+    }
+
+    remove();
+}
+
+/// The release which the packages were fetched in advance of, if it is known.
+pub fn release() -> Option<String> { fs::read_to_string(RELEASE).ok() }
+
+/// Discards the packages which were fetched in advance.
+pub fn remove() { let _ = fs::remove_dir_all(PREFETCH); }
@@ -0,0 +1,363 @@
+//! Previews which packages a release upgrade would install, upgrade, remove, or downgrade, and
+//! how much it would download.
+//!
+//! The upgrade is resolved by apt against the sources of the new release, using a package list
+//! and cache in a temporary directory, so that the state of apt on the system is left untouched.
+
+use super::repos::{self, MAIN_FILE};
+use as_result::MapResult;
+use async_process::Command;
+use futures::lock::Mutex;
+use std::{io, path::Path, sync::Arc};
+use tempfile::TempDir;
+use thiserror::Error;
+
+/// The source which apt lists installed packages from.
+const DPKG_STATUS: &str = "/var/lib/dpkg/status";
+
+#[derive(Debug, Error)]
+pub enum PreviewError {
+    #[error("failed to create a temporary directory for the preview")]
+    TempDir(#[source] io::Error),
+
+    #[error("failed to generate sources for {}", _0)]
+    Sources(String, #[source] anyhow::Error),
+
+    #[error("failed to write sources for the preview")]
+    Write(#[source] io::Error),
+
+    #[error("failed to fetch package lists for the new release")]
+    Update(#[source] io::Error),
+
+    #[error("failed to list the installed packages")]
+    Installed(#[source] io::Error),
+
+    #[error("failed to query the package lists of the new release")]
+    Policy(#[source] io::Error),
+
+    #[error("failed to simulate the release upgrade")]
+    Simulate(#[source] io::Error),
+
+    #[error("{}", _0)]
+    Fetched(String),
+}
+
+/// Packages which would be affected by a release upgrade.
+#[derive(Debug, Default, PartialEq)]
+pub struct Preview {
+    pub installed:  Vec<String>,
+    pub upgraded:   Vec<String>,
+    pub removed:    Vec<String>,
+    pub downgraded: Vec<String>,
+}
+
+/// The outcome of a release upgrade, and how much it would download.
+#[derive(Debug, Default, PartialEq)]
+pub struct Simulation {
+    pub preview:       Preview,
+    /// Bytes of packages to fetch, excluding those which are already in apt's cache.
+    pub download_size: u64,
+}
+
+/// Simulates an upgrade to the `to` release, and lists the packages it would affect.
+pub async fn preview(to: &str) -> Result<Preview, PreviewError> {
+    simulate(&ReleaseLists::fetch(to).await?).await
+}
+
+/// Simulates an upgrade to the `to` release, and measures how much it would download.
+pub async fn simulation(to: &str) -> Result<Simulation, PreviewError> {
+    let lists = ReleaseLists::fetch(to).await?;
+    let preview = simulate(&lists).await?;
+    let download_size = lists.download_size().await?;
+
+    Ok(Simulation { preview, download_size })
+}
+
+/// Simulates an upgrade against the package lists of a release.
+pub async fn simulate(lists: &ReleaseLists) -> Result<Preview, PreviewError> {
+    let output = lists
+        .command("apt-get")
+        .args(&["-s", "-y"])
+        .env("LANG", "C")
+        .arg("full-upgrade")
+        .output()
+        .await
+        .map_err(PreviewError::Simulate)?;
+
+    if !output.status.success() {
+        return Err(PreviewError::Simulate(io::Error::new(
+            io::ErrorKind::Other,
+            format!("apt-get exited with {}", output.status),
+        )));
+    }
+
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+/// The package lists of a release, which are fetched once, when they are first needed, and then
+/// shared by every check which needs them.
+pub struct SharedLists {
+    to:    String,
+    lists: Mutex<Option<Result<Arc<ReleaseLists>, String>>>,
+}
+
+impl SharedLists {
+    pub fn new(to: &str) -> Self { Self { to: to.into(), lists: Mutex::new(None) } }
+
+    /// The release whose package lists are fetched.
+    pub fn release(&self) -> &str { &self.to }
+
+    /// Fetches the package lists the first time that they are needed. If that failed, its error
+    /// is returned again, rather than fetching them again.
+    pub async fn get(&self) -> Result<Arc<ReleaseLists>, PreviewError> {
+        let mut lists = self.lists.lock().await;
+
+        if lists.is_none() {
+            *lists = Some(
+                ReleaseLists::fetch(&self.to)
+                    .await
+                    .map(Arc::new)
+                    .map_err(|ref why| crate::misc::format_error(why)),
+            );
+        }
+
+        match lists.as_ref() {
+            Some(Ok(lists)) => Ok(lists.clone()),
+            Some(Err(why)) => Err(PreviewError::Fetched(why.clone())),
+            None => unreachable!("package lists were fetched"),
+        }
+    }
+}
+
+/// The package lists of a release, which are fetched into a temporary directory.
+pub struct ReleaseLists {
+    options: Vec<String>,
+    _temp:   TempDir,
+}
+
+impl ReleaseLists {
+    /// Fetches the package lists of the system sources of the `to` release.
+    pub async fn fetch(to: &str) -> Result<Self, PreviewError> {
+        let temp = tempfile::tempdir().map_err(PreviewError::TempDir)?;
+        let root = temp.path();
+
+        let parts = root.join("sources.list.d");
+        let lists = root.join("lists");
+
+        std::fs::create_dir_all(&parts)
+            .and_then(|_| std::fs::create_dir_all(lists.join("partial")))
+            .map_err(PreviewError::Write)?;
+
+        // Third party sources are disabled during an upgrade, so only the system sources are used.
+        for (path, contents) in
+            repos::release_sources(to).map_err(|why| PreviewError::Sources(to.into(), why))?
+        {
+            let path = Path::new(path);
+            let destination = if path == Path::new(MAIN_FILE) {
+                root.join("sources.list")
+            } else {
+                parts.join(path.file_name().expect("source without a file name"))
+            };
+
+            std::fs::write(destination, contents).map_err(PreviewError::Write)?;
+        }
+
+        let options = vec![
+            ["Dir::Etc::SourceList=", &*root.join("sources.list").to_string_lossy()].concat(),
+            ["Dir::Etc::SourceParts=", &*parts.to_string_lossy()].concat(),
+            ["Dir::State::Lists=", &*lists.to_string_lossy()].concat(),
+            "Dir::Cache::pkgcache=".into(),
+            "Dir::Cache::srcpkgcache=".into(),
+            "Debug::NoLocking=true".into(),
+        ];
+
+        let release_lists = ReleaseLists { options, _temp: temp };
+
+        info!("fetching package lists for {}", to);
+
+        release_lists
+            .command("apt-get")
+            .args(&["-qq", "update"])
+            .status()
+            .await
+            .map_result()
+            .map_err(PreviewError::Update)?;
+
+        Ok(release_lists)
+    }
+
+    /// The packages which have a version in these package lists.
+    pub async fn available(&self, packages: &[String]) -> Result<Vec<String>, PreviewError> {
+        let output = self
+            .command("apt-cache")
+            .arg("policy")
+            .args(packages)
+            .env("LANG", "C")
+            .output()
+            .await
+            .map_err(PreviewError::Policy)?;
+
+        Ok(parse_available(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Bytes of the packages which an upgrade against these package lists would fetch.
+    pub async fn download_size(&self) -> Result<u64, PreviewError> {
+        let output = self
+            .command("apt-get")
+            .args(&["-qq", "-y", "--print-uris"])
+            .arg("full-upgrade")
+            .output()
+            .await
+            .map_err(PreviewError::Simulate)?;
+
+        if !output.status.success() {
+            return Err(PreviewError::Simulate(io::Error::new(
+                io::ErrorKind::Other,
+                format!("apt-get exited with {}", output.status),
+            )));
+        }
+
+        Ok(parse_uris(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// Creates an apt command, such as `apt-get` or `apt-cache`, which uses these package lists.
+    pub fn command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        for option in &self.options {
+            command.arg("-o").arg(option);
+        }
+
+        command
+    }
+}
+
+/// Collects the packages listed under the sections of apt's output.
+fn parse(output: &str) -> Preview {
+    let mut preview = Preview::default();
+    let mut section: Option<&mut Vec<String>> = None;
+
+    for line in output.lines() {
+        if line.starts_with("  ") {
+            if let Some(packages) = section.as_mut() {
+                // Packages which would be purged are marked with a trailing `*`.
+                packages.extend(line.split_whitespace().map(|p| p.trim_end_matches('*').into()));
+            }
+
+            continue;
+        }
+
+        section = match line {
+            "The following NEW packages will be installed:" => Some(&mut preview.installed),
+            "The following packages will be upgraded:" => Some(&mut preview.upgraded),
+            "The following packages will be REMOVED:" => Some(&mut preview.removed),
+            "The following packages will be DOWNGRADED:" => Some(&mut preview.downgraded),
+            _ => None,
+        };
+    }
+
+    preview
+}
+
+/// The total size of the packages in the output of `apt-get --print-uris`, whose lines are of the
+/// form `'URI' FILENAME SIZE HASH`.
+fn parse_uris(output: &str) -> u64 {
+    output
+        .lines()
+        .filter(|line| line.starts_with('\''))
+        .filter_map(|line| line.split_whitespace().nth(2)?.parse::<u64>().ok())
+        .sum()
+}
+
+/// The packages in the output of `apt-cache policy` which have a version in the package lists,
+/// rather than only the version that is installed.
+fn parse_available(output: &str) -> Vec<String> {
+    let mut available = Vec::new();
+    let mut package = None;
+
+    for line in output.lines() {
+        if !line.starts_with(' ') {
+            package = line.strip_suffix(':');
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let source = match (fields.next(), fields.next()) {
+            (Some(priority), Some(source)) if priority.parse::<i32>().is_ok() => source,
+            _ => continue,
+        };
+
+        if let Some(package) = package {
+            if source.contains('/') && source != DPKG_STATUS && !available.contains(&package) {
+                available.push(package);
+            }
+        }
+    }
+
+    available.into_iter().map(String::from).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OUTPUT: &str = "Reading package lists...
+Building dependency tree...
+Calculating upgrade...
+The following packages will be REMOVED:
+  libfoo1* python2.7
+  python2.7-minimal
+The following NEW packages will be installed:
+  libfoo2
+The following packages will be upgraded:
+  libc6
+The following packages will be DOWNGRADED:
+  example-app
+1 upgraded, 1 newly installed, 1 downgraded, 3 to remove and 0 not upgraded.
+";
+
+    #[test]
+    fn simulation_output() {
+        assert_eq!(
+            parse(OUTPUT),
+            Preview {
+                installed:  vec!["libfoo2".into()],
+                upgraded:   vec!["libc6".into()],
+                removed:    vec!["libfoo1".into(), "python2.7".into(), "python2.7-minimal".into()],
+                downgraded: vec!["example-app".into()],
+            }
+        );
+    }
+
+    #[test]
+    fn download_size() {
+        let output = "'http://apt.pop-os.org/ubuntu/pool/main/libc6_2.35_amd64.deb' \
+                      libc6_2.35_amd64.deb 3235484 SHA512:9f2c
+'http://apt.pop-os.org/ubuntu/pool/main/libfoo2_2.0-1_amd64.deb' libfoo2_2.0-1_amd64.deb 1024 \
+                      SHA512:77ab
+";
+
+        assert_eq!(parse_uris(output), 3236508);
+        assert_eq!(parse_uris(""), 0);
+    }
+
+    #[test]
+    fn policy() {
+        let output = "libc6:i386:
+  Installed: 2.31-0ubuntu9
+  Candidate: 2.33-0ubuntu5
+  Version table:
+     2.33-0ubuntu5 500
+        500 http://us.archive.ubuntu.com/ubuntu hirsute/main i386 Packages
+ *** 2.31-0ubuntu9 100
+        100 /var/lib/dpkg/status
+libfoo1:i386:
+  Installed: 1.0-1
+  Candidate: 1.0-1
+  Version table:
+ *** 1.0-1 100
+        100 /var/lib/dpkg/status
+";
+
+        assert_eq!(parse_available(output), vec!["libc6:i386"]);
+    }
+}
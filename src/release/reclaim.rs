@@ -0,0 +1,153 @@
+//! Reclaims the space which a completed release upgrade leaves behind, by removing packages which
+//! are no longer required, and clearing the package cache.
+
+use super::UpgradeEvent;
+use crate::{apt_lock, config::Config, preflight};
+use anyhow::Context;
+use apt_cmd::AptGet;
+use async_process::Stdio;
+use futures::{io::BufReader, prelude::*};
+use std::{collections::HashSet, fs, os::unix::fs::MetadataExt, path::Path};
+
+/// Filesystems which packages are removed from, and the package cache is stored on.
+const PATHS: &[&str] = &["/", "/var/cache/apt/archives"];
+
+/// The outcome of reclaiming space.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Reclaimed {
+    /// Packages which were removed, as they were no longer required.
+    pub removed: Vec<String>,
+    /// Bytes which were freed.
+    pub bytes:   u64,
+}
+
+/// Reclaims space once a release upgrade has completed, if enabled in the configuration.
+pub async fn after_upgrade(logger: &dyn Fn(UpgradeEvent), progress: &dyn Fn(u8)) {
+    if !Config::load_or_default().release.autoclean {
+        return;
+    }
+
+    (*logger)(UpgradeEvent::ReclaimingSpace);
+
+    match reclaim(progress).await {
+        Ok(reclaimed) => info!(
+            "reclaimed {} MiB, and removed {} packages",
+            reclaimed.bytes / 1024 / 1024,
+            reclaimed.removed.len()
+        ),
+        Err(why) => error!("failed to reclaim space: {}", crate::misc::format_error(why.as_ref())),
+    }
+}
+
+/// Removes packages which are no longer required, and then clears the package cache. The
+/// percentage of the packages which have been removed is passed to `progress`.
+pub async fn reclaim(progress: &dyn Fn(u8)) -> anyhow::Result<Reclaimed> {
+    apt_lock::wait(&|_| ()).await.context("failed to wait on the package manager lock")?;
+
+    let before = free_space();
+
+    // The removal is simulated first, to find how many packages it will remove.
+    let simulation = AptGet::new()
+        .noninteractive()
+        .args(&["--simulate", "autoremove", "--purge"])
+        .env("LANG", "C")
+        .output()
+        .await
+        .context("failed to simulate `apt-get autoremove`")?;
+
+    let total = count_removals(&String::from_utf8_lossy(&simulation.stdout));
+
+    let mut child = AptGet::new()
+        .noninteractive()
+        .args(&["autoremove", "--purge"])
+        .env("LANG", "C")
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("failed to run `apt-get autoremove`")?;
+
+    let mut removed = Vec::new();
+    if let Some(stdout) = child.stdout.take() {
+        let mut lines = BufReader::new(stdout).lines();
+        while let Some(line) = lines.next().await {
+            let line = line.context("failed to read the output of `apt-get autoremove`")?;
+            if let Some(package) = removed_package(&line) {
+                removed.push(package.to_owned());
+                progress(percent(removed.len(), total));
+            }
+        }
+    }
+
+    let output = child.output().await.context("failed to run `apt-get autoremove`")?;
+
+    if !output.status.success() {
+        return Err(anyhow!(
+            "`apt-get autoremove` exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    AptGet::new().arg("clean").status().await.context("failed to run `apt-get clean`")?;
+
+    let bytes = free_space().saturating_sub(before);
+
+    Ok(Reclaimed { removed, bytes })
+}
+
+/// The space available on each of the filesystems which are reclaimed from.
+fn free_space() -> u64 {
+    let mut devices = HashSet::new();
+
+    PATHS
+        .iter()
+        .filter(|path| fs::metadata(path).map_or(false, |metadata| devices.insert(metadata.dev())))
+        .filter_map(|path| preflight::available(Path::new(path)).ok())
+        .sum()
+}
+
+/// The package which dpkg reports removing on a line of its output.
+fn removed_package(line: &str) -> Option<&str> {
+    line.strip_prefix("Removing ")?.split_whitespace().next()
+}
+
+/// The number of packages which a simulated removal reports that it would remove.
+fn count_removals(simulation: &str) -> usize {
+    simulation.lines().filter(|line| line.starts_with("Remv ")).count()
+}
+
+fn percent(done: usize, total: usize) -> u8 {
+    if total == 0 {
+        100
+    } else {
+        (done * 100 / total).min(100) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed() {
+        let output = "Reading package lists...\n\
+                      The following packages will be REMOVED:\n  \
+                      linux-image-5.8.0-7642-generic*\n\
+                      Removing linux-image-5.8.0-7642-generic (5.8.0-7642.47) ...\n\
+                      Purging configuration files for linux-image-5.8.0-7642-generic ...\n";
+
+        let removed = output.lines().filter_map(removed_package).collect::<Vec<_>>();
+        assert_eq!(removed, vec!["linux-image-5.8.0-7642-generic"]);
+    }
+
+    #[test]
+    fn removals() {
+        let simulation = "NOTE: This is only a simulation!\n\
+                          Remv linux-image-5.8.0-7642-generic [5.8.0-7642.47]\n\
+                          Remv linux-modules-5.8.0-7642-generic [5.8.0-7642.47]\n";
+
+        assert_eq!(count_removals(simulation), 2);
+        assert_eq!(percent(1, 2), 50);
+        assert_eq!(percent(0, 0), 100);
+    }
+}
@@ -0,0 +1,238 @@
+//! A report of what a release upgrade changed, which is written once the upgrade has been
+//! installed and has passed its health check, so that users know what to look over afterwards.
+
+use crate::logging::LOG_DIR;
+use chrono::{DateTime, Local, NaiveDateTime};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    ffi::OsStr,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// The log of the packages which dpkg has installed, upgraded, and removed.
+const DPKG_LOG: &str = "/var/log/dpkg.log";
+
+/// Where dpkg leaves the new versions of the configuration files whose modifications were kept.
+const ETC: &str = "/etc";
+
+#[derive(Debug, Default, Deserialize, PartialEq, Serialize)]
+pub struct Report {
+    pub from:             String,
+    pub to:               String,
+    /// The local date on which the upgrade was installed, as `YYYY-MM-DD`.
+    pub date:             String,
+    /// Packages which were removed or purged by the upgrade, and by the cleanup after it.
+    pub removed:          Vec<String>,
+    /// Third party sources which remain disabled, because they do not support the new release.
+    pub disabled_sources: Vec<PathBuf>,
+    /// Modified configuration files which were kept, beside which dpkg left the new versions
+    /// with a `.dpkg-dist` suffix.
+    pub conffiles_kept:   Vec<PathBuf>,
+    /// Problems which were found once the upgrade was installed, but which did not fail it.
+    pub warnings:         Vec<String>,
+}
+
+impl Report {
+    /// Collects what the upgrade from the `from` release to the `to` release changed, since it
+    /// began.
+    pub fn collect(
+        from: &str,
+        to: &str,
+        since: SystemTime,
+        disabled_sources: Vec<PathBuf>,
+        warnings: Vec<String>,
+    ) -> Self {
+        let local = DateTime::<Local>::from(since).naive_local();
+        let removed = fs::read_to_string(DPKG_LOG)
+            .map_or_else(|_| Vec::new(), |log| removals(&log, local));
+
+        let mut conffiles_kept = Vec::new();
+        dpkg_dist(Path::new(ETC), since, &mut conffiles_kept);
+        conffiles_kept.sort();
+
+        Self {
+            from: from.to_owned(),
+            to:   to.to_owned(),
+            date: Local::now().format("%Y-%m-%d").to_string(),
+            removed,
+            disabled_sources,
+            conffiles_kept,
+            warnings,
+        }
+    }
+
+    /// The most recent report, if an upgrade has been installed.
+    pub fn latest() -> io::Result<Option<Self>> {
+        let latest = match fs::read_dir(LOG_DIR) {
+            Ok(entries) => entries
+                .filter_map(Result::ok)
+                .map(|entry| entry.path())
+                .filter(|path| is_report(path))
+                .max(),
+            Err(why) if why.kind() == io::ErrorKind::NotFound => None,
+            Err(why) => return Err(why),
+        };
+
+        let path = match latest {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+
+        serde_json::from_slice(&fs::read(&path)?).map(Some).map_err(io::Error::from)
+    }
+
+    /// Writes the report to `/var/log/pop-upgrade/report-<date>.json`, returning its path.
+    pub fn store(&self) -> io::Result<PathBuf> {
+        let path = Path::new(LOG_DIR).join(["report-", &self.date, ".json"].concat());
+        fs::create_dir_all(LOG_DIR)?;
+        fs::write(&path, serde_json::to_vec_pretty(self)?)?;
+        Ok(path)
+    }
+
+    /// A summary of the report for people to read.
+    pub fn summary(&self) -> String {
+        let mut summary = fomat!("upgraded from " (self.from) " to " (self.to) " on " (self.date));
+
+        let mut section = |heading: &str, items: &mut dyn Iterator<Item = String>| {
+            let items = items.collect::<Vec<_>>();
+            if !items.is_empty() {
+                summary.push_str(&fomat!("\n" (heading) " (" (items.len()) "):"));
+                for item in items {
+                    summary.push_str(&fomat!("\n  " (item)));
+                }
+            }
+        };
+
+        section("packages removed", &mut self.removed.iter().cloned());
+        section(
+            "third party sources which remain disabled",
+            &mut self.disabled_sources.iter().map(|path| path.display().to_string()),
+        );
+        section(
+            "modified configuration files which were kept, beside their new `.dpkg-dist` versions",
+            &mut self.conffiles_kept.iter().map(|path| path.display().to_string()),
+        );
+        section("warnings", &mut self.warnings.iter().cloned());
+
+        summary
+    }
+}
+
+fn is_report(path: &Path) -> bool {
+    path.file_name()
+        .and_then(OsStr::to_str)
+        .map_or(false, |name| name.starts_with("report-") && name.ends_with(".json"))
+}
+
+/// The packages which dpkg logged as removed or purged since the given local time, in the order
+/// that they were first removed.
+fn removals(log: &str, since: NaiveDateTime) -> Vec<String> {
+    let mut removed: Vec<String> = Vec::new();
+
+    for line in log.lines() {
+        let mut fields = line.split_whitespace();
+        let (date, time, action, package) =
+            match (fields.next(), fields.next(), fields.next(), fields.next()) {
+                (Some(date), Some(time), Some(action), Some(package)) => {
+                    (date, time, action, package)
+                }
+                _ => continue,
+            };
+
+        if action != "remove" && action != "purge" {
+            continue;
+        }
+
+        let at = [date, " ", time].concat();
+        match NaiveDateTime::parse_from_str(&at, "%Y-%m-%d %H:%M:%S") {
+            Ok(at) if at >= since => (),
+            _ => continue,
+        }
+
+        // Packages which are removed and then purged are listed once.
+        if !removed.iter().any(|name| name == package) {
+            removed.push(package.to_owned());
+        }
+    }
+
+    removed
+}
+
+/// Collects the configuration files beneath the directory which have a `.dpkg-dist` version
+/// that dpkg left since the given time.
+fn dpkg_dist(dir: &Path, since: SystemTime, found: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(_) => continue,
+        };
+
+        if file_type.is_dir() {
+            dpkg_dist(&path, since, found);
+        } else if file_type.is_file() && path.extension() == Some(OsStr::new("dpkg-dist")) {
+            let modified = entry.metadata().and_then(|meta| meta.modified());
+            if modified.map_or(false, |modified| modified >= since) {
+                found.push(path.with_extension(""));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::UNIX_EPOCH;
+
+    #[test]
+    fn dpkg_log() {
+        let log = "2022-06-04 22:59:59 remove oldpkg:amd64 1.0 <none>
+2022-06-04 23:10:01 status installed libc6:amd64 2.35-0ubuntu3
+2022-06-04 23:10:02 remove libfoo1:amd64 1.0-1 <none>
+2022-06-04 23:10:03 purge libfoo1:amd64 1.0-1 <none>
+2022-06-04 23:10:04 upgrade libc6:amd64 2.34-0ubuntu3 2.35-0ubuntu3
+2022-06-04 23:10:05 purge python2.7:amd64 2.7.18-13 <none>
+";
+
+        let since = NaiveDateTime::parse_from_str("2022-06-04 23:00:00", "%Y-%m-%d %H:%M:%S");
+        assert_eq!(removals(log, since.unwrap()), vec!["libfoo1:amd64", "python2.7:amd64"]);
+    }
+
+    #[test]
+    fn conffiles() {
+        let etc = tempfile::tempdir().unwrap();
+        let etc = etc.path();
+
+        fs::create_dir_all(etc.join("default")).unwrap();
+        fs::write(etc.join("default/grub.dpkg-dist"), b"").unwrap();
+        fs::write(etc.join("default/grub"), b"").unwrap();
+        fs::write(etc.join("fstab.dpkg-old"), b"").unwrap();
+
+        let mut found = Vec::new();
+        dpkg_dist(etc, UNIX_EPOCH, &mut found);
+        assert_eq!(found, vec![etc.join("default/grub")]);
+    }
+
+    #[test]
+    fn summary() {
+        let report = Report {
+            from:    "21.10".into(),
+            to:      "22.04".into(),
+            date:    "2022-06-04".into(),
+            removed: vec!["libfoo1:amd64".into()],
+            ..Report::default()
+        };
+
+        assert_eq!(
+            report.summary(),
+            "upgraded from 21.10 to 22.04 on 2022-06-04\npackages removed (1):\n  libfoo1:amd64"
+        );
+    }
+}
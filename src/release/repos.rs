@@ -1,4 +1,7 @@
-use super::eol::{EolDate, EolStatus};
+use super::{
+    eol::{EolDate, EolStatus},
+    ppa::{DisabledSource, Manifest},
+};
 use anyhow::Context;
 use os_str_bytes::OsStrBytes;
 use std::{
@@ -11,12 +14,13 @@ use std::{
 use ubuntu_version::Codename;
 
 const BACKUP_MAIN_FILE: &str = "/etc/apt/sources.list.save";
-const MAIN_FILE: &str = "/etc/apt/sources.list";
-const PPA_DIR: &str = "/etc/apt/sources.list.d";
-const NEW_MAIN_FILE: &str = "/etc/apt/sources.list.d/system.sources";
+pub(crate) const MAIN_FILE: &str = "/etc/apt/sources.list";
+pub(crate) const PPA_DIR: &str = "/etc/apt/sources.list.d";
+pub(crate) const NEW_MAIN_FILE: &str = "/etc/apt/sources.list.d/system.sources";
 const APPS_FILE: &str = "/etc/apt/sources.list.d/pop-os-apps.sources";
 const POP_PPA_FILE: &str = "/etc/apt/sources.list.d/pop-os-ppa.list";
 const PROPRIETARY_URL: &str = "http://apt.pop-os.org/proprietary";
+const POP_PPA_URL: &str = "http://ppa.launchpad.net/system76/pop/ubuntu";
 
 enum ReleaseSupport {
     BeforeGroovy,
@@ -87,10 +91,24 @@ pub fn backup(release: &str) -> anyhow::Result<()> {
 }
 
 /// For each `.list` in `sources.list.d`, add `#` to the `deb` lines.
-pub fn disable_third_parties(release: &str) -> anyhow::Result<()> {
+///
+/// The original contents of each disabled file are recorded in a manifest beforehand, so that
+/// they may be restored if the upgrade is interrupted, or re-enabled once it completes.
+pub fn disable_third_parties(from: &str, to: &str) -> anyhow::Result<()> {
+    let mut manifest = Manifest::new(from, to);
+    if let Some(earlier) = Manifest::load()? {
+        manifest.carry_over(earlier);
+    }
+
     let dir = fs::read_dir(PPA_DIR).context("cannot read PPA directory")?;
     iter_files(dir, |entry| {
         let path = entry.path();
+
+        // The original contents of sources which are already disabled were carried over.
+        if manifest.sources.iter().any(|source| source.path == path) {
+            return Ok(());
+        }
+
         if path.extension().map_or(false, |e| e == "list") {
             if let Some(fname) = path.file_name() {
                 const POP_PPA: &[u8] = b"system76-ubuntu-pop";
@@ -100,30 +118,36 @@ pub fn disable_third_parties(release: &str) -> anyhow::Result<()> {
                 }
             }
 
-            info!("disabling sources in {}", path.display());
-
             let contents = fs::read_to_string(&path)
                 .with_context(|| fomat!("failed to read "(&path.display())))?;
 
-            let mut replaced = String::new();
-            for line in contents.lines() {
-                let trimmed = line.trim();
-                if trimmed.starts_with("deb") {
-                    replaced.push_str("# ")
-                }
+            manifest.sources.push(DisabledSource { path, contents, release: None });
+        }
 
-                replaced.push_str(trimmed);
-                replaced.push('\n');
+        Ok(())
+    })?;
+
+    manifest.store().context("failed to record the sources to disable")?;
+
+    for source in &manifest.sources {
+        info!("disabling sources in {}", source.path.display());
+
+        let mut replaced = String::new();
+        for line in source.contents.lines() {
+            let trimmed = line.trim();
+            if trimmed.starts_with("deb") {
+                replaced.push_str("# ")
             }
 
-            fs::write(&path, replaced.as_bytes())
-                .with_context(|| fomat!("failed to open " (&path.display()) " for writing"))?;
+            replaced.push_str(trimmed);
+            replaced.push('\n');
         }
 
-        Ok(())
-    })?;
+        fs::write(&source.path, replaced.as_bytes())
+            .with_context(|| fomat!("failed to open " (&source.path.display()) " for writing"))?;
+    }
 
-    create_new_sources_list(release)?;
+    create_new_sources_list(from)?;
 
     Ok(())
 }
@@ -142,7 +166,9 @@ pub fn is_old_release(codename: Codename) -> bool {
     ]
     .concat();
 
-    isahc::head(url).ok().map_or(false, |resp| resp.status().is_success())
+    crate::http::client(url)
+        .and_then(|client| client.head(url))
+        .map_or(false, |resp| resp.status().is_success())
 }
 
 pub fn repair(release: &str) -> anyhow::Result<()> {
@@ -214,6 +240,10 @@ pub fn restore(release: &str) -> anyhow::Result<()> {
         }
     }
 
+    if let Some(manifest) = Manifest::load()? {
+        manifest.restore().context("failed to restore disabled sources")?;
+    }
+
     Ok(())
 }
 
@@ -257,15 +287,8 @@ fn replace_with_old_releases_(
 }
 
 pub fn create_new_sources_list(release: &str) -> anyhow::Result<()> {
-    if let ReleaseSupport::PostGroovy = ReleaseSupport::get(release)? {
-        // new sources
-        fs::write(NEW_MAIN_FILE, new_system_sources(release))?;
-        fs::write(APPS_FILE, pop_apps_source(release))?;
-        fs::write(POP_PPA_FILE, pop_ppa_source(release))?;
-        fs::write(MAIN_FILE, new_sources_file())?;
-    } else {
-        // old sources
-        fs::write(MAIN_FILE, default_sources(release))?;
+    for (path, contents) in release_sources(release)? {
+        fs::write(path, contents)?;
     }
 
     // TODO: Ensure that the GPG keys are added for the Ubuntu archives.
@@ -273,6 +296,35 @@ pub fn create_new_sources_list(release: &str) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// The Pop repositories which a release enables, by URI, with the sources file which enables
+/// each and its contents. Releases before groovy enable them in `sources.list` instead.
+pub fn pop_sources(release: &str) -> anyhow::Result<Vec<(&'static str, &'static str, String)>> {
+    Ok(if let ReleaseSupport::PostGroovy = ReleaseSupport::get(release)? {
+        vec![
+            (PROPRIETARY_URL, APPS_FILE, pop_apps_source(release)),
+            (POP_PPA_URL, POP_PPA_FILE, pop_ppa_source(release)),
+        ]
+    } else {
+        Vec::new()
+    })
+}
+
+/// The system sources of a release, and the paths they are written to.
+pub fn release_sources(release: &str) -> anyhow::Result<Vec<(&'static str, String)>> {
+    Ok(if let ReleaseSupport::PostGroovy = ReleaseSupport::get(release)? {
+        // new sources
+        vec![
+            (NEW_MAIN_FILE, new_system_sources(release)),
+            (APPS_FILE, pop_apps_source(release)),
+            (POP_PPA_FILE, pop_ppa_source(release)),
+            (MAIN_FILE, new_sources_file()),
+        ]
+    } else {
+        // old sources
+        vec![(MAIN_FILE, default_sources(release))]
+    })
+}
+
 pub fn new_system_sources(release: &str) -> String {
     format!(
         r#"X-Repolib-Name: Pop_OS System Sources
@@ -349,7 +401,7 @@ deb http://apt.pop-os.org/proprietary {0} main
 
 fn iter_files(
     dir: ReadDir,
-    callback: impl Fn(DirEntry) -> anyhow::Result<()>,
+    mut callback: impl FnMut(DirEntry) -> anyhow::Result<()>,
 ) -> anyhow::Result<()> {
     for e in dir {
         let entry = match e {
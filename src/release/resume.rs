@@ -0,0 +1,140 @@
+//! Records the progress of a release upgrade, so that an upgrade which was interrupted by a crash
+//! or power loss may be resumed from the last phase that it completed.
+
+use super::UpgradeMethod;
+use num_traits::FromPrimitive;
+use std::{fs, io};
+
+/// Records the release upgrade in progress, as
+/// `<method> <from> <to> <lift_holds> <phase> <started>`.
+const UPGRADE_STATE: &str = "/var/lib/pop-upgrade/upgrade_state";
+
+/// Phases of a release upgrade, in the order that they are completed.
+#[repr(u8)]
+#[derive(Clone, Copy, Debug, Eq, FromPrimitive, Ord, PartialEq, PartialOrd)]
+pub enum Phase {
+    /// The system was repaired, and a snapshot was taken.
+    Prepared = 1,
+    /// Automatic refreshes of snaps were paused, until the upgrade has been installed.
+    SnapRefreshPaused = 2,
+    /// Packages of the current release were upgraded.
+    CurrentUpgraded = 3,
+    /// The sources were rewritten to the new release, and their package lists updated.
+    SourcesRewritten = 4,
+    /// Packages of the new release were fetched, and the upgrade was simulated.
+    PackagesFetched = 5,
+    /// The upgrade was staged, for dpkg to install and configure on the next boot.
+    Staged = 6,
+}
+
+impl From<Phase> for &'static str {
+    fn from(phase: Phase) -> Self {
+        match phase {
+            Phase::Prepared => "prepared",
+            Phase::SnapRefreshPaused => "snap refreshes paused",
+            Phase::CurrentUpgraded => "current release upgraded",
+            Phase::SourcesRewritten => "sources rewritten",
+            Phase::PackagesFetched => "packages fetched",
+            Phase::Staged => "staged",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct UpgradeState {
+    pub method:     UpgradeMethod,
+    pub from:       String,
+    pub to:         String,
+    pub lift_holds: bool,
+    /// The last phase which the upgrade completed.
+    pub phase:      Option<Phase>,
+    /// When the upgrade began, in seconds since the Unix epoch, or `0` if it is unknown.
+    pub started:    u64,
+}
+
+impl UpgradeState {
+    pub fn new(method: UpgradeMethod, from: &str, to: &str, lift_holds: bool) -> Self {
+        Self {
+            method,
+            from:    from.into(),
+            to:      to.into(),
+            lift_holds,
+            phase:   None,
+            started: crate::notification::now(),
+        }
+    }
+
+    pub fn load() -> Option<Self> { Self::parse(&fs::read_to_string(UPGRADE_STATE).ok()?) }
+
+    pub fn remove() { let _ = fs::remove_file(UPGRADE_STATE); }
+
+    /// Whether the upgrade has already completed this phase.
+    pub fn completed(&self, phase: Phase) -> bool {
+        self.phase.map_or(false, |last| last >= phase)
+    }
+
+    /// Records that the upgrade has completed a phase.
+    pub fn advance(&mut self, phase: Phase) {
+        info!("release upgrade phase completed: {}", <&'static str>::from(phase));
+        self.phase = Some(phase);
+
+        if let Err(why) = self.store() {
+            warn!("failed to record the progress of the release upgrade: {}", why);
+        }
+    }
+
+    /// Rewinds the phase to account for the cleanup of an interrupted upgrade, which restores the
+    /// sources of the current release. Upgraded packages of the current release are retained.
+    pub fn rewind(&mut self) {
+        if self.completed(Phase::SourcesRewritten) {
+            self.phase = Some(Phase::CurrentUpgraded);
+
+            if let Err(why) = self.store() {
+                warn!("failed to record the progress of the release upgrade: {}", why);
+            }
+        }
+    }
+
+    fn store(&self) -> io::Result<()> {
+        let state = fomat!(
+            (self.method as u8) " " (self.from) " " (self.to) " " (self.lift_holds as u8) " "
+            (self.phase.map_or(0, |phase| phase as u8)) " " (self.started)
+        );
+
+        fs::write(UPGRADE_STATE, state)
+    }
+
+    fn parse(state: &str) -> Option<Self> {
+        let mut fields = state.split_whitespace();
+
+        let method = UpgradeMethod::from_u8(fields.next()?.parse().ok()?)?;
+        let from = fields.next()?.into();
+        let to = fields.next()?.into();
+        let lift_holds = fields.next()? == "1";
+        let phase = Phase::from_u8(fields.next()?.parse().ok()?);
+        let started = fields.next().and_then(|started| started.parse().ok()).unwrap_or(0);
+
+        Some(Self { method, from, to, lift_holds, phase, started })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn state() {
+        let mut state = UpgradeState::new(UpgradeMethod::Offline, "20.10", "21.04", true);
+        assert!(!state.completed(Phase::Prepared));
+
+        state.phase = Some(Phase::PackagesFetched);
+        assert!(state.completed(Phase::CurrentUpgraded));
+        assert!(!state.completed(Phase::Staged));
+
+        state.started = 1650000000;
+        assert_eq!(UpgradeState::parse("1 20.10 21.04 1 5 1650000000"), Some(state));
+        assert_eq!(UpgradeState::parse("1 20.10 21.04 1 5").map(|state| state.started), Some(0));
+        assert_eq!(UpgradeState::parse("1 20.10 21.04 0 0").and_then(|state| state.phase), None);
+        assert_eq!(UpgradeState::parse("2 20.10 21.04 0 0"), None);
+    }
+}
@@ -0,0 +1,51 @@
+use sha2::{Digest, Sha256};
+use std::{convert::TryInto, fs};
+
+/// Uniquely identifies this installation, without revealing anything about it.
+const MACHINE_ID: &str = "/etc/machine-id";
+
+/// Whether a release which is being rolled out to `percentage` percent of machines has been
+/// rolled out to this machine.
+///
+/// Machines without a readable machine ID are always included, rather than never upgrading.
+pub fn includes(version: &str, percentage: u8) -> bool {
+    if percentage >= 100 {
+        return true;
+    }
+
+    match fs::read_to_string(MACHINE_ID) {
+        Ok(machine_id) => bucket(machine_id.trim(), version) < percentage,
+        Err(why) => {
+            warn!("failed to read {}: {}", MACHINE_ID, why);
+            true
+        }
+    }
+}
+
+/// Places a machine into one of 100 buckets, which are filled in order as a rollout progresses.
+///
+/// The version is included in the hash so that the same machines are not always the first to
+/// receive each release.
+fn bucket(machine_id: &str, version: &str) -> u8 {
+    let digest = Sha256::new().chain(machine_id).chain(":").chain(version).finalize();
+    let value = u64::from_be_bytes(digest[..8].try_into().expect("digest is too short"));
+    (value % 100) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buckets() {
+        let machine = "4f7c2bbd9a2a4c4bb0d4d4c0c8a3b0e1";
+        assert_eq!(bucket(machine, "21.04"), bucket(machine, "21.04"));
+        assert!(bucket(machine, "21.04") < 100);
+
+        let filled = (0..1000).filter(|id| bucket(&id.to_string(), "21.04") < 25).count();
+        assert!(filled > 150 && filled < 350, "{} of 1000 machines in 25% of buckets", filled);
+    }
+
+    #[test]
+    fn complete_rollout() { assert!(includes("21.04", 100)); }
+}
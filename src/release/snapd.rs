@@ -1,6 +1,15 @@
 use super::errors::ReleaseError;
 use apt_cmd::{AptCache, AptMark};
 use async_fs as fs;
+use async_process::Command;
+use chrono::{DateTime, Duration, Utc};
+use std::path::Path;
+
+const SNAP: &str = "/usr/bin/snap";
+
+/// Days for which refreshes are held, which outlasts an upgrade that is left staged for a while,
+/// without leaving snaps unrefreshed indefinitely if the hold is never released.
+const HOLD_DAYS: i64 = 14;
 
 /// Holds all packages which have a pre-depend on snapd.
 ///
@@ -27,3 +36,93 @@ pub async fn hold_transitional_packages() -> Result<(), ReleaseError> {
 
     Ok(())
 }
+
+/// Pauses the automatic refreshes of snapd, so that they neither compete with the upgrade for
+/// bandwidth, nor mount and unmount squashfs images while packages are being upgraded. The hold
+/// which was configured beforehand is recorded, to be restored by `resume_refresh`.
+pub async fn pause_refresh() {
+    if !Path::new(SNAP).exists() || Path::new(crate::SNAP_REFRESH_HOLD).exists() {
+        return;
+    }
+
+    let previous = match snap(&["get", "system", "refresh.hold"]).await {
+        Ok(hold) => hold,
+        // snap exits with an error when the option is unset.
+        Err(_) => String::new(),
+    };
+
+    if let Err(why) = fs::write(crate::SNAP_REFRESH_HOLD, previous.trim()).await {
+        error!("failed to record the snap refresh hold: {}", why);
+        return;
+    }
+
+    let hold = ["refresh.hold=", &hold_until(Utc::now())].concat();
+    match snap(&["set", "system", &hold]).await {
+        Ok(_) => info!("paused snap refreshes for the upgrade"),
+        Err(why) => error!("failed to pause snap refreshes: {}", why),
+    }
+}
+
+/// Restores the refresh hold which was configured before the upgrade paused refreshes, and then
+/// refreshes snaps if the upgrade was installed.
+pub async fn resume_refresh(refresh: bool) {
+    let previous = match fs::read_to_string(crate::SNAP_REFRESH_HOLD).await {
+        Ok(previous) => previous,
+        Err(_) => return,
+    };
+
+    let result = if previous.trim().is_empty() {
+        snap(&["unset", "system", "refresh.hold"]).await
+    } else {
+        snap(&["set", "system", &["refresh.hold=", previous.trim()].concat()]).await
+    };
+
+    match result {
+        Ok(_) => {
+            info!("resumed snap refreshes");
+            let _ = fs::remove_file(crate::SNAP_REFRESH_HOLD).await;
+        }
+        Err(why) => {
+            error!("failed to resume snap refreshes: {}", why);
+            return;
+        }
+    }
+
+    if refresh {
+        if let Err(why) = snap(&["refresh"]).await {
+            error!("failed to refresh snaps: {}", why);
+        }
+    }
+}
+
+/// The time at which a hold that is placed at `now` expires, in the format snapd accepts.
+fn hold_until(now: DateTime<Utc>) -> String {
+    (now + Duration::days(HOLD_DAYS)).to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+async fn snap(args: &[&str]) -> Result<String, String> {
+    let output = Command::new(SNAP).args(args).output().await.map_err(|why| why.to_string())?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "`snap {}` exited with {}: {}",
+            args.join(" "),
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn hold() {
+        let now = Utc.ymd(2021, 4, 29).and_hms(15, 30, 0);
+        assert_eq!(hold_until(now), "2021-05-13T15:30:00Z");
+    }
+}
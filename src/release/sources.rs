@@ -0,0 +1,559 @@
+//! Parses apt sources in both the one-line `.list` format and the deb822 `.sources` format,
+//! and rewrites the suites of their enabled entries from one release to another.
+//!
+//! Only the suites are ever modified: comments, options, and whitespace are preserved as is.
+
+use super::repos::{MAIN_FILE, PPA_DIR};
+use std::{
+    fmt::{self, Display, Formatter},
+    fs, io,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use ubuntu_version::Codename;
+
+#[derive(Debug, Error)]
+pub enum SourcesError {
+    #[error("failed to read {:?}", _0)]
+    Read(PathBuf, #[source] io::Error),
+
+    #[error("failed to parse {:?}", _0)]
+    Syntax(PathBuf, #[source] SyntaxError),
+
+    #[error("{:?} still refers to {} after it was rewritten", _0, _1)]
+    Unrewritten(PathBuf, String),
+
+    #[error("failed to write {:?}", _0)]
+    Write(PathBuf, #[source] io::Error),
+}
+
+#[derive(Debug, Error, PartialEq)]
+#[error("line {}: {}", line, message)]
+pub struct SyntaxError {
+    pub line:    usize,
+    pub message: &'static str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// `deb uri suite components`, one entry per line.
+    List,
+    /// Stanzas of `Field: value` pairs, separated by blank lines.
+    Deb822,
+}
+
+impl Format {
+    /// The format of a sources file, by its extension.
+    pub fn of(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "list" => Some(Format::List),
+            "sources" => Some(Format::Deb822),
+            _ => None,
+        }
+    }
+}
+
+/// Where a value is written in a sources file.
+#[derive(Clone, Copy, Debug)]
+struct Span {
+    line:  usize,
+    start: usize,
+    end:   usize,
+}
+
+impl Span {
+    /// The span of `value`, which must be a slice of `line`.
+    fn of(number: usize, line: &str, value: &str) -> Self {
+        let start = value.as_ptr() as usize - line.as_ptr() as usize;
+        Span { line: number, start, end: start + value.len() }
+    }
+
+    fn get<'a>(&self, lines: &'a [String]) -> &'a str { &lines[self.line][self.start..self.end] }
+}
+
+/// The URIs and suites of an enabled entry, or stanza, in a sources file.
+#[derive(Debug)]
+struct Entry {
+    uris:   Vec<Span>,
+    suites: Vec<Span>,
+}
+
+/// A parsed sources file, which is displayed exactly as it was read unless rewritten.
+#[derive(Debug)]
+pub struct SourcesFile {
+    format:  Format,
+    lines:   Vec<String>,
+    entries: Vec<Entry>,
+}
+
+impl SourcesFile {
+    pub fn parse(format: Format, contents: &str) -> Result<Self, SyntaxError> {
+        let lines: Vec<String> = contents.split('\n').map(String::from).collect();
+
+        let entries = match format {
+            Format::List => list_entries(&lines)?,
+            Format::Deb822 => deb822_entries(&lines)?,
+        };
+
+        Ok(Self { format, lines, entries })
+    }
+
+    /// The suites of every enabled entry, such as `focal` or `focal-updates`.
+    pub fn suites(&self) -> impl Iterator<Item = &str> {
+        let lines = &self.lines;
+        self.entries.iter().flat_map(move |entry| entry.suites.iter().map(move |s| s.get(lines)))
+    }
+
+    /// Each URI and suite pair of every enabled entry.
+    pub fn sources(&self) -> impl Iterator<Item = (&str, &str)> {
+        let lines = &self.lines;
+        self.entries.iter().flat_map(move |entry| {
+            entry.uris.iter().flat_map(move |uri| {
+                entry.suites.iter().map(move |suite| (uri.get(lines), suite.get(lines)))
+            })
+        })
+    }
+
+    /// Replaces the `from` codename with `to` in the suites of every enabled entry.
+    ///
+    /// Returns the number of suites that were rewritten. If `to` is not a suite which may be
+    /// written in place of `from`, the file is left as it was.
+    pub fn rewrite(&mut self, from: &str, to: &str) -> Result<usize, SyntaxError> {
+        let mut lines = self.lines.clone();
+        let mut rewritten = 0;
+
+        // Suites are rewritten back to front so that the spans of those before remain valid.
+        for span in self.entries.iter().flat_map(|entry| entry.suites.iter()).rev() {
+            let line = &mut lines[span.line];
+            if let Some(suite) = rewrite_suite(&line[span.start..span.end], from, to) {
+                line.replace_range(span.start..span.end, &suite);
+                rewritten += 1;
+            }
+        }
+
+        if rewritten != 0 {
+            *self = Self::parse(self.format, &lines.join("\n"))?;
+        }
+
+        Ok(rewritten)
+    }
+
+    /// The distinct Ubuntu releases which the suites of enabled entries refer to, by codename.
+    pub fn codenames(&self) -> Vec<&str> {
+        let mut codenames = Vec::new();
+
+        for codename in self.suites().filter_map(suite_codename) {
+            if !codenames.contains(&codename) {
+                codenames.push(codename);
+            }
+        }
+
+        codenames
+    }
+
+    /// Whether any enabled entry still refers to the `codename` release.
+    pub fn refers_to(&self, codename: &str) -> bool {
+        self.suites().any(|suite| rewrite_suite(suite, codename, "").is_some())
+    }
+}
+
+impl Display for SourcesFile {
+    fn fmt(&self, fmt: &mut Formatter) -> fmt::Result { fmt.write_str(&self.lines.join("\n")) }
+}
+
+/// Rewrites the suites of the given sources files from one codename to another.
+///
+/// Every file is parsed and rewritten before any are written, so that a file which cannot be
+/// parsed leaves all sources untouched. Returns the files which were modified.
+pub fn rewrite_files(
+    paths: Vec<PathBuf>,
+    from: &str,
+    to: &str,
+) -> Result<Vec<PathBuf>, SourcesError> {
+    let mut rewritten = Vec::new();
+
+    for path in paths {
+        let format = match Format::of(&path) {
+            Some(format) => format,
+            None => continue,
+        };
+
+        let contents =
+            fs::read_to_string(&path).map_err(|why| SourcesError::Read(path.clone(), why))?;
+        let syntax = |why| SourcesError::Syntax(path.clone(), why);
+        let mut file = SourcesFile::parse(format, &contents).map_err(syntax)?;
+
+        if file.rewrite(from, to).map_err(syntax)? == 0 {
+            continue;
+        }
+
+        if file.refers_to(from) {
+            return Err(SourcesError::Unrewritten(path, from.into()));
+        }
+
+        rewritten.push((path, file));
+    }
+
+    for (path, file) in &rewritten {
+        info!("rewriting suites in {} from {} to {}", path.display(), from, to);
+
+        // Write to a temporary file first so that a partial write never replaces a source.
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, file.to_string())
+            .and_then(|_| fs::rename(&temporary, path))
+            .map_err(|why| SourcesError::Write(path.clone(), why))?;
+    }
+
+    Ok(rewritten.into_iter().map(|(path, _)| path).collect())
+}
+
+/// Whether the repository at `uri` publishes a `Release` file for `suite`.
+pub fn published(uri: &str, suite: &str) -> bool {
+    let url = release_url(uri, suite);
+    crate::http::client(&url)
+        .and_then(|client| client.head(url))
+        .map_or(false, |resp| resp.status().is_success())
+}
+
+/// The URL of the `Release` file for a suite, which may be an exact path ending with `/`.
+fn release_url(uri: &str, suite: &str) -> String {
+    let uri = uri.trim_end_matches('/');
+
+    if suite.ends_with('/') {
+        [uri, "/", suite, "Release"].concat()
+    } else {
+        [uri, "/dists/", suite, "/Release"].concat()
+    }
+}
+
+/// `/etc/apt/sources.list`, followed by the files in `/etc/apt/sources.list.d`.
+pub fn source_files() -> Result<Vec<PathBuf>, SourcesError> {
+    let mut files = Vec::new();
+
+    if Path::new(MAIN_FILE).exists() {
+        files.push(PathBuf::from(MAIN_FILE));
+    }
+
+    let dir = match fs::read_dir(PPA_DIR) {
+        Ok(dir) => dir,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(files),
+        Err(why) => return Err(SourcesError::Read(PPA_DIR.into(), why)),
+    };
+
+    let mut entries = dir
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect::<Vec<_>>();
+
+    entries.sort();
+    files.extend(entries);
+
+    Ok(files)
+}
+
+/// The Ubuntu codename of a suite, such as `focal` for `focal-updates`.
+fn suite_codename(suite: &str) -> Option<&str> {
+    let codename = suite.split('-').next()?;
+    codename.parse::<Codename>().ok().map(|_| codename)
+}
+
+/// The suite with the `from` codename replaced by `to`, if it is a suite of the `from` release.
+fn rewrite_suite(suite: &str, from: &str, to: &str) -> Option<String> {
+    if suite == from {
+        Some(to.into())
+    } else if suite.starts_with(from) && suite[from.len()..].starts_with('-') {
+        Some([to, &suite[from.len()..]].concat())
+    } else {
+        None
+    }
+}
+
+/// Locates the URI and suite of each `deb` and `deb-src` line, skipping those which are
+/// commented out.
+fn list_entries(lines: &[String]) -> Result<Vec<Entry>, SyntaxError> {
+    let mut entries = Vec::new();
+
+    for (number, line) in lines.iter().enumerate() {
+        let error = |message| SyntaxError { line: number + 1, message };
+
+        // Everything after a `#` is a comment.
+        let entry = line.split('#').next().unwrap_or("").trim();
+        if entry.is_empty() {
+            continue;
+        }
+
+        let mut rest = match entry.find(char::is_whitespace) {
+            Some(pos) if &entry[..pos] == "deb" || &entry[..pos] == "deb-src" => {
+                entry[pos..].trim_start()
+            }
+            _ => return Err(error("expected `deb` or `deb-src`")),
+        };
+
+        if rest.starts_with('[') {
+            rest = match rest.find(']') {
+                Some(end) => &rest[end + 1..],
+                None => return Err(error("options are missing a closing `]`")),
+            };
+        }
+
+        let mut fields = rest.split_whitespace();
+        let uri = fields.next().ok_or_else(|| error("missing URI"))?;
+        let suite = fields.next().ok_or_else(|| error("missing suite"))?;
+
+        // A suite which ends with `/` is an exact path, which does not have components.
+        if !suite.ends_with('/') && fields.next().is_none() {
+            return Err(error("missing components"));
+        }
+
+        entries.push(Entry {
+            uris:   vec![Span::of(number, line, uri)],
+            suites: vec![Span::of(number, line, suite)],
+        });
+    }
+
+    Ok(entries)
+}
+
+/// Locates the `URIs` and `Suites` fields of each stanza, skipping stanzas which are disabled.
+fn deb822_entries(lines: &[String]) -> Result<Vec<Entry>, SyntaxError> {
+    #[derive(Default)]
+    struct Stanza {
+        start:      usize,
+        enabled:    bool,
+        types:      bool,
+        components: bool,
+        uris:       Vec<Span>,
+        suites:     Vec<Span>,
+    }
+
+    impl Stanza {
+        fn finish(self, lines: &[String], entries: &mut Vec<Entry>) -> Result<(), SyntaxError> {
+            let error = |message| SyntaxError { line: self.start + 1, message };
+
+            if !self.types {
+                return Err(error("stanza is missing a `Types` field"));
+            } else if self.uris.is_empty() {
+                return Err(error("stanza is missing a `URIs` field"));
+            } else if self.suites.is_empty() {
+                return Err(error("stanza is missing a `Suites` field"));
+            }
+
+            let exact = |span: &Span| span.get(lines).ends_with('/');
+            if !self.components && !self.suites.iter().all(exact) {
+                return Err(error("stanza is missing a `Components` field"));
+            }
+
+            if self.enabled {
+                entries.push(Entry { uris: self.uris, suites: self.suites });
+            }
+
+            Ok(())
+        }
+    }
+
+    let mut entries = Vec::new();
+    let mut stanza: Option<Stanza> = None;
+    let mut field = "";
+
+    for (number, line) in lines.iter().enumerate() {
+        if line.trim().is_empty() {
+            if let Some(stanza) = stanza.take() {
+                stanza.finish(lines, &mut entries)?;
+            }
+
+            field = "";
+            continue;
+        }
+
+        if line.starts_with('#') {
+            continue;
+        }
+
+        let current = stanza.get_or_insert_with(|| Stanza {
+            start: number,
+            enabled: true,
+            ..Stanza::default()
+        });
+
+        // Lines beginning with whitespace continue the value of the previous field.
+        let value = if line.starts_with(char::is_whitespace) {
+            line.as_str()
+        } else {
+            let colon = line
+                .find(':')
+                .ok_or(SyntaxError { line: number + 1, message: "expected `Field: value`" })?;
+
+            field = &line[..colon];
+            let value = &line[colon + 1..];
+
+            if field.eq_ignore_ascii_case("Enabled") {
+                current.enabled = !value.trim().eq_ignore_ascii_case("no");
+            } else if field.eq_ignore_ascii_case("Types") {
+                current.types = true;
+            } else if field.eq_ignore_ascii_case("Components") {
+                current.components = true;
+            }
+
+            value
+        };
+
+        let spans = if field.eq_ignore_ascii_case("URIs") {
+            &mut current.uris
+        } else if field.eq_ignore_ascii_case("Suites") {
+            &mut current.suites
+        } else {
+            continue;
+        };
+
+        spans.extend(value.split_whitespace().map(|value| Span::of(number, line, value)));
+    }
+
+    if let Some(stanza) = stanza {
+        stanza.finish(lines, &mut entries)?;
+    }
+
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::release::repos::{default_sources, new_system_sources, pop_apps_source};
+
+    const LIST: &str = "# Ubuntu Repositories
+
+deb http://us.archive.ubuntu.com/ubuntu/ focal restricted multiverse universe main
+deb-src  http://us.archive.ubuntu.com/ubuntu/   focal-updates main # trailing comment
+deb [arch=amd64 signed-by=/usr/share/keyrings/pop.gpg] http://apt.pop-os.org/proprietary focal main
+# deb http://ppa.launchpad.net/example/ppa/ubuntu focal main
+deb http://example.com/flat ./
+deb http://security.ubuntu.com/ubuntu focalish main
+";
+
+    const DEB822: &str = "X-Repolib-Name: Pop_OS System Sources
+Enabled: yes
+Types: deb deb-src
+URIs: http://us.archive.ubuntu.com/ubuntu/
+Suites: focal focal-security
+  focal-updates focal-backports
+Components: main restricted universe multiverse
+
+# A disabled source
+Enabled: no
+Types: deb
+URIs: http://example.com/ubuntu
+Suites: focal
+Components: main
+";
+
+    fn suites(file: &SourcesFile) -> Vec<&str> { file.suites().collect() }
+
+    #[test]
+    fn list_round_trip() {
+        let file = SourcesFile::parse(Format::List, LIST).unwrap();
+        assert_eq!(file.to_string(), LIST);
+        assert_eq!(suites(&file), vec!["focal", "focal-updates", "focal", "./", "focalish"]);
+
+        let generated = default_sources("focal");
+        let file = SourcesFile::parse(Format::List, &generated).unwrap();
+        assert_eq!(file.to_string(), generated);
+    }
+
+    #[test]
+    fn list_rewrite() {
+        let mut file = SourcesFile::parse(Format::List, LIST).unwrap();
+        assert_eq!(file.rewrite("focal", "groovy"), Ok(3));
+        assert!(!file.refers_to("focal"));
+
+        let expected = LIST
+            .replace("/ focal ", "/ groovy ")
+            .replace("focal-updates", "groovy-updates")
+            .replace("proprietary focal", "proprietary groovy");
+        assert_eq!(file.to_string(), expected);
+
+        let generated = default_sources("focal");
+        let mut file = SourcesFile::parse(Format::List, &generated).unwrap();
+        file.rewrite("focal", "groovy").unwrap();
+        assert_eq!(file.to_string(), default_sources("groovy"));
+
+        // A suite which would leave the file unparseable is refused.
+        let mut file = SourcesFile::parse(Format::List, LIST).unwrap();
+        assert!(file.rewrite("focal", "").is_err());
+        assert_eq!(file.to_string(), LIST);
+    }
+
+    #[test]
+    fn codenames() {
+        let file = SourcesFile::parse(Format::List, LIST).unwrap();
+        assert_eq!(file.codenames(), vec!["focal"]);
+
+        let mut file = SourcesFile::parse(Format::Deb822, DEB822).unwrap();
+        file.rewrite("focal", "groovy").unwrap();
+        assert_eq!(file.codenames(), vec!["groovy"]);
+
+        let mixed = "deb http://a.com focal main\ndeb http://a.com groovy-updates main\n";
+        let file = SourcesFile::parse(Format::List, mixed).unwrap();
+        assert_eq!(file.codenames(), vec!["focal", "groovy"]);
+    }
+
+    #[test]
+    fn deb822_round_trip() {
+        let file = SourcesFile::parse(Format::Deb822, DEB822).unwrap();
+        assert_eq!(file.to_string(), DEB822);
+        assert_eq!(
+            suites(&file),
+            vec!["focal", "focal-security", "focal-updates", "focal-backports"]
+        );
+        assert_eq!(
+            file.sources().nth(3),
+            Some(("http://us.archive.ubuntu.com/ubuntu/", "focal-backports"))
+        );
+    }
+
+    #[test]
+    fn deb822_rewrite() {
+        let mut file = SourcesFile::parse(Format::Deb822, DEB822).unwrap();
+        assert_eq!(file.rewrite("focal", "groovy"), Ok(4));
+        assert!(!file.refers_to("focal"));
+        assert!(file.to_string().ends_with("Suites: focal\nComponents: main\n"));
+
+        let generators: [fn(&str) -> String; 2] = [new_system_sources, pop_apps_source];
+        for generate in &generators {
+            let mut file = SourcesFile::parse(Format::Deb822, &generate("focal")).unwrap();
+            file.rewrite("focal", "groovy").unwrap();
+            assert_eq!(file.to_string(), generate("groovy"));
+        }
+    }
+
+    #[test]
+    fn release_urls() {
+        assert_eq!(
+            release_url("http://us.archive.ubuntu.com/ubuntu/", "focal-updates"),
+            "http://us.archive.ubuntu.com/ubuntu/dists/focal-updates/Release"
+        );
+        assert_eq!(
+            release_url("http://example.com/flat", "./"),
+            "http://example.com/flat/./Release"
+        );
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let error = |format, contents| SourcesFile::parse(format, contents).unwrap_err();
+
+        assert_eq!(error(Format::List, "deb http://example.com\n").message, "missing suite");
+        assert_eq!(error(Format::List, "\ndeb http://a.com focal\n").line, 2);
+        assert_eq!(error(Format::List, "rpm http://a.com focal main").line, 1);
+        assert_eq!(
+            error(Format::List, "deb [arch=amd64 http://a.com focal main").message,
+            "options are missing a closing `]`"
+        );
+
+        assert_eq!(
+            error(Format::Deb822, "Types: deb\nSuites: focal\nComponents: main\n").message,
+            "stanza is missing a `URIs` field"
+        );
+        assert_eq!(error(Format::Deb822, "Types: deb\nnonsense\n").line, 2);
+    }
+}
@@ -1,4 +1,5 @@
 use super::*;
+use crate::loader;
 
 use anyhow::Context;
 use std::fs;
@@ -39,6 +40,10 @@ impl BootConf {
             previous = Self::DEFAULT_BOOT;
         }
 
+        if crate::dual_boot::boots_windows(previous) {
+            warn!("changing the default boot entry away from {}, which boots Windows", previous);
+        }
+
         let _ = fs::write(PREVIOUS_DEFAULT, previous);
 
         modify(&mut self.0)?;
@@ -109,9 +114,44 @@ pub fn upgrade_set(from: &str, to: &str) -> RelResult<()> {
         .map(<&'static str>::from)
         .unwrap_or(to);
 
-    fs::write(STARTUP_UPGRADE_FILE, &format!("{} {}", current, new))
+    // The upgrade entry is added before the upgrade is staged, so that nothing is staged if it
+    // cannot be written. An unusable default entry is only worth a warning, as it may be repaired
+    // before the system is rebooted.
+    let systemd_boot = Path::new(loader::ENTRIES).exists();
+    if systemd_boot {
+        if let Err(why) = loader::verify_default() {
+            warn!("the default boot entry is unusable: {}", crate::misc::format_error(&why));
+        }
+
+        loader::add_upgrade_entry().map_err(ReleaseError::Loader)?;
+    }
+
+    let staged = fs::write(STARTUP_UPGRADE_FILE, &format!("{} {}", current, new))
         .and_then(|_| symlink("/var/cache/apt/archives", SYSTEM_UPDATE))
-        .map_err(ReleaseError::StartupFileCreation)
+        .and_then(|_| health::UpgradeRecord::store(current, new));
+
+    if let Err(why) = staged {
+        if systemd_boot {
+            let _ = loader::remove(loader::UPGRADE_ENTRY);
+        }
+
+        return Err(ReleaseError::StartupFileCreation(why));
+    }
+
+    if let Err(why) = kernelstub::capture() {
+        warn!("{}", crate::misc::format_error(why.as_ref()));
+    }
+
+    Ok(())
+}
+
+/// Stage the packages which were fetched for the current release to be installed by systemd at
+/// startup, instead of upgrading them on the running system.
+pub fn update_set() -> RelResult<()> {
+    upgrade_prereq()?;
+
+    let _ = fs::remove_file(SYSTEM_UPDATE);
+    symlink("/var/cache/apt/archives", SYSTEM_UPDATE).map_err(ReleaseError::StartupFileCreation)
 }
 
 /// Validate that the pre-required files for performing a system upgrade are in place.
@@ -0,0 +1,46 @@
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, io, path::PathBuf};
+
+/// Location where responses from the release API are cached.
+const CACHE_DIR: &str = "/var/cache/pop-upgrade/api";
+
+/// A response from the release API, with the validators needed to revalidate it.
+#[derive(Debug, Deserialize, Serialize)]
+pub struct Entry {
+    pub etag:          Option<String>,
+    pub last_modified: Option<String>,
+    pub body:          String,
+}
+
+impl Entry {
+    /// Loads the cached response for `url`, if one exists and is readable.
+    pub fn load(url: &str) -> Option<Self> {
+        let data = fs::read(path(url)).ok()?;
+        match serde_json::from_slice(&data) {
+            Ok(entry) => Some(entry),
+            Err(why) => {
+                warn!("ignoring corrupt cache entry for {}: {}", url, why);
+                None
+            }
+        }
+    }
+
+    /// Caches this response for `url`.
+    pub fn store(&self, url: &str) -> io::Result<()> {
+        let data = serde_json::to_vec(self)?;
+        fs::create_dir_all(CACHE_DIR)?;
+
+        // Write to a temporary file first so that a partial write never replaces a good entry.
+        let path = path(url);
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, &data)?;
+        fs::rename(&temporary, &path)
+    }
+}
+
+/// URLs are hashed to produce a file name which is safe to use in the cache directory.
+fn path(url: &str) -> PathBuf {
+    let key = hex::encode(Sha256::digest(url.as_bytes()));
+    PathBuf::from([CACHE_DIR, "/", &key, ".json"].concat())
+}
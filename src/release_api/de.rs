@@ -0,0 +1,50 @@
+//! Deserializers for fields which the release API encodes as strings.
+
+use serde::de::{self, Deserializer, Unexpected};
+use std::{convert::TryFrom, fmt};
+
+/// Accepts a build number as either an integer, or a string containing an integer.
+pub fn build<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u16, D::Error> {
+    struct Visitor;
+
+    impl<'de> de::Visitor<'de> for Visitor {
+        type Value = u16;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a build number between 0 and 65535")
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<u16, E> {
+            u16::try_from(value).map_err(|_| E::invalid_value(Unexpected::Unsigned(value), &self))
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<u16, E> {
+            value.parse().map_err(|_| E::invalid_value(Unexpected::Str(value), &self))
+        }
+    }
+
+    deserializer.deserialize_any(Visitor)
+}
+
+/// Accepts either a boolean, or the strings `"true"` and `"false"`.
+pub fn boolean<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    struct Visitor;
+
+    impl<'de> de::Visitor<'de> for Visitor {
+        type Value = bool;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result { f.write_str("a boolean") }
+
+        fn visit_bool<E: de::Error>(self, value: bool) -> Result<bool, E> { Ok(value) }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<bool, E> {
+            match value {
+                "true" => Ok(true),
+                "false" => Ok(false),
+                _ => Err(E::invalid_value(Unexpected::Str(value), &self)),
+            }
+        }
+    }
+
+    deserializer.deserialize_any(Visitor)
+}
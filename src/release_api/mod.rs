@@ -0,0 +1,361 @@
+mod cache;
+mod de;
+
+use self::cache::Entry;
+use crate::config::Config;
+use async_io::Timer;
+use chrono::{DateTime, Utc};
+use futures::io::AsyncReadExt;
+use isahc::{
+    config::Configurable,
+    http::{header, Request, Response, StatusCode},
+    AsyncBody, HttpClient,
+};
+use rand::Rng;
+use serde_derive::Deserialize;
+use std::{env, time::Duration};
+use thiserror::Error;
+
+const BASE: &str = "https://api.pop-os.org/";
+
+/// Number of times a request which failed due to a transient error will be retried.
+const RETRIES: u32 = 3;
+
+/// Delay before the first retry, which doubles with each subsequent attempt.
+const RETRY_DELAY_MS: u64 = 500;
+
+/// Assumed wait when the server throttles requests without specifying a `Retry-After`.
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Longest `Retry-After` that will be waited on before retrying a throttled request.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Maximum time that a single request to the release API may take.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Environment variable which overrides the release API endpoint, taking precedence over the
+/// endpoint in the configuration file.
+pub const ENDPOINT_ENV: &str = "POP_UPGRADE_API";
+
+#[derive(Debug, Error)]
+pub enum ApiError {
+    #[error("failed to GET release API")]
+    Get(#[from] isahc::Error),
+
+    #[error("release manifest has an invalid `{}` field", field)]
+    Manifest { field: String, source: serde_json::Error },
+
+    #[error("failed to read response body")]
+    Read(#[source] std::io::Error),
+
+    #[error("failed to build request")]
+    Request(#[source] isahc::http::Error),
+
+    #[error("server returned an error status: {:?}", _0)]
+    Status(isahc::http::StatusCode),
+
+    #[error("server is throttling requests: retry after {:?}", _0)]
+    Throttled(Duration),
+}
+
+/// A release manifest, as returned by the release API.
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub version:       String,
+    pub url:           String,
+    pub size:          u64,
+    pub sha_sum:       String,
+    pub channel:       String,
+    #[serde(deserialize_with = "de::build")]
+    pub build:         u16,
+    #[serde(deserialize_with = "de::boolean")]
+    pub urgent:        bool,
+    /// The algorithm of `sha_sum`, if the API specifies it.
+    #[serde(default)]
+    pub checksum_type: Option<String>,
+    /// Location of a detached GPG signature of the ISO, if the API provides one.
+    #[serde(default)]
+    pub signature_url: Option<String>,
+    /// Location of the release notes for this release, if the API provides one.
+    #[serde(default)]
+    pub release_notes: Option<String>,
+    /// Percentage of machines that the release is being offered to, if it is being phased in.
+    #[serde(default)]
+    pub rollout:       Option<u8>,
+    /// Set when the API was unreachable, and this release was served from the cache.
+    #[serde(skip)]
+    pub stale:         bool,
+}
+
+impl Release {
+    /// The checksum of the ISO, tagged with its algorithm if the API specified one.
+    pub fn checksum(&self) -> String {
+        match self.checksum_type {
+            Some(ref algorithm) => [algorithm.as_str(), ":", &self.sha_sum].concat(),
+            None => self.sha_sum.clone(),
+        }
+    }
+
+    /// Blocks the current thread until the release has been fetched.
+    ///
+    /// Prefer `get_release_async` from an async context.
+    pub fn get_release(version: &str, channel: &str) -> Result<Release, ApiError> {
+        async_io::block_on(Self::get_release_async(version, channel))
+    }
+
+    pub async fn get_release_async(version: &str, channel: &str) -> Result<Release, ApiError> {
+        info!("checking for build {} in channel {}", version, channel);
+        let url = [&*endpoint(), "builds/", version, "/", channel].concat();
+
+        let (body, stale) = get(&url).await?;
+
+        let mut release = Self::parse(&body)?;
+        release.stale = stale;
+        Ok(release)
+    }
+
+    /// Parses a release manifest, identifying the field at fault if it is malformed.
+    pub fn parse(manifest: &str) -> Result<Release, ApiError> {
+        let deserializer = &mut serde_json::Deserializer::from_str(manifest);
+        serde_path_to_error::deserialize(deserializer).map_err(|why| ApiError::Manifest {
+            field:  why.path().to_string(),
+            source: why.into_inner(),
+        })
+    }
+
+    pub fn build_exists(version: &str, channel: &str) -> Result<u16, ApiError> {
+        Self::get_release(version, channel).map(|r| r.build)
+    }
+
+    pub async fn build_exists_async(version: &str, channel: &str) -> Result<u16, ApiError> {
+        Self::get_release_async(version, channel).await.map(|r| r.build)
+    }
+}
+
+/// Fetches the table of supported releases and their upgrade paths, in TOML.
+pub fn upgrade_paths() -> Result<String, ApiError> {
+    let url = [&*endpoint(), "upgrade-paths.toml"].concat();
+    async_io::block_on(get(&url)).map(|(table, _stale)| table)
+}
+
+/// Fetches the ASCII-armored detached signature of the table of upgrade paths.
+pub fn upgrade_paths_signature() -> Result<String, ApiError> {
+    let url = [&*endpoint(), "upgrade-paths.toml.asc"].concat();
+    async_io::block_on(get(&url)).map(|(signature, _stale)| signature)
+}
+
+/// Fetches the release notes document that a release manifest links to.
+pub fn release_notes(url: &str) -> Result<String, ApiError> {
+    async_io::block_on(get(url)).map(|(notes, _stale)| notes)
+}
+
+/// GETs a resource from the release API, revalidating any cached copy of it.
+///
+/// If the API cannot be reached, the cached copy is returned instead, and flagged as stale.
+async fn get(url: &str) -> Result<(String, bool), ApiError> {
+    let cached = Entry::load(url);
+
+    match request(url, cached.as_ref()).await {
+        Ok(response) if response.status() == StatusCode::NOT_MODIFIED => match cached {
+            Some(entry) => Ok((entry.body, false)),
+            None => Err(ApiError::Status(StatusCode::NOT_MODIFIED)),
+        },
+        Ok(response) => {
+            let etag = header_value(&response, header::ETAG);
+            let last_modified = header_value(&response, header::LAST_MODIFIED);
+
+            let mut body = String::new();
+            response.into_body().read_to_string(&mut body).await.map_err(ApiError::Read)?;
+
+            let entry = Entry { etag, last_modified, body };
+
+            if let Err(why) = entry.store(url) {
+                warn!("failed to cache response from {}: {}", url, why);
+            }
+
+            Ok((entry.body, false))
+        }
+        Err(why) => match cached {
+            Some(entry) if is_transient(&why) || matches!(why, ApiError::Throttled(_)) => {
+                warn!("serving stale response for {}: {}", url, why);
+                Ok((entry.body, true))
+            }
+            _ => Err(why),
+        },
+    }
+}
+
+/// Sends a conditional GET request, retrying with exponential backoff on transient errors.
+///
+/// If the server throttles the request, it will be retried once after the requested delay.
+async fn request(url: &str, cached: Option<&Entry>) -> Result<Response<AsyncBody>, ApiError> {
+    let client = crate::http::client(url)?;
+    let mut attempt = 0;
+    let mut throttled = false;
+
+    loop {
+        let result = send(&client, url, cached).await.and_then(|response| {
+            let status = response.status();
+            if status.is_success() || status == StatusCode::NOT_MODIFIED {
+                Ok(response)
+            } else if status == StatusCode::TOO_MANY_REQUESTS {
+                Err(ApiError::Throttled(retry_after(&response)))
+            } else {
+                Err(ApiError::Status(status))
+            }
+        });
+
+        match result {
+            Err(ApiError::Throttled(wait)) if !throttled && wait <= MAX_RETRY_AFTER => {
+                warn!("request to {} was throttled: retrying in {:?}", url, wait);
+                Timer::after(wait).await;
+                throttled = true;
+            }
+            Err(ref why) if attempt < RETRIES && is_transient(why) => {
+                let delay = backoff(attempt);
+                warn!("request to {} failed: {}: retrying in {:?}", url, why, delay);
+                Timer::after(delay).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+async fn send(
+    client: &HttpClient,
+    url: &str,
+    cached: Option<&Entry>,
+) -> Result<Response<AsyncBody>, ApiError> {
+    let mut request = Request::get(url).timeout(TIMEOUT);
+
+    if let Some(entry) = cached {
+        if let Some(ref etag) = entry.etag {
+            request = request.header(header::IF_NONE_MATCH, etag.as_str());
+        }
+
+        if let Some(ref last_modified) = entry.last_modified {
+            request = request.header(header::IF_MODIFIED_SINCE, last_modified.as_str());
+        }
+    }
+
+    let request = request.body(()).map_err(ApiError::Request)?;
+    client.send_async(request).await.map_err(ApiError::Get)
+}
+
+fn header_value<B>(response: &Response<B>, name: header::HeaderName) -> Option<String> {
+    response.headers().get(name)?.to_str().ok().map(String::from)
+}
+
+/// How long the server asked us to wait before retrying a throttled request.
+fn retry_after<B>(response: &Response<B>) -> Duration {
+    header_value(response, header::RETRY_AFTER)
+        .and_then(|value| parse_retry_after(&value, Utc::now()))
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+}
+
+/// `Retry-After` may be given as either a number of seconds, or a HTTP date.
+fn parse_retry_after(value: &str, now: DateTime<Utc>) -> Option<Duration> {
+    let value = value.trim();
+
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some((date.with_timezone(&Utc) - now).to_std().unwrap_or_default())
+}
+
+/// Connection issues and server errors may resolve themselves when retried.
+fn is_transient(error: &ApiError) -> bool {
+    match error {
+        ApiError::Get(why) => why.is_network() || why.is_timeout(),
+        ApiError::Status(status) => status.is_server_error(),
+        _ => false,
+    }
+}
+
+/// The delay before the next attempt, with jitter to avoid synchronized retries across clients.
+fn backoff(attempt: u32) -> Duration {
+    let delay = RETRY_DELAY_MS << attempt;
+    Duration::from_millis(delay + rand::thread_rng().gen_range(0..=delay / 2))
+}
+
+/// The base URL of the release API, ending with a `/`.
+pub fn endpoint() -> String {
+    let mut endpoint = env::var(ENDPOINT_ENV)
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+        .or_else(|| Config::load_or_default().api.endpoint)
+        .unwrap_or_else(|| BASE.to_owned());
+
+    if !endpoint.ends_with('/') {
+        endpoint.push('/');
+    }
+
+    endpoint
+}
+
+#[test]
+pub fn release_exists() {
+    let result = Release::get_release("20.04", "intel");
+    assert!(result.is_ok());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"{
+        "version": "20.10",
+        "url": "https://example.com/pop-os_20.10_amd64_intel_11.iso",
+        "size": 2487779328,
+        "sha_sum": "2b4d1c3c5f6a",
+        "channel": "intel",
+        "build": "11",
+        "urgent": "false"
+    }"#;
+
+    #[test]
+    fn manifest() {
+        let release = Release::parse(MANIFEST).unwrap();
+        assert_eq!(release.build, 11);
+        assert_eq!(release.size, 2487779328);
+        assert!(!release.urgent);
+        assert!(!release.stale);
+        assert_eq!(release.rollout, None);
+
+        let phased = MANIFEST.replace(r#""build""#, r#""rollout": 25, "build""#);
+        assert_eq!(Release::parse(&phased).unwrap().rollout, Some(25));
+    }
+
+    #[test]
+    fn manifest_field_errors() {
+        let field = |manifest: &str| match Release::parse(manifest) {
+            Err(ApiError::Manifest { field, .. }) => field,
+            other => panic!("expected a manifest error: {:?}", other),
+        };
+
+        assert_eq!(field(&MANIFEST.replace(r#""11""#, r#""eleven""#)), "build");
+        assert_eq!(field(&MANIFEST.replace(r#""false""#, r#""maybe""#)), "urgent");
+        assert_eq!(field(&MANIFEST.replace("2487779328", r#""big""#)), "size");
+    }
+
+    #[test]
+    fn retry_after() {
+        let now = DateTime::parse_from_rfc2822("Wed, 21 Oct 2015 07:28:00 GMT")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(parse_retry_after("120", now), Some(Duration::from_secs(120)));
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:29:30 GMT", now),
+            Some(Duration::from_secs(90))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::default())
+        );
+        assert_eq!(parse_retry_after("soon", now), None);
+    }
+}
@@ -1,26 +1,67 @@
-use std::io;
+use crate::config::Config;
+use std::{io, path::Path};
 use sysfs_class::{PciDevice, SysClass};
 use thiserror::Error;
 
+/// ISO variants which may be chosen in the configuration file.
+const VARIANTS: &[&str] = &["intel", "nvidia", "raspi"];
+
+/// Exists when the NVIDIA kernel driver is loaded.
+const NVIDIA_MODULE: &str = "/sys/module/nvidia";
+
 #[derive(Debug, Error)]
 pub enum ReleaseArchError {
     #[error("error when probing PCI device")]
     PciProbe(#[source] io::Error),
 
+    #[error("error fetching class of PCI device")]
+    PciClass(#[source] io::Error),
+
     #[error("error fetching vendor ID of PCI device")]
     PciVendor(#[source] io::Error),
+
+    #[error("configured release variant ({}) is not one of: {}", _0, VARIANTS.join(", "))]
+    UnknownVariant(String),
 }
 
-/// Probe PCI devices for the existence of NVIDIA hardware, and return either "intel" or "nvidia".
+/// Detect which variant of the ISO this system requires.
+///
+/// The variant set in the configuration file takes precedence. ARM64 systems use the "raspi"
+/// ISO. Otherwise, "nvidia" is chosen if the NVIDIA driver is loaded, or if NVIDIA graphics
+/// hardware is present, and "intel" is chosen for everything else.
 pub fn detect_arch() -> Result<&'static str, ReleaseArchError> {
+    if let Some(variant) = Config::load_or_default().release.variant {
+        return VARIANTS
+            .iter()
+            .find(|&&known| known == variant)
+            .cloned()
+            .ok_or(ReleaseArchError::UnknownVariant(variant));
+    }
+
+    if cfg!(target_arch = "aarch64") {
+        return Ok("raspi");
+    }
+
+    if Path::new(NVIDIA_MODULE).exists() || nvidia_graphics()? {
+        return Ok("nvidia");
+    }
+
+    Ok("intel")
+}
+
+/// Probe PCI devices for the existence of NVIDIA display controllers.
+fn nvidia_graphics() -> Result<bool, ReleaseArchError> {
+    const CLASS_DISPLAY: u32 = 0x03;
     const VID_NVIDIA: u16 = 0x10DE;
 
     for device in PciDevice::iter() {
         let device = device.map_err(ReleaseArchError::PciProbe)?;
-        if device.vendor().map_err(ReleaseArchError::PciVendor)? == VID_NVIDIA {
-            return Ok("nvidia");
+        if device.vendor().map_err(ReleaseArchError::PciVendor)? == VID_NVIDIA
+            && device.class().map_err(ReleaseArchError::PciClass)? >> 16 == CLASS_DISPLAY
+        {
+            return Ok(true);
         }
     }
 
-    Ok("intel")
+    Ok(false)
 }
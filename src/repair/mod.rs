@@ -2,6 +2,7 @@ pub mod crypttab;
 pub mod fstab;
 pub mod misc;
 pub mod packaging;
+pub mod sources;
 
 use self::fstab::FstabError;
 use std::io;
@@ -24,18 +25,24 @@ pub enum RepairError {
     #[error("packaging error")]
     Packaging(#[source] anyhow::Error),
 
+    #[error("failed to restore apt sources")]
+    Sources(#[source] anyhow::Error),
+
     #[error("failed to wipe pulseaudio settings for users")]
     WipePulse(#[source] io::Error),
 }
 
-pub async fn repair() -> Result<(), RepairError> {
+/// Repairs the system, returning a description of each source and package that was repaired.
+pub async fn repair() -> Result<Vec<String>, RepairError> {
     info!("performing release repair");
 
     crypttab::repair().map_err(RepairError::Crypttab)?;
     fstab::repair().map_err(RepairError::Fstab)?;
-    packaging::repair().await.map_err(RepairError::Packaging)?;
 
-    Ok(())
+    let mut repairs = sources::repair().map_err(RepairError::Sources)?;
+    repairs.extend(packaging::repair().await.map_err(RepairError::Packaging)?);
+
+    Ok(repairs)
 }
 
 pub fn pre_upgrade() -> Result<(), RepairError> {
@@ -1,16 +1,119 @@
+use crate::config::Config;
 use anyhow::Context;
 use apt_cmd::{AptGet, Dpkg};
+use std::process::Output;
 
-pub async fn repair() -> anyhow::Result<()> {
-    AptGet::new()
+/// Configures packages which dpkg left unconfigured, and then repairs broken dependencies,
+/// returning a description of each package that was repaired.
+///
+/// Packages which cannot be configured until their dependencies are repaired are configured
+/// again once they have been.
+pub async fn repair() -> anyhow::Result<Vec<String>> {
+    let configured = configure_all().await;
+    if let Err(ref why) = configured {
+        warn!("repairing dependencies before configuring packages: {:#}", why);
+    }
+
+    let conffiles = Config::load_or_default().release.conffiles;
+
+    let fixed = AptGet::new()
         .args(&["install", "-f", "-y", "--allow-downgrades"])
-        .status()
+        .args(conffiles.apt_options())
+        .env("LANG", "C")
+        .output()
         .await
-        .context("failed to repair broken packages with `apt-get install -f`")?;
+        .context("failed to repair broken packages with `apt-get install -f`")
+        .and_then(|output| succeeded("apt-get install -f", output))?;
+
+    let mut repairs = Vec::new();
 
+    match configured {
+        Ok(configured) => {
+            collect_repairs(&configured, &mut repairs);
+            collect_repairs(&fixed, &mut repairs);
+        }
+        Err(_) => {
+            collect_repairs(&fixed, &mut repairs);
+            collect_repairs(&configure_all().await?, &mut repairs);
+        }
+    }
+
+    for repair in &repairs {
+        info!("repaired packaging: {}", repair);
+    }
+
+    Ok(repairs)
+}
+
+async fn configure_all() -> anyhow::Result<String> {
     Dpkg::new()
         .configure_all()
-        .status()
+        .env("LANG", "C")
+        .output()
         .await
         .context("failed to configure packages with `dpkg --configure -a`")
+        .and_then(|output| succeeded("dpkg --configure -a", output))
+}
+
+fn succeeded(command: &str, output: Output) -> anyhow::Result<String> {
+    if output.status.success() {
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+
+    Err(anyhow!(
+        "`{}` exited with {}: {}",
+        command,
+        output.status,
+        String::from_utf8_lossy(&output.stderr).trim()
+    ))
+}
+
+/// Describes the packages which dpkg installed, configured, or removed in its output. Packages
+/// which were installed are not also described as configured.
+fn collect_repairs(output: &str, repairs: &mut Vec<String>) {
+    for line in output.lines() {
+        let (action, package) = if let Some(rest) = line.strip_prefix("Unpacking ") {
+            ("installed ", rest)
+        } else if let Some(rest) = line.strip_prefix("Setting up ") {
+            ("configured ", rest)
+        } else if let Some(rest) = line.strip_prefix("Removing ") {
+            ("removed ", rest)
+        } else {
+            continue;
+        };
+
+        let package = match package.split_whitespace().next() {
+            Some(package) => package,
+            None => continue,
+        };
+
+        let installed = ["installed ", package].concat();
+        let repair = [action, package].concat();
+
+        if !repairs.contains(&installed) && !repairs.contains(&repair) {
+            repairs.push(repair);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repairs() {
+        let configured = "Setting up libfoo1:amd64 (1.2-1) ...\n\
+                          Processing triggers for libc-bin (2.31-0ubuntu9) ...\n";
+
+        let fixed = "Reading package lists...\n\
+                     Removing bar (0.9-2) ...\n\
+                     Unpacking baz (3.0-1) ...\n\
+                     Setting up baz (3.0-1) ...\n";
+
+        let mut repairs = Vec::new();
+        collect_repairs(configured, &mut repairs);
+        collect_repairs(fixed, &mut repairs);
+
+        assert_eq!(repairs, vec!["configured libfoo1:amd64", "removed bar", "installed baz"]);
+    }
 }
@@ -0,0 +1,136 @@
+//! Restores apt sources which a failed upgrade left half-rewritten, such as those whose suites
+//! refer to the release being upgraded to, or which lack the Pop repositories.
+
+use crate::release::{
+    repos::{self, MAIN_FILE},
+    sources::{self, Format, SourcesFile},
+};
+use anyhow::Context;
+use std::{
+    convert::TryFrom,
+    fs,
+    path::{Path, PathBuf},
+};
+use ubuntu_version::{Codename, Version};
+
+/// Restores the sources of the current release, returning a description of each repair.
+///
+/// Sources are left as they are while a release upgrade is pending, as they refer to the release
+/// being upgraded to until it completes.
+pub fn repair() -> anyhow::Result<Vec<String>> {
+    if crate::release::upgrade_in_progress() {
+        info!("not repairing sources while a release upgrade is pending");
+        return Ok(Vec::new());
+    }
+
+    let version = Version::detect().context("failed to detect the current release")?;
+    let current = Codename::try_from(version)
+        .map_err(|_| anyhow!("the current release does not have a codename"))?;
+
+    repair_release(<&'static str>::from(current))
+}
+
+fn repair_release(current: &str) -> anyhow::Result<Vec<String>> {
+    let mut repairs = Vec::new();
+
+    if !Path::new(MAIN_FILE).exists() {
+        repos::create_new_sources_list(current).context("failed to create new sources.list")?;
+        repairs.push(fomat!("created " (MAIN_FILE) " for " (current)));
+    }
+
+    let managed = repos::release_sources(current)?
+        .into_iter()
+        .map(|(path, _)| PathBuf::from(path))
+        .collect::<Vec<_>>();
+
+    let mut stale: Vec<(String, Vec<PathBuf>)> = Vec::new();
+    let mut uris = Vec::new();
+
+    for path in sources::source_files()? {
+        let format = match Format::of(&path) {
+            Some(format) => format,
+            None => continue,
+        };
+
+        let contents = fs::read_to_string(&path)
+            .with_context(|| fomat!("failed to read " (path.display())))?;
+        let file = SourcesFile::parse(format, &contents)
+            .with_context(|| fomat!("failed to parse " (path.display())))?;
+
+        uris.extend(file.sources().map(|(uri, _)| normalize(uri).to_owned()));
+
+        let codenames = file.codenames();
+        for codename in stale_codenames(current, &codenames, managed.contains(&path)) {
+            match stale.iter_mut().find(|entry| entry.0 == codename) {
+                Some((_, paths)) => paths.push(path.clone()),
+                None => stale.push((codename.to_owned(), vec![path.clone()])),
+            }
+        }
+    }
+
+    for (codename, paths) in stale {
+        for path in sources::rewrite_files(paths, &codename, current)? {
+            repairs.push(fomat!(
+                "rewrote suites in " (path.display()) " from " (codename) " to " (current)
+            ));
+        }
+    }
+
+    // Existing files are left as they are, as their repositories may have been disabled.
+    for (uri, path, contents) in repos::pop_sources(current)? {
+        let uri_enabled = uris.iter().any(|enabled| enabled.as_str() == normalize(uri));
+        if uri_enabled || Path::new(path).exists() {
+            continue;
+        }
+
+        info!("restoring the Pop repository at {} in {}", uri, path);
+        fs::write(path, contents).with_context(|| fomat!("failed to create " (path)))?;
+        repairs.push(fomat!("restored " (uri) " in " (path)));
+    }
+
+    for repair in &repairs {
+        info!("repaired sources: {}", repair);
+    }
+
+    Ok(repairs)
+}
+
+/// A URI without its scheme or trailing slash, so that `http` and `https` mirrors of the same
+/// repository compare as equal.
+fn normalize(uri: &str) -> &str {
+    let uri = uri.find("://").map_or(uri, |pos| &uri[pos + 3..]);
+    uri.trim_end_matches('/')
+}
+
+/// The codenames of a sources file which are to be rewritten to the current release. The files
+/// which pop-upgrade manages must only refer to the current release, whereas other files are
+/// only rewritten if they refer to the current release alongside others, as third-party
+/// repositories may only publish suites of older releases.
+fn stale_codenames<'a>(current: &str, codenames: &[&'a str], managed: bool) -> Vec<&'a str> {
+    let mixed = codenames.len() > 1 && codenames.contains(&current);
+    if !managed && !mixed {
+        return Vec::new();
+    }
+
+    codenames.iter().cloned().filter(|&codename| codename != current).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stale() {
+        assert!(stale_codenames("focal", &["focal"], true).is_empty());
+        assert_eq!(stale_codenames("focal", &["groovy"], true), vec!["groovy"]);
+        assert!(stale_codenames("focal", &["bionic"], false).is_empty());
+        assert_eq!(stale_codenames("focal", &["focal", "groovy"], false), vec!["groovy"]);
+    }
+
+    #[test]
+    fn normalized_uris() {
+        assert_eq!(normalize("http://apt.pop-os.org/release/"), "apt.pop-os.org/release");
+        assert_eq!(normalize("https://apt.pop-os.org/release"), "apt.pop-os.org/release");
+        assert_eq!(normalize("apt.pop-os.org/release"), "apt.pop-os.org/release");
+    }
+}
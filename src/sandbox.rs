@@ -0,0 +1,131 @@
+//! Downloads are performed by a helper process, which runs as an unprivileged user without any
+//! capabilities, so that a malicious mirror which exploits the HTTP or TLS stack does not gain
+//! the daemon's root privileges.
+//!
+//! The helper writes the length of the response, followed by its body, to a pipe. The daemon
+//! writes the body to its destination, and validates it, as it is read from the pipe.
+
+use async_process::{unix::CommandExt, Child, ChildStdout, Command, Stdio};
+use futures::{io::AllowStdIo, prelude::*};
+use isahc::config::Configurable;
+use std::{
+    io::{self, BufWriter},
+    process::ExitStatus,
+    time::Duration,
+};
+use thiserror::Error;
+
+/// The system user which downloads are performed as, which is created on installation.
+pub const USER: &str = "_pop-upgrade";
+
+/// The hidden subcommand which the daemon invokes itself with to run the helper.
+pub const HELPER: &str = "fetch-helper";
+
+#[derive(Debug, Error)]
+pub enum SandboxError {
+    #[error("the {} user, which downloads are performed as, does not exist", USER)]
+    NoUser,
+
+    #[error("failed to spawn the download helper")]
+    Spawn(#[source] io::Error),
+
+    #[error("failed to read from the download helper")]
+    Read(#[source] io::Error),
+
+    #[error("download helper exited with {}", _0)]
+    Helper(ExitStatus),
+}
+
+/// A download which is being performed by the helper.
+pub struct Download {
+    /// The length of the body, if the server reported it.
+    pub length: Option<u64>,
+    pub body:   ChildStdout,
+    child:      Child,
+}
+
+impl Download {
+    /// Waits for the helper to exit, which fails if the body was not received in full.
+    pub async fn finish(mut self) -> Result<(), SandboxError> {
+        let status = self.child.status().await.map_err(SandboxError::Read)?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(SandboxError::Helper(status))
+        }
+    }
+}
+
+/// Downloads the URL from the helper. The helper is killed if the download is dropped.
+pub async fn download(url: &str) -> Result<Download, SandboxError> {
+    let user = users::get_user_by_name(USER).ok_or(SandboxError::NoUser)?;
+
+    let mut command = Command::new("/proc/self/exe");
+    command
+        .args(&[HELPER, url])
+        .current_dir("/")
+        .env_clear()
+        .env("PATH", "/usr/sbin:/usr/bin:/sbin:/bin")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .gid(user.primary_group_id())
+        .uid(user.uid());
+
+    // Capabilities are dropped along with root, and may not be regained through setuid binaries.
+    unsafe {
+        command.pre_exec(|| match libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) {
+            0 => Ok(()),
+            _ => Err(io::Error::last_os_error()),
+        });
+    }
+
+    let mut child = command.spawn().map_err(SandboxError::Spawn)?;
+    let mut body = child.stdout.take().expect("stdout of download helper is not piped");
+
+    let mut length = [0u8; 8];
+    if let Err(why) = body.read_exact(&mut length).await {
+        // The helper exits without writing the length if the request failed.
+        let status = child.status().await.map_err(SandboxError::Read)?;
+        return Err(if status.success() {
+            SandboxError::Read(why)
+        } else {
+            SandboxError::Helper(status)
+        });
+    }
+
+    let length = u64::from_le_bytes(length);
+    Ok(Download { length: if length == 0 { None } else { Some(length) }, body, child })
+}
+
+/// Runs the helper, which fetches the URL, and writes its length and body to stdout.
+pub async fn serve(url: &str) -> anyhow::Result<()> {
+    if users::get_current_uid() == 0 {
+        return Err(anyhow!("the download helper must not be run as root"));
+    }
+
+    let response = crate::http::builder(url)
+        .low_speed_timeout(1, Duration::from_secs(15))
+        .build()?
+        .get_async(url)
+        .await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(anyhow!("request for {} failed due to status code {}", url, status));
+    }
+
+    let length = response
+        .headers()
+        .get("content-length")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    let mut stdout = AllowStdIo::new(BufWriter::new(io::stdout()));
+    stdout.write_all(&length.to_le_bytes()).await?;
+    futures::io::copy(response.into_body(), &mut stdout).await?;
+    stdout.flush().await?;
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// The EFI variable which records whether Secure Boot is enforced.
+const SECURE_BOOT_VAR: &str =
+    "/sys/firmware/efi/efivars/SecureBoot-8be4df61-93ca-11d2-aa0d-00e098032b8c";
+
+/// The Machine Owner Key which DKMS signs the modules that it builds with.
+const MOK_CERTIFICATE: &str = "/var/lib/shim-signed/mok/MOK.der";
+
+/// Appended to kernel modules which have been signed.
+const MODULE_SIGNATURE: &[u8] = b"~Module signature appended~\n";
+
+/// The Secure Boot state, and the third party modules which it affects.
+#[derive(Debug, Default, PartialEq)]
+pub struct SecureBoot {
+    pub enabled:      bool,
+    /// Whether the key which DKMS signs modules with is enrolled in the firmware.
+    pub mok_enrolled: bool,
+    /// Modules built by DKMS for the running kernel which are not signed.
+    pub unsigned:     Vec<PathBuf>,
+    /// Modules built by DKMS for the running kernel which are signed.
+    pub signed:       Vec<PathBuf>,
+}
+
+impl SecureBoot {
+    /// Whether the modules which are rebuilt for the kernel of a new release will be unable to
+    /// load, because they will not be signed by an enrolled key.
+    pub fn modules_unloadable(&self) -> bool {
+        self.enabled
+            && (!self.unsigned.is_empty() || (!self.signed.is_empty() && !self.mok_enrolled))
+    }
+}
+
+/// Inspects the Secure Boot state, and the modules which DKMS built for the running kernel.
+pub fn inspect() -> io::Result<SecureBoot> {
+    let enabled = enabled()?;
+    if !enabled {
+        return Ok(SecureBoot::default());
+    }
+
+    let release = fs::read_to_string("/proc/sys/kernel/osrelease")?;
+    let modules = Path::new("/lib/modules").join(release.trim()).join("updates/dkms");
+
+    let mut state = SecureBoot { enabled, mok_enrolled: mok_enrolled(), ..SecureBoot::default() };
+
+    let entries = match fs::read_dir(&modules) {
+        Ok(entries) => entries,
+        Err(why) if why.kind() == io::ErrorKind::NotFound => return Ok(state),
+        Err(why) => return Err(why),
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.extension().map_or(true, |ext| ext != "ko") {
+            continue;
+        }
+
+        if is_signed(&tail(&path, MODULE_SIGNATURE.len())?) {
+            state.signed.push(path);
+        } else {
+            state.unsigned.push(path);
+        }
+    }
+
+    state.signed.sort();
+    state.unsigned.sort();
+
+    Ok(state)
+}
+
+/// Whether Secure Boot is enforced by the firmware. Systems booted in legacy BIOS mode do not
+/// support it.
+pub fn enabled() -> io::Result<bool> {
+    match fs::read(SECURE_BOOT_VAR) {
+        Ok(variable) => Ok(parse_efivar(&variable)),
+        Err(why) if why.kind() == io::ErrorKind::NotFound => Ok(false),
+        Err(why) => Err(why),
+    }
+}
+
+/// Whether the key which DKMS signs modules with has been enrolled with `mokutil`.
+fn mok_enrolled() -> bool {
+    if !Path::new(MOK_CERTIFICATE).exists() {
+        return false;
+    }
+
+    Command::new("mokutil")
+        .args(&["--test-key", MOK_CERTIFICATE])
+        .output()
+        .map_or(false, |output| {
+            String::from_utf8_lossy(&output.stdout).contains("is already enrolled")
+        })
+}
+
+/// Reads up to `length` bytes from the end of a file.
+fn tail(path: &Path, length: usize) -> io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let size = file.metadata()?.len();
+    file.seek(SeekFrom::Start(size.saturating_sub(length as u64)))?;
+
+    let mut tail = Vec::with_capacity(length);
+    file.read_to_end(&mut tail)?;
+    Ok(tail)
+}
+
+/// EFI variables are prefixed by four bytes of attributes, which are followed by the value.
+fn parse_efivar(variable: &[u8]) -> bool { variable.get(4) == Some(&1) }
+
+fn is_signed(module: &[u8]) -> bool { module.ends_with(MODULE_SIGNATURE) }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn efivar() {
+        assert!(parse_efivar(&[6, 0, 0, 0, 1]));
+        assert!(!parse_efivar(&[6, 0, 0, 0, 0]));
+        assert!(!parse_efivar(&[]));
+    }
+
+    #[test]
+    fn unloadable() {
+        let module = || vec![PathBuf::from("/lib/modules/5.11.0-7614-generic/updates/dkms/wl.ko")];
+
+        let state = |enabled, mok_enrolled, unsigned, signed| SecureBoot {
+            enabled,
+            mok_enrolled,
+            unsigned,
+            signed,
+        };
+
+        assert!(!state(false, false, module(), Vec::new()).modules_unloadable());
+        assert!(!state(true, false, Vec::new(), Vec::new()).modules_unloadable());
+        assert!(!state(true, true, Vec::new(), module()).modules_unloadable());
+        assert!(state(true, false, Vec::new(), module()).modules_unloadable());
+        assert!(state(true, true, module(), Vec::new()).modules_unloadable());
+    }
+
+    #[test]
+    fn signatures() {
+        assert!(is_signed(b"\x7fELF...~Module signature appended~\n"));
+        assert!(!is_signed(b"\x7fELF..."));
+    }
+}
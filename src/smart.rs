@@ -0,0 +1,252 @@
+use crate::{external, luks::parse_pairs};
+use serde_json::Value;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use thiserror::Error;
+
+/// Installed by `smartmontools`, which is not installed by default.
+const SMARTCTL: &str = "/usr/sbin/smartctl";
+
+const LSBLK: &str = "/usr/bin/lsblk";
+
+/// ATA attribute which counts sectors that were remapped after failing.
+const REALLOCATED_SECTORS: u64 = 5;
+
+/// ATA attribute which counts unreadable sectors that are waiting to be remapped.
+const PENDING_SECTORS: u64 = 197;
+
+/// Exit status bits of smartctl which mean that the device could not be assessed at all, such
+/// as virtual disks and USB enclosures which do not pass SMART commands through.
+const SMARTCTL_UNASSESSABLE: i32 = 0b11;
+
+#[derive(Debug, Error)]
+pub enum SmartError {
+    #[error("failed to find the disks beneath {}", _0.display())]
+    Disks(PathBuf, #[source] io::Error),
+
+    #[error("failed to run smartctl on {}", _0.display())]
+    Smartctl(PathBuf, #[source] io::Error),
+
+    #[error("smartctl returned invalid JSON for {}", _0.display())]
+    Json(PathBuf, #[source] serde_json::Error),
+}
+
+/// The SMART health which a disk reports.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DiskHealth {
+    pub disk:         PathBuf,
+    /// Whether the disk passes its overall self-assessment, if it reports one.
+    pub passed:       Option<bool>,
+    pub reallocated:  u64,
+    pub pending:      u64,
+    /// Media and data integrity errors which an NVMe disk has logged.
+    pub media_errors: u64,
+}
+
+impl DiskHealth {
+    /// The disk predicts that it will fail imminently.
+    pub fn is_failing(&self) -> bool { self.passed == Some(false) }
+
+    /// The disk has sectors which failed, which often precede further failures.
+    pub fn is_degraded(&self) -> bool {
+        self.reallocated != 0 || self.pending != 0 || self.media_errors != 0
+    }
+}
+
+impl fmt::Display for DiskHealth {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let mut issues = Vec::new();
+
+        if self.is_failing() {
+            issues.push("failed its SMART self-assessment".to_owned());
+        }
+
+        let counts = [
+            (self.reallocated, "reallocated sectors"),
+            (self.pending, "pending sectors"),
+            (self.media_errors, "media errors"),
+        ];
+
+        for &(count, what) in &counts {
+            if count != 0 {
+                issues.push(format!("{} {}", count, what));
+            }
+        }
+
+        if issues.is_empty() {
+            issues.push("healthy".to_owned());
+        }
+
+        write!(fmt, "{}: {}", self.disk.display(), issues.join(", "))
+    }
+}
+
+/// Whether smartmontools is installed, without which disks cannot be assessed.
+pub fn available() -> bool { Path::new(SMARTCTL).exists() }
+
+/// Assesses the SMART health of the disks which the mounted filesystems reside on. Disks which
+/// do not support SMART are omitted.
+pub fn assess(mounts: &[&Path]) -> Result<Vec<DiskHealth>, SmartError> {
+    let mut disks = Vec::new();
+
+    for mount in mounts {
+        for disk in disks_of(mount)? {
+            if !disks.contains(&disk) {
+                disks.push(disk);
+            }
+        }
+    }
+
+    let mut health = Vec::new();
+
+    for disk in disks {
+        let output = Command::new(SMARTCTL)
+            .args(&["--json", "-H", "-A"])
+            .arg(&disk)
+            .output()
+            .map_err(|why| SmartError::Smartctl(disk.clone(), why))?;
+
+        // The other bits of the exit status describe the health which is parsed below.
+        if output.status.code().map_or(true, |code| code & SMARTCTL_UNASSESSABLE != 0) {
+            info!("SMART health of {} could not be assessed", disk.display());
+            continue;
+        }
+
+        let json = serde_json::from_slice::<Value>(&output.stdout)
+            .map_err(|why| SmartError::Json(disk.clone(), why))?;
+
+        health.push(parse_smartctl(disk, &json));
+    }
+
+    Ok(health)
+}
+
+/// Logs a warning for each disk beneath the mounted filesystems which is failing or degraded.
+pub fn warn_unhealthy(mounts: &[&Path]) {
+    if !available() {
+        return;
+    }
+
+    match assess(mounts) {
+        Ok(health) => {
+            for disk in health.iter().filter(|disk| disk.is_failing() || disk.is_degraded()) {
+                warn!("disk is unhealthy: {}", disk);
+            }
+        }
+        Err(why) => warn!("{}", crate::misc::format_error(&why)),
+    }
+}
+
+/// The whole disks which a mounted filesystem resides on, through any partitions, LVM volumes,
+/// RAID arrays, and encrypted volumes beneath it.
+fn disks_of(mount: &Path) -> Result<Vec<PathBuf>, SmartError> {
+    let output = async_io::block_on(async {
+        let source = external::mount_source(mount).await?;
+        external::output(LSBLK, &["-s", "-p", "-P", "-o", "NAME,TYPE", &source]).await
+    })
+    .map_err(|why| SmartError::Disks(mount.to_owned(), why))?;
+
+    Ok(parse_disks(&output))
+}
+
+fn parse_disks(lsblk: &str) -> Vec<PathBuf> {
+    let mut disks = Vec::new();
+
+    for device in lsblk.lines().map(parse_pairs) {
+        if device.get("TYPE") != Some(&"disk") {
+            continue;
+        }
+
+        if let Some(&name) = device.get("NAME") {
+            let disk = PathBuf::from(name);
+            if !disks.contains(&disk) {
+                disks.push(disk);
+            }
+        }
+    }
+
+    disks
+}
+
+fn parse_smartctl(disk: PathBuf, json: &Value) -> DiskHealth {
+    let mut health = DiskHealth {
+        disk,
+        passed: json["smart_status"]["passed"].as_bool(),
+        media_errors: json["nvme_smart_health_information_log"]["media_errors"]
+            .as_u64()
+            .unwrap_or(0),
+        ..DiskHealth::default()
+    };
+
+    let attributes =
+        json["ata_smart_attributes"]["table"].as_array().map_or(&[][..], Vec::as_slice);
+
+    for attribute in attributes {
+        let raw = attribute["raw"]["value"].as_u64().unwrap_or(0);
+        match attribute["id"].as_u64() {
+            Some(REALLOCATED_SECTORS) => health.reallocated = raw,
+            Some(PENDING_SECTORS) => health.pending = raw,
+            _ => (),
+        }
+    }
+
+    health
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disks() {
+        let lsblk = r#"NAME="/dev/mapper/data-root" TYPE="lvm"
+NAME="/dev/mapper/cryptdata" TYPE="crypt"
+NAME="/dev/nvme0n1p3" TYPE="part"
+NAME="/dev/nvme0n1" TYPE="disk"
+"#;
+
+        assert_eq!(parse_disks(lsblk), vec![PathBuf::from("/dev/nvme0n1")]);
+    }
+
+    #[test]
+    fn ata() {
+        let json = serde_json::json!({
+            "smart_status": { "passed": true },
+            "ata_smart_attributes": {
+                "table": [
+                    { "id": 5, "name": "Reallocated_Sector_Ct", "raw": { "value": 8 } },
+                    { "id": 9, "name": "Power_On_Hours", "raw": { "value": 21503 } },
+                    { "id": 197, "name": "Current_Pending_Sector", "raw": { "value": 2 } }
+                ]
+            }
+        });
+
+        let health = parse_smartctl("/dev/sda".into(), &json);
+        assert_eq!(health, DiskHealth {
+            disk:         "/dev/sda".into(),
+            passed:       Some(true),
+            reallocated:  8,
+            pending:      2,
+            media_errors: 0,
+        });
+
+        assert!(!health.is_failing());
+        assert!(health.is_degraded());
+        assert_eq!(health.to_string(), "/dev/sda: 8 reallocated sectors, 2 pending sectors");
+    }
+
+    #[test]
+    fn nvme() {
+        let json = serde_json::json!({
+            "smart_status": { "passed": false },
+            "nvme_smart_health_information_log": { "critical_warning": 4, "media_errors": 0 }
+        });
+
+        let health = parse_smartctl("/dev/nvme0n1".into(), &json);
+        assert!(health.is_failing());
+        assert!(!health.is_degraded());
+    }
+}
@@ -0,0 +1,310 @@
+use crate::external;
+use std::{fs, io, path::Path};
+use thiserror::Error;
+
+const BTRFS: &str = "/usr/bin/btrfs";
+const TIMESHIFT: &str = "/usr/bin/timeshift";
+const ZFS: &str = "/usr/sbin/zfs";
+
+/// The name of the snapshot taken before the last release upgrade.
+const LAST_SNAPSHOT: &str = "/var/lib/pop-upgrade/snapshot";
+
+/// Where btrfs snapshots of the root subvolume are created.
+const BTRFS_SNAPSHOTS: &str = "/.snapshots/pop-upgrade";
+
+/// All snapshots created by pop-upgrade are given this prefix.
+const PREFIX: &str = "pop-upgrade-";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error(
+        "snapshots are not supported: the root filesystem is neither btrfs nor ZFS, and \
+         timeshift is not installed"
+    )]
+    Unsupported,
+
+    #[error("failed to detect the root filesystem")]
+    Detect(#[source] io::Error),
+
+    #[error("failed to create a snapshot with {}", _0)]
+    Create(&'static str, #[source] io::Error),
+
+    #[error("failed to list snapshots with {}", _0)]
+    List(&'static str, #[source] io::Error),
+
+    #[error("snapshot {} was not found", _0)]
+    NotFound(String),
+
+    #[error("failed to restore snapshot {}", _0)]
+    Restore(String, #[source] io::Error),
+
+    #[error("failed to record the pre-upgrade snapshot")]
+    Record(#[source] io::Error),
+
+    #[error("{} snapshots cannot be restored from the running system: {}", _0, _1)]
+    Unrestorable(&'static str, &'static str),
+}
+
+/// The tool which snapshots of the root filesystem are taken with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Btrfs,
+    Timeshift,
+    Zfs,
+}
+
+impl Backend {
+    /// Timeshift is preferred when installed, as it manages its own snapshots, and is able to
+    /// restore them from within the running system.
+    pub fn detect() -> Result<Option<Self>, SnapshotError> {
+        if Path::new(TIMESHIFT).exists() {
+            return Ok(Some(Backend::Timeshift));
+        }
+
+        let fstype =
+            async_io::block_on(external::findmnt("/", "FSTYPE")).map_err(SnapshotError::Detect)?;
+
+        Ok(match fstype.as_str() {
+            "btrfs" => Some(Backend::Btrfs),
+            "zfs" => Some(Backend::Zfs),
+            _ => None,
+        })
+    }
+}
+
+impl From<Backend> for &'static str {
+    fn from(backend: Backend) -> Self {
+        match backend {
+            Backend::Btrfs => "btrfs",
+            Backend::Timeshift => "timeshift",
+            Backend::Zfs => "zfs",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Snapshot {
+    pub backend:     Backend,
+    pub name:        String,
+    /// Whether this snapshot was taken before the last release upgrade.
+    pub pre_upgrade: bool,
+}
+
+/// Takes a snapshot of the root filesystem before upgrading to the `to` release, and records it
+/// as the pre-upgrade snapshot.
+///
+/// Returns `None` if the system does not support snapshots.
+pub fn create(to: &str) -> Result<Option<Snapshot>, SnapshotError> {
+    let backend = match Backend::detect()? {
+        Some(backend) => backend,
+        None => return Ok(None),
+    };
+
+    let timestamp = chrono::Local::now().format("%Y%m%d-%H%M%S").to_string();
+    let name = [PREFIX, to, "-", &*timestamp].concat();
+
+    info!("creating {} snapshot {}", <&'static str>::from(backend), name);
+
+    let error = |why| SnapshotError::Create(backend.into(), why);
+
+    // A stale record would otherwise point a rollback at the snapshot of an earlier upgrade.
+    match fs::remove_file(LAST_SNAPSHOT) {
+        Err(why) if why.kind() != io::ErrorKind::NotFound => {
+            return Err(SnapshotError::Record(why))
+        }
+        _ => (),
+    }
+
+    let name = match backend {
+        Backend::Btrfs => {
+            fs::create_dir_all(BTRFS_SNAPSHOTS).map_err(error)?;
+            let path = [BTRFS_SNAPSHOTS, "/", &*name].concat();
+            run(BTRFS, &["subvolume", "snapshot", "/", &*path]).map_err(error)?;
+            name
+        }
+        Backend::Timeshift => {
+            // Timeshift names snapshots by their timestamp, and lists the newest last.
+            let comments = ["pop-upgrade: before upgrading to ", to].concat();
+            run(TIMESHIFT, &["--create", "--comments", &*comments]).map_err(error)?;
+
+            timeshift_snapshots().map_err(error)?.pop().ok_or_else(|| {
+                error(io::Error::new(io::ErrorKind::NotFound, "snapshot was not created"))
+            })?
+        }
+        Backend::Zfs => {
+            let snapshot = [&*zfs_root().map_err(error)?, "@", &*name].concat();
+            run(ZFS, &["snapshot", &*snapshot]).map_err(error)?;
+            name
+        }
+    };
+
+    fs::write(LAST_SNAPSHOT, &name).map_err(SnapshotError::Record)?;
+
+    Ok(Some(Snapshot { backend, name, pre_upgrade: true }))
+}
+
+/// Snapshots of the root filesystem created by pop-upgrade, oldest first.
+pub fn list() -> Result<Vec<Snapshot>, SnapshotError> {
+    let backend = Backend::detect()?.ok_or(SnapshotError::Unsupported)?;
+    let error = |why| SnapshotError::List(backend.into(), why);
+
+    let names = match backend {
+        Backend::Btrfs => match fs::read_dir(BTRFS_SNAPSHOTS) {
+            Ok(dir) => {
+                let mut names = dir
+                    .filter_map(Result::ok)
+                    .filter_map(|entry| entry.file_name().into_string().ok())
+                    .collect::<Vec<_>>();
+                names.sort();
+                names
+            }
+            Err(why) if why.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(why) => return Err(error(why)),
+        },
+        Backend::Timeshift => timeshift_snapshots().map_err(error)?,
+        Backend::Zfs => {
+            let root = zfs_root().map_err(error)?;
+            let output = run(ZFS, &[
+                "list", "-H", "-t", "snapshot", "-o", "name", "-s", "creation", &*root,
+            ])
+            .map_err(error)?;
+
+            output
+                .lines()
+                .filter_map(|line| line.split('@').nth(1))
+                .filter(|name| name.starts_with(PREFIX))
+                .map(String::from)
+                .collect()
+        }
+    };
+
+    let last = fs::read_to_string(LAST_SNAPSHOT).unwrap_or_default();
+
+    Ok(names
+        .into_iter()
+        .map(|name| Snapshot { backend, pre_upgrade: name == last.trim(), name })
+        .collect())
+}
+
+/// Restores the root filesystem to a snapshot.
+///
+/// For btrfs, the snapshot becomes the default subvolume, which takes effect on the next boot.
+/// This is refused when the root filesystem is mounted by an explicit subvolume, as the default
+/// subvolume would then be ignored. ZFS snapshots are refused, as rolling back the root dataset
+/// while it is mounted would pull the filesystem out from under the running system.
+pub fn restore(name: &str) -> Result<Backend, SnapshotError> {
+    let snapshot = list()?
+        .into_iter()
+        .find(|snapshot| snapshot.name == name)
+        .ok_or_else(|| SnapshotError::NotFound(name.into()))?;
+
+    info!("restoring {} snapshot {}", <&'static str>::from(snapshot.backend), name);
+
+    let error = |why| SnapshotError::Restore(name.into(), why);
+
+    match snapshot.backend {
+        Backend::Btrfs => {
+            let fstab = fs::read_to_string("/etc/fstab").unwrap_or_default();
+            let cmdline = fs::read_to_string("/proc/cmdline").unwrap_or_default();
+            if pins_subvolume(&fstab, &cmdline) {
+                return Err(SnapshotError::Unrestorable(
+                    "btrfs",
+                    "the root filesystem is mounted by subvolume, so the default subvolume is \
+                     not used at boot",
+                ));
+            }
+
+            let path = [BTRFS_SNAPSHOTS, "/", name].concat();
+            run(BTRFS, &["subvolume", "set-default", &*path]).map_err(error)?;
+        }
+        Backend::Timeshift => {
+            let args = ["--restore", "--snapshot", name, "--scripted", "--yes"];
+            run(TIMESHIFT, &args).map_err(error)?;
+        }
+        Backend::Zfs => {
+            return Err(SnapshotError::Unrestorable(
+                "zfs",
+                "roll back the root dataset from the recovery partition instead",
+            ));
+        }
+    }
+
+    Ok(snapshot.backend)
+}
+
+/// Whether the root filesystem is mounted by an explicit subvolume, either by its options in
+/// fstab or by the `rootflags` on the kernel command line.
+fn pins_subvolume(fstab: &str, cmdline: &str) -> bool {
+    let pins = |options: &str| {
+        options.split(',').any(|option| {
+            option.starts_with("subvol=") || option.starts_with("subvolid=")
+        })
+    };
+
+    let in_fstab = fstab
+        .lines()
+        .map(str::split_whitespace)
+        .filter_map(|mut fields| Some((fields.nth(1)?, fields.nth(1)?)))
+        .any(|(target, options)| target == "/" && pins(options));
+
+    in_fstab
+        || cmdline
+            .split_whitespace()
+            .filter_map(|arg| arg.strip_prefix("rootflags="))
+            .any(pins)
+}
+
+/// The names of the snapshots created by pop-upgrade, which timeshift lists with their comments.
+fn timeshift_snapshots() -> io::Result<Vec<String>> {
+    Ok(parse_timeshift(&run(TIMESHIFT, &["--list"])?))
+}
+
+fn parse_timeshift(output: &str) -> Vec<String> {
+    // Snapshots are listed as `<num> > <name> <tags> <comments>`.
+    output
+        .lines()
+        .filter(|line| line.contains("pop-upgrade:"))
+        .filter_map(|line| line.split_whitespace().nth(2))
+        .map(String::from)
+        .collect()
+}
+
+/// The ZFS dataset mounted as the root filesystem.
+fn zfs_root() -> io::Result<String> { async_io::block_on(external::findmnt("/", "SOURCE")) }
+
+fn run(program: &str, args: &[&str]) -> io::Result<String> {
+    async_io::block_on(external::output(program, args))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TIMESHIFT: &str = "Device : /dev/nvme0n1p3
+UUID   : 8b6d6ae1-0e8a-4d8b-9a4e-3f2c9a1e6b7d
+Path   : /run/timeshift/backup
+Mode   : RSYNC
+------------------------------------------------------------------------------
+
+Num     Name                 Tags  Description
+------------------------------------------------------------------------------
+0    >  2021-03-01_10-00-01  D
+1    >  2021-04-22_15-30-12  O     pop-upgrade: before upgrading to 21.04
+";
+
+    #[test]
+    fn timeshift_list() {
+        assert_eq!(parse_timeshift(TIMESHIFT), vec!["2021-04-22_15-30-12"]);
+    }
+
+    #[test]
+    fn subvolume_pinned() {
+        let fstab = "UUID=1234 /     btrfs defaults,subvol=@ 0 0\n\
+                     UUID=1234 /home btrfs defaults,subvol=@home 0 0\n";
+        assert!(pins_subvolume(fstab, "root=UUID=1234 ro quiet"));
+
+        let fstab = "UUID=1234 / btrfs defaults 0 0\n";
+        assert!(!pins_subvolume(fstab, "root=UUID=1234 ro quiet"));
+        assert!(pins_subvolume(fstab, "root=UUID=1234 rootflags=subvolid=256 ro"));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{external, luks::parse_pairs};
+use std::{fmt, fs, io, path::Path};
+use thiserror::Error;
+
+const LSBLK: &str = "/usr/bin/lsblk";
+
+/// Installed by `lvm2`, and required for the initramfs to activate logical volumes.
+const LVM_HOOK: &str = "/usr/share/initramfs-tools/hooks/lvm2";
+
+/// Installed by `mdadm`, and required for the initramfs to assemble software RAID arrays.
+const MDADM_HOOK: &str = "/usr/share/initramfs-tools/hooks/mdadm";
+
+/// Installed by `dmraid`, and required for the initramfs to activate firmware RAID sets.
+const DMRAID_HOOK: &str = "/usr/share/initramfs-tools/hooks/dmraid";
+
+/// Defines the arrays which the initramfs assembles.
+const MDADM_CONF: &str = "/etc/mdadm/mdadm.conf";
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("failed to inspect the root filesystem")]
+    Inspect(#[source] io::Error),
+
+    #[error(
+        "the root filesystem is on {}, but {} is not installed, so the upgraded initramfs would \
+         be unable to mount it",
+        _0,
+        _1
+    )]
+    MissingHooks(Layout, &'static str),
+
+    #[error(
+        "the root filesystem is on the RAID array {}, which /etc/mdadm/mdadm.conf does not \
+         define, so the upgraded initramfs may be unable to assemble it",
+        _0
+    )]
+    UndefinedArray(String),
+}
+
+impl StorageError {
+    /// Whether the error describes a layout which the initramfs would be unable to mount, rather
+    /// than a failure to inspect the system.
+    pub fn is_inconsistent(&self) -> bool { !matches!(self, StorageError::Inspect(_)) }
+}
+
+/// A layer of the storage stack beneath the root filesystem, which the initramfs must set up.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Layout {
+    Lvm,
+    Mdadm,
+    DmRaid,
+}
+
+impl Layout {
+    /// The initramfs hook which sets up the layout, and the package which installs it.
+    fn hook(self) -> (&'static str, &'static str) {
+        match self {
+            Layout::Lvm => (LVM_HOOK, "lvm2"),
+            Layout::Mdadm => (MDADM_HOOK, "mdadm"),
+            Layout::DmRaid => (DMRAID_HOOK, "dmraid"),
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str(match self {
+            Layout::Lvm => "LVM",
+            Layout::Mdadm => "mdadm RAID",
+            Layout::DmRaid => "dm-raid",
+        })
+    }
+}
+
+/// The storage stack beneath the root filesystem.
+#[derive(Debug, Default, PartialEq)]
+pub struct RootStorage {
+    pub layouts: Vec<Layout>,
+    /// The UUIDs of the software RAID arrays which the root filesystem depends on.
+    pub arrays:  Vec<String>,
+}
+
+/// Inspects the devices which the root filesystem resides on.
+pub fn root_storage() -> Result<RootStorage, StorageError> {
+    let output = async_io::block_on(async {
+        let source = external::mount_source("/").await?;
+        external::output(LSBLK, &["-s", "-P", "-o", "NAME,TYPE,FSTYPE,UUID", &source]).await
+    })
+    .map_err(StorageError::Inspect)?;
+
+    Ok(parse_lsblk(&output))
+}
+
+/// Validates that the initramfs of the new release would be able to set up the devices which
+/// the root filesystem resides on. Returns the storage of the root filesystem.
+pub fn validate() -> Result<RootStorage, StorageError> {
+    let storage = root_storage()?;
+
+    for &layout in &storage.layouts {
+        let (hook, package) = layout.hook();
+        if !Path::new(hook).exists() {
+            return Err(StorageError::MissingHooks(layout, package));
+        }
+    }
+
+    if !storage.arrays.is_empty() {
+        let conf = fs::read_to_string(MDADM_CONF).unwrap_or_default();
+        check_mdadm_conf(&conf, &storage.arrays)?;
+    }
+
+    Ok(storage)
+}
+
+fn parse_lsblk(output: &str) -> RootStorage {
+    let mut storage = RootStorage::default();
+
+    for device in output.lines().map(parse_pairs) {
+        let layout = match device.get("TYPE").copied().unwrap_or("") {
+            "lvm" => Some(Layout::Lvm),
+            "dmraid" => Some(Layout::DmRaid),
+            kind if kind.starts_with("raid") || kind == "linear" => Some(Layout::Mdadm),
+            _ => None,
+        };
+
+        if let Some(layout) = layout {
+            if !storage.layouts.contains(&layout) {
+                storage.layouts.push(layout);
+            }
+        }
+
+        // The members of an array share the UUID of the array.
+        if device.get("FSTYPE") == Some(&"linux_raid_member") {
+            if let Some(uuid) = device.get("UUID").filter(|uuid| !uuid.is_empty()) {
+                let uuid = normalize_uuid(uuid);
+                if !storage.arrays.contains(&uuid) {
+                    storage.arrays.push(uuid);
+                }
+            }
+        }
+    }
+
+    storage
+}
+
+/// Requires that each array is defined by an `ARRAY` line in the mdadm configuration.
+fn check_mdadm_conf(conf: &str, arrays: &[String]) -> Result<(), StorageError> {
+    let defined = conf
+        .lines()
+        .map(str::trim)
+        .filter(|line| line.starts_with("ARRAY"))
+        .flat_map(|line| line.split_whitespace())
+        .filter_map(|field| field.strip_prefix("UUID="))
+        .map(normalize_uuid)
+        .collect::<Vec<_>>();
+
+    match arrays.iter().find(|uuid| !defined.contains(uuid)) {
+        Some(uuid) => Err(StorageError::UndefinedArray(uuid.clone())),
+        None => Ok(()),
+    }
+}
+
+/// mdadm writes UUIDs as `a:b:c:d`, whereas lsblk writes them as `a-b-c-d-e`.
+fn normalize_uuid(uuid: &str) -> String {
+    uuid.chars().filter(char::is_ascii_hexdigit).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LSBLK: &str = r#"NAME="data-root" TYPE="lvm" FSTYPE="ext4" UUID="0d5b4e3c-7a0e-4b4e-9d4b"
+NAME="md0" TYPE="raid1" FSTYPE="LVM2_member" UUID="kWb3Xr-1nYq-3oXq-aB2c-dE4f-gH5i-jK6l7m"
+NAME="sda1" TYPE="part" FSTYPE="linux_raid_member" UUID="3a0f7d4c-91b2-e5a8-6c1d-0b9e8f7a6d5c"
+NAME="sda" TYPE="disk" FSTYPE="" UUID=""
+NAME="sdb1" TYPE="part" FSTYPE="linux_raid_member" UUID="3a0f7d4c-91b2-e5a8-6c1d-0b9e8f7a6d5c"
+NAME="sdb" TYPE="disk" FSTYPE="" UUID=""
+"#;
+
+    const ARRAY: &str = "3a0f7d4c91b2e5a86c1d0b9e8f7a6d5c";
+
+    #[test]
+    fn layouts() {
+        assert_eq!(parse_lsblk(LSBLK), RootStorage {
+            layouts: vec![Layout::Lvm, Layout::Mdadm],
+            arrays:  vec![ARRAY.into()],
+        });
+
+        let plain = r#"NAME="nvme0n1p3" TYPE="part" FSTYPE="ext4" UUID="ed9e7007""#;
+        assert_eq!(parse_lsblk(plain), RootStorage::default());
+    }
+
+    #[test]
+    fn mdadm_conf() {
+        let conf = "# mdadm.conf\nARRAY /dev/md/0 metadata=1.2 \
+                    UUID=3a0f7d4c:91b2e5a8:6c1d0b9e:8f7a6d5c name=pop-os:0\n";
+        assert!(check_mdadm_conf(conf, &[ARRAY.into()]).is_ok());
+
+        assert!(matches!(
+            check_mdadm_conf("# mdadm.conf\n", &[ARRAY.into()]),
+            Err(StorageError::UndefinedArray(_))
+        ));
+    }
+}
@@ -0,0 +1,113 @@
+//! Opt-in reports of the outcomes of release upgrades, which show the maintainers the phases in
+//! which upgrades fail most often. Nothing is sent unless `[telemetry]` is enabled in the system
+//! configuration, and reports contain no package lists, host names, or other identifiers.
+
+use crate::{config::Config, release::UpgradeEvent};
+use isahc::{
+    config::Configurable,
+    http::{header, Request},
+};
+use serde_derive::Serialize;
+use std::{fmt::Debug, time::Duration};
+
+const TIMEOUT: Duration = Duration::from_secs(15);
+
+/// The outcome of a release upgrade.
+#[derive(Debug, PartialEq, Serialize)]
+pub struct Report {
+    /// The version of pop-upgrade which performed the upgrade.
+    pub version: &'static str,
+    pub from:    String,
+    pub to:      String,
+    pub success: bool,
+    /// The phase that the upgrade failed in.
+    pub phase:   Option<&'static str>,
+    /// The kind of error that the upgrade failed with, such as `Held`, without its details.
+    pub error:   Option<String>,
+}
+
+impl Report {
+    pub fn new<E: Debug>(
+        from: &str,
+        to: &str,
+        phase: Option<UpgradeEvent>,
+        result: Result<&(), &E>,
+    ) -> Self {
+        let error = result.err().map(class);
+
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            from:    from.to_owned(),
+            to:      to.to_owned(),
+            success: error.is_none(),
+            phase:   if error.is_some() { phase.map(<&'static str>::from) } else { None },
+            error,
+        }
+    }
+}
+
+/// Sends the report from a background thread, if reporting is enabled.
+pub fn report(report: Report) {
+    let config = Config::load_or_default().telemetry;
+    let endpoint = match config.endpoint {
+        Some(endpoint) if config.enabled => endpoint,
+        _ => return,
+    };
+
+    std::thread::spawn(move || {
+        if let Err(why) = send(&endpoint, &report) {
+            warn!("failed to report the outcome of the upgrade: {}", why);
+        }
+    });
+}
+
+fn send(endpoint: &str, report: &Report) -> anyhow::Result<()> {
+    let request = Request::post(endpoint)
+        .timeout(TIMEOUT)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(report)?)?;
+
+    let status = crate::http::client(endpoint)?.send(request)?.status();
+    if !status.is_success() {
+        return Err(anyhow!("{} responded with {}", endpoint, status));
+    }
+
+    info!("reported the outcome of the upgrade to {}", endpoint);
+    Ok(())
+}
+
+/// The name of the variant of an error, which omits the details that it carries.
+fn class(why: &impl Debug) -> String {
+    let debug = fomat!([why]);
+    debug.chars().take_while(|c| c.is_alphanumeric() || *c == '_').collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug)]
+    enum Error {
+        Held(Vec<String>),
+        Cancelled,
+    }
+
+    #[test]
+    fn reports() {
+        let held = Error::Held(vec!["linux-generic".into()]);
+        let phase = Some(UpgradeEvent::UpgradingPackages);
+
+        let report = Report::new("21.10", "22.04", phase, Err(&held));
+        assert!(!report.success);
+        assert_eq!(report.error.as_deref(), Some("Held"));
+        assert_eq!(report.phase, Some(<&'static str>::from(UpgradeEvent::UpgradingPackages)));
+
+        let report = Report::new("21.10", "22.04", phase, Err(&Error::Cancelled));
+        assert_eq!(report.error.as_deref(), Some("Cancelled"));
+
+        let report = Report::new::<Error>("21.10", "22.04", phase, Ok(&()));
+        assert!(report.success);
+        assert_eq!(report.phase, None);
+        assert_eq!(report.error, None);
+    }
+}